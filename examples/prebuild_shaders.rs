@@ -0,0 +1,58 @@
+//! Compiles every shader in `shaders/src` in every keyword combination into `shaders/prebuilt`,
+//! which builds without the `shader-compiler` feature load instead of compiling.
+//! Run it after changing a shader: `cargo run --example prebuild_shaders`
+
+use std::fs;
+use std::path::Path;
+
+use pupsy_engine::utility::constants;
+use pupsy_engine::vk::shader_compiler::ShaderCompiler;
+use pupsy_engine::vk::shader_variants::ShaderAsset;
+
+fn main() {
+    let source_dir = Path::new(constants::SHADER_SOURCE_DIR);
+    let prebuilt_dir = Path::new(constants::SHADER_PREBUILT_DIR);
+
+    // files of older versions of the sources would never be looked up again
+    if prebuilt_dir.exists() {
+        fs::remove_dir_all(prebuilt_dir).expect("Failed to clear the prebuilt shaders");
+    }
+
+    // the compiler caches under the names the engine looks the prebuilt files up by
+    let mut shader_compiler = ShaderCompiler::new(&[source_dir], prebuilt_dir);
+
+    let mut sources: Vec<_> = fs::read_dir(source_dir)
+        .expect("Failed to read the shader sources")
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| ShaderCompiler::shader_stage(path).is_some())
+        .collect();
+    sources.sort();
+
+    let mut failed = 0;
+    for source in sources.iter() {
+        let mut asset = ShaderAsset::load(source);
+        let keywords = asset.keywords().to_vec();
+
+        for variant in 0..1u64 << keywords.len() {
+            let enabled: Vec<&str> = keywords
+                .iter()
+                .enumerate()
+                .filter(|(i, _)| variant & (1 << i) != 0)
+                .map(|(_, keyword)| keyword.as_str())
+                .collect();
+
+            match asset.variant(&mut shader_compiler, &enabled) {
+                Ok(_) => println!("{} {:?}", source.display(), enabled),
+                Err(error) => {
+                    eprint!("{}", error);
+                    failed += 1;
+                },
+            }
+        }
+    }
+
+    if failed > 0 {
+        eprintln!("{} shader variants failed to compile", failed);
+        std::process::exit(1);
+    }
+}
@@ -0,0 +1,160 @@
+//! The demo scene: a spinning quad with a triangle attached, inside an instanced ring of triangles.
+//! Left drag orbits the camera, right drag pans, the wheel zooms. Run it with `cargo run --example spinning_quad`
+
+use cgmath::{Deg, Matrix4, Point3, Quaternion, Rotation3, Vector2, Vector3};
+use winit::event::MouseButton;
+
+use pupsy_engine::core::app::{App, EngineConfig};
+use pupsy_engine::core::camera::{CameraInput, OrbitController};
+use pupsy_engine::core::engine::Engine;
+use pupsy_engine::core::input::{AxisBinding, Binding, GamepadAxis};
+use pupsy_engine::core::scene::{NodeId, SceneGraph, Transform};
+use pupsy_engine::vk::render_device::MeshId;
+use pupsy_engine::vk::mesh::MeshIndices;
+use pupsy_engine::vk::vertex::Vertex;
+
+const ORBIT_ACTION: &str = "orbit";
+const PAN_ACTION: &str = "pan";
+const ORBIT_X_AXIS: &str = "orbit_x";
+const ORBIT_Y_AXIS: &str = "orbit_y";
+const ZOOM_AXIS: &str = "zoom";
+
+/// How fast a fully deflected stick orbits, in the pixels of mouse movement it stands for per second.
+const STICK_ORBIT_SPEED: f32 = 600.0;
+
+const TRIANGLE_VERTICES: [Vertex; 3] = [
+    Vertex {
+        pos: [0.0, 0.5],
+        color: [1.0, 1.0, 0.0],
+    },
+    Vertex {
+        pos: [-0.5, -0.5],
+        color: [0.0, 1.0, 1.0],
+    },
+    Vertex {
+        pos: [0.5, -0.5],
+        color: [1.0, 0.0, 1.0],
+    },
+];
+const TRIANGLE_INDICES: [u16; 3] = [0, 1, 2];
+
+/// Triangles circling the quad, drawn as one instanced draw.
+const RING_INSTANCES: usize = 12;
+
+/// The demo scene: a quad spinning at a fixed rate, simulated at the fixed timestep, with a
+/// smaller triangle attached to it that follows along, inside a ring of triangles turning the other way.
+struct SpinningQuad {
+    scene: SceneGraph,
+    spinner: NodeId,
+    satellite: NodeId,
+    triangle: Option<MeshId>,
+
+    /// state of the last two fixed updates, blended for rendering
+    previous_rotation: Deg<f32>,
+    rotation: Deg<f32>,
+
+    orbit: Option<OrbitController>,
+}
+
+impl SpinningQuad {
+    fn new() -> SpinningQuad {
+        let mut scene = SceneGraph::new();
+        let spinner = scene.add_node("spinner", Transform::identity(), None);
+        let satellite = scene.add_node(
+            "satellite",
+            Transform {
+                scale: Vector3::new(0.25, 0.25, 0.25),
+                ..Transform::from_translation(Vector3::new(0.75, 0.0, 0.0))
+            },
+            Some(spinner));
+
+        SpinningQuad {
+            scene,
+            spinner,
+            satellite,
+            triangle: None,
+            previous_rotation: Deg(0.0),
+            rotation: Deg(0.0),
+            orbit: None,
+        }
+    }
+}
+
+impl App for SpinningQuad {
+    fn init(&mut self, engine: &mut Engine) {
+        let quad = engine.render_device.quad_mesh();
+        let triangle = engine.render_device
+            .create_mesh(&TRIANGLE_VERTICES, MeshIndices::U16(&TRIANGLE_INDICES), &[])
+            .expect("Failed to create triangle mesh");
+        self.scene.set_mesh(self.spinner, Some(quad));
+        self.scene.set_mesh(self.satellite, Some(triangle));
+        self.triangle = Some(triangle);
+
+        // left drag orbits, right drag pans, the wheel zooms
+        let map = &mut engine.input.map;
+        map.bind_action(ORBIT_ACTION, Binding::Mouse(MouseButton::Left));
+        map.bind_action(PAN_ACTION, Binding::Mouse(MouseButton::Right));
+        map.bind_axis(ORBIT_X_AXIS, AxisBinding::Gamepad(GamepadAxis::RightStickX));
+        map.bind_axis(ORBIT_Y_AXIS, AxisBinding::Gamepad(GamepadAxis::RightStickY));
+        map.bind_axis(ZOOM_AXIS, AxisBinding::Scroll);
+
+        self.orbit = Some(OrbitController::new(&engine.camera, Point3::new(0.0, 0.0, 0.0), Vector3::unit_z()));
+    }
+
+    fn fixed_update(&mut self, _engine: &mut Engine, step: f32) {
+        self.previous_rotation = self.rotation;
+        self.rotation += Deg(90.0) * step;
+
+        // keep the angles small without changing the blend between them
+        if self.rotation.0 >= 360.0 {
+            self.previous_rotation -= Deg(360.0);
+            self.rotation -= Deg(360.0);
+        }
+    }
+
+    fn update(&mut self, engine: &mut Engine, delta_time: f32) {
+        let input = &engine.input;
+        let mut camera_input = CameraInput::default();
+
+        if input.action_held(ORBIT_ACTION) {
+            camera_input.look += input.mouse_delta();
+        }
+        if input.action_held(PAN_ACTION) {
+            camera_input.pan += input.mouse_delta();
+        }
+        // stick +Y is up, mouse +Y is down
+        camera_input.look += Vector2::new(input.axis(ORBIT_X_AXIS), -input.axis(ORBIT_Y_AXIS)) * STICK_ORBIT_SPEED * delta_time;
+        camera_input.zoom = input.axis(ZOOM_AXIS);
+
+        if let Some(orbit) = self.orbit.as_mut() {
+            orbit.update(&mut engine.camera, &camera_input);
+        }
+    }
+
+    fn render(&mut self, engine: &mut Engine, alpha: f32) {
+        let rotation = self.previous_rotation + (self.rotation - self.previous_rotation) * alpha;
+        self.scene.set_rotation(self.spinner, Quaternion::from_angle_z(rotation));
+        engine.render_device.draw_scene(&self.scene);
+
+        if let Some(triangle) = self.triangle {
+            let ring: Vec<Matrix4<f32>> = (0..RING_INSTANCES)
+                .map(|i| {
+                    let angle = Deg(360.0 * i as f32 / RING_INSTANCES as f32) - rotation;
+                    Matrix4::from_angle_z(angle) * Matrix4::from_translation(Vector3::new(1.5, 0.0, 0.0)) * Matrix4::from_scale(0.2)
+                })
+                .collect();
+            engine.render_device.draw_mesh_instanced(triangle, &ring);
+        }
+    }
+
+    fn ui(&mut self, ui: &imgui::Ui) {
+        imgui::Window::new("Demo").build(ui, || {
+            ui.text(format!("Rotation: {:.0} deg", self.rotation.0));
+            ui.text(format!("Ring instances: {}", RING_INSTANCES));
+        });
+    }
+}
+
+fn main() {
+    Engine::run(SpinningQuad::new(), EngineConfig::default());
+}
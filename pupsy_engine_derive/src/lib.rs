@@ -0,0 +1,358 @@
+//! Derives for `pupsy_engine`, which re-exports them.
+
+use proc_macro::TokenStream;
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Expr, Fields, GenericArgument, Ident, Lit, LitInt, PathArguments, Type};
+
+/// Implements `BindingDescriptions` and `AttributeDescriptions` for a `#[repr(C)]` struct, with
+/// one attribute per field, or one per column of a matrix. Also implements `Pod`, so the fields have
+/// to be `Pod` themselves and leave no padding, which fails to compile otherwise.
+///
+/// Formats follow the field types: `f32`, `u32` and `i32` and arrays of up to 4 of them, cgmath's
+/// `Vector2`-`4` and `Point2`-`3` of `f32`, normalized `u8`, `[u8; 2]` and `[u8; 4]`, and `Matrix4<f32>` or
+/// `[[f32; 4]; 4]` for 4 locations. Anything else takes `#[format(R16G16_SFLOAT)]`.
+///
+/// Locations count up from 0 in field order, `#[location(n)]` moves a field and the ones after it.
+/// `#[vertex(binding = 1, per_instance)]` on the struct sets the binding and the input rate.
+#[proc_macro_derive(Vertex, attributes(vertex, location, format))]
+pub fn derive_vertex(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand_vertex(&input)
+        .unwrap_or_else(|error| error.to_compile_error())
+        .into()
+}
+
+struct VertexOptions {
+    binding: u32,
+    per_instance: bool,
+}
+
+/// How one field is fed to the shader: `count` consecutive locations of `format`, `stride` bytes apart.
+struct FieldFormat {
+    format: Ident,
+    count: u32,
+    stride: u32,
+}
+
+fn expand_vertex(input: &DeriveInput) -> Result<TokenStream2, Error> {
+    let name = &input.ident;
+
+    if !has_repr_c(input) {
+        return Err(Error::new(name.span(), "#[derive(Vertex)] needs #[repr(C)] so the field offsets are stable"));
+    }
+    if !input.generics.params.is_empty() {
+        return Err(Error::new(input.generics.span(), "#[derive(Vertex)] does not support generic structs"));
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Err(Error::new(name.span(), "#[derive(Vertex)] needs a struct with named fields")),
+        },
+        _ => return Err(Error::new(name.span(), "#[derive(Vertex)] only applies to structs")),
+    };
+
+    let options = parse_vertex_options(input)?;
+    let binding = options.binding;
+    let input_rate = if options.per_instance {
+        quote!(::pupsy_engine::ash::vk::VertexInputRate::INSTANCE)
+    } else {
+        quote!(::pupsy_engine::ash::vk::VertexInputRate::VERTEX)
+    };
+
+    let mut attributes = vec![];
+    let mut used_locations: Vec<(u32, Span)> = vec![];
+    let mut next_location = 0u32;
+
+    for field in fields {
+        let field_name = field.ident.as_ref().expect("named fields have names");
+        let mut location = next_location;
+        let mut format_override = None;
+
+        for attribute in field.attrs.iter() {
+            if attribute.path().is_ident("location") {
+                let value: LitInt = attribute.parse_args()?;
+                location = value.base10_parse()?;
+            } else if attribute.path().is_ident("format") {
+                format_override = Some(attribute.parse_args::<Ident>()?);
+            }
+        }
+
+        let field_format = match format_override {
+            Some(format) => FieldFormat {
+                format,
+                count: 1,
+                stride: 0,
+            },
+            None => infer_format(&field.ty)?,
+        };
+
+        for column in 0..field_format.count {
+            let column_location = location + column;
+            if let Some((_, previous)) = used_locations.iter().find(|(used, _)| *used == column_location) {
+                let mut error = Error::new(field.span(), format!("location {} is already taken", column_location));
+                error.combine(Error::new(*previous, "by this field"));
+                return Err(error);
+            }
+            used_locations.push((column_location, field.span()));
+
+            let format = &field_format.format;
+            let column_offset = column * field_format.stride;
+            attributes.push(quote! {
+                ::pupsy_engine::ash::vk::VertexInputAttributeDescription {
+                    binding: #binding,
+                    location: #column_location,
+                    format: ::pupsy_engine::ash::vk::Format::#format,
+                    offset: (::core::mem::offset_of!(Self, #field_name) as u32) + #column_offset,
+                }
+            });
+        }
+
+        next_location = location + field_format.count;
+    }
+
+    let field_types: Vec<&Type> = fields.iter().map(|field| &field.ty).collect();
+    let padding_message = format!("{} has padding between or after its fields, which can't be uploaded as bytes", name);
+
+    Ok(quote! {
+        unsafe impl ::pupsy_engine::vk::pod::Pod for #name where #(#field_types: ::pupsy_engine::vk::pod::Pod),* {}
+
+        const _: () = ::core::assert!(
+            ::core::mem::size_of::<#name>() == 0 #(+ ::core::mem::size_of::<#field_types>())*,
+            #padding_message
+        );
+
+        impl ::pupsy_engine::vk::vertex::BindingDescriptions for #name {
+            fn get_binding_descriptions() -> ::std::vec::Vec<::pupsy_engine::ash::vk::VertexInputBindingDescription> {
+                ::std::vec![::pupsy_engine::ash::vk::VertexInputBindingDescription {
+                    binding: #binding,
+                    stride: ::core::mem::size_of::<Self>() as u32,
+                    input_rate: #input_rate,
+                }]
+            }
+        }
+
+        impl ::pupsy_engine::vk::vertex::AttributeDescriptions for #name {
+            fn get_attribute_descriptions() -> ::std::vec::Vec<::pupsy_engine::ash::vk::VertexInputAttributeDescription> {
+                ::std::vec![#(#attributes),*]
+            }
+        }
+    })
+}
+
+fn has_repr_c(input: &DeriveInput) -> bool {
+    input.attrs.iter()
+        .filter(|attribute| attribute.path().is_ident("repr"))
+        .any(|attribute| {
+            let mut is_c = false;
+            let _ = attribute.parse_nested_meta(|meta| {
+                is_c |= meta.path.is_ident("C");
+                Ok(())
+            });
+            is_c
+        })
+}
+
+fn parse_vertex_options(input: &DeriveInput) -> Result<VertexOptions, Error> {
+    let mut options = VertexOptions {
+        binding: 0,
+        per_instance: false,
+    };
+
+    for attribute in input.attrs.iter().filter(|attribute| attribute.path().is_ident("vertex")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("binding") {
+                let value: LitInt = meta.value()?.parse()?;
+                options.binding = value.base10_parse()?;
+                Ok(())
+            } else if meta.path.is_ident("per_instance") {
+                options.per_instance = true;
+                Ok(())
+            } else if meta.path.is_ident("per_vertex") {
+                options.per_instance = false;
+                Ok(())
+            } else {
+                Err(meta.error("expected `binding = n`, `per_instance` or `per_vertex`"))
+            }
+        })?;
+    }
+
+    Ok(options)
+}
+
+fn infer_format(ty: &Type) -> Result<FieldFormat, Error> {
+    let unknown = || Error::new(ty.span(), "can't pick a vk::Format for this type, give it one with #[format(...)]");
+    let single = |format: &str| FieldFormat {
+        format: Ident::new(format, Span::call_site()),
+        count: 1,
+        stride: 0,
+    };
+
+    match ty {
+        Type::Array(array) => {
+            let length = array_length(&array.len).ok_or_else(unknown)?;
+
+            // a 4x4 matrix as its columns
+            if let Type::Array(column) = &*array.elem {
+                if length == 4 && array_length(&column.len) == Some(4) && scalar_name(&column.elem).as_deref() == Some("f32") {
+                    return Ok(FieldFormat {
+                        format: Ident::new("R32G32B32A32_SFLOAT", Span::call_site()),
+                        count: 4,
+                        stride: 16,
+                    });
+                }
+                return Err(unknown());
+            }
+
+            let scalar = scalar_name(&array.elem).ok_or_else(unknown)?;
+            if scalar == "u8" && length == 3 {
+                return Err(Error::new(
+                    ty.span(),
+                    "few GPUs read R8G8B8_UNORM vertex attributes, use [u8; 4] and leave the last byte unused",
+                ));
+            }
+            vector_format(&scalar, length).map(single).ok_or_else(unknown)
+        },
+        Type::Path(path) => {
+            let segment = path.path.segments.last().ok_or_else(unknown)?;
+            let component = match &segment.arguments {
+                PathArguments::AngleBracketed(arguments) => match arguments.args.first() {
+                    Some(GenericArgument::Type(component)) => scalar_name(component),
+                    _ => None,
+                },
+                PathArguments::None => None,
+                PathArguments::Parenthesized(_) => return Err(unknown()),
+            };
+
+            let name = segment.ident.to_string();
+            match (name.as_str(), component.as_deref()) {
+                ("Vector2" | "Point2", Some("f32")) => Ok(single("R32G32_SFLOAT")),
+                ("Vector3" | "Point3", Some("f32")) => Ok(single("R32G32B32_SFLOAT")),
+                ("Vector4", Some("f32")) => Ok(single("R32G32B32A32_SFLOAT")),
+                ("Matrix4", Some("f32")) => Ok(FieldFormat {
+                    format: Ident::new("R32G32B32A32_SFLOAT", Span::call_site()),
+                    count: 4,
+                    stride: 16,
+                }),
+                (scalar, None) => vector_format(scalar, 1).map(single).ok_or_else(unknown),
+                _ => Err(unknown()),
+            }
+        },
+        _ => Err(unknown()),
+    }
+}
+
+fn vector_format(scalar: &str, length: u32) -> Option<&'static str> {
+    let formats = match scalar {
+        "f32" => ["R32_SFLOAT", "R32G32_SFLOAT", "R32G32B32_SFLOAT", "R32G32B32A32_SFLOAT"],
+        "u32" => ["R32_UINT", "R32G32_UINT", "R32G32B32_UINT", "R32G32B32A32_UINT"],
+        "i32" => ["R32_SINT", "R32G32_SINT", "R32G32B32_SINT", "R32G32B32A32_SINT"],
+        // bytes are most often colours, read as 0..1
+        // 3 bytes are rejected before getting here
+        "u8" => ["R8_UNORM", "R8G8_UNORM", "R8G8B8_UNORM", "R8G8B8A8_UNORM"],
+        _ => return None,
+    };
+
+    match length {
+        1..=4 => Some(formats[length as usize - 1]),
+        _ => None,
+    }
+}
+
+fn scalar_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.get_ident().map(|ident| ident.to_string()),
+        _ => None,
+    }
+}
+
+fn array_length(length: &Expr) -> Option<u32> {
+    match length {
+        Expr::Lit(literal) => match &literal.lit {
+            Lit::Int(value) => value.base10_parse().ok(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::parse_quote;
+
+    fn expand_error(input: DeriveInput) -> String {
+        expand_vertex(&input).err().map(|error| error.to_string()).unwrap_or_default()
+    }
+
+    #[test]
+    fn overlapping_locations_are_rejected() {
+        let error = expand_error(parse_quote! {
+            #[repr(C)]
+            struct Instance {
+                model: [[f32; 4]; 4],
+                #[location(2)]
+                color: [f32; 4],
+            }
+        });
+        assert_eq!(error, "location 2 is already taken");
+
+        let error = expand_error(parse_quote! {
+            #[repr(C)]
+            struct Vertex {
+                #[location(1)]
+                position: [f32; 3],
+                #[location(1)]
+                normal: [f32; 3],
+            }
+        });
+        assert_eq!(error, "location 1 is already taken");
+    }
+
+    #[test]
+    fn three_bytes_are_rejected_with_a_hint() {
+        let error = expand_error(parse_quote! {
+            #[repr(C)]
+            struct Vertex {
+                color: [u8; 3],
+            }
+        });
+        assert!(error.contains("use [u8; 4]"), "{}", error);
+
+        let expanded = expand_vertex(&parse_quote! {
+            #[repr(C)]
+            struct Vertex {
+                color: [u8; 4],
+            }
+        });
+        assert!(expanded.unwrap().to_string().contains("R8G8B8A8_UNORM"));
+    }
+
+    #[test]
+    fn pod_is_implemented_with_a_padding_check() {
+        let expanded = expand_vertex(&parse_quote! {
+            #[repr(C)]
+            struct Vertex {
+                position: [f32; 3],
+                color: [u8; 4],
+            }
+        })
+        .unwrap()
+        .to_string();
+
+        assert!(expanded.contains("unsafe impl :: pupsy_engine :: vk :: pod :: Pod for Vertex where [f32 ; 3] :"), "{}", expanded);
+        assert!(expanded.contains("Vertex has padding"), "{}", expanded);
+    }
+
+    #[test]
+    fn unsupported_structs_and_types_are_rejected() {
+        assert!(expand_error(parse_quote! { struct Vertex { position: [f32; 3] } }).contains("#[repr(C)]"));
+        assert!(expand_error(parse_quote! { #[repr(C)] struct Vertex([f32; 3]); }).contains("named fields"));
+        assert!(expand_error(parse_quote! { #[repr(C)] struct Vertex { weight: f64 } }).contains("#[format(...)]"));
+        assert!(expand_error(parse_quote! { #[repr(C)] #[vertex(rate = 1)] struct Vertex { weight: f32 } })
+            .contains("expected `binding = n`"));
+    }
+}
@@ -3,11 +3,27 @@
 
 #extension GL_ARB_separate_shader_objects : enable
 
+#pragma keywords GRAYSCALE
+
+layout(constant_id = 0) const float BRIGHTNESS = 1.0;
+
+// MaterialConstants, the vertex colors take the place of a texture
+layout(push_constant) uniform Material {
+    vec4 baseColorFactor;
+    float alphaCutoff;
+} material;
+
 layout(location = 0) in vec3 fragColor;
 
 layout(location = 0) out vec4 outColor;
 
 void main() {
 
-    outColor = vec4(fragColor, 1.0);
+#if GRAYSCALE
+    vec3 color = vec3(dot(fragColor, vec3(0.299, 0.587, 0.114)));
+#else
+    vec3 color = fragColor;
+#endif
+
+    outColor = material.baseColorFactor * vec4(color * BRIGHTNESS, 1.0);
 }
@@ -4,13 +4,14 @@
 #extension GL_ARB_separate_shader_objects : enable
 
 layout(set = 0, binding = 0) uniform UniformBufferObject {
-    mat4 model;
     mat4 view;
     mat4 proj;
 } ubo;
 
 layout(location = 0) in vec2 inPosition;
 layout(location = 1) in vec3 inColor;
+// per instance, one column per location
+layout(location = 4) in mat4 inModel;
 
 layout(location = 0) out vec3 fragColor;
 
@@ -21,7 +22,7 @@ out gl_PerVertex {
 
 void main() {
 
-    gl_Position = ubo.proj * ubo.view * ubo.model * vec4(inPosition, 0.0, 1.0);
+    gl_Position = ubo.proj * ubo.view * inModel * vec4(inPosition, 0.0, 1.0);
     //gl_Position = vec4(inPosition, 0.0, 1.0);
     fragColor = inColor;
 }
@@ -0,0 +1,17 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(location = 0) out vec2 fragUV;
+
+out gl_PerVertex {
+
+    vec4 gl_Position;
+};
+
+// one triangle covering the whole screen, no vertex buffer needed
+void main() {
+
+    fragUV = vec2((gl_VertexIndex << 1) & 2, gl_VertexIndex & 2);
+    gl_Position = vec4(fragUV * 2.0 - 1.0, 0.0, 1.0);
+}
@@ -0,0 +1,34 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+
+// maps imgui's display rectangle to clip space
+layout(push_constant) uniform UiTransform {
+    vec2 scale;
+    vec2 translate;
+} transform;
+
+layout(location = 0) in vec2 inPosition;
+layout(location = 1) in vec2 inUV;
+layout(location = 2) in vec4 inColor;
+
+layout(location = 0) out vec4 fragColor;
+layout(location = 1) out vec2 fragUV;
+
+out gl_PerVertex {
+
+    vec4 gl_Position;
+};
+
+// imgui's colors are sRGB, the sRGB swapchain expects linear values
+vec3 srgbToLinear(vec3 color) {
+
+    return mix(color / 12.92, pow((color + 0.055) / 1.055, vec3(2.4)), step(0.04045, color));
+}
+
+void main() {
+
+    gl_Position = vec4(inPosition * transform.scale + transform.translate, 0.0, 1.0);
+    fragColor = vec4(srgbToLinear(inColor.rgb), inColor.a);
+    fragUV = inUV;
+}
@@ -0,0 +1,31 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 1, binding = 0) uniform sampler2D baseColorTexture;
+
+// MaterialConstants
+layout(push_constant) uniform Material {
+    vec4 baseColorFactor;
+    float alphaCutoff;
+} material;
+
+layout(location = 0) in vec3 fragNormal;
+layout(location = 1) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+// a fixed light until the scene has lights of its own
+const vec3 LIGHT_DIRECTION = vec3(0.27, 0.89, 0.36);
+
+void main() {
+
+    vec4 baseColor = material.baseColorFactor * texture(baseColorTexture, fragUV);
+    if (baseColor.a < material.alphaCutoff) {
+        discard;
+    }
+
+    float diffuse = max(dot(normalize(fragNormal), LIGHT_DIRECTION), 0.0);
+
+    outColor = vec4(baseColor.rgb * (0.2 + 0.8 * diffuse), baseColor.a);
+}
@@ -0,0 +1,30 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(set = 0, binding = 0) uniform UniformBufferObject {
+    mat4 view;
+    mat4 proj;
+} ubo;
+
+layout(location = 0) in vec3 inPosition;
+layout(location = 1) in vec3 inNormal;
+layout(location = 2) in vec2 inUV;
+// per instance, one column per location
+layout(location = 4) in mat4 inModel;
+
+layout(location = 0) out vec3 fragNormal;
+layout(location = 1) out vec2 fragUV;
+
+out gl_PerVertex {
+
+    vec4 gl_Position;
+};
+
+void main() {
+
+    gl_Position = ubo.proj * ubo.view * inModel * vec4(inPosition, 1.0);
+    // enough for the uniform scales scene nodes usually have
+    fragNormal = mat3(inModel) * inNormal;
+    fragUV = inUV;
+}
@@ -0,0 +1,19 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(constant_id = 0) const float EXPOSURE = 1.0;
+
+layout(set = 0, binding = 0) uniform sampler2D inputColor;
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+
+    vec3 hdr = texture(inputColor, fragUV).rgb * EXPOSURE;
+
+    // Reinhard, the sRGB swapchain does the gamma encoding
+    outColor = vec4(hdr / (hdr + vec3(1.0)), 1.0);
+}
@@ -0,0 +1,20 @@
+#version 450
+
+#extension GL_ARB_separate_shader_objects : enable
+
+layout(constant_id = 0) const float STRENGTH = 0.5;
+
+layout(set = 0, binding = 0) uniform sampler2D inputColor;
+
+layout(location = 0) in vec2 fragUV;
+
+layout(location = 0) out vec4 outColor;
+
+void main() {
+
+    vec2 centered = fragUV - 0.5;
+    float vignette = 1.0 - STRENGTH * dot(centered, centered) * 2.0;
+
+    vec4 color = texture(inputColor, fragUV);
+    outColor = vec4(color.rgb * vignette, color.a);
+}
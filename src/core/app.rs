@@ -0,0 +1,59 @@
+use winit::event::WindowEvent;
+
+use std::path::PathBuf;
+
+use crate::core::engine::Engine;
+use crate::utility::constants;
+
+/// Hooks a game implements to run inside `Engine::run`. Every hook has an empty default.
+pub trait App {
+    /// Called once after the window and the render device are created. Default input bindings go
+    /// into `engine.input.map` here, saved bindings are applied over them afterwards.
+    fn init(&mut self, _engine: &mut Engine) {}
+
+    /// Called at the fixed simulation rate with the step length in seconds, possibly several times per frame.
+    fn fixed_update(&mut self, _engine: &mut Engine, _step: f32) {}
+
+    /// Called once per frame with the frame's delta time in seconds.
+    fn update(&mut self, _engine: &mut Engine, _delta_time: f32) {}
+
+    /// Called before the frame is recorded. `alpha` is how far the frame is between the
+    /// last two fixed updates, for interpolating simulated state.
+    fn render(&mut self, _engine: &mut Engine, _alpha: f32) {}
+
+    /// Called every frame while the UI is built, after `render`. Windows added here are drawn over the scene.
+    fn ui(&mut self, _ui: &imgui::Ui) {}
+
+    fn event(&mut self, _engine: &mut Engine, _event: &WindowEvent) {}
+}
+
+#[derive(Clone, Debug)]
+pub struct EngineConfig {
+    pub window_title: String,
+    pub window_width: u32,
+    pub window_height: u32,
+
+    /// frame rate cap, `None` to run unlimited
+    pub target_fps: Option<f32>,
+    pub fixed_updates_per_second: f32,
+    pub max_fixed_updates_per_frame: u32,
+
+    pub exit_on_escape: bool,
+    /// bindings file loaded over the default bindings after `App::init`, `None` to keep the defaults
+    pub input_config_path: Option<PathBuf>,
+}
+
+impl Default for EngineConfig {
+    fn default() -> EngineConfig {
+        EngineConfig {
+            window_title: constants::WINDOW_TITLE.to_string(),
+            window_width: constants::WINDOW_WIDTH,
+            window_height: constants::WINDOW_HEIGHT,
+            target_fps: constants::TARGET_FPS,
+            fixed_updates_per_second: constants::FIXED_UPDATES_PER_SECOND,
+            max_fixed_updates_per_frame: constants::MAX_FIXED_UPDATES_PER_FRAME,
+            exit_on_escape: true,
+            input_config_path: Some(PathBuf::from(constants::INPUT_CONFIG_PATH)),
+        }
+    }
+}
@@ -0,0 +1,339 @@
+use cgmath::{Deg, EuclideanSpace, InnerSpace, Matrix3, Matrix4, One, Point3, Quaternion, Rad, Rotation, Vector2, Vector3, Zero};
+
+use std::f32::consts::FRAC_PI_2;
+
+/// Keeps the pitch of the controllers short of straight up or down, where yaw stops being defined.
+const MAX_PITCH: Rad<f32> = Rad(FRAC_PI_2 - 0.01);
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective {
+        fov_y: Rad<f32>,
+        near: f32,
+        far: f32,
+    },
+    Orthographic {
+        /// world units visible vertically, the width follows from the aspect
+        height: f32,
+        near: f32,
+        far: f32,
+    },
+}
+
+/// A view into the world. Looks down its local -Z with local +Y up; projections map to Vulkan
+/// clip space, with Y pointing down and depth in `[0, 1]`.
+#[derive(Clone, Copy, Debug)]
+pub struct Camera {
+    pub position: Point3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub projection: Projection,
+    aspect: f32,
+}
+
+impl Camera {
+    pub fn perspective<A: Into<Rad<f32>>>(fov_y: A, near: f32, far: f32) -> Camera {
+        Camera::new(Projection::Perspective {
+            fov_y: fov_y.into(),
+            near,
+            far,
+        })
+    }
+
+    pub fn orthographic(height: f32, near: f32, far: f32) -> Camera {
+        Camera::new(Projection::Orthographic {
+            height,
+            near,
+            far,
+        })
+    }
+
+    pub fn new(projection: Projection) -> Camera {
+        Camera {
+            position: Point3::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::one(),
+            projection,
+            aspect: 1.0,
+        }
+    }
+
+    pub fn aspect(&self) -> f32 {
+        self.aspect
+    }
+
+    /// Takes the aspect from the size of the image rendered to. Zero-sized viewports, as when minimised, are ignored.
+    pub fn set_viewport(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.aspect = width as f32 / height as f32;
+        }
+    }
+
+    pub fn forward(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(-Vector3::unit_z())
+    }
+
+    pub fn right(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_x())
+    }
+
+    pub fn up(&self) -> Vector3<f32> {
+        self.rotation.rotate_vector(Vector3::unit_y())
+    }
+
+    /// Turns the camera to look along `direction`, keeping it level to `up`.
+    pub fn look_to(&mut self, direction: Vector3<f32>, up: Vector3<f32>) {
+        let forward = direction.normalize();
+        let right = forward.cross(up).normalize();
+        let camera_up = right.cross(forward);
+
+        self.rotation = Quaternion::from(Matrix3::from_cols(right, camera_up, -forward)).normalize();
+    }
+
+    pub fn look_at(&mut self, target: Point3<f32>, up: Vector3<f32>) {
+        self.look_to(target - self.position, up);
+    }
+
+    pub fn view_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from(self.rotation.invert()) * Matrix4::from_translation(-self.position.to_vec())
+    }
+
+    pub fn projection_matrix(&self) -> Matrix4<f32> {
+        // cgmath takes the columns one after another
+        match self.projection {
+            Projection::Perspective { fov_y, near, far } => {
+                let focal_length = 1.0 / (fov_y.0 * 0.5).tan();
+
+                Matrix4::new(
+                    focal_length / self.aspect, 0.0, 0.0, 0.0,
+                    0.0, -focal_length, 0.0, 0.0,
+                    0.0, 0.0, far / (near - far), -1.0,
+                    0.0, 0.0, near * far / (near - far), 0.0,
+                )
+            },
+            Projection::Orthographic { height, near, far } => {
+                let half_height = height * 0.5;
+                let half_width = half_height * self.aspect;
+
+                Matrix4::new(
+                    1.0 / half_width, 0.0, 0.0, 0.0,
+                    0.0, -1.0 / half_height, 0.0, 0.0,
+                    0.0, 0.0, 1.0 / (near - far), 0.0,
+                    0.0, 0.0, near / (near - far), 1.0,
+                )
+            },
+        }
+    }
+
+    pub fn view_projection_matrix(&self) -> Matrix4<f32> {
+        self.projection_matrix() * self.view_matrix()
+    }
+}
+
+/// What the controllers are driven by this frame, gathered from whatever input the game uses.
+#[derive(Clone, Copy, Debug)]
+pub struct CameraInput {
+    /// x right, y up, z forward, each in `[-1, 1]`
+    pub movement: Vector3<f32>,
+    /// mouse movement in pixels, turns the camera
+    pub look: Vector2<f32>,
+    /// mouse movement in pixels, moves the orbit target
+    pub pan: Vector2<f32>,
+    /// scroll in lines, positive zooms in
+    pub zoom: f32,
+}
+
+impl Default for CameraInput {
+    fn default() -> CameraInput {
+        CameraInput {
+            movement: Vector3::zero(),
+            look: Vector2::zero(),
+            pan: Vector2::zero(),
+            zoom: 0.0,
+        }
+    }
+}
+
+/// The direction `yaw` and `pitch` point in, around the world `up`.
+fn direction_from_angles(up: Vector3<f32>, yaw: Rad<f32>, pitch: Rad<f32>) -> Vector3<f32> {
+    let (horizontal_x, horizontal_y) = horizontal_axes(up);
+    let horizontal = horizontal_x * yaw.0.cos() + horizontal_y * yaw.0.sin();
+
+    horizontal * pitch.0.cos() + up * pitch.0.sin()
+}
+
+/// Two axes spanning the plane perpendicular to `up`, with yaw 0 along the first.
+fn horizontal_axes(up: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let reference = if up.x.abs() < 0.9 { Vector3::unit_x() } else { Vector3::unit_y() };
+    let horizontal_x = (reference - up * reference.dot(up)).normalize();
+
+    (horizontal_x, up.cross(horizontal_x))
+}
+
+fn angles_from_direction(up: Vector3<f32>, direction: Vector3<f32>) -> (Rad<f32>, Rad<f32>) {
+    let (horizontal_x, horizontal_y) = horizontal_axes(up);
+    let direction = direction.normalize();
+
+    let yaw = Rad(direction.dot(horizontal_y).atan2(direction.dot(horizontal_x)));
+    let pitch = Rad(direction.dot(up).clamp(-1.0, 1.0).asin());
+    (yaw, pitch)
+}
+
+/// Free flight: `movement` moves relative to where the camera looks, `look` turns it.
+pub struct FlyController {
+    pub up: Vector3<f32>,
+    /// world units per second
+    pub speed: f32,
+    /// radians per pixel of mouse movement
+    pub sensitivity: f32,
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+}
+
+impl FlyController {
+    /// Starts from where `camera` looks now.
+    pub fn new(camera: &Camera, up: Vector3<f32>) -> FlyController {
+        let up = up.normalize();
+        let (yaw, pitch) = angles_from_direction(up, camera.forward());
+
+        FlyController {
+            up,
+            speed: 3.0,
+            sensitivity: Rad::from(Deg(0.15)).0,
+            yaw,
+            pitch,
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &CameraInput, delta_time: f32) {
+        self.yaw -= Rad(input.look.x * self.sensitivity);
+        self.pitch = clamp_pitch(self.pitch - Rad(input.look.y * self.sensitivity));
+
+        let forward = direction_from_angles(self.up, self.yaw, self.pitch);
+        camera.look_to(forward, self.up);
+
+        let movement = camera.right() * input.movement.x + self.up * input.movement.y + forward * input.movement.z;
+        if movement.magnitude2() > 0.0 {
+            let length = movement.magnitude().min(1.0);
+            camera.position += movement.normalize() * length * self.speed * delta_time;
+        }
+    }
+}
+
+/// Circles a target point: `look` orbits, `pan` moves the target, `zoom` changes the distance.
+pub struct OrbitController {
+    pub target: Point3<f32>,
+    pub up: Vector3<f32>,
+    pub distance: f32,
+    pub min_distance: f32,
+    pub max_distance: f32,
+    /// radians per pixel of mouse movement
+    pub sensitivity: f32,
+    /// how much one scroll line scales the distance
+    pub zoom_factor: f32,
+    yaw: Rad<f32>,
+    pitch: Rad<f32>,
+}
+
+impl OrbitController {
+    /// Orbits `target` from where `camera` is now.
+    pub fn new(camera: &Camera, target: Point3<f32>, up: Vector3<f32>) -> OrbitController {
+        let up = up.normalize();
+        let offset = target - camera.position;
+        let (yaw, pitch) = angles_from_direction(up, offset);
+
+        OrbitController {
+            target,
+            up,
+            distance: offset.magnitude(),
+            min_distance: 0.1,
+            max_distance: 1000.0,
+            sensitivity: Rad::from(Deg(0.3)).0,
+            zoom_factor: 0.9,
+            yaw,
+            pitch,
+        }
+    }
+
+    pub fn update(&mut self, camera: &mut Camera, input: &CameraInput) {
+        self.yaw -= Rad(input.look.x * self.sensitivity);
+        self.pitch = clamp_pitch(self.pitch + Rad(input.look.y * self.sensitivity));
+        self.distance = (self.distance * self.zoom_factor.powf(input.zoom)).clamp(self.min_distance, self.max_distance);
+
+        // pans move by the same share of the view at any distance
+        let pan_scale = self.distance * self.sensitivity * 0.5;
+        self.target += (camera.up() * input.pan.y - camera.right() * input.pan.x) * pan_scale;
+
+        let forward = direction_from_angles(self.up, self.yaw, self.pitch);
+        camera.position = self.target - forward * self.distance;
+        camera.look_to(forward, self.up);
+    }
+}
+
+fn clamp_pitch(pitch: Rad<f32>) -> Rad<f32> {
+    Rad(pitch.0.clamp(-MAX_PITCH.0, MAX_PITCH.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector4;
+
+    use super::*;
+
+    /// Clip coordinates after the perspective divide.
+    fn project(camera: &Camera, point: Point3<f32>) -> Vector3<f32> {
+        let clip = camera.view_projection_matrix() * point.to_homogeneous();
+        clip.truncate() / clip.w
+    }
+
+    fn assert_close(actual: Vector3<f32>, expected: Vector3<f32>) {
+        assert!((actual - expected).magnitude() < 1e-4, "{:?} is not close to {:?}", actual, expected);
+    }
+
+    #[test]
+    fn perspective_maps_the_frustum_to_vulkan_clip_space() {
+        let mut camera = Camera::perspective(Deg(90.0), 0.5, 100.0);
+        camera.set_viewport(200, 100);
+
+        // depth runs from 0 at the near plane to 1 at the far one
+        assert_close(project(&camera, Point3::new(0.0, 0.0, -0.5)), Vector3::new(0.0, 0.0, 0.0));
+        assert_close(project(&camera, Point3::new(0.0, 0.0, -100.0)), Vector3::new(0.0, 0.0, 1.0));
+
+        // +Y up in the world is the top of the image, which is -Y in Vulkan
+        let top_right = project(&camera, Point3::new(20.0, 10.0, -10.0));
+        assert!((top_right.x - 1.0).abs() < 1e-4 && (top_right.y + 1.0).abs() < 1e-4);
+        assert!(top_right.z > 0.0 && top_right.z < 1.0);
+
+        // the w the rasterizer divides by is the distance in front of the camera
+        let clip = camera.projection_matrix() * Vector4::new(0.0, 0.0, -7.0, 1.0);
+        assert!((clip.w - 7.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn orthographic_maps_the_box_to_vulkan_clip_space() {
+        let mut camera = Camera::orthographic(4.0, 1.0, 11.0);
+        camera.set_viewport(300, 100);
+
+        assert_close(project(&camera, Point3::new(6.0, 2.0, -1.0)), Vector3::new(1.0, -1.0, 0.0));
+        assert_close(project(&camera, Point3::new(-6.0, -2.0, -11.0)), Vector3::new(-1.0, 1.0, 1.0));
+        assert_close(project(&camera, Point3::new(0.0, 0.0, -6.0)), Vector3::new(0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn the_view_follows_the_camera_position_and_rotation() {
+        let mut camera = Camera::orthographic(2.0, 0.0, 10.0);
+        camera.position = Point3::new(5.0, 0.0, 0.0);
+        camera.look_at(Point3::new(10.0, 0.0, 0.0), Vector3::unit_y());
+
+        assert_close(camera.forward(), Vector3::unit_x());
+        assert_close((camera.view_matrix() * Vector4::new(10.0, 1.0, 0.0, 1.0)).truncate(), Vector3::new(0.0, 1.0, -5.0));
+        assert_close(project(&camera, Point3::new(10.0, 1.0, 1.0)), Vector3::new(1.0, -1.0, 0.5));
+    }
+
+    #[test]
+    fn zero_sized_viewports_keep_the_aspect() {
+        let mut camera = Camera::perspective(Deg(60.0), 0.1, 10.0);
+        camera.set_viewport(1600, 900);
+        camera.set_viewport(0, 0);
+
+        assert!((camera.aspect() - 16.0 / 9.0).abs() < 1e-6);
+    }
+}
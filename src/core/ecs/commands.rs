@@ -0,0 +1,124 @@
+use crate::core::ecs::entity::Entity;
+use crate::core::ecs::storage::{Bundle, Component};
+use crate::core::ecs::world::World;
+
+type Command = Box<dyn FnOnce(&mut World) + Send>;
+
+/// Structural changes recorded while the world is shared, applied later through `CommandQueue::apply`.
+pub struct Commands<'w> {
+    world: &'w World,
+    queue: CommandQueue,
+}
+
+impl<'w> Commands<'w> {
+    pub fn new(world: &'w World) -> Commands<'w> {
+        Commands {
+            world,
+            queue: CommandQueue::new(),
+        }
+    }
+
+    /// The entity handle is valid right away, its components are added when the queue is applied.
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.world.reserve_entity();
+        self.add(move |world| world.spawn_at(entity, bundle));
+        entity
+    }
+
+    pub fn despawn(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.despawn(entity);
+        });
+    }
+
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) {
+        self.add(move |world| {
+            world.insert(entity, component);
+        });
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) {
+        self.add(move |world| {
+            world.remove::<T>(entity);
+        });
+    }
+
+    pub fn insert_resource<R: Component>(&mut self, resource: R) {
+        self.add(move |world| world.insert_resource(resource));
+    }
+
+    pub fn add<F: FnOnce(&mut World) + Send + 'static>(&mut self, command: F) {
+        self.queue.commands.push(Box::new(command));
+    }
+
+    pub fn into_queue(self) -> CommandQueue {
+        self.queue
+    }
+}
+
+pub struct CommandQueue {
+    commands: Vec<Command>,
+}
+
+impl Default for CommandQueue {
+    fn default() -> CommandQueue {
+        CommandQueue::new()
+    }
+}
+
+impl CommandQueue {
+    pub fn new() -> CommandQueue {
+        CommandQueue {
+            commands: vec![],
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.commands.is_empty()
+    }
+
+    /// Runs the commands in the order they were recorded.
+    pub fn apply(self, world: &mut World) {
+        for command in self.commands {
+            command(world);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Health(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Score(i32);
+
+    #[test]
+    fn commands_apply_in_order() {
+        let mut world = World::new();
+        let existing = world.spawn((Health(1),));
+
+        let mut commands = Commands::new(&world);
+        let spawned = commands.spawn((Health(2),));
+        let despawned_early = commands.spawn((Health(3),));
+        commands.despawn(despawned_early);
+        commands.insert(existing, Score(10));
+        commands.remove::<Health>(existing);
+        commands.insert_resource(Score(0));
+        let queue = commands.into_queue();
+
+        // reserved right away, but without components until the queue is applied
+        assert!(world.is_alive(spawned));
+        assert!(world.get::<Health>(spawned).is_none());
+
+        queue.apply(&mut world);
+        assert_eq!(*world.get::<Health>(spawned).unwrap(), Health(2));
+        assert!(!world.is_alive(despawned_early));
+        assert!(!world.has::<Health>(existing));
+        assert_eq!(*world.get::<Score>(existing).unwrap(), Score(10));
+        assert_eq!(*world.resource::<Score>(), Score(0));
+        assert_eq!(world.entity_count(), 2);
+    }
+}
@@ -0,0 +1,124 @@
+/// A handle to an entity. The generation tells a despawned entity apart from a later one reusing its index.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// Where the components of an entity live: its archetype and its row in there.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EntityLocation {
+    pub archetype: usize,
+    pub row: usize,
+}
+
+struct EntityMeta {
+    generation: u32,
+    is_alive: bool,
+    /// `None` while the entity is reserved but not spawned yet
+    location: Option<EntityLocation>,
+}
+
+/// Allocates entity handles and tracks where every live entity is stored.
+pub struct Entities {
+    meta: Vec<EntityMeta>,
+    free_indices: Vec<u32>,
+    alive_count: usize,
+}
+
+impl Default for Entities {
+    fn default() -> Entities {
+        Entities::new()
+    }
+}
+
+impl Entities {
+    pub fn new() -> Entities {
+        Entities {
+            meta: vec![],
+            free_indices: vec![],
+            alive_count: 0,
+        }
+    }
+
+    pub fn alloc(&mut self) -> Entity {
+        self.alive_count += 1;
+
+        match self.free_indices.pop() {
+            Some(index) => {
+                let meta = &mut self.meta[index as usize];
+                meta.is_alive = true;
+                meta.location = None;
+
+                Entity {
+                    index,
+                    generation: meta.generation,
+                }
+            },
+            None => {
+                self.meta.push(EntityMeta {
+                    generation: 0,
+                    is_alive: true,
+                    location: None,
+                });
+
+                Entity {
+                    index: self.meta.len() as u32 - 1,
+                    generation: 0,
+                }
+            },
+        }
+    }
+
+    /// Frees the entity and returns where it was stored. `None` if it was not alive.
+    pub fn free(&mut self, entity: Entity) -> Option<Option<EntityLocation>> {
+        if !self.contains(entity) {
+            return None;
+        }
+
+        let meta = &mut self.meta[entity.index as usize];
+        meta.is_alive = false;
+        meta.generation = meta.generation.wrapping_add(1);
+        self.free_indices.push(entity.index);
+        self.alive_count -= 1;
+
+        Some(meta.location.take())
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.meta
+            .get(entity.index as usize)
+            .is_some_and(|meta| meta.is_alive && meta.generation == entity.generation)
+    }
+
+    pub fn location(&self, entity: Entity) -> Option<EntityLocation> {
+        if self.contains(entity) {
+            self.meta[entity.index as usize].location
+        } else {
+            None
+        }
+    }
+
+    pub fn set_location(&mut self, entity: Entity, location: EntityLocation) {
+        debug_assert!(self.contains(entity), "Setting the location of dead entity {:?}", entity);
+        self.meta[entity.index as usize].location = Some(location);
+    }
+
+    pub fn len(&self) -> usize {
+        self.alive_count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alive_count == 0
+    }
+}
@@ -0,0 +1,15 @@
+pub mod entity;
+pub mod storage;
+pub mod world;
+pub mod query;
+pub mod commands;
+pub mod system;
+pub mod schedule;
+
+pub use self::commands::{CommandQueue, Commands};
+pub use self::entity::Entity;
+pub use self::query::{Changed, Query, With, Without};
+pub use self::schedule::Schedule;
+pub use self::storage::{Bundle, Component};
+pub use self::system::{System, SystemBuilder, SystemContext};
+pub use self::world::{Res, ResMut, World};
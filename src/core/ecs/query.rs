@@ -0,0 +1,378 @@
+use std::any::{type_name, TypeId};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{RwLockReadGuard, RwLockWriteGuard};
+
+use crate::core::ecs::entity::Entity;
+use crate::core::ecs::storage::{AnyColumn, Archetype, Column, Component};
+use crate::core::ecs::system::Access;
+use crate::core::ecs::world::World;
+
+/// What a query fetches per entity: `&T`, `&mut T`, `Option<&T>`, `Entity`, or a tuple of those.
+pub trait WorldQuery {
+    type Item<'a>;
+    /// The locks held on one archetype while iterating it.
+    type State<'a>;
+
+    fn access(access: &mut Access);
+    fn matches(archetype: &Archetype) -> bool;
+    fn lock(archetype: &Archetype) -> Self::State<'_>;
+    fn fetch<'s>(state: &'s mut Self::State<'_>, entity: Entity, row: usize, tick: u32) -> Self::Item<'s>;
+}
+
+impl<T: Component> WorldQuery for &T {
+    type Item<'a> = &'a T;
+    type State<'a> = RwLockReadGuard<'a, Box<dyn AnyColumn>>;
+
+    fn access(access: &mut Access) {
+        access.add_read(TypeId::of::<T>());
+    }
+
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has(TypeId::of::<T>())
+    }
+
+    fn lock(archetype: &Archetype) -> Self::State<'_> {
+        archetype.column(TypeId::of::<T>()).expect("Archetype has no column for the component").read()
+    }
+
+    fn fetch<'s>(state: &'s mut Self::State<'_>, _entity: Entity, row: usize, _tick: u32) -> Self::Item<'s> {
+        &state.as_any().downcast_ref::<Column<T>>().expect("Component column has a different type").data[row]
+    }
+}
+
+/// Fetching marks the component changed, whether or not it is written to.
+impl<T: Component> WorldQuery for &mut T {
+    type Item<'a> = &'a mut T;
+    type State<'a> = (RwLockWriteGuard<'a, Box<dyn AnyColumn>>, &'a [AtomicU32]);
+
+    fn access(access: &mut Access) {
+        access.add_write(TypeId::of::<T>());
+    }
+
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has(TypeId::of::<T>())
+    }
+
+    fn lock(archetype: &Archetype) -> Self::State<'_> {
+        let column = archetype.column(TypeId::of::<T>()).expect("Archetype has no column for the component");
+        (column.write(), column.changed_ticks())
+    }
+
+    fn fetch<'s>(state: &'s mut Self::State<'_>, _entity: Entity, row: usize, tick: u32) -> Self::Item<'s> {
+        let (data, changed_ticks) = state;
+        changed_ticks[row].store(tick, Ordering::Relaxed);
+        &mut data.as_any_mut().downcast_mut::<Column<T>>().expect("Component column has a different type").data[row]
+    }
+}
+
+impl<T: Component> WorldQuery for Option<&T> {
+    type Item<'a> = Option<&'a T>;
+    type State<'a> = Option<RwLockReadGuard<'a, Box<dyn AnyColumn>>>;
+
+    fn access(access: &mut Access) {
+        access.add_read(TypeId::of::<T>());
+    }
+
+    fn matches(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn lock(archetype: &Archetype) -> Self::State<'_> {
+        archetype.column(TypeId::of::<T>()).map(|column| column.read())
+    }
+
+    fn fetch<'s>(state: &'s mut Self::State<'_>, _entity: Entity, row: usize, _tick: u32) -> Self::Item<'s> {
+        state.as_ref().map(|data| {
+            &data.as_any().downcast_ref::<Column<T>>().expect("Component column has a different type").data[row]
+        })
+    }
+}
+
+impl WorldQuery for Entity {
+    type Item<'a> = Entity;
+    type State<'a> = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn matches(_archetype: &Archetype) -> bool {
+        true
+    }
+
+    fn lock(_archetype: &Archetype) -> Self::State<'_> {}
+
+    fn fetch<'s>(_state: &'s mut Self::State<'_>, entity: Entity, _row: usize, _tick: u32) -> Self::Item<'s> {
+        entity
+    }
+}
+
+/// Narrows a query down without fetching anything: `With<T>`, `Without<T>`, `Changed<T>`, or a tuple of those.
+pub trait QueryFilter {
+    type State<'a>;
+
+    fn access(access: &mut Access);
+    fn matches(archetype: &Archetype) -> bool;
+    fn lock(archetype: &Archetype) -> Self::State<'_>;
+    fn filter(state: &Self::State<'_>, row: usize, last_run: u32) -> bool;
+}
+
+/// Only entities that have a `T`.
+pub struct With<T>(PhantomData<T>);
+
+/// Only entities that do not have a `T`.
+pub struct Without<T>(PhantomData<T>);
+
+/// Only entities whose `T` was added or fetched mutably since the system last ran.
+pub struct Changed<T>(PhantomData<T>);
+
+impl<T: Component> QueryFilter for With<T> {
+    type State<'a> = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has(TypeId::of::<T>())
+    }
+
+    fn lock(_archetype: &Archetype) -> Self::State<'_> {}
+
+    fn filter(_state: &Self::State<'_>, _row: usize, _last_run: u32) -> bool {
+        true
+    }
+}
+
+impl<T: Component> QueryFilter for Without<T> {
+    type State<'a> = ();
+
+    fn access(_access: &mut Access) {}
+
+    fn matches(archetype: &Archetype) -> bool {
+        !archetype.has(TypeId::of::<T>())
+    }
+
+    fn lock(_archetype: &Archetype) -> Self::State<'_> {}
+
+    fn filter(_state: &Self::State<'_>, _row: usize, _last_run: u32) -> bool {
+        true
+    }
+}
+
+/// Reads the change ticks only, so it can be combined with `&mut T` of the same component.
+impl<T: Component> QueryFilter for Changed<T> {
+    type State<'a> = &'a [AtomicU32];
+
+    fn access(access: &mut Access) {
+        access.add_read(TypeId::of::<T>());
+    }
+
+    fn matches(archetype: &Archetype) -> bool {
+        archetype.has(TypeId::of::<T>())
+    }
+
+    fn lock(archetype: &Archetype) -> Self::State<'_> {
+        archetype.column(TypeId::of::<T>()).expect("Archetype has no column for the component").changed_ticks()
+    }
+
+    fn filter(state: &Self::State<'_>, row: usize, last_run: u32) -> bool {
+        state[row].load(Ordering::Relaxed) > last_run
+    }
+}
+
+macro_rules! impl_query_tuple {
+    ($(($query:ident, $state:ident)),*) => {
+        impl<$($query: WorldQuery),*> WorldQuery for ($($query,)*) {
+            type Item<'a> = ($($query::Item<'a>,)*);
+            type State<'a> = ($($query::State<'a>,)*);
+
+            #[allow(unused_variables)]
+            fn access(access: &mut Access) {
+                $($query::access(access);)*
+            }
+
+            #[allow(unused_variables)]
+            fn matches(archetype: &Archetype) -> bool {
+                true $(&& $query::matches(archetype))*
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn lock(archetype: &Archetype) -> Self::State<'_> {
+                ($($query::lock(archetype),)*)
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn fetch<'s>(state: &'s mut Self::State<'_>, entity: Entity, row: usize, tick: u32) -> Self::Item<'s> {
+                let ($($state,)*) = state;
+                ($($query::fetch($state, entity, row, tick),)*)
+            }
+        }
+
+        impl<$($query: QueryFilter),*> QueryFilter for ($($query,)*) {
+            type State<'a> = ($($query::State<'a>,)*);
+
+            #[allow(unused_variables)]
+            fn access(access: &mut Access) {
+                $($query::access(access);)*
+            }
+
+            #[allow(unused_variables)]
+            fn matches(archetype: &Archetype) -> bool {
+                true $(&& $query::matches(archetype))*
+            }
+
+            #[allow(unused_variables, clippy::unused_unit)]
+            fn lock(archetype: &Archetype) -> Self::State<'_> {
+                ($($query::lock(archetype),)*)
+            }
+
+            #[allow(unused_variables)]
+            fn filter(state: &Self::State<'_>, row: usize, last_run: u32) -> bool {
+                let ($($state,)*) = state;
+                true $(&& $query::filter($state, row, last_run))*
+            }
+        }
+    };
+}
+
+impl_query_tuple!();
+impl_query_tuple!((A, a));
+impl_query_tuple!((A, a), (B, b));
+impl_query_tuple!((A, a), (B, b), (C, c));
+impl_query_tuple!((A, a), (B, b), (C, c), (D, d));
+impl_query_tuple!((A, a), (B, b), (C, c), (D, d), (E, e));
+impl_query_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f));
+impl_query_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f), (G, g));
+impl_query_tuple!((A, a), (B, b), (C, c), (D, d), (E, e), (F, f), (G, g), (H, h));
+
+/// Iterates the entities matching `Q` and `F`. Locks the columns of one archetype at a time, for
+/// the duration of the callback calls on it.
+pub struct Query<'w, Q: WorldQuery, F: QueryFilter = ()> {
+    world: &'w World,
+    last_run: u32,
+    this_run: u32,
+    marker: PhantomData<fn() -> (Q, F)>,
+}
+
+impl<'w, Q: WorldQuery, F: QueryFilter> Query<'w, Q, F> {
+    /// `Changed` filters pass for changes after `last_run`; mutable fetches are stamped with `this_run`.
+    pub fn new(world: &'w World, last_run: u32, this_run: u32) -> Query<'w, Q, F> {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        assert!(
+            access.is_self_compatible(),
+            "Query {} fetches a component mutably more than once, or both mutably and immutably", type_name::<Q>()
+        );
+
+        Query {
+            world,
+            last_run,
+            this_run,
+            marker: PhantomData,
+        }
+    }
+
+    pub fn for_each<C>(&self, mut callback: C)
+    where
+        C: for<'s> FnMut(Q::Item<'s>),
+    {
+        for archetype in self.matching_archetypes() {
+            let mut state = Q::lock(archetype);
+            let filter_state = F::lock(archetype);
+
+            for (row, &entity) in archetype.entities().iter().enumerate() {
+                if F::filter(&filter_state, row, self.last_run) {
+                    callback(Q::fetch(&mut state, entity, row, self.this_run));
+                }
+            }
+        }
+    }
+
+    /// Runs `callback` on the entity if it matches the query.
+    pub fn get<R, C>(&self, entity: Entity, callback: C) -> Option<R>
+    where
+        C: for<'s> FnOnce(Q::Item<'s>) -> R,
+    {
+        let location = self.world.location(entity)?;
+        let archetype = &self.world.archetypes()[location.archetype];
+        if !Q::matches(archetype) || !F::matches(archetype) {
+            return None;
+        }
+
+        let mut state = Q::lock(archetype);
+        let filter_state = F::lock(archetype);
+        if !F::filter(&filter_state, location.row, self.last_run) {
+            return None;
+        }
+
+        Some(callback(Q::fetch(&mut state, entity, location.row, self.this_run)))
+    }
+
+    pub fn count(&self) -> usize {
+        self.matching_archetypes()
+            .map(|archetype| {
+                let filter_state = F::lock(archetype);
+                (0..archetype.len()).filter(|&row| F::filter(&filter_state, row, self.last_run)).count()
+            })
+            .sum()
+    }
+
+    fn matching_archetypes(&self) -> impl Iterator<Item = &'w Archetype> {
+        self.world.archetypes().iter()
+            .filter(|archetype| !archetype.is_empty() && Q::matches(archetype) && F::matches(archetype))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Position(i32);
+
+    struct Velocity(i32);
+
+    struct Frozen;
+
+    #[test]
+    fn with_and_without_filter_by_component() {
+        let mut world = World::new();
+        world.spawn((Position(0),));
+        world.spawn((Position(1), Velocity(1)));
+        world.spawn((Position(2), Velocity(2), Frozen));
+
+        assert_eq!(world.query::<&Position, ()>().count(), 3);
+        assert_eq!(world.query::<&Position, With<Velocity>>().count(), 2);
+        assert_eq!(world.query::<&Position, (With<Velocity>, Without<Frozen>)>().count(), 1);
+
+        let mut positions = vec![];
+        world.query::<&Position, Without<Velocity>>().for_each(|position| positions.push(position.0));
+        assert_eq!(positions, vec![0]);
+    }
+
+    #[test]
+    fn changed_passes_for_fetches_after_the_last_run() {
+        let mut world = World::new();
+        let moving = world.spawn((Position(0), Velocity(1)));
+        world.spawn((Position(0),));
+
+        let last_run = world.increment_change_tick();
+        assert_eq!(Query::<&Position, Changed<Position>>::new(&world, last_run, last_run).count(), 0);
+
+        let this_run = world.increment_change_tick();
+        Query::<(&mut Position, &Velocity), ()>::new(&world, last_run, this_run)
+            .for_each(|(position, velocity)| position.0 += velocity.0);
+
+        let mut changed = vec![];
+        Query::<(Entity, &Position), Changed<Position>>::new(&world, last_run, this_run)
+            .for_each(|(entity, position)| changed.push((entity, position.0)));
+        assert_eq!(changed, vec![(moving, 1)]);
+        assert_eq!(Query::<&Position, Changed<Position>>::new(&world, this_run, this_run).count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "mutably more than once")]
+    fn aliased_mutable_fetches_are_rejected() {
+        let mut world = World::new();
+        world.spawn((Position(0),));
+
+        world.query::<(&mut Position, &mut Position), ()>();
+    }
+}
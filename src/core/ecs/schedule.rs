@@ -0,0 +1,163 @@
+use crate::core::ecs::commands::CommandQueue;
+use crate::core::ecs::system::System;
+use crate::core::ecs::world::World;
+
+/// Runs systems in the order they were added, except that systems whose accesses do not conflict
+/// run in parallel. Commands are applied after each parallel batch, in system order.
+pub struct Schedule {
+    systems: Vec<System>,
+    /// indices into `systems`, rebuilt when a system is added
+    batches: Option<Vec<Vec<usize>>>,
+}
+
+impl Default for Schedule {
+    fn default() -> Schedule {
+        Schedule::new()
+    }
+}
+
+impl Schedule {
+    pub fn new() -> Schedule {
+        Schedule {
+            systems: vec![],
+            batches: None,
+        }
+    }
+
+    pub fn add_system(&mut self, system: System) -> &mut Schedule {
+        self.systems.push(system);
+        self.batches = None;
+        self
+    }
+
+    pub fn systems(&self) -> &[System] {
+        &self.systems
+    }
+
+    /// The systems that run together, by name.
+    pub fn batch_names(&mut self) -> Vec<Vec<String>> {
+        let systems = &self.systems;
+        self.batches.get_or_insert_with(|| Schedule::build_batches(systems))
+            .iter()
+            .map(|batch| batch.iter().map(|&index| systems[index].name().to_string()).collect())
+            .collect()
+    }
+
+    pub fn run(&mut self, world: &mut World) {
+        let systems = &mut self.systems;
+        let batches = self.batches.get_or_insert_with(|| Schedule::build_batches(systems));
+
+        for batch in batches.iter() {
+            let queues = Schedule::run_batch(systems, batch, world);
+            for queue in queues {
+                queue.apply(world);
+            }
+        }
+    }
+
+    /// Puts every system one batch after the last earlier system it conflicts with, so conflicting
+    /// systems keep their order and everything else runs as early as possible.
+    fn build_batches(systems: &[System]) -> Vec<Vec<usize>> {
+        let mut batch_of_system: Vec<usize> = Vec::with_capacity(systems.len());
+        let mut batches: Vec<Vec<usize>> = vec![];
+
+        for (index, system) in systems.iter().enumerate() {
+            let batch = systems[..index].iter()
+                .zip(batch_of_system.iter())
+                .filter(|(earlier, _)| !earlier.access().is_compatible(system.access()))
+                .map(|(_, &batch)| batch + 1)
+                .max()
+                .unwrap_or(0);
+
+            if batch == batches.len() {
+                batches.push(vec![]);
+            }
+            batches[batch].push(index);
+            batch_of_system.push(batch);
+        }
+
+        batches
+    }
+
+    fn run_batch(systems: &mut [System], batch: &[usize], world: &World) -> Vec<CommandQueue> {
+        let mut batch_systems: Vec<&mut System> = systems.iter_mut()
+            .enumerate()
+            .filter(|(index, _)| batch.contains(index))
+            .map(|(_, system)| system)
+            .collect();
+
+        if let [system] = batch_systems.as_mut_slice() {
+            crate::profile_scope!("run system");
+            return vec![system.run(world)];
+        }
+
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = batch_systems.into_iter()
+                .map(|system| scope.spawn(move || {
+                    crate::profile_scope!("run system");
+                    system.run(world)
+                }))
+                .collect();
+
+            handles.into_iter()
+                .map(|handle| handle.join().expect("System panicked"))
+                .collect()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::ecs::system::SystemBuilder;
+
+    struct Position(i32);
+
+    struct Velocity(i32);
+
+    struct Spawned(u32);
+
+    #[test]
+    fn conflicting_systems_go_to_later_batches() {
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(SystemBuilder::new("read velocity").query::<&Velocity, ()>().build(|_| {}))
+            .add_system(SystemBuilder::new("move").query::<(&mut Position, &Velocity), ()>().build(|_| {}))
+            .add_system(SystemBuilder::new("read position").query::<&Position, ()>().build(|_| {}))
+            .add_system(SystemBuilder::new("write velocity").query::<&mut Velocity, ()>().build(|_| {}))
+            .add_system(SystemBuilder::new("count").write_resource::<Spawned>().build(|_| {}));
+
+        assert_eq!(schedule.batch_names(), vec![
+            vec!["read velocity", "move", "count"],
+            vec!["read position", "write velocity"],
+        ]);
+    }
+
+    #[test]
+    fn run_applies_commands_between_batches() {
+        let mut world = World::new();
+        world.insert_resource(Spawned(0));
+        world.spawn((Position(0), Velocity(2)));
+
+        let mut schedule = Schedule::new();
+        schedule
+            .add_system(SystemBuilder::new("spawn").build(|context| {
+                context.commands().spawn((Position(10), Velocity(1)));
+            }))
+            .add_system(SystemBuilder::new("move").query::<(&mut Position, &Velocity), ()>().build(|context| {
+                context.query::<(&mut Position, &Velocity), ()>().for_each(|(position, velocity)| position.0 += velocity.0);
+            }))
+            .add_system(SystemBuilder::new("count").query::<&Position, ()>().write_resource::<Spawned>().build(|context| {
+                context.resource_mut::<Spawned>().0 = context.query::<&Position, ()>().count() as u32;
+            }));
+
+        assert_eq!(schedule.batch_names(), vec![vec!["spawn", "move"], vec!["count"]]);
+        schedule.run(&mut world);
+        assert_eq!(world.resource::<Spawned>().0, 2);
+
+        let mut positions = vec![];
+        world.query::<&Position, ()>().for_each(|position| positions.push(position.0));
+        positions.sort();
+        assert_eq!(positions, vec![2, 10]);
+    }
+}
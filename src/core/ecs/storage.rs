@@ -0,0 +1,270 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::core::ecs::entity::Entity;
+
+/// Anything that can be stored on an entity. Implemented for every `Send + Sync + 'static` type.
+pub trait Component: Send + Sync + 'static {}
+
+impl<T: Send + Sync + 'static> Component for T {}
+
+/// A type-erased `Column<T>`, so archetypes can move rows without knowing the component types.
+pub trait AnyColumn: Send + Sync {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+    fn new_empty(&self) -> Box<dyn AnyColumn>;
+    fn swap_remove(&mut self, row: usize);
+    /// Swap-removes `row` and pushes it onto `target`, which has to be a column of the same type.
+    fn swap_remove_into(&mut self, row: usize, target: &mut dyn AnyColumn);
+}
+
+pub struct Column<T> {
+    pub data: Vec<T>,
+}
+
+impl<T: Component> Default for Column<T> {
+    fn default() -> Column<T> {
+        Column::new()
+    }
+}
+
+impl<T: Component> Column<T> {
+    pub fn new() -> Column<T> {
+        Column {
+            data: vec![],
+        }
+    }
+}
+
+impl<T: Component> AnyColumn for Column<T> {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+
+    fn new_empty(&self) -> Box<dyn AnyColumn> {
+        Box::new(Column::<T>::new())
+    }
+
+    fn swap_remove(&mut self, row: usize) {
+        self.data.swap_remove(row);
+    }
+
+    fn swap_remove_into(&mut self, row: usize, target: &mut dyn AnyColumn) {
+        let target = target.as_any_mut().downcast_mut::<Column<T>>()
+            .expect("Failed to move a component between columns of different types");
+        target.data.push(self.data.swap_remove(row));
+    }
+}
+
+/// The storage for one component type in an archetype. The change ticks sit outside the lock,
+/// so `Changed` filters can read them while a query holds the column for writing.
+pub struct ComponentColumn {
+    data: RwLock<Box<dyn AnyColumn>>,
+    changed_ticks: Vec<AtomicU32>,
+}
+
+impl ComponentColumn {
+    fn new(data: Box<dyn AnyColumn>) -> ComponentColumn {
+        ComponentColumn {
+            data: RwLock::new(data),
+            changed_ticks: vec![],
+        }
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Box<dyn AnyColumn>> {
+        self.data.read().expect("Component column lock is poisoned")
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, Box<dyn AnyColumn>> {
+        self.data.write().expect("Component column lock is poisoned")
+    }
+
+    pub fn changed_ticks(&self) -> &[AtomicU32] {
+        &self.changed_ticks
+    }
+
+    pub fn typed_mut<T: Component>(&mut self) -> &mut Column<T> {
+        self.data.get_mut().expect("Component column lock is poisoned")
+            .as_any_mut().downcast_mut::<Column<T>>()
+            .expect("Component column has a different type")
+    }
+
+    pub fn mark_changed(&self, row: usize, tick: u32) {
+        self.changed_ticks[row].store(tick, Ordering::Relaxed);
+    }
+}
+
+/// All entities with exactly the same set of component types, stored as one column per type.
+pub struct Archetype {
+    types: Vec<TypeId>,
+    columns: HashMap<TypeId, ComponentColumn>,
+    entities: Vec<Entity>,
+}
+
+impl Archetype {
+    /// `columns` are empty columns, one per component type of the archetype.
+    pub fn new(columns: Vec<(TypeId, Box<dyn AnyColumn>)>) -> Archetype {
+        let mut types: Vec<TypeId> = columns.iter().map(|(type_id, _)| *type_id).collect();
+        types.sort();
+
+        Archetype {
+            types,
+            columns: columns.into_iter()
+                .map(|(type_id, column)| (type_id, ComponentColumn::new(column)))
+                .collect(),
+            entities: vec![],
+        }
+    }
+
+    /// The component types, sorted.
+    pub fn types(&self) -> &[TypeId] {
+        &self.types
+    }
+
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+
+    pub fn len(&self) -> usize {
+        self.entities.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entities.is_empty()
+    }
+
+    pub fn has(&self, type_id: TypeId) -> bool {
+        self.columns.contains_key(&type_id)
+    }
+
+    pub fn column(&self, type_id: TypeId) -> Option<&ComponentColumn> {
+        self.columns.get(&type_id)
+    }
+
+    pub fn column_mut(&mut self, type_id: TypeId) -> Option<&mut ComponentColumn> {
+        self.columns.get_mut(&type_id)
+    }
+
+    /// Empty columns of the same types, for creating a neighbouring archetype.
+    pub fn new_empty_columns(&self) -> Vec<(TypeId, Box<dyn AnyColumn>)> {
+        self.columns.iter()
+            .map(|(type_id, column)| (*type_id, column.read().new_empty()))
+            .collect()
+    }
+
+    /// Pushes `entity` and returns its row. Every column has to get one value pushed by `push_component` afterwards.
+    pub fn push_entity(&mut self, entity: Entity) -> usize {
+        self.entities.push(entity);
+        self.entities.len() - 1
+    }
+
+    pub fn push_component<T: Component>(&mut self, component: T, tick: u32) {
+        let column = self.columns.get_mut(&TypeId::of::<T>())
+            .expect("Archetype has no column for the component");
+        column.typed_mut::<T>().data.push(component);
+        column.changed_ticks.push(AtomicU32::new(tick));
+    }
+
+    /// Drops the row and returns the entity that was moved into it, if any.
+    pub fn swap_remove(&mut self, row: usize) -> Option<Entity> {
+        for column in self.columns.values_mut() {
+            column.data.get_mut().expect("Component column lock is poisoned").swap_remove(row);
+            column.changed_ticks.swap_remove(row);
+        }
+
+        self.remove_entity(row)
+    }
+
+    /// Moves the row into `target`, dropping components `target` has no column for. Components of
+    /// `target` that this archetype lacks have to be pushed by the caller. Returns the new row
+    /// and the entity that was moved into the old one, if any.
+    pub fn move_row(&mut self, row: usize, target: &mut Archetype) -> (usize, Option<Entity>) {
+        self.move_row_except(row, target, None)
+    }
+
+    /// Like `move_row`, but hands the `T` of the row back instead of dropping it.
+    pub fn move_row_taking<T: Component>(&mut self, row: usize, target: &mut Archetype) -> (T, usize, Option<Entity>) {
+        let type_id = TypeId::of::<T>();
+        let component = {
+            let column = self.columns.get_mut(&type_id).expect("Archetype has no column for the component");
+            column.changed_ticks.swap_remove(row);
+            column.typed_mut::<T>().data.swap_remove(row)
+        };
+
+        let (target_row, moved) = self.move_row_except(row, target, Some(type_id));
+        (component, target_row, moved)
+    }
+
+    fn move_row_except(&mut self, row: usize, target: &mut Archetype, skipped: Option<TypeId>) -> (usize, Option<Entity>) {
+        let entity = self.entities[row];
+
+        for (type_id, column) in self.columns.iter_mut() {
+            if Some(*type_id) == skipped {
+                continue;
+            }
+
+            let tick = column.changed_ticks.swap_remove(row);
+            let data = column.data.get_mut().expect("Component column lock is poisoned");
+
+            match target.columns.get_mut(type_id) {
+                Some(target_column) => {
+                    data.swap_remove_into(row, &mut **target_column.data.get_mut().expect("Component column lock is poisoned"));
+                    target_column.changed_ticks.push(tick);
+                },
+                None => data.swap_remove(row),
+            }
+        }
+
+        let target_row = target.push_entity(entity);
+        (target_row, self.remove_entity(row))
+    }
+
+    fn remove_entity(&mut self, row: usize) -> Option<Entity> {
+        self.entities.swap_remove(row);
+        self.entities.get(row).copied()
+    }
+}
+
+/// A set of components spawned together. Implemented for tuples of up to eight components.
+pub trait Bundle: Send + Sync + 'static {
+    fn type_ids() -> Vec<TypeId>;
+    fn empty_columns() -> Vec<(TypeId, Box<dyn AnyColumn>)>;
+    /// Pushes every component onto its column of `archetype`.
+    fn push(self, archetype: &mut Archetype, tick: u32);
+}
+
+macro_rules! impl_bundle {
+    ($($component:ident),*) => {
+        impl<$($component: Component),*> Bundle for ($($component,)*) {
+            fn type_ids() -> Vec<TypeId> {
+                vec![$(TypeId::of::<$component>()),*]
+            }
+
+            fn empty_columns() -> Vec<(TypeId, Box<dyn AnyColumn>)> {
+                vec![$((TypeId::of::<$component>(), Box::new(Column::<$component>::new()) as Box<dyn AnyColumn>)),*]
+            }
+
+            #[allow(non_snake_case, unused_variables)]
+            fn push(self, archetype: &mut Archetype, tick: u32) {
+                let ($($component,)*) = self;
+                $(archetype.push_component($component, tick);)*
+            }
+        }
+    };
+}
+
+impl_bundle!();
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
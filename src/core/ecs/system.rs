@@ -0,0 +1,202 @@
+use std::any::{type_name, TypeId};
+use std::collections::HashSet;
+
+use crate::core::ecs::commands::{CommandQueue, Commands};
+use crate::core::ecs::query::{Query, QueryFilter, WorldQuery};
+use crate::core::ecs::storage::Component;
+use crate::core::ecs::world::{Res, ResMut, World};
+
+/// The components and resources a system reads and writes, used to find systems that can run in parallel.
+#[derive(Clone, Debug, Default)]
+pub struct Access {
+    reads: HashSet<TypeId>,
+    writes: HashSet<TypeId>,
+    resource_reads: HashSet<TypeId>,
+    resource_writes: HashSet<TypeId>,
+    /// components added as writes more than once, e.g. by a `(&mut A, &mut A)` query
+    aliased_writes: HashSet<TypeId>,
+}
+
+impl Access {
+    pub fn new() -> Access {
+        Access::default()
+    }
+
+    pub fn add_read(&mut self, type_id: TypeId) {
+        self.reads.insert(type_id);
+    }
+
+    pub fn add_write(&mut self, type_id: TypeId) {
+        if !self.writes.insert(type_id) {
+            self.aliased_writes.insert(type_id);
+        }
+    }
+
+    pub fn add_resource_read(&mut self, type_id: TypeId) {
+        self.resource_reads.insert(type_id);
+    }
+
+    pub fn add_resource_write(&mut self, type_id: TypeId) {
+        self.resource_writes.insert(type_id);
+    }
+
+    pub fn extend(&mut self, other: &Access) {
+        self.reads.extend(&other.reads);
+        self.writes.extend(&other.writes);
+        self.resource_reads.extend(&other.resource_reads);
+        self.resource_writes.extend(&other.resource_writes);
+    }
+
+    /// Whether two systems with these accesses can run at the same time.
+    pub fn is_compatible(&self, other: &Access) -> bool {
+        let is_compatible = |reads: &HashSet<TypeId>, writes: &HashSet<TypeId>, other_reads: &HashSet<TypeId>, other_writes: &HashSet<TypeId>| {
+            writes.is_disjoint(other_reads) && writes.is_disjoint(other_writes) && reads.is_disjoint(other_writes)
+        };
+
+        is_compatible(&self.reads, &self.writes, &other.reads, &other.writes)
+            && is_compatible(&self.resource_reads, &self.resource_writes, &other.resource_reads, &other.resource_writes)
+    }
+
+    /// False if something is both read and written, or written twice, which would deadlock a single query.
+    pub fn is_self_compatible(&self) -> bool {
+        self.aliased_writes.is_empty()
+            && self.reads.is_disjoint(&self.writes)
+            && self.resource_reads.is_disjoint(&self.resource_writes)
+    }
+
+    /// Whether everything `other` touches is covered by this access.
+    pub fn covers(&self, other: &Access) -> bool {
+        other.reads.iter().all(|type_id| self.reads.contains(type_id) || self.writes.contains(type_id))
+            && other.writes.is_subset(&self.writes)
+            && other.resource_reads.iter()
+                .all(|type_id| self.resource_reads.contains(type_id) || self.resource_writes.contains(type_id))
+            && other.resource_writes.is_subset(&self.resource_writes)
+    }
+}
+
+type SystemFn = Box<dyn FnMut(&mut SystemContext) + Send>;
+
+/// A function over the world with its access declared up front, so a `Schedule` can run it next
+/// to systems it does not conflict with. Built with `SystemBuilder`.
+pub struct System {
+    name: String,
+    access: Access,
+    run: SystemFn,
+    last_run: u32,
+}
+
+impl System {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
+    /// Runs the system and returns the commands it recorded.
+    pub fn run(&mut self, world: &World) -> CommandQueue {
+        let this_run = world.increment_change_tick();
+
+        let mut context = SystemContext {
+            world,
+            name: &self.name,
+            access: &self.access,
+            last_run: self.last_run,
+            this_run,
+            commands: Commands::new(world),
+        };
+        (self.run)(&mut context);
+        let queue = context.commands.into_queue();
+
+        self.last_run = this_run;
+        queue
+    }
+}
+
+pub struct SystemBuilder {
+    name: String,
+    access: Access,
+}
+
+impl SystemBuilder {
+    pub fn new(name: &str) -> SystemBuilder {
+        SystemBuilder {
+            name: name.to_string(),
+            access: Access::new(),
+        }
+    }
+
+    pub fn query<Q: WorldQuery, F: QueryFilter>(mut self) -> SystemBuilder {
+        Q::access(&mut self.access);
+        F::access(&mut self.access);
+        self
+    }
+
+    pub fn read_resource<R: Component>(mut self) -> SystemBuilder {
+        self.access.add_resource_read(TypeId::of::<R>());
+        self
+    }
+
+    pub fn write_resource<R: Component>(mut self) -> SystemBuilder {
+        self.access.add_resource_write(TypeId::of::<R>());
+        self
+    }
+
+    pub fn build<F: FnMut(&mut SystemContext) + Send + 'static>(self, run: F) -> System {
+        System {
+            name: self.name,
+            access: self.access,
+            run: Box::new(run),
+            last_run: 0,
+        }
+    }
+}
+
+/// What a running system sees. Queries and resources outside the declared access panic.
+pub struct SystemContext<'w> {
+    world: &'w World,
+    name: &'w str,
+    access: &'w Access,
+    last_run: u32,
+    this_run: u32,
+    commands: Commands<'w>,
+}
+
+impl<'w> SystemContext<'w> {
+    /// `Changed` filters pass for changes since the previous run of this system.
+    pub fn query<Q: WorldQuery, F: QueryFilter>(&self) -> Query<'w, Q, F> {
+        let mut access = Access::new();
+        Q::access(&mut access);
+        F::access(&mut access);
+        assert!(
+            self.access.covers(&access),
+            "System {} queries {} outside of its declared access", self.name, type_name::<Q>()
+        );
+
+        Query::new(self.world, self.last_run, self.this_run)
+    }
+
+    pub fn resource<R: Component>(&self) -> Res<'w, R> {
+        let type_id = TypeId::of::<R>();
+        assert!(
+            self.access.resource_reads.contains(&type_id) || self.access.resource_writes.contains(&type_id),
+            "System {} reads resource {} outside of its declared access", self.name, type_name::<R>()
+        );
+
+        self.world.resource::<R>()
+    }
+
+    pub fn resource_mut<R: Component>(&self) -> ResMut<'w, R> {
+        assert!(
+            self.access.resource_writes.contains(&TypeId::of::<R>()),
+            "System {} writes resource {} outside of its declared access", self.name, type_name::<R>()
+        );
+
+        self.world.resource_mut::<R>()
+    }
+
+    pub fn commands(&mut self) -> &mut Commands<'w> {
+        &mut self.commands
+    }
+}
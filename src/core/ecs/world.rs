@@ -0,0 +1,404 @@
+use std::any::{type_name, Any, TypeId};
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::core::ecs::entity::{Entities, Entity, EntityLocation};
+use crate::core::ecs::query::{Query, QueryFilter, WorldQuery};
+use crate::core::ecs::storage::{AnyColumn, Archetype, Bundle, Column, Component};
+
+type Resource = Box<dyn Any + Send + Sync>;
+
+/// Holds every entity, component and resource. Structural changes need `&mut World`; systems
+/// running in parallel share a `&World` and lock the columns they query.
+pub struct World {
+    entities: Mutex<Entities>,
+    archetypes: Vec<Archetype>,
+    archetype_index: HashMap<Vec<TypeId>, usize>,
+    resources: HashMap<TypeId, RwLock<Resource>>,
+    change_tick: AtomicU32,
+}
+
+impl Default for World {
+    fn default() -> World {
+        World::new()
+    }
+}
+
+impl World {
+    pub fn new() -> World {
+        World {
+            entities: Mutex::new(Entities::new()),
+            archetypes: vec![],
+            archetype_index: HashMap::new(),
+            resources: HashMap::new(),
+            // starts past zero so everything spawned counts as changed for a first run at tick 0
+            change_tick: AtomicU32::new(1),
+        }
+    }
+
+    pub fn spawn<B: Bundle>(&mut self, bundle: B) -> Entity {
+        let entity = self.entities_mut().alloc();
+        self.spawn_at(entity, bundle);
+        entity
+    }
+
+    /// Allocates an entity handle without storing anything, for spawning it later through `spawn_at`.
+    pub fn reserve_entity(&self) -> Entity {
+        self.entities.lock().expect("Entity allocator lock is poisoned").alloc()
+    }
+
+    /// Spawns a reserved entity. Does nothing if it was despawned in the meantime.
+    pub fn spawn_at<B: Bundle>(&mut self, entity: Entity, bundle: B) {
+        if !self.is_alive(entity) {
+            return;
+        }
+        assert!(self.entities_mut().location(entity).is_none(), "Entity {:?} is already spawned", entity);
+
+        let mut types = B::type_ids();
+        types.sort();
+        let type_count = types.len();
+        types.dedup();
+        assert_eq!(types.len(), type_count, "Bundle {} has the same component twice", type_name::<B>());
+
+        let tick = self.increment_change_tick();
+        let archetype_index = self.archetype_for(types, |_| B::empty_columns());
+        let archetype = &mut self.archetypes[archetype_index];
+        let row = archetype.push_entity(entity);
+        bundle.push(archetype, tick);
+
+        self.entities_mut().set_location(entity, EntityLocation {
+            archetype: archetype_index,
+            row,
+        });
+    }
+
+    /// Returns false if the entity was not alive.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        match self.entities_mut().free(entity) {
+            Some(Some(location)) => {
+                let moved = self.archetypes[location.archetype].swap_remove(location.row);
+                self.relocate(moved, location);
+                true
+            },
+            Some(None) => true,
+            None => false,
+        }
+    }
+
+    pub fn is_alive(&self, entity: Entity) -> bool {
+        self.entities.lock().expect("Entity allocator lock is poisoned").contains(entity)
+    }
+
+    pub fn entity_count(&self) -> usize {
+        self.entities.lock().expect("Entity allocator lock is poisoned").len()
+    }
+
+    /// Adds or replaces a component. Returns false if the entity is not spawned.
+    pub fn insert<T: Component>(&mut self, entity: Entity, component: T) -> bool {
+        let location = match self.entities_mut().location(entity) {
+            Some(location) => location,
+            None => return false,
+        };
+        let tick = self.increment_change_tick();
+        let type_id = TypeId::of::<T>();
+
+        let source = &mut self.archetypes[location.archetype];
+        if let Some(column) = source.column_mut(type_id) {
+            column.typed_mut::<T>().data[location.row] = component;
+            column.mark_changed(location.row, tick);
+            return true;
+        }
+
+        let mut types = source.types().to_vec();
+        types.push(type_id);
+        types.sort();
+        let target_index = self.archetype_for(types, |archetypes| {
+            let mut columns = archetypes[location.archetype].new_empty_columns();
+            columns.push((type_id, Box::new(Column::<T>::new()) as Box<dyn AnyColumn>));
+            columns
+        });
+
+        let (source, target) = World::archetype_pair(&mut self.archetypes, location.archetype, target_index);
+        let (row, moved) = source.move_row(location.row, target);
+        target.push_component(component, tick);
+
+        self.relocate(moved, location);
+        self.entities_mut().set_location(entity, EntityLocation {
+            archetype: target_index,
+            row,
+        });
+        true
+    }
+
+    pub fn remove<T: Component>(&mut self, entity: Entity) -> Option<T> {
+        let location = self.entities_mut().location(entity)?;
+        let type_id = TypeId::of::<T>();
+
+        let source = &self.archetypes[location.archetype];
+        if !source.has(type_id) {
+            return None;
+        }
+
+        let types: Vec<TypeId> = source.types().iter().copied().filter(|&id| id != type_id).collect();
+        let target_index = self.archetype_for(types, |archetypes| {
+            archetypes[location.archetype].new_empty_columns().into_iter().filter(|(id, _)| *id != type_id).collect()
+        });
+
+        let (source, target) = World::archetype_pair(&mut self.archetypes, location.archetype, target_index);
+        let (component, row, moved) = source.move_row_taking::<T>(location.row, target);
+
+        self.relocate(moved, location);
+        self.entities_mut().set_location(entity, EntityLocation {
+            archetype: target_index,
+            row,
+        });
+        Some(component)
+    }
+
+    pub fn has<T: Component>(&self, entity: Entity) -> bool {
+        self.location(entity)
+            .is_some_and(|location| self.archetypes[location.archetype].has(TypeId::of::<T>()))
+    }
+
+    pub fn get<T: Component>(&self, entity: Entity) -> Option<Ref<'_, T>> {
+        let location = self.location(entity)?;
+        let column = self.archetypes[location.archetype].column(TypeId::of::<T>())?;
+
+        Some(Ref {
+            guard: column.read(),
+            row: location.row,
+            marker: PhantomData,
+        })
+    }
+
+    /// Marks the component changed.
+    pub fn get_mut<T: Component>(&mut self, entity: Entity) -> Option<&mut T> {
+        let location = self.entities_mut().location(entity)?;
+        let tick = self.increment_change_tick();
+        let column = self.archetypes[location.archetype].column_mut(TypeId::of::<T>())?;
+
+        column.mark_changed(location.row, tick);
+        Some(&mut column.typed_mut::<T>().data[location.row])
+    }
+
+    /// Runs a query outside of a system. `Changed` matches everything changed since the world was created.
+    pub fn query<Q: WorldQuery, F: QueryFilter>(&self) -> Query<'_, Q, F> {
+        Query::new(self, 0, self.change_tick())
+    }
+
+    pub fn insert_resource<R: Component>(&mut self, resource: R) {
+        self.resources.insert(TypeId::of::<R>(), RwLock::new(Box::new(resource)));
+    }
+
+    pub fn remove_resource<R: Component>(&mut self) -> Option<R> {
+        let resource = self.resources.remove(&TypeId::of::<R>())?
+            .into_inner().expect("Resource lock is poisoned");
+        resource.downcast::<R>().ok().map(|resource| *resource)
+    }
+
+    pub fn has_resource<R: Component>(&self) -> bool {
+        self.resources.contains_key(&TypeId::of::<R>())
+    }
+
+    /// Panics if the resource does not exist.
+    pub fn resource<R: Component>(&self) -> Res<'_, R> {
+        self.get_resource().unwrap_or_else(|| panic!("Resource {} does not exist", type_name::<R>()))
+    }
+
+    /// Panics if the resource does not exist.
+    pub fn resource_mut<R: Component>(&self) -> ResMut<'_, R> {
+        self.get_resource_mut().unwrap_or_else(|| panic!("Resource {} does not exist", type_name::<R>()))
+    }
+
+    pub fn get_resource<R: Component>(&self) -> Option<Res<'_, R>> {
+        let resource = self.resources.get(&TypeId::of::<R>())?;
+
+        Some(Res {
+            guard: resource.read().expect("Resource lock is poisoned"),
+            marker: PhantomData,
+        })
+    }
+
+    pub fn get_resource_mut<R: Component>(&self) -> Option<ResMut<'_, R>> {
+        let resource = self.resources.get(&TypeId::of::<R>())?;
+
+        Some(ResMut {
+            guard: resource.write().expect("Resource lock is poisoned"),
+            marker: PhantomData,
+        })
+    }
+
+    /// The latest tick handed out. Every system run and every write through `&mut World` advances it,
+    /// so `Changed` sees writes made between two runs of a system.
+    pub fn change_tick(&self) -> u32 {
+        self.change_tick.load(Ordering::Relaxed)
+    }
+
+    /// Advances the change tick and returns the new value.
+    pub fn increment_change_tick(&self) -> u32 {
+        self.change_tick.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn archetypes(&self) -> &[Archetype] {
+        &self.archetypes
+    }
+
+    /// `None` for dead and for reserved but not yet spawned entities.
+    pub fn location(&self, entity: Entity) -> Option<EntityLocation> {
+        self.entities.lock().expect("Entity allocator lock is poisoned").location(entity)
+    }
+
+    fn entities_mut(&mut self) -> &mut Entities {
+        self.entities.get_mut().expect("Entity allocator lock is poisoned")
+    }
+
+    /// `types` has to be sorted.
+    fn archetype_for<C>(&mut self, types: Vec<TypeId>, columns: C) -> usize
+    where
+        C: FnOnce(&[Archetype]) -> Vec<(TypeId, Box<dyn AnyColumn>)>,
+    {
+        if let Some(&index) = self.archetype_index.get(&types) {
+            return index;
+        }
+
+        let archetype = Archetype::new(columns(&self.archetypes));
+        self.archetypes.push(archetype);
+        self.archetype_index.insert(types, self.archetypes.len() - 1);
+        self.archetypes.len() - 1
+    }
+
+    /// Points an entity swapped into a freed row at its new place.
+    fn relocate(&mut self, moved: Option<Entity>, location: EntityLocation) {
+        if let Some(moved) = moved {
+            self.entities_mut().set_location(moved, location);
+        }
+    }
+
+    fn archetype_pair(archetypes: &mut [Archetype], first: usize, second: usize) -> (&mut Archetype, &mut Archetype) {
+        assert_ne!(first, second, "Failed to borrow the same archetype twice");
+
+        if first < second {
+            let (head, tail) = archetypes.split_at_mut(second);
+            (&mut head[first], &mut tail[0])
+        } else {
+            let (head, tail) = archetypes.split_at_mut(first);
+            (&mut tail[0], &mut head[second])
+        }
+    }
+}
+
+/// A component borrowed from the world. Holds its column's read lock.
+pub struct Ref<'w, T> {
+    guard: RwLockReadGuard<'w, Box<dyn AnyColumn>>,
+    row: usize,
+    marker: PhantomData<T>,
+}
+
+impl<'w, T: Component> Deref for Ref<'w, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard.as_any().downcast_ref::<Column<T>>()
+            .expect("Component column has a different type")
+            .data[self.row]
+    }
+}
+
+pub struct Res<'w, R> {
+    guard: RwLockReadGuard<'w, Resource>,
+    marker: PhantomData<R>,
+}
+
+impl<'w, R: Component> Deref for Res<'w, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.guard.downcast_ref::<R>().expect("Resource has a different type")
+    }
+}
+
+pub struct ResMut<'w, R> {
+    guard: RwLockWriteGuard<'w, Resource>,
+    marker: PhantomData<R>,
+}
+
+impl<'w, R: Component> Deref for ResMut<'w, R> {
+    type Target = R;
+
+    fn deref(&self) -> &R {
+        self.guard.downcast_ref::<R>().expect("Resource has a different type")
+    }
+}
+
+impl<'w, R: Component> DerefMut for ResMut<'w, R> {
+    fn deref_mut(&mut self) -> &mut R {
+        self.guard.downcast_mut::<R>().expect("Resource has a different type")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct Position(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Velocity(i32);
+
+    #[derive(Debug, PartialEq)]
+    struct Name(&'static str);
+
+    #[test]
+    fn despawned_indices_are_reused_with_a_new_generation() {
+        let mut world = World::new();
+        let first = world.spawn((Position(0),));
+        let second = world.spawn((Position(1),));
+
+        assert!(world.despawn(first));
+        assert!(!world.despawn(first));
+        assert!(!world.is_alive(first));
+
+        let reused = world.spawn((Position(2),));
+        assert_eq!(reused.index(), first.index());
+        assert_ne!(reused.generation(), first.generation());
+        assert!(world.get::<Position>(first).is_none());
+        assert_eq!(*world.get::<Position>(reused).unwrap(), Position(2));
+        assert_eq!(*world.get::<Position>(second).unwrap(), Position(1));
+        assert_eq!(world.entity_count(), 2);
+    }
+
+    #[test]
+    fn insert_and_remove_move_entities_between_archetypes() {
+        let mut world = World::new();
+        let first = world.spawn((Position(0), Name("first")));
+        let second = world.spawn((Position(1), Name("second")));
+
+        // moving `first` out swaps `second` into its row, which has to be tracked
+        assert!(world.insert(first, Velocity(5)));
+        assert_ne!(world.location(first).unwrap().archetype, world.location(second).unwrap().archetype);
+        assert_eq!(world.location(second).unwrap().row, 0);
+        assert_eq!(*world.get::<Name>(second).unwrap(), Name("second"));
+        assert_eq!(*world.get::<Position>(first).unwrap(), Position(0));
+        assert_eq!(*world.get::<Velocity>(first).unwrap(), Velocity(5));
+
+        // replacing a component keeps the entity where it is
+        let location = world.location(first);
+        assert!(world.insert(first, Velocity(6)));
+        assert_eq!(world.location(first), location);
+        assert_eq!(*world.get::<Velocity>(first).unwrap(), Velocity(6));
+
+        assert_eq!(world.remove::<Name>(first), Some(Name("first")));
+        assert_eq!(world.remove::<Name>(first), None);
+        assert!(!world.has::<Name>(first));
+        assert_eq!(*world.get::<Position>(first).unwrap(), Position(0));
+        assert_eq!(*world.get::<Velocity>(first).unwrap(), Velocity(6));
+
+        assert!(world.despawn(second));
+        assert!(!world.insert(second, Velocity(0)));
+        assert_eq!(world.remove::<Position>(second), None);
+    }
+}
@@ -0,0 +1,348 @@
+use winit::event::{Event, VirtualKeyCode, WindowEvent};
+use winit::event_loop::{EventLoop, ControlFlow};
+
+use ash::vk;
+use std::ptr;
+use std::path::Path;
+
+use cgmath::{Deg, Point3, Vector3};
+
+use crate::core::app::{App, EngineConfig};
+use crate::core::camera::Camera;
+use crate::core::input::{Binding, Input, InputMap};
+use crate::imgui::pupsy_ui_engine::PupsyUiEngine;
+use crate::rhi::window::Window;
+use crate::utility::constants as global_constants;
+use crate::utility::fps::FPSManager;
+use crate::utility::shader_watcher::ShaderWatcher;
+use crate::utility::timestep::FixedTimestep;
+use crate::vk::render_device::VkRenderDevice;
+use crate::vk::shader_compiler::ShaderCompiler;
+
+pub const EXIT_ACTION: &str = "exit";
+pub const EXPORT_GPU_TRACE_ACTION: &str = "export_gpu_trace";
+pub const EXPORT_CPU_TRACE_ACTION: &str = "export_cpu_trace";
+
+/// Owns the window, the renderer and the UI, and drives an `App` from the event loop.
+pub struct Engine {
+    pub render_device: VkRenderDevice,
+    pub window: Window,
+    pub fps_manager: FPSManager,
+    pub ui_engine: PupsyUiEngine,
+    pub input: Input,
+    /// what the scene is drawn from, its aspect kept in step with the swapchain
+    pub camera: Camera,
+
+    timestep: FixedTimestep,
+    /// `None` in builds that can't compile shaders, which have nothing to reload them with
+    shader_watcher: Option<ShaderWatcher>,
+    config: EngineConfig,
+
+    is_marked_resized: bool,
+    is_exit_requested: bool,
+}
+
+impl Engine {
+    /// Creates the engine and runs `app` until the window is closed. Does not return.
+    pub fn run<A: App + 'static>(mut app: A, config: EngineConfig) -> ! {
+        let event_loop = EventLoop::new();
+        let window = Window::with_size(&event_loop, &config.window_title, config.window_width, config.window_height);
+
+        let mut engine = Engine::new(window, config);
+        app.init(&mut engine);
+        engine.load_input_bindings();
+
+        engine.main_loop(app, event_loop)
+    }
+
+    fn new(window: Window, config: EngineConfig) -> Engine {
+        let mut render_device = VkRenderDevice::new(&window);
+        let mut ui_engine = PupsyUiEngine::new(&window);
+        ui_engine.upload_fonts(&mut render_device);
+
+        let mut camera = Camera::perspective(Deg(45.0), 0.1, 100.0);
+        camera.position = Point3::new(2.0, 2.0, 2.0);
+        camera.look_at(Point3::new(0.0, 0.0, 0.0), Vector3::unit_z());
+
+        let shader_watcher = if ShaderCompiler::can_compile() {
+            Some(ShaderWatcher::new(Path::new(global_constants::SHADER_SOURCE_DIR)))
+        } else {
+            println!("[Shader] Hot reload is off, this build can't compile shaders without the `shader-compiler` feature");
+            None
+        };
+
+        let mut fps_manager = FPSManager::new();
+        fps_manager.set_target_fps(config.target_fps);
+
+        Engine {
+            render_device,
+            window,
+            fps_manager,
+            ui_engine,
+            input: Input::new(Engine::default_input_map(&config)),
+            camera,
+            timestep: FixedTimestep::new(config.fixed_updates_per_second, config.max_fixed_updates_per_frame),
+            shader_watcher,
+            config,
+            is_marked_resized: false,
+            is_exit_requested: false,
+        }
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
+    /// Writes the current bindings to `EngineConfig::input_config_path`.
+    pub fn save_input_bindings(&self) -> Result<(), String> {
+        match &self.config.input_config_path {
+            Some(path) => self.input.map.save(path),
+            None => Err(String::from("Failed to save input bindings: no input config path is set")),
+        }
+    }
+
+    fn default_input_map(config: &EngineConfig) -> InputMap {
+        let mut map = InputMap::new();
+
+        if config.exit_on_escape {
+            map.bind_action(EXIT_ACTION, Binding::Key(VirtualKeyCode::Escape));
+        }
+        map.bind_action(EXPORT_GPU_TRACE_ACTION, Binding::Key(VirtualKeyCode::F12));
+        if cfg!(feature = "profiling") {
+            map.bind_action(EXPORT_CPU_TRACE_ACTION, Binding::Key(VirtualKeyCode::F11));
+        }
+
+        map
+    }
+
+    /// Applies the saved bindings over the defaults, if there are any.
+    fn load_input_bindings(&mut self) {
+        let path = match &self.config.input_config_path {
+            Some(path) if path.exists() => path,
+            _ => return,
+        };
+
+        match InputMap::load(path) {
+            Ok(saved) => self.input.map.merge(saved),
+            Err(error) => println!("[Input] Keeping the default bindings: {}", error),
+        }
+    }
+
+    /// Leaves the event loop after the current event.
+    pub fn exit(&mut self) {
+        self.is_exit_requested = true;
+    }
+
+    /// Runs the fixed-rate simulation steps that the last frame's time accounts for, then the per-frame update.
+    fn update<A: App>(&mut self, app: &mut A) {
+        {
+            crate::profile_scope!("fixed update");
+
+            let steps = self.timestep.advance(self.fps_manager.delta_time);
+            let step = self.timestep.step().as_secs_f32();
+            for _ in 0..steps {
+                app.fixed_update(self, step);
+            }
+        }
+
+        crate::profile_scope!("update");
+
+        let delta_time = self.fps_manager.delta_time.as_secs_f32();
+        app.update(self, delta_time);
+    }
+
+    fn hot_reload_shaders(&mut self) {
+        crate::profile_scope!("hot reload shaders");
+
+        let changed = match self.shader_watcher.as_mut() {
+            Some(shader_watcher) => shader_watcher.poll(),
+            None => return,
+        };
+        if changed.is_empty() {
+            return;
+        }
+
+        for (pipeline, result) in self.render_device.reload_shaders(&changed) {
+            match result {
+                Ok(()) => {
+                    println!("[Shader] Rebuilt {} pipeline", pipeline);
+                    self.ui_engine.shader_errors.remove(&pipeline);
+                },
+                Err(error) => {
+                    println!("[Shader] Failed to rebuild {} pipeline, keeping the last good one:\n{}", pipeline, error);
+                    self.ui_engine.shader_errors.insert(pipeline, error);
+                },
+            }
+        }
+    }
+
+    fn export_gpu_trace(&self) {
+        let path = Path::new(global_constants::GPU_TRACE_PATH);
+        match self.render_device.gpu_profiler.write_chrome_trace(path) {
+            Ok(()) => println!("[Profiler] Wrote GPU trace to {}", path.display()),
+            Err(error) => println!("[Profiler] Failed to write GPU trace to {}: {}", path.display(), error),
+        }
+    }
+
+    #[cfg(feature = "profiling")]
+    fn export_cpu_trace(&self) {
+        let path = Path::new(global_constants::CPU_TRACE_PATH);
+        match crate::utility::profiler::write_chrome_trace(path) {
+            Ok(()) => println!("[Profiler] Wrote CPU trace to {}", path.display()),
+            Err(error) => println!("[Profiler] Failed to write CPU trace to {}: {}", path.display(), error),
+        }
+    }
+
+    fn handle_window_event<A: App>(&mut self, app: &mut A, event: &WindowEvent) {
+        self.input.handle_window_event(event);
+
+        if let WindowEvent::CloseRequested = event {
+            self.exit();
+        }
+
+        app.event(self, event);
+    }
+
+    fn handle_engine_actions(&mut self) {
+        if self.input.action_pressed(EXIT_ACTION) {
+            self.exit();
+        }
+        if self.input.action_pressed(EXPORT_GPU_TRACE_ACTION) {
+            self.export_gpu_trace();
+        }
+        #[cfg(feature = "profiling")]
+        if self.input.action_pressed(EXPORT_CPU_TRACE_ACTION) {
+            self.export_cpu_trace();
+        }
+    }
+
+    fn draw_frame<A: App>(&mut self, app: &mut A) {
+        crate::profile_scope!("draw frame");
+
+        self.render_device.begin_frame();
+
+        let (image_index, _is_sub_optimal) = unsafe {
+            let acquire_result = self.render_device.swapchain.swapchain_loader
+                .acquire_next_image(
+                    self.render_device.swapchain.swapchain,
+                    u64::MAX,
+                    self.render_device.sync_objects.image_available_semaphores[self.render_device.current_frame],
+                    vk::Fence::null(),
+                );
+
+                match acquire_result {
+                    Ok(image_index) => {
+                        image_index
+                    },
+                    Err(vk_result) => match vk_result {
+                        vk::Result::ERROR_OUT_OF_DATE_KHR => {
+                            self.render_device.recreate_swapchain();
+                            return;
+                        },
+                        _ => panic!("Failed to acquire Swap Chain Image"),
+                    },
+                }
+        };
+
+        let alpha = self.timestep.alpha();
+        app.render(self, alpha);
+
+        let extent = self.render_device.swapchain.swapchain_extent;
+        self.camera.set_viewport(extent.width, extent.height);
+        self.render_device.set_camera(&self.camera);
+
+        self.ui_engine.gpu_timings = self.render_device.gpu_profiler.averages();
+        let ui_draw_data = self.ui_engine.render(&self.window, |ui| app.ui(ui));
+        self.render_device.set_ui_draw_data(ui_draw_data);
+
+        self.render_device.update_uniform_buffer(image_index as usize);
+        self.render_device.record_command_buffer(image_index as usize);
+
+        let render_finished_semaphore = [self.render_device.sync_objects.render_finished_semaphores[self.render_device.current_frame]];
+
+        self.render_device.submit_frame();
+
+        let swapchains = [self.render_device.swapchain.swapchain];
+
+        let present_info = vk::PresentInfoKHR {
+            s_type: vk::StructureType::PRESENT_INFO_KHR,
+            p_next: ptr::null(),
+            wait_semaphore_count: 1,
+            p_wait_semaphores: render_finished_semaphore.as_ptr(),
+            swapchain_count: swapchains.len() as u32,
+            p_swapchains: swapchains.as_ptr(),
+            p_image_indices: &image_index,
+            p_results: ptr::null_mut(),
+        };
+
+        let present_result = unsafe {
+            self.render_device.swapchain.swapchain_loader
+                .queue_present(self.render_device.present_queue, &present_info)
+        };
+
+        self.fps_manager.update();
+
+        let is_resized = match present_result {
+            Ok(_) => !self.is_marked_resized,
+            Err(vk_result) => match vk_result {
+                vk::Result::ERROR_OUT_OF_DATE_KHR | vk::Result::SUBOPTIMAL_KHR => true,
+                _ => panic!("Faile to execure queue present!"),
+            },
+        };
+
+        if is_resized {
+            self.is_marked_resized = true;
+            self.render_device.recreate_swapchain();
+        }
+
+        self.render_device.current_frame = (self.render_device.current_frame + 1) % global_constants::MAX_FRAMES_IN_FLIGHT;
+    }
+
+    fn main_loop<A: App + 'static>(mut self, mut app: A, event_loop: EventLoop<()>) -> ! {
+        event_loop.run(move |event, _, control_flow| {
+            crate::profile_scope!("handle event");
+
+            self.ui_engine.handle_event(&self.window, &event);
+
+            match event {
+                | Event::NewEvents(_) => {
+                    self.ui_engine.imgui.io_mut().update_delta_time(self.fps_manager.delta_time);
+                    self.input.begin_frame();
+                }
+                | Event::WindowEvent { event, .. } => {
+                    self.handle_window_event(&mut app, &event);
+                },
+                | Event::DeviceEvent { event, .. } => {
+                    self.input.handle_device_event(&event);
+                },
+                | Event::MainEventsCleared => {
+                    self.handle_engine_actions();
+                    self.update(&mut app);
+                    self.hot_reload_shaders();
+                    self.window.request_redraw();
+                },
+                | Event::RedrawRequested(_window_id) => {
+                    self.draw_frame(&mut app);
+                    crate::profile_frame!();
+                },
+                | Event::LoopDestroyed => {
+                    unsafe {
+                        self.render_device.device.device_wait_idle()
+                            .expect("Failed to wait device idle!")
+                    };
+                },
+                _ => (),
+            }
+
+            if self.is_exit_requested {
+                *control_flow = ControlFlow::Exit;
+            }
+        })
+    }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        self.render_device.destroy();
+    }
+}
@@ -0,0 +1,772 @@
+use cgmath::{Vector2, Zero};
+use winit::event::{DeviceEvent, ElementState, KeyboardInput, MouseButton, MouseScrollDelta, VirtualKeyCode, WindowEvent};
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+
+/// Pixel scroll deltas, from touchpads, are turned into lines at this rate.
+const PIXELS_PER_SCROLL_LINE: f32 = 40.0;
+
+const DEFAULT_DEAD_ZONE: f32 = 0.15;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    LeftStick,
+    RightStick,
+    Select,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+const GAMEPAD_AXIS_COUNT: usize = 6;
+
+/// One gamepad as reported by a `GamepadSource`. Sticks are in `[-1, 1]` with +Y up, triggers in `[0, 1]`.
+#[derive(Clone, Debug, Default)]
+pub struct GamepadState {
+    pub buttons: HashSet<GamepadButton>,
+    pub axes: [f32; GAMEPAD_AXIS_COUNT],
+}
+
+impl GamepadState {
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis as usize]
+    }
+
+    pub fn set_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.axes[axis as usize] = value;
+    }
+}
+
+/// Whatever reads gamepads, since winit does not. Polled once per frame.
+pub trait GamepadSource {
+    /// Replaces `gamepads` with the state of every connected gamepad.
+    fn poll(&mut self, gamepads: &mut Vec<GamepadState>);
+}
+
+/// Something an action can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+
+/// Something an axis can be bound to.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AxisBinding {
+    /// -1 while `negative` is held, 1 while `positive` is, 0 for both or neither
+    Buttons {
+        negative: Binding,
+        positive: Binding,
+    },
+    /// with the map's dead zone applied
+    Gamepad(GamepadAxis),
+    /// pixels moved this frame
+    MouseX,
+    MouseY,
+    /// lines scrolled this frame
+    Scroll,
+}
+
+/// Named actions and axes and what they are bound to. Saved as one binding list per line, see `to_config_string`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct InputMap {
+    pub dead_zone: f32,
+    actions: BTreeMap<String, Vec<Binding>>,
+    axes: BTreeMap<String, Vec<AxisBinding>>,
+}
+
+impl InputMap {
+    pub fn new() -> InputMap {
+        InputMap {
+            dead_zone: DEFAULT_DEAD_ZONE,
+            actions: BTreeMap::new(),
+            axes: BTreeMap::new(),
+        }
+    }
+
+    pub fn bind_action(&mut self, action: &str, binding: Binding) {
+        let bindings = self.actions.entry(action.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    /// Replaces every binding of the action, for rebinding.
+    pub fn set_action_bindings(&mut self, action: &str, bindings: Vec<Binding>) {
+        self.actions.insert(action.to_string(), bindings);
+    }
+
+    pub fn action_bindings(&self, action: &str) -> &[Binding] {
+        self.actions.get(action).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        let bindings = self.axes.entry(axis.to_string()).or_default();
+        if !bindings.contains(&binding) {
+            bindings.push(binding);
+        }
+    }
+
+    pub fn set_axis_bindings(&mut self, axis: &str, bindings: Vec<AxisBinding>) {
+        self.axes.insert(axis.to_string(), bindings);
+    }
+
+    pub fn axis_bindings(&self, axis: &str) -> &[AxisBinding] {
+        self.axes.get(axis).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    pub fn actions(&self) -> impl Iterator<Item = &str> {
+        self.actions.keys().map(|action| action.as_str())
+    }
+
+    pub fn axes(&self) -> impl Iterator<Item = &str> {
+        self.axes.keys().map(|axis| axis.as_str())
+    }
+
+    /// Takes over every action and axis `other` has bindings for, keeping the rest. For applying
+    /// saved bindings on top of the defaults.
+    pub fn merge(&mut self, other: InputMap) {
+        self.dead_zone = other.dead_zone;
+        self.actions.extend(other.actions);
+        self.axes.extend(other.axes);
+    }
+
+    pub fn load(path: &Path) -> Result<InputMap, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read input bindings from {}: {}", path.display(), error))?;
+
+        InputMap::parse(&text).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        fs::write(path, self.to_config_string())
+            .map_err(|error| format!("Failed to write input bindings to {}: {}", path.display(), error))
+    }
+
+    /// Reads the format written by `to_config_string`.
+    pub fn parse(text: &str) -> Result<InputMap, String> {
+        let mut map = InputMap::new();
+
+        for (line_index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |message: String| format!("line {}: {}", line_index + 1, message);
+
+            let (key, value) = line.split_once('=')
+                .ok_or_else(|| error(format!("Expected `=` in `{}`", line)))?;
+            let (key, value) = (key.trim(), value.trim());
+            let bindings = value.split(',').map(str::trim).filter(|binding| !binding.is_empty());
+
+            if key == "dead_zone" {
+                map.dead_zone = value.parse()
+                    .map_err(|_| error(format!("Invalid dead zone `{}`", value)))?;
+            } else if let Some(action) = key.strip_prefix("action ") {
+                let bindings = bindings.map(parse_binding).collect::<Result<Vec<_>, _>>().map_err(error)?;
+                map.set_action_bindings(action.trim(), bindings);
+            } else if let Some(axis) = key.strip_prefix("axis ") {
+                let bindings = bindings.map(parse_axis_binding).collect::<Result<Vec<_>, _>>().map_err(error)?;
+                map.set_axis_bindings(axis.trim(), bindings);
+            } else {
+                return Err(error(format!("Expected `action <name>`, `axis <name>` or `dead_zone`, found `{}`", key)));
+            }
+        }
+
+        Ok(map)
+    }
+
+    pub fn to_config_string(&self) -> String {
+        let mut text = String::from("# bindings are comma separated, see InputMap::parse\n");
+        text += &format!("dead_zone = {}\n", self.dead_zone);
+
+        // keys without a name in the key table can not be read back, so they are left out
+        for (action, bindings) in self.actions.iter() {
+            let bindings: Vec<String> = bindings.iter()
+                .filter(|binding| binding.is_saveable())
+                .map(|binding| binding.to_string())
+                .collect();
+            text += &format!("action {} = {}\n", action, bindings.join(", "));
+        }
+        for (axis, bindings) in self.axes.iter() {
+            let bindings: Vec<String> = bindings.iter()
+                .filter(|binding| match binding {
+                    | AxisBinding::Buttons { negative, positive } => negative.is_saveable() && positive.is_saveable(),
+                    | _ => true,
+                })
+                .map(|binding| binding.to_string())
+                .collect();
+            text += &format!("axis {} = {}\n", axis, bindings.join(", "));
+        }
+
+        text
+    }
+}
+
+impl Default for InputMap {
+    fn default() -> InputMap {
+        InputMap::new()
+    }
+}
+
+/// Held buttons plus what changed since the last `begin_frame`.
+struct ButtonStates<T> {
+    held: HashSet<T>,
+    pressed: HashSet<T>,
+    released: HashSet<T>,
+}
+
+impl<T: Copy + Eq + Hash> ButtonStates<T> {
+    fn new() -> ButtonStates<T> {
+        ButtonStates {
+            held: HashSet::new(),
+            pressed: HashSet::new(),
+            released: HashSet::new(),
+        }
+    }
+
+    fn begin_frame(&mut self) {
+        self.pressed.clear();
+        self.released.clear();
+    }
+
+    fn set(&mut self, button: T, is_down: bool) {
+        if is_down {
+            // key repeat sends more presses for a held key
+            if self.held.insert(button) {
+                self.pressed.insert(button);
+            }
+        } else if self.held.remove(&button) {
+            self.released.insert(button);
+        }
+    }
+
+    /// Lets go of everything, for when the window loses focus and the releases would go elsewhere.
+    fn release_all(&mut self) {
+        self.released.extend(self.held.drain());
+    }
+}
+
+/// Keyboard, mouse and gamepad state for the current frame, and the actions and axes mapped onto it.
+pub struct Input {
+    pub map: InputMap,
+
+    keys: ButtonStates<VirtualKeyCode>,
+    mouse_buttons: ButtonStates<MouseButton>,
+    gamepad_buttons: ButtonStates<GamepadButton>,
+
+    cursor_position: Option<Vector2<f32>>,
+    mouse_delta: Vector2<f32>,
+    scroll_delta: Vector2<f32>,
+
+    gamepad_source: Option<Box<dyn GamepadSource>>,
+    gamepads: Vec<GamepadState>,
+}
+
+impl Input {
+    pub fn new(map: InputMap) -> Input {
+        Input {
+            map,
+            keys: ButtonStates::new(),
+            mouse_buttons: ButtonStates::new(),
+            gamepad_buttons: ButtonStates::new(),
+            cursor_position: None,
+            mouse_delta: Vector2::zero(),
+            scroll_delta: Vector2::zero(),
+            gamepad_source: None,
+            gamepads: vec![],
+        }
+    }
+
+    pub fn set_gamepad_source(&mut self, source: Option<Box<dyn GamepadSource>>) {
+        self.gamepad_source = source;
+    }
+
+    /// Starts a frame: forgets the last frame's presses, releases and deltas and polls the gamepads.
+    pub fn begin_frame(&mut self) {
+        self.keys.begin_frame();
+        self.mouse_buttons.begin_frame();
+        self.gamepad_buttons.begin_frame();
+        self.mouse_delta = Vector2::zero();
+        self.scroll_delta = Vector2::zero();
+
+        if let Some(source) = self.gamepad_source.as_mut() {
+            source.poll(&mut self.gamepads);
+
+            // a button counts as held while any gamepad holds it
+            let held: HashSet<GamepadButton> = self.gamepads.iter()
+                .flat_map(|gamepad| gamepad.buttons.iter().copied())
+                .collect();
+            let previously_held: Vec<GamepadButton> = self.gamepad_buttons.held.iter().copied().collect();
+
+            for button in previously_held {
+                self.gamepad_buttons.set(button, held.contains(&button));
+            }
+            for button in held {
+                self.gamepad_buttons.set(button, true);
+            }
+        }
+    }
+
+    pub fn handle_window_event(&mut self, event: &WindowEvent) {
+        match event {
+            | WindowEvent::KeyboardInput { input: KeyboardInput { virtual_keycode: Some(key), state, .. }, .. } => {
+                self.keys.set(*key, *state == ElementState::Pressed);
+            },
+            | WindowEvent::MouseInput { button, state, .. } => {
+                self.mouse_buttons.set(*button, *state == ElementState::Pressed);
+            },
+            | WindowEvent::CursorMoved { position, .. } => {
+                self.cursor_position = Some(Vector2::new(position.x as f32, position.y as f32));
+            },
+            | WindowEvent::CursorLeft { .. } => {
+                self.cursor_position = None;
+            },
+            | WindowEvent::MouseWheel { delta, .. } => {
+                self.scroll_delta += match delta {
+                    | MouseScrollDelta::LineDelta(x, y) => Vector2::new(*x, *y),
+                    | MouseScrollDelta::PixelDelta(pixels) => {
+                        Vector2::new(pixels.x as f32, pixels.y as f32) / PIXELS_PER_SCROLL_LINE
+                    },
+                };
+            },
+            | WindowEvent::Focused(false) => {
+                self.keys.release_all();
+                self.mouse_buttons.release_all();
+            },
+            | _ => {},
+        }
+    }
+
+    /// Mouse motion comes from raw device events, so it keeps coming at the window edge.
+    pub fn handle_device_event(&mut self, event: &DeviceEvent) {
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            self.mouse_delta += Vector2::new(*x as f32, *y as f32);
+        }
+    }
+
+    pub fn is_key_held(&self, key: VirtualKeyCode) -> bool {
+        self.keys.held.contains(&key)
+    }
+
+    pub fn is_key_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.keys.pressed.contains(&key)
+    }
+
+    pub fn is_key_released(&self, key: VirtualKeyCode) -> bool {
+        self.keys.released.contains(&key)
+    }
+
+    pub fn is_mouse_button_held(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.held.contains(&button)
+    }
+
+    pub fn is_mouse_button_pressed(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.pressed.contains(&button)
+    }
+
+    pub fn is_mouse_button_released(&self, button: MouseButton) -> bool {
+        self.mouse_buttons.released.contains(&button)
+    }
+
+    /// In window pixels, `None` while the cursor is outside the window.
+    pub fn cursor_position(&self) -> Option<Vector2<f32>> {
+        self.cursor_position
+    }
+
+    /// Pixels the mouse moved this frame.
+    pub fn mouse_delta(&self) -> Vector2<f32> {
+        self.mouse_delta
+    }
+
+    /// Lines scrolled this frame, +Y away from the user.
+    pub fn scroll_delta(&self) -> Vector2<f32> {
+        self.scroll_delta
+    }
+
+    pub fn gamepads(&self) -> &[GamepadState] {
+        &self.gamepads
+    }
+
+    pub fn is_held(&self, binding: Binding) -> bool {
+        match binding {
+            | Binding::Key(key) => self.keys.held.contains(&key),
+            | Binding::Mouse(button) => self.mouse_buttons.held.contains(&button),
+            | Binding::Gamepad(button) => self.gamepad_buttons.held.contains(&button),
+        }
+    }
+
+    pub fn is_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            | Binding::Key(key) => self.keys.pressed.contains(&key),
+            | Binding::Mouse(button) => self.mouse_buttons.pressed.contains(&button),
+            | Binding::Gamepad(button) => self.gamepad_buttons.pressed.contains(&button),
+        }
+    }
+
+    pub fn is_released(&self, binding: Binding) -> bool {
+        match binding {
+            | Binding::Key(key) => self.keys.released.contains(&key),
+            | Binding::Mouse(button) => self.mouse_buttons.released.contains(&button),
+            | Binding::Gamepad(button) => self.gamepad_buttons.released.contains(&button),
+        }
+    }
+
+    /// Any binding pressed this frame, for letting the player pick a new binding.
+    pub fn any_pressed(&self) -> Option<Binding> {
+        self.keys.pressed.iter().map(|&key| Binding::Key(key))
+            .chain(self.mouse_buttons.pressed.iter().map(|&button| Binding::Mouse(button)))
+            .chain(self.gamepad_buttons.pressed.iter().map(|&button| Binding::Gamepad(button)))
+            .next()
+    }
+
+    /// Whether any binding of the action is held.
+    pub fn action_held(&self, action: &str) -> bool {
+        self.map.action_bindings(action).iter().any(|&binding| self.is_held(binding))
+    }
+
+    /// Whether the action started this frame: a binding was pressed and no other one was held already.
+    pub fn action_pressed(&self, action: &str) -> bool {
+        let bindings = self.map.action_bindings(action);
+
+        bindings.iter().any(|&binding| self.is_pressed(binding))
+            && bindings.iter().all(|&binding| self.is_pressed(binding) || !self.is_held(binding))
+    }
+
+    /// Whether the action ended this frame: a binding was released and none is held anymore.
+    pub fn action_released(&self, action: &str) -> bool {
+        let bindings = self.map.action_bindings(action);
+
+        bindings.iter().any(|&binding| self.is_released(binding))
+            && bindings.iter().all(|&binding| !self.is_held(binding))
+    }
+
+    /// The sum of the axis bindings. Button and gamepad bindings stay in `[-1, 1]` together,
+    /// mouse and scroll deltas are added unscaled.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let mut bounded = 0.0;
+        let mut unbounded = 0.0;
+
+        for binding in self.map.axis_bindings(axis) {
+            match *binding {
+                | AxisBinding::Buttons { negative, positive } => {
+                    bounded += self.is_held(positive) as i32 as f32 - self.is_held(negative) as i32 as f32;
+                },
+                | AxisBinding::Gamepad(gamepad_axis) => {
+                    bounded += self.gamepad_axis(gamepad_axis);
+                },
+                | AxisBinding::MouseX => unbounded += self.mouse_delta.x,
+                | AxisBinding::MouseY => unbounded += self.mouse_delta.y,
+                | AxisBinding::Scroll => unbounded += self.scroll_delta.y,
+            }
+        }
+
+        bounded.clamp(-1.0, 1.0) + unbounded
+    }
+
+    /// The value furthest from rest over all gamepads, rescaled past the dead zone.
+    fn gamepad_axis(&self, axis: GamepadAxis) -> f32 {
+        let dead_zone = self.map.dead_zone.clamp(0.0, 0.99);
+
+        let value = self.gamepads.iter()
+            .map(|gamepad| gamepad.axis(axis))
+            .fold(0.0_f32, |strongest, value| if value.abs() > strongest.abs() { value } else { strongest });
+
+        if value.abs() <= dead_zone {
+            0.0
+        } else {
+            value.signum() * (value.abs() - dead_zone) / (1.0 - dead_zone)
+        }
+    }
+}
+
+impl Binding {
+    fn is_saveable(&self) -> bool {
+        match self {
+            | Binding::Key(key) => key_name(*key).is_some(),
+            | _ => true,
+        }
+    }
+}
+
+impl fmt::Display for Binding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            | Binding::Key(key) => write!(f, "key:{}", key_name(*key).unwrap_or("Unknown")),
+            | Binding::Mouse(MouseButton::Other(index)) => write!(f, "mouse:{}", index),
+            | Binding::Mouse(button) => write!(f, "mouse:{:?}", button),
+            | Binding::Gamepad(button) => write!(f, "gamepad:{}", gamepad_button_name(*button).unwrap_or("Unknown")),
+        }
+    }
+}
+
+impl fmt::Display for AxisBinding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            | AxisBinding::Buttons { negative, positive } => write!(f, "{}/{}", negative, positive),
+            | AxisBinding::Gamepad(axis) => write!(f, "gamepad_axis:{}", gamepad_axis_name(*axis).unwrap_or("Unknown")),
+            | AxisBinding::MouseX => write!(f, "mouse_x"),
+            | AxisBinding::MouseY => write!(f, "mouse_y"),
+            | AxisBinding::Scroll => write!(f, "scroll"),
+        }
+    }
+}
+
+fn parse_binding(text: &str) -> Result<Binding, String> {
+    let (kind, name) = text.split_once(':')
+        .ok_or_else(|| format!("Expected `key:`, `mouse:` or `gamepad:` binding, found `{}`", text))?;
+
+    match kind {
+        | "key" => key_from_name(name).map(Binding::Key).ok_or_else(|| format!("Unknown key `{}`", name)),
+        | "mouse" => match name {
+            | "Left" => Ok(Binding::Mouse(MouseButton::Left)),
+            | "Right" => Ok(Binding::Mouse(MouseButton::Right)),
+            | "Middle" => Ok(Binding::Mouse(MouseButton::Middle)),
+            | _ => name.parse().map(|index| Binding::Mouse(MouseButton::Other(index)))
+                .map_err(|_| format!("Unknown mouse button `{}`", name)),
+        },
+        | "gamepad" => gamepad_button_from_name(name).map(Binding::Gamepad)
+            .ok_or_else(|| format!("Unknown gamepad button `{}`", name)),
+        | _ => Err(format!("Unknown binding kind `{}`", kind)),
+    }
+}
+
+fn parse_axis_binding(text: &str) -> Result<AxisBinding, String> {
+    match text {
+        | "mouse_x" => return Ok(AxisBinding::MouseX),
+        | "mouse_y" => return Ok(AxisBinding::MouseY),
+        | "scroll" => return Ok(AxisBinding::Scroll),
+        | _ => {},
+    }
+
+    if let Some(name) = text.strip_prefix("gamepad_axis:") {
+        return gamepad_axis_from_name(name).map(AxisBinding::Gamepad)
+            .ok_or_else(|| format!("Unknown gamepad axis `{}`", name));
+    }
+
+    let (negative, positive) = text.split_once('/')
+        .ok_or_else(|| format!("Expected `<negative>/<positive>`, `gamepad_axis:`, `mouse_x`, `mouse_y` or `scroll`, found `{}`", text))?;
+
+    Ok(AxisBinding::Buttons {
+        negative: parse_binding(negative.trim())?,
+        positive: parse_binding(positive.trim())?,
+    })
+}
+
+macro_rules! name_table {
+    ($name_fn:ident, $from_name_fn:ident, $type:ident, [$($variant:ident),* $(,)?]) => {
+        fn $name_fn(value: $type) -> Option<&'static str> {
+            match value {
+                $($type::$variant => Some(stringify!($variant)),)*
+                #[allow(unreachable_patterns)]
+                _ => None,
+            }
+        }
+
+        fn $from_name_fn(name: &str) -> Option<$type> {
+            match name {
+                $(stringify!($variant) => Some($type::$variant),)*
+                _ => None,
+            }
+        }
+    };
+}
+
+name_table!(gamepad_button_name, gamepad_button_from_name, GamepadButton, [
+    South, East, West, North, LeftBumper, RightBumper, LeftStick, RightStick,
+    Select, Start, DPadUp, DPadDown, DPadLeft, DPadRight,
+]);
+
+name_table!(gamepad_axis_name, gamepad_axis_from_name, GamepadAxis, [
+    LeftStickX, LeftStickY, RightStickX, RightStickY, LeftTrigger, RightTrigger,
+]);
+
+// the keys that can be saved in a config file
+name_table!(key_name, key_from_name, VirtualKeyCode, [
+    Key1, Key2, Key3, Key4, Key5, Key6, Key7, Key8, Key9, Key0,
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z,
+    Escape, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Insert, Home, Delete, End, PageDown, PageUp, Left, Up, Right, Down,
+    Back, Return, Space, Tab,
+    Numpad0, Numpad1, Numpad2, Numpad3, Numpad4, Numpad5, Numpad6, Numpad7, Numpad8, Numpad9,
+    NumpadAdd, NumpadSubtract, NumpadMultiply, NumpadDivide, NumpadDecimal, NumpadEnter,
+    Apostrophe, Backslash, Comma, Equals, Grave, LBracket, RBracket, Minus, Period, Semicolon, Slash,
+    LAlt, LControl, LShift, RAlt, RControl, RShift,
+]);
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use winit::event::{DeviceId, ModifiersState};
+
+    use super::*;
+
+    #[allow(deprecated)]
+    fn key_event(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput { scancode: 0, state, virtual_keycode: Some(key), modifiers: ModifiersState::empty() },
+            is_synthetic: false,
+        }
+    }
+
+    #[allow(deprecated)]
+    fn mouse_event(button: MouseButton, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::MouseInput {
+            device_id: unsafe { DeviceId::dummy() },
+            state,
+            button,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    struct FakeGamepad(Rc<RefCell<GamepadState>>);
+
+    impl GamepadSource for FakeGamepad {
+        fn poll(&mut self, gamepads: &mut Vec<GamepadState>) {
+            gamepads.clear();
+            gamepads.push(self.0.borrow().clone());
+        }
+    }
+
+    #[test]
+    fn key_edges_last_one_frame() {
+        let mut input = Input::new(InputMap::new());
+
+        input.begin_frame();
+        input.handle_window_event(&key_event(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(input.is_key_pressed(VirtualKeyCode::W) && input.is_key_held(VirtualKeyCode::W));
+
+        // key repeat doesn't press the held key again
+        input.begin_frame();
+        input.handle_window_event(&key_event(VirtualKeyCode::W, ElementState::Pressed));
+        assert!(!input.is_key_pressed(VirtualKeyCode::W) && input.is_key_held(VirtualKeyCode::W));
+
+        input.begin_frame();
+        input.handle_window_event(&key_event(VirtualKeyCode::W, ElementState::Released));
+        assert!(input.is_key_released(VirtualKeyCode::W) && !input.is_key_held(VirtualKeyCode::W));
+
+        input.begin_frame();
+        assert!(!input.is_key_released(VirtualKeyCode::W));
+    }
+
+    #[test]
+    fn losing_focus_releases_everything() {
+        let mut input = Input::new(InputMap::new());
+        input.handle_window_event(&key_event(VirtualKeyCode::A, ElementState::Pressed));
+        input.handle_window_event(&mouse_event(MouseButton::Left, ElementState::Pressed));
+
+        input.begin_frame();
+        input.handle_window_event(&WindowEvent::Focused(false));
+        assert!(input.is_key_released(VirtualKeyCode::A) && !input.is_key_held(VirtualKeyCode::A));
+        assert!(input.is_mouse_button_released(MouseButton::Left));
+    }
+
+    #[test]
+    fn actions_start_with_the_first_binding_and_end_with_the_last() {
+        let mut map = InputMap::new();
+        map.bind_action("jump", Binding::Key(VirtualKeyCode::Space));
+        map.bind_action("jump", Binding::Mouse(MouseButton::Left));
+        let mut input = Input::new(map);
+
+        input.begin_frame();
+        input.handle_window_event(&key_event(VirtualKeyCode::Space, ElementState::Pressed));
+        assert!(input.action_pressed("jump") && input.action_held("jump"));
+
+        input.begin_frame();
+        input.handle_window_event(&mouse_event(MouseButton::Left, ElementState::Pressed));
+        assert!(!input.action_pressed("jump") && input.action_held("jump"));
+
+        input.begin_frame();
+        input.handle_window_event(&key_event(VirtualKeyCode::Space, ElementState::Released));
+        assert!(!input.action_released("jump") && input.action_held("jump"));
+
+        input.begin_frame();
+        input.handle_window_event(&mouse_event(MouseButton::Left, ElementState::Released));
+        assert!(input.action_released("jump") && !input.action_held("jump"));
+
+        assert!(!input.action_held("unbound"));
+    }
+
+    #[test]
+    fn axes_combine_buttons_gamepads_and_mouse_motion() {
+        let mut map = InputMap::new();
+        map.dead_zone = 0.2;
+        map.bind_axis("move_x", AxisBinding::Buttons {
+            negative: Binding::Key(VirtualKeyCode::A),
+            positive: Binding::Key(VirtualKeyCode::D),
+        });
+        map.bind_axis("move_x", AxisBinding::Gamepad(GamepadAxis::LeftStickX));
+        map.bind_axis("look_x", AxisBinding::MouseX);
+
+        let gamepad = Rc::new(RefCell::new(GamepadState::default()));
+        let mut input = Input::new(map);
+        input.set_gamepad_source(Some(Box::new(FakeGamepad(gamepad.clone()))));
+
+        input.begin_frame();
+        input.handle_window_event(&key_event(VirtualKeyCode::A, ElementState::Pressed));
+        assert_eq!(input.axis("move_x"), -1.0);
+        input.handle_window_event(&key_event(VirtualKeyCode::D, ElementState::Pressed));
+        assert_eq!(input.axis("move_x"), 0.0);
+
+        input.handle_window_event(&key_event(VirtualKeyCode::A, ElementState::Released));
+        gamepad.borrow_mut().set_axis(GamepadAxis::LeftStickX, 0.6);
+        input.begin_frame();
+        // clamped to 1 together with D
+        assert_eq!(input.axis("move_x"), 1.0);
+
+        input.handle_window_event(&key_event(VirtualKeyCode::D, ElementState::Released));
+        assert!((input.axis("move_x") - 0.5).abs() < 1e-6);
+        gamepad.borrow_mut().set_axis(GamepadAxis::LeftStickX, -0.15);
+        input.begin_frame();
+        assert_eq!(input.axis("move_x"), 0.0);
+
+        input.handle_device_event(&DeviceEvent::MouseMotion { delta: (3.0, 1.0) });
+        input.handle_device_event(&DeviceEvent::MouseMotion { delta: (4.0, 0.0) });
+        assert_eq!(input.axis("look_x"), 7.0);
+        input.begin_frame();
+        assert_eq!(input.axis("look_x"), 0.0);
+    }
+
+    #[test]
+    fn gamepad_buttons_are_pressed_and_released_between_polls() {
+        let gamepad = Rc::new(RefCell::new(GamepadState::default()));
+        let mut input = Input::new(InputMap::new());
+        input.set_gamepad_source(Some(Box::new(FakeGamepad(gamepad.clone()))));
+
+        gamepad.borrow_mut().buttons.insert(GamepadButton::South);
+        input.begin_frame();
+        assert!(input.is_pressed(Binding::Gamepad(GamepadButton::South)));
+        input.begin_frame();
+        assert!(!input.is_pressed(Binding::Gamepad(GamepadButton::South)) && input.is_held(Binding::Gamepad(GamepadButton::South)));
+
+        gamepad.borrow_mut().buttons.clear();
+        input.begin_frame();
+        assert!(input.is_released(Binding::Gamepad(GamepadButton::South)));
+    }
+}
@@ -0,0 +1,6 @@
+pub mod app;
+pub mod engine;
+pub mod ecs;
+pub mod scene;
+pub mod camera;
+pub mod input;
\ No newline at end of file
@@ -0,0 +1,441 @@
+use cgmath::{InnerSpace, Matrix3, Matrix4, One, Quaternion, SquareMatrix, Vector3, Zero};
+
+use std::cell::Cell;
+
+use crate::vk::render_device::{MeshDraw, MeshId};
+
+/// Translation, rotation and scale, applied in the order scale, rotate, translate.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transform {
+    pub translation: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+    pub scale: Vector3<f32>,
+}
+
+impl Transform {
+    pub fn identity() -> Transform {
+        Transform {
+            translation: Vector3::zero(),
+            rotation: Quaternion::one(),
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        }
+    }
+
+    pub fn from_translation(translation: Vector3<f32>) -> Transform {
+        Transform {
+            translation,
+            ..Transform::identity()
+        }
+    }
+
+    pub fn to_matrix(&self) -> Matrix4<f32> {
+        Matrix4::from_translation(self.translation)
+            * Matrix4::from(self.rotation)
+            * Matrix4::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+    }
+
+    /// Splits an affine matrix back into TRS. Shear, which a hierarchy with non-uniform scale
+    /// can produce, is lost.
+    pub fn from_matrix(matrix: Matrix4<f32>) -> Transform {
+        let basis = Matrix3::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate());
+
+        let mut scale = Vector3::new(basis.x.magnitude(), basis.y.magnitude(), basis.z.magnitude());
+        if basis.determinant() < 0.0 {
+            scale.x = -scale.x;
+        }
+
+        let rotation = if scale.x == 0.0 || scale.y == 0.0 || scale.z == 0.0 {
+            Quaternion::one()
+        } else {
+            Quaternion::from(Matrix3::from_cols(basis.x / scale.x, basis.y / scale.y, basis.z / scale.z)).normalize()
+        };
+
+        Transform {
+            translation: matrix.w.truncate(),
+            rotation,
+            scale,
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Transform {
+        Transform::identity()
+    }
+}
+
+/// A handle to a scene node. Stale handles of removed nodes are rejected.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId {
+    index: u32,
+    generation: u32,
+}
+
+struct SceneNode {
+    name: String,
+    local: Transform,
+    parent: Option<NodeId>,
+    children: Vec<NodeId>,
+    mesh: Option<MeshId>,
+
+    /// Cached world matrix, valid while `is_dirty` is false. A dirty node's descendants are all dirty too.
+    world: Cell<Matrix4<f32>>,
+    is_dirty: Cell<bool>,
+}
+
+struct NodeSlot {
+    generation: u32,
+    node: Option<SceneNode>,
+}
+
+/// A hierarchy of nodes with local transforms. World matrices are computed on request and cached
+/// until the node or one of its ancestors changes.
+pub struct SceneGraph {
+    slots: Vec<NodeSlot>,
+    free_indices: Vec<u32>,
+    roots: Vec<NodeId>,
+}
+
+impl SceneGraph {
+    pub fn new() -> SceneGraph {
+        SceneGraph {
+            slots: vec![],
+            free_indices: vec![],
+            roots: vec![],
+        }
+    }
+
+    /// Adds a node under `parent`, or as a root. `local` is relative to the parent.
+    pub fn add_node(&mut self, name: &str, local: Transform, parent: Option<NodeId>) -> NodeId {
+        if let Some(parent) = parent {
+            assert!(self.contains(parent), "Failed to add node {} under a removed parent", name);
+        }
+
+        let node = SceneNode {
+            name: name.to_string(),
+            local,
+            parent,
+            children: vec![],
+            mesh: None,
+            world: Cell::new(Matrix4::identity()),
+            is_dirty: Cell::new(true),
+        };
+
+        let id = match self.free_indices.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.node = Some(node);
+
+                NodeId {
+                    index,
+                    generation: slot.generation,
+                }
+            },
+            None => {
+                self.slots.push(NodeSlot {
+                    generation: 0,
+                    node: Some(node),
+                });
+
+                NodeId {
+                    index: self.slots.len() as u32 - 1,
+                    generation: 0,
+                }
+            },
+        };
+
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+
+        id
+    }
+
+    /// Removes the node and everything under it.
+    pub fn remove_node(&mut self, id: NodeId) {
+        if !self.contains(id) {
+            return;
+        }
+
+        self.detach(id);
+
+        let mut stack = vec![id];
+        while let Some(id) = stack.pop() {
+            let slot = &mut self.slots[id.index as usize];
+            let node = slot.node.take().expect("Scene node is missing");
+            slot.generation = slot.generation.wrapping_add(1);
+            self.free_indices.push(id.index);
+
+            stack.extend(node.children);
+        }
+    }
+
+    pub fn contains(&self, id: NodeId) -> bool {
+        self.slots
+            .get(id.index as usize)
+            .is_some_and(|slot| slot.generation == id.generation && slot.node.is_some())
+    }
+
+    pub fn roots(&self) -> &[NodeId] {
+        &self.roots
+    }
+
+    pub fn name(&self, id: NodeId) -> &str {
+        &self.node(id).name
+    }
+
+    pub fn parent(&self, id: NodeId) -> Option<NodeId> {
+        self.node(id).parent
+    }
+
+    pub fn children(&self, id: NodeId) -> &[NodeId] {
+        &self.node(id).children
+    }
+
+    pub fn mesh(&self, id: NodeId) -> Option<MeshId> {
+        self.node(id).mesh
+    }
+
+    pub fn set_mesh(&mut self, id: NodeId, mesh: Option<MeshId>) {
+        self.node_mut(id).mesh = mesh;
+    }
+
+    pub fn local(&self, id: NodeId) -> &Transform {
+        &self.node(id).local
+    }
+
+    pub fn set_local(&mut self, id: NodeId, local: Transform) {
+        self.node_mut(id).local = local;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_translation(&mut self, id: NodeId, translation: Vector3<f32>) {
+        self.node_mut(id).local.translation = translation;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_rotation(&mut self, id: NodeId, rotation: Quaternion<f32>) {
+        self.node_mut(id).local.rotation = rotation;
+        self.mark_dirty(id);
+    }
+
+    pub fn set_scale(&mut self, id: NodeId, scale: Vector3<f32>) {
+        self.node_mut(id).local.scale = scale;
+        self.mark_dirty(id);
+    }
+
+    /// The node's transform relative to the scene root, recomputed only if it or an ancestor changed.
+    pub fn world_matrix(&self, id: NodeId) -> Matrix4<f32> {
+        let node = self.node(id);
+        if !node.is_dirty.get() {
+            return node.world.get();
+        }
+
+        let local = node.local.to_matrix();
+        let world = match node.parent {
+            Some(parent) => self.world_matrix(parent) * local,
+            None => local,
+        };
+
+        node.world.set(world);
+        node.is_dirty.set(false);
+        world
+    }
+
+    pub fn world_transform(&self, id: NodeId) -> Transform {
+        Transform::from_matrix(self.world_matrix(id))
+    }
+
+    /// Moves the node under `parent`, or to the roots, keeping where it is in the world.
+    pub fn set_parent(&mut self, id: NodeId, parent: Option<NodeId>) -> Result<(), String> {
+        if let Some(parent) = parent {
+            if !self.contains(parent) {
+                return Err(format!("Failed to reparent {}: the new parent was removed", self.name(id)));
+            }
+            if self.is_ancestor_or_self(id, parent) {
+                return Err(format!("Failed to reparent {} under its own descendant {}", self.name(id), self.name(parent)));
+            }
+        }
+
+        let world = self.world_matrix(id);
+        let parent_world = match parent {
+            Some(parent) => self.world_matrix(parent),
+            None => Matrix4::identity(),
+        };
+        let parent_inverse = parent_world.invert()
+            .ok_or_else(|| format!("Failed to reparent {}: the new parent has a degenerate transform", self.name(id)))?;
+
+        self.detach(id);
+        match parent {
+            Some(parent) => self.node_mut(parent).children.push(id),
+            None => self.roots.push(id),
+        }
+
+        let node = self.node_mut(id);
+        node.parent = parent;
+        node.local = Transform::from_matrix(parent_inverse * world);
+        self.mark_dirty(id);
+
+        Ok(())
+    }
+
+    /// Appends a draw for every node with a mesh, with the node's world matrix.
+    pub fn collect_mesh_draws(&self, draws: &mut Vec<MeshDraw>) {
+        let mut stack: Vec<NodeId> = self.roots.iter().rev().copied().collect();
+
+        while let Some(id) = stack.pop() {
+            let node = self.node(id);
+            if let Some(mesh) = node.mesh {
+                draws.push(MeshDraw {
+                    mesh,
+                    model: self.world_matrix(id),
+                });
+            }
+
+            stack.extend(node.children.iter().rev());
+        }
+    }
+
+    fn node(&self, id: NodeId) -> &SceneNode {
+        assert!(self.contains(id), "Scene node {:?} was removed", id);
+        self.slots[id.index as usize].node.as_ref().expect("Scene node is missing")
+    }
+
+    fn node_mut(&mut self, id: NodeId) -> &mut SceneNode {
+        assert!(self.contains(id), "Scene node {:?} was removed", id);
+        self.slots[id.index as usize].node.as_mut().expect("Scene node is missing")
+    }
+
+    /// Unlinks the node from its parent or from the roots.
+    fn detach(&mut self, id: NodeId) {
+        let siblings = match self.node(id).parent {
+            Some(parent) => &mut self.node_mut(parent).children,
+            None => &mut self.roots,
+        };
+        siblings.retain(|&sibling| sibling != id);
+    }
+
+    fn is_ancestor_or_self(&self, ancestor: NodeId, mut id: NodeId) -> bool {
+        loop {
+            if id == ancestor {
+                return true;
+            }
+
+            match self.node(id).parent {
+                Some(parent) => id = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn mark_dirty(&self, id: NodeId) {
+        let mut stack = vec![id];
+
+        while let Some(id) = stack.pop() {
+            let node = self.node(id);
+            // the descendants of a node that was dirty already are dirty too
+            if node.is_dirty.replace(true) {
+                continue;
+            }
+
+            stack.extend(node.children.iter());
+        }
+    }
+}
+
+impl Default for SceneGraph {
+    fn default() -> SceneGraph {
+        SceneGraph::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Rotation3};
+
+    use super::*;
+
+    fn assert_close(actual: Matrix4<f32>, expected: Matrix4<f32>) {
+        let (actual_columns, expected_columns): (&[[f32; 4]; 4], &[[f32; 4]; 4]) = (actual.as_ref(), expected.as_ref());
+        for (actual_column, expected_column) in actual_columns.iter().zip(expected_columns.iter()) {
+            for (a, e) in actual_column.iter().zip(expected_column.iter()) {
+                assert!((a - e).abs() < 1e-5, "{:?} is not close to {:?}", actual, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn world_transforms_propagate_to_descendants() {
+        let mut scene = SceneGraph::new();
+        let root = scene.add_node("root", Transform {
+            translation: Vector3::new(10.0, 0.0, 0.0),
+            rotation: Quaternion::from_angle_z(Deg(90.0)),
+            scale: Vector3::new(2.0, 2.0, 2.0),
+        }, None);
+        let child = scene.add_node("child", Transform::from_translation(Vector3::new(1.0, 0.0, 0.0)), Some(root));
+        let grandchild = scene.add_node("grandchild", Transform::from_translation(Vector3::new(0.0, 0.0, 1.0)), Some(child));
+
+        // scaled by 2, then turned from +X to +Y
+        assert_close(scene.world_matrix(child), scene.world_matrix(root) * Matrix4::from_translation(Vector3::new(1.0, 0.0, 0.0)));
+        assert!((scene.world_transform(child).translation - Vector3::new(10.0, 2.0, 0.0)).magnitude() < 1e-5);
+        assert!((scene.world_transform(grandchild).translation - Vector3::new(10.0, 2.0, 2.0)).magnitude() < 1e-5);
+
+        // the cached matrices of the subtree are refreshed
+        scene.set_translation(root, Vector3::zero());
+        assert!((scene.world_transform(grandchild).translation - Vector3::new(0.0, 2.0, 2.0)).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn reparenting_keeps_the_world_transform() {
+        let mut scene = SceneGraph::new();
+        let a = scene.add_node("a", Transform::from_translation(Vector3::new(5.0, 0.0, 0.0)), None);
+        let b = scene.add_node("b", Transform {
+            rotation: Quaternion::from_angle_y(Deg(45.0)),
+            ..Transform::from_translation(Vector3::new(0.0, 3.0, 0.0))
+        }, None);
+        let child = scene.add_node("child", Transform::from_translation(Vector3::new(1.0, 1.0, 1.0)), Some(a));
+        let world = scene.world_matrix(child);
+
+        scene.set_parent(child, Some(b)).unwrap();
+        assert_eq!(scene.parent(child), Some(b));
+        assert!(scene.children(a).is_empty());
+        assert_eq!(scene.children(b), &[child]);
+        assert_close(scene.world_matrix(child), world);
+
+        scene.set_parent(child, None).unwrap();
+        assert_eq!(scene.roots(), &[a, b, child]);
+        assert_close(scene.local(child).to_matrix(), world);
+    }
+
+    #[test]
+    fn reparenting_under_a_descendant_is_rejected() {
+        let mut scene = SceneGraph::new();
+        let root = scene.add_node("root", Transform::identity(), None);
+        let child = scene.add_node("child", Transform::identity(), Some(root));
+        let grandchild = scene.add_node("grandchild", Transform::identity(), Some(child));
+
+        assert!(scene.set_parent(root, Some(grandchild)).is_err());
+        assert!(scene.set_parent(child, Some(child)).is_err());
+        assert_eq!(scene.parent(root), None);
+        assert_eq!(scene.children(child), &[grandchild]);
+    }
+
+    #[test]
+    fn removing_a_node_removes_its_subtree() {
+        let mut scene = SceneGraph::new();
+        let root = scene.add_node("root", Transform::identity(), None);
+        let child = scene.add_node("child", Transform::identity(), Some(root));
+        let grandchild = scene.add_node("grandchild", Transform::identity(), Some(child));
+        let sibling = scene.add_node("sibling", Transform::identity(), Some(root));
+
+        scene.remove_node(child);
+        assert!(!scene.contains(child) && !scene.contains(grandchild));
+        assert_eq!(scene.children(root), &[sibling]);
+
+        // the freed slots are reused with new generations, so stale handles stay stale
+        let reused = scene.add_node("reused", Transform::identity(), None);
+        assert!(scene.contains(reused));
+        assert!(!scene.contains(child) && !scene.contains(grandchild));
+    }
+}
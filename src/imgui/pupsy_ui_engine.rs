@@ -5,11 +5,22 @@ use imgui_winit_support::{HiDpiMode, WinitPlatform};
 use crate::imgui::constants as imgui_constants;
 
 use ash::vk;
+use winit::event::Event;
+
+use crate::vk::gpu_profiler;
+use crate::vk::render_device::VkRenderDevice;
+
+use std::collections::BTreeMap;
 
 pub struct PupsyUiEngine {
     pub imgui:  imgui::Context,
     pub imgui_platform: WinitPlatform,
 
+    /// last error of every pipeline whose shaders currently fail to build
+    pub shader_errors: BTreeMap<String, String>,
+    /// averaged GPU time of every profiled scope, in milliseconds
+    pub gpu_timings: Vec<(String, f64)>,
+
     dokdo: FontId,
     roboto: FontId,
 }
@@ -32,22 +43,39 @@ impl PupsyUiEngine {
         }]);
 
         PupsyUiEngine{
-            imgui: imgui,
+            imgui,
             imgui_platform: platform,
-            dokdo: dokdo,
-            roboto: roboto
+            shader_errors: BTreeMap::new(),
+            gpu_timings: vec![],
+            dokdo,
+            roboto
         }
     }
 
-    pub fn render(
-        &mut self, window: &Window,
-        rhi_cmd : &vk::CommandBuffer) {
+    /// Uploads the font atlas the UI is drawn with.
+    pub fn upload_fonts(&mut self, render_device: &mut VkRenderDevice) {
+        let mut fonts = self.imgui.fonts();
+        let atlas = fonts.build_rgba32_texture();
+        let extent = vk::Extent2D {
+            width: atlas.width,
+            height: atlas.height,
+        };
+
+        render_device.set_ui_fonts(extent, atlas.data)
+            .expect("Failed to upload imgui fonts!");
+    }
 
-        return;
+    /// Passes window input on to imgui.
+    pub fn handle_event<T>(&mut self, window: &Window, event: &Event<T>) {
+        self.imgui_platform.handle_event(self.imgui.io_mut(), &window.window, event);
+    }
 
-        self.imgui_platform.prepare_frame(self.imgui.io_mut(), &window.window);
+    /// Builds the frame's UI. `app_ui` adds the application's own windows to it.
+    pub fn render<F: FnOnce(&Ui)>(&mut self, window: &Window, app_ui: F) -> &DrawData {
+        self.imgui_platform.prepare_frame(self.imgui.io_mut(), &window.window)
+            .expect("Failed to prepare imgui frame!");
 
-        let mut ui = self.imgui.frame();
+        let ui = self.imgui.frame();
 
         let mut run = false;
 
@@ -63,14 +91,29 @@ impl PupsyUiEngine {
             ui.text("Hello, I'm the default font again!");
         });
 
-        self.imgui_platform.prepare_render(&ui, &window.window);
-
-        let ui_draw_data = ui.render();
+        if !self.shader_errors.is_empty() {
+            imgui::Window::new("Shader errors").build(&ui, || {
+                for (pipeline, error) in self.shader_errors.iter() {
+                    ui.text_colored([1.0, 0.3, 0.3, 1.0], pipeline);
+                    ui.text_wrapped(error);
+                    ui.separator();
+                }
+            });
+        }
 
-        
+        app_ui(&ui);
 
-        if !run {
-            return;
+        if !self.gpu_timings.is_empty() {
+            imgui::Window::new("GPU timings").build(&ui, || {
+                ui.text_disabled(format!("averaged over {} frames", gpu_profiler::AVERAGE_FRAME_COUNT));
+                for (scope, milliseconds) in self.gpu_timings.iter() {
+                    ui.text(format!("{}: {:.3} ms", scope, milliseconds));
+                }
+            });
         }
+
+        self.imgui_platform.prepare_render(&ui, &window.window);
+
+        ui.render()
     }
 }
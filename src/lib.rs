@@ -1,3 +1,9 @@
+// lets `#[derive(Vertex)]` name this crate as `::pupsy_engine` inside it too
+extern crate self as pupsy_engine;
+
+/// The Vulkan bindings the engine's types are made of, for derived code and apps.
+pub use ash;
+
 pub mod utility;
 pub mod vk;
 pub mod rhi;
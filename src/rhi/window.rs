@@ -1,8 +1,4 @@
-use imgui::Context;
-use imgui_winit_support::{HiDpiMode, WinitPlatform};
-
-use winit::event::{Event, VirtualKeyCode, ElementState, KeyboardInput, WindowEvent};
-use winit::event_loop::{EventLoop, ControlFlow};
+use winit::event_loop::EventLoop;
 
 use crate::utility::constants;
 
@@ -12,14 +8,18 @@ pub struct Window {
 
 impl Window {
     pub fn new(event_loop: &EventLoop<()>) -> Window {
+        Window::with_size(event_loop, constants::WINDOW_TITLE, constants::WINDOW_WIDTH, constants::WINDOW_HEIGHT)
+    }
+
+    pub fn with_size(event_loop: &EventLoop<()>, title: &str, width: u32, height: u32) -> Window {
         let window = winit::window::WindowBuilder::new()
-            .with_title(constants::WINDOW_TITLE)
-            .with_inner_size(winit::dpi::LogicalSize::new(constants::WINDOW_WIDTH, constants::WINDOW_HEIGHT))
+            .with_title(title)
+            .with_inner_size(winit::dpi::LogicalSize::new(width, height))
             .build(event_loop)
             .expect("Failed to create window.");
 
         Window{
-            window: window
+            window
         }
     }
 
@@ -6,7 +6,24 @@ pub const ENGINE_VERSION: u32 = vk::make_api_version(0, 1, 0, 0);
 pub const WINDOW_WIDTH: u32 = 800;
 pub const WINDOW_HEIGHT: u32 = 600;
 
-pub const WINDOW_TITLE: &'static str = "Pupsy Window";
-pub const ENGINE_TITLE: &'static str = "Pupsy Engine";
+pub const WINDOW_TITLE: &str = "Pupsy Window";
+pub const ENGINE_TITLE: &str = "Pupsy Engine";
 
-pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
\ No newline at end of file
+pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+pub const FIXED_UPDATES_PER_SECOND: f32 = 60.0;
+pub const MAX_FIXED_UPDATES_PER_FRAME: u32 = 8;
+
+/// Frame rate cap, `None` to run unlimited.
+pub const TARGET_FPS: Option<f32> = None;
+
+pub const SHADER_SOURCE_DIR: &str = "shaders/src";
+pub const SHADER_SPV_DIR: &str = "shaders/spv";
+/// SPIR-V checked in with the engine, used when the `shader-compiler` feature is off
+pub const SHADER_PREBUILT_DIR: &str = "shaders/prebuilt";
+pub const SHADER_POLL_INTERVAL_MS: u64 = 250;
+
+pub const GPU_TRACE_PATH: &str = "gpu_trace.json";
+pub const CPU_TRACE_PATH: &str = "cpu_trace.json";
+
+/// Saved input bindings, applied over the defaults on startup.
+pub const INPUT_CONFIG_PATH: &str = "input.cfg";
@@ -2,7 +2,6 @@ use ash::vk;
 
 use std::ffi::CStr;
 use std::os::raw::c_void;
-use std::ptr;
 
 pub struct ValidationInfo {
     pub is_enable: bool,
@@ -10,6 +9,10 @@ pub struct ValidationInfo {
 }
 
 /// the callback function used in Debug Utils.
+///
+/// # Safety
+///
+/// Only for Vulkan to call, with valid callback data.
 pub unsafe extern "system"  fn vulkan_debug_utils_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
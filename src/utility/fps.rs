@@ -1,29 +1,208 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Frames the statistics are taken over.
+const FRAME_TIME_WINDOW: usize = 1000;
+
+const HISTOGRAM_BUCKET_MS: f32 = 2.0;
+const HISTOGRAM_BUCKET_COUNT: usize = 25;
+
+/// The limiter sleeps until this close to the deadline and spins the rest, since sleeps overshoot.
+const LIMITER_SPIN_MARGIN: Duration = Duration::from_millis(2);
+
+/// Frame times bucketed by `bucket_width_ms`. The last bucket also counts everything slower.
+#[derive(Clone, Debug)]
+pub struct FrameTimeHistogram {
+    pub bucket_width_ms: f32,
+    pub counts: Vec<u32>,
+}
+
+#[derive(Clone, Debug)]
+pub struct FrameStats {
+    pub frame_count: usize,
+    pub average_ms: f32,
+    pub min_ms: f32,
+    pub max_ms: f32,
+    pub average_fps: f32,
+    /// fps of the 99th percentile frame time
+    pub low_1_percent_fps: f32,
+    /// fps of the 99.9th percentile frame time
+    pub low_0_1_percent_fps: f32,
+    pub histogram: FrameTimeHistogram,
+}
+
 pub struct FPSManager {
-    pub delta_time: u128,
-    fps: f32,
+    pub delta_time: Duration,
 
-    last_time: u128,
+    last_frame: Option<Instant>,
+    frame_times_ms: VecDeque<f32>,
+    target_frame_time: Option<Duration>,
 }
 
 impl FPSManager {
     pub fn new() -> FPSManager {
         FPSManager {
-            delta_time: 0u128,
-            fps: 0f32,
-            last_time: 0u128
+            delta_time: Duration::ZERO,
+            last_frame: None,
+            frame_times_ms: VecDeque::with_capacity(FRAME_TIME_WINDOW),
+            target_frame_time: None,
         }
     }
 
-    pub fn update(&mut self, new_time: u128)
-    {
-        let dt = new_time - self.last_time;
-        self.delta_time = dt;
-        if dt != 0u128
-        {
-            self.fps = 1f32 / (dt as f32) * 1000000f32;
-            self.last_time = new_time;
+    /// Caps the frame rate in `update`. `None` or a non-positive rate turns the limiter off.
+    pub fn set_target_fps(&mut self, target_fps: Option<f32>) {
+        self.target_frame_time = target_fps
+            .filter(|&fps| fps > 0.0)
+            .map(|fps| Duration::from_secs_f64(1.0 / fps as f64));
+    }
+
+    pub fn target_fps(&self) -> Option<f32> {
+        self.target_frame_time.map(|frame_time| 1.0 / frame_time.as_secs_f32())
+    }
+
+    /// Ends the frame: waits out the rest of the target frame time if the limiter is on,
+    /// then measures the time since the previous call.
+    pub fn update(&mut self) {
+        if let (Some(last_frame), Some(target_frame_time)) = (self.last_frame, self.target_frame_time) {
+            FPSManager::wait_until(last_frame + target_frame_time);
+        }
+
+        let now = Instant::now();
+        if let Some(last_frame) = self.last_frame {
+            self.record_frame_time(now - last_frame);
         }
+        self.last_frame = Some(now);
+    }
+
+    /// Adds a frame that took `delta_time` to the statistics. `update` calls it with the measured time.
+    pub fn record_frame_time(&mut self, delta_time: Duration) {
+        self.delta_time = delta_time;
+
+        if self.frame_times_ms.len() == FRAME_TIME_WINDOW {
+            self.frame_times_ms.pop_front();
+        }
+        self.frame_times_ms.push_back(delta_time.as_secs_f32() * 1000.0);
+    }
+
+    /// Statistics over the last frames, `None` before the second `update`.
+    pub fn stats(&self) -> Option<FrameStats> {
+        if self.frame_times_ms.is_empty() {
+            return None;
+        }
+
+        let mut sorted_ms: Vec<f32> = self.frame_times_ms.iter().copied().collect();
+        sorted_ms.sort_by(|a, b| a.total_cmp(b));
+
+        let frame_count = sorted_ms.len();
+        let average_ms = sorted_ms.iter().sum::<f32>() / frame_count as f32;
+        let percentile_ms = |percentile: f32| {
+            let index = ((frame_count as f32 * percentile).ceil() as usize).clamp(1, frame_count) - 1;
+            sorted_ms[index]
+        };
+        let to_fps = |ms: f32| if ms > 0.0 { 1000.0 / ms } else { 0.0 };
+
+        let mut counts = vec![0; HISTOGRAM_BUCKET_COUNT];
+        for &frame_time_ms in sorted_ms.iter() {
+            let bucket = ((frame_time_ms / HISTOGRAM_BUCKET_MS) as usize).min(HISTOGRAM_BUCKET_COUNT - 1);
+            counts[bucket] += 1;
+        }
+
+        Some(FrameStats {
+            frame_count,
+            average_ms,
+            min_ms: sorted_ms[0],
+            max_ms: sorted_ms[frame_count - 1],
+            average_fps: to_fps(average_ms),
+            low_1_percent_fps: to_fps(percentile_ms(0.99)),
+            low_0_1_percent_fps: to_fps(percentile_ms(0.999)),
+            histogram: FrameTimeHistogram {
+                bucket_width_ms: HISTOGRAM_BUCKET_MS,
+                counts,
+            },
+        })
+    }
 
-        //println!("FPS: {}", self.fps);
-    } 
-}
\ No newline at end of file
+    fn wait_until(deadline: Instant) {
+        let now = Instant::now();
+        if deadline <= now {
+            return;
+        }
+
+        let remaining = deadline - now;
+        if remaining > LIMITER_SPIN_MARGIN {
+            std::thread::sleep(remaining - LIMITER_SPIN_MARGIN);
+        }
+
+        while Instant::now() < deadline {
+            std::hint::spin_loop();
+        }
+    }
+}
+
+impl Default for FPSManager {
+    fn default() -> FPSManager {
+        FPSManager::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record_frames(fps_manager: &mut FPSManager, count: usize, frame_time_ms: u64) {
+        for _ in 0..count {
+            fps_manager.record_frame_time(Duration::from_millis(frame_time_ms));
+        }
+    }
+
+    #[test]
+    fn no_stats_before_the_first_frame() {
+        assert!(FPSManager::new().stats().is_none());
+    }
+
+    #[test]
+    fn stats_cover_average_extremes_and_lows() {
+        let mut fps_manager = FPSManager::new();
+        record_frames(&mut fps_manager, 196, 10);
+        record_frames(&mut fps_manager, 3, 20);
+        record_frames(&mut fps_manager, 1, 40);
+
+        let stats = fps_manager.stats().unwrap();
+        assert_eq!(stats.frame_count, 200);
+        assert!((stats.average_ms - 10.3).abs() < 1e-3);
+        assert!((stats.average_fps - 1000.0 / 10.3).abs() < 1e-2);
+        assert_eq!(stats.min_ms, 10.0);
+        assert_eq!(stats.max_ms, 40.0);
+        // the 198th and 200th slowest of 200 frames
+        assert_eq!(stats.low_1_percent_fps, 50.0);
+        assert_eq!(stats.low_0_1_percent_fps, 25.0);
+    }
+
+    #[test]
+    fn histogram_buckets_frame_times_and_clamps_slow_frames() {
+        let mut fps_manager = FPSManager::new();
+        record_frames(&mut fps_manager, 5, 1);
+        record_frames(&mut fps_manager, 3, 7);
+        record_frames(&mut fps_manager, 2, 500);
+
+        let histogram = fps_manager.stats().unwrap().histogram;
+        assert_eq!(histogram.bucket_width_ms, HISTOGRAM_BUCKET_MS);
+        assert_eq!(histogram.counts.len(), HISTOGRAM_BUCKET_COUNT);
+        assert_eq!(histogram.counts[0], 5);
+        assert_eq!(histogram.counts[3], 3);
+        assert_eq!(histogram.counts[HISTOGRAM_BUCKET_COUNT - 1], 2);
+        assert_eq!(histogram.counts.iter().sum::<u32>(), 10);
+    }
+
+    #[test]
+    fn only_the_latest_frames_are_kept() {
+        let mut fps_manager = FPSManager::new();
+        record_frames(&mut fps_manager, 1, 100);
+        record_frames(&mut fps_manager, FRAME_TIME_WINDOW, 10);
+
+        let stats = fps_manager.stats().unwrap();
+        assert_eq!(stats.frame_count, FRAME_TIME_WINDOW);
+        assert_eq!(stats.max_ms, 10.0);
+        assert_eq!(fps_manager.delta_time, Duration::from_millis(10));
+    }
+}
@@ -0,0 +1,616 @@
+use std::fs;
+use std::path::Path;
+
+use ash::vk;
+use cgmath::{EuclideanSpace, Point3, Quaternion, Rad, Vector3};
+use gltf::image::Format;
+use gltf::mesh::Mode;
+use gltf::texture::{MagFilter, MinFilter, WrappingMode};
+
+use crate::core::camera::{Camera, Projection};
+use crate::core::scene::{NodeId, SceneGraph, Transform};
+use crate::vk::material::{AlphaMode, MaterialTexture, PbrMaterial};
+use crate::vk::mesh::{MaterialSubmesh, MeshData, Submesh};
+use crate::vk::render_device::{MaterialId, MeshId, TextureId, VkRenderDevice};
+use crate::vk::texture::SamplerDesc;
+use crate::vk::vertex::MeshVertex;
+
+/// Extensions whose data ends up in the imported model. Any other one is reported in `warnings`.
+const SUPPORTED_EXTENSIONS: &[&str] = &["KHR_materials_emissive_strength"];
+
+/// Used for perspective cameras without a far plane, which glTF allows.
+const INFINITE_CAMERA_FAR: f32 = 10000.0;
+
+/// A texture of a material, before the textures exist on the device.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GltfTextureRef {
+    /// index into `GltfModel::textures`
+    pub texture: usize,
+    pub tex_coord: u32,
+}
+
+/// A decoded image, converted to tightly packed RGBA8.
+#[derive(Clone, Debug)]
+pub struct GltfImage {
+    pub name: String,
+    pub extent: vk::Extent2D,
+    pub pixels: Vec<u8>,
+    /// whether a material samples it as colour, so it is stored as sRGB
+    pub srgb: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GltfTexture {
+    /// index into `GltfModel::images`
+    pub image: usize,
+    pub sampler: SamplerDesc,
+}
+
+#[derive(Clone, Debug)]
+pub struct GltfNode {
+    pub name: String,
+    pub transform: Transform,
+    /// index into `GltfModel::meshes`
+    pub mesh: Option<usize>,
+    /// index into `GltfModel::cameras`
+    pub camera: Option<usize>,
+    pub children: Vec<usize>,
+}
+
+/// A glTF 2.0 scene read into memory without touching the GPU, indexed as in the file.
+///
+/// POSITION, NORMAL and TEXCOORD_0 fill `MeshVertex`, other attributes are dropped. Primitives
+/// without normals get flat ones, as the specification asks. Nodes keep glTF's Y up axes.
+#[derive(Clone, Debug)]
+pub struct GltfModel {
+    /// primitives become the submeshes, with materials indexing `materials`
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<PbrMaterial<GltfTextureRef>>,
+    pub textures: Vec<GltfTexture>,
+    pub images: Vec<GltfImage>,
+    pub cameras: Vec<Projection>,
+    pub nodes: Vec<GltfNode>,
+    /// the nodes of the default scene, or the first one if none is marked
+    pub roots: Vec<usize>,
+    /// what was imported only in part or skipped, such as unsupported extensions
+    pub warnings: Vec<String>,
+}
+
+/// What `GltfModel::instantiate` created, indexed like the model.
+pub struct GltfInstance {
+    /// `None` for meshes without a triangle primitive
+    pub meshes: Vec<Option<MeshId>>,
+    pub textures: Vec<TextureId>,
+    pub materials: Vec<PbrMaterial>,
+    /// the device side of `materials`, as the meshes are drawn with them
+    pub material_ids: Vec<MaterialId>,
+    /// `None` for nodes outside the imported scene
+    pub nodes: Vec<Option<NodeId>>,
+    /// each camera node with a camera placed at its world transform
+    pub cameras: Vec<(NodeId, Camera)>,
+}
+
+impl GltfModel {
+    /// Reads a `.gltf` or `.glb` file. External buffers and images are looked up next to it.
+    pub fn load(path: &Path) -> Result<GltfModel, String> {
+        let bytes = fs::read(path)
+            .map_err(|error| format!("Failed to read glTF model from {}: {}", path.display(), error))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        GltfModel::from_slice(&bytes, directory).map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Reads glTF JSON or GLB `bytes`, resolving relative URIs against `directory`.
+    pub fn from_slice(bytes: &[u8], directory: &Path) -> Result<GltfModel, String> {
+        let gltf::Gltf { document, blob } = gltf::Gltf::from_slice_without_validation(bytes)
+            .map_err(|error| format!("Failed to parse glTF: {}", error))?;
+
+        let mut warnings = vec![];
+
+        // extensions are only warned about, so the ones the crate doesn't know may not fail validation
+        let mut json = document.into_json();
+        let required_only = json.extensions_required.iter().filter(|extension| !json.extensions_used.contains(extension));
+        for extension in json.extensions_used.iter().chain(required_only) {
+            if !SUPPORTED_EXTENSIONS.contains(&extension.as_str()) {
+                let required = json.extensions_required.contains(extension);
+                warnings.push(format!(
+                    "Unsupported extension {}{}",
+                    extension,
+                    if required { " is required, the model may look wrong" } else { " is ignored" }));
+            }
+        }
+        json.extensions_required.clear();
+
+        let document = gltf::Document::from_json(json)
+            .map_err(|error| format!("Invalid glTF: {}", error))?;
+        let buffers = gltf::import_buffers(&document, Some(directory), blob)
+            .map_err(|error| format!("Failed to load glTF buffers: {}", error))?;
+        let image_data = gltf::import_images(&document, Some(directory), &buffers)
+            .map_err(|error| format!("Failed to load glTF images: {}", error))?;
+
+        if document.animations().next().is_some() || document.skins().next().is_some() {
+            warnings.push(String::from("Animations and skins are not imported"));
+        }
+
+        let mut meshes = vec![];
+        for mesh in document.meshes() {
+            meshes.push(import_mesh(&mesh, &buffers, &mut warnings)?);
+        }
+
+        let materials: Vec<_> = document.materials().map(|material| import_material(&material)).collect();
+
+        let textures: Vec<_> = document.textures()
+            .map(|texture| GltfTexture {
+                image: texture.source().index(),
+                sampler: import_sampler(&texture.sampler()),
+            })
+            .collect();
+
+        let mut images = vec![];
+        for (image, data) in document.images().zip(image_data) {
+            images.push(GltfImage {
+                name: image.name().map_or_else(|| format!("image {}", image.index()), String::from),
+                extent: vk::Extent2D {
+                    width: data.width,
+                    height: data.height,
+                },
+                pixels: to_rgba8(&data),
+                srgb: false,
+            });
+        }
+        for material in materials.iter() {
+            for color_texture in [material.base_color_texture, material.emissive_texture].iter().flatten() {
+                images[textures[color_texture.texture].image].srgb = true;
+            }
+        }
+
+        let cameras = document.cameras().map(|camera| import_camera(&camera)).collect();
+
+        let nodes = document.nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                GltfNode {
+                    name: node.name().map_or_else(|| format!("node {}", node.index()), String::from),
+                    transform: Transform {
+                        translation: Vector3::from(translation),
+                        rotation: Quaternion::new(rotation[3], rotation[0], rotation[1], rotation[2]),
+                        scale: Vector3::from(scale),
+                    },
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    camera: node.camera().map(|camera| camera.index()),
+                    children: node.children().map(|child| child.index()).collect(),
+                }
+            })
+            .collect();
+
+        let roots = document.default_scene()
+            .or_else(|| document.scenes().next())
+            .map_or_else(Vec::new, |scene| scene.nodes().map(|node| node.index()).collect());
+
+        let model = GltfModel {
+            meshes,
+            materials,
+            textures,
+            images,
+            cameras,
+            nodes,
+            roots,
+            warnings,
+        };
+        model.check_hierarchy()?;
+        Ok(model)
+    }
+
+    /// The scene has to be a forest: no node may be reached twice from the roots.
+    fn check_hierarchy(&self) -> Result<(), String> {
+        let mut visited = vec![false; self.nodes.len()];
+        let mut stack = self.roots.clone();
+
+        while let Some(index) = stack.pop() {
+            if visited[index] {
+                return Err(format!("Node `{}` has more than one parent or is part of a cycle", self.nodes[index].name));
+            }
+            visited[index] = true;
+            stack.extend_from_slice(&self.nodes[index].children);
+        }
+        Ok(())
+    }
+
+    /// Creates the textures, samplers, materials and meshes on `render_device` and adds the
+    /// scene's nodes to `scene`, under `parent` if given.
+    pub fn instantiate(
+        &self,
+        render_device: &mut VkRenderDevice,
+        scene: &mut SceneGraph,
+        parent: Option<NodeId>,
+    ) -> Result<GltfInstance, String> {
+        let mut textures = vec![];
+        for image in self.images.iter() {
+            let id = render_device.create_texture(image.extent, &image.pixels, image.srgb)
+                .map_err(|error| format!("{}: {}", image.name, error))?;
+            textures.push(id);
+        }
+
+        let material_textures: Vec<_> = self.textures.iter()
+            .map(|texture| (textures[texture.image], render_device.create_sampler(&texture.sampler)))
+            .collect();
+        let materials: Vec<_> = self.materials.iter()
+            .map(|material| material.map_textures(|reference| {
+                let (texture, sampler) = material_textures[reference.texture];
+                MaterialTexture {
+                    texture,
+                    sampler,
+                    tex_coord: reference.tex_coord,
+                }
+            }))
+            .collect();
+
+        let mut material_ids = vec![];
+        for material in materials.iter() {
+            material_ids.push(render_device.create_material(material)?);
+        }
+
+        let mut meshes = vec![];
+        for mesh in self.meshes.iter() {
+            let id = if mesh.indices.is_empty() {
+                None
+            } else {
+                Some(render_device.create_mesh_data(mesh, &material_ids)?)
+            };
+            meshes.push(id);
+        }
+
+        let mut nodes = vec![None; self.nodes.len()];
+        let mut cameras = vec![];
+        let mut stack: Vec<(usize, Option<NodeId>)> = self.roots.iter().rev().map(|&root| (root, parent)).collect();
+
+        while let Some((index, parent)) = stack.pop() {
+            let node = &self.nodes[index];
+            let id = scene.add_node(&node.name, node.transform, parent);
+            scene.set_mesh(id, node.mesh.and_then(|mesh| meshes[mesh]));
+            nodes[index] = Some(id);
+
+            if let Some(camera) = node.camera {
+                let world = scene.world_transform(id);
+                let mut camera = Camera::new(self.cameras[camera]);
+                camera.position = Point3::from_vec(world.translation);
+                camera.rotation = world.rotation;
+                cameras.push((id, camera));
+            }
+
+            stack.extend(node.children.iter().rev().map(|&child| (child, Some(id))));
+        }
+
+        Ok(GltfInstance {
+            meshes,
+            textures,
+            materials,
+            material_ids,
+            nodes,
+            cameras,
+        })
+    }
+}
+
+fn import_mesh(mesh: &gltf::Mesh, buffers: &[gltf::buffer::Data], warnings: &mut Vec<String>) -> Result<MeshData, String> {
+    let name = mesh.name().map_or_else(|| format!("mesh {}", mesh.index()), String::from);
+    let mut data = MeshData {
+        name,
+        ..MeshData::default()
+    };
+
+    for primitive in mesh.primitives() {
+        let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+        let primitive_name = format!("{} primitive {}", data.name, primitive.index());
+
+        let positions: Vec<[f32; 3]> = match reader.read_positions() {
+            Some(positions) => positions.collect(),
+            None => {
+                warnings.push(format!("Skipped {}: it has no positions", primitive_name));
+                continue;
+            },
+        };
+        let mut indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..positions.len() as u32).collect(),
+        };
+        if let Some(&index) = indices.iter().find(|&&index| index as usize >= positions.len()) {
+            return Err(format!("{} uses vertex {} of its {}", primitive_name, index, positions.len()));
+        }
+
+        indices = match primitive.mode() {
+            Mode::Triangles => indices,
+            Mode::TriangleStrip => strip_to_list(&indices),
+            Mode::TriangleFan => fan_to_list(&indices),
+            mode => {
+                warnings.push(format!("Skipped {}: {:?} are not drawn", primitive_name, mode));
+                continue;
+            },
+        };
+        indices.truncate(indices.len() - indices.len() % 3);
+        if indices.is_empty() {
+            continue;
+        }
+
+        // the file is read without validation, so attributes may disagree on the vertex count
+        let uvs: Option<Vec<[f32; 2]>> = reader.read_tex_coords(0).map(|uvs| uvs.into_f32().collect());
+        let normals: Option<Vec<[f32; 3]>> = reader.read_normals().map(|normals| normals.collect());
+        for (attribute, count) in [("TEXCOORD_0", uvs.as_ref().map(Vec::len)), ("NORMAL", normals.as_ref().map(Vec::len))] {
+            if let Some(count) = count.filter(|&count| count != positions.len()) {
+                return Err(format!(
+                    "{} has {} {} values for its {} positions", primitive_name, count, attribute, positions.len()));
+            }
+        }
+        let uv = |vertex: usize| uvs.as_ref().map_or([0.0; 2], |uvs| uvs[vertex]);
+
+        let mut vertices = vec![];
+        match normals {
+            Some(normals) => {
+                for (vertex, normal) in normals.into_iter().enumerate() {
+                    vertices.push(MeshVertex {
+                        position: positions[vertex],
+                        normal,
+                        uv: uv(vertex),
+                    });
+                }
+            },
+            None => {
+                // flat normals need a vertex per triangle corner
+                for triangle in indices.chunks(3) {
+                    let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+                    let normal = flat_normal(a, b, c);
+                    for &vertex in triangle {
+                        vertices.push(MeshVertex {
+                            position: positions[vertex as usize],
+                            normal,
+                            uv: uv(vertex as usize),
+                        });
+                    }
+                }
+                indices = (0..vertices.len() as u32).collect();
+            },
+        }
+
+        data.submeshes.push(MaterialSubmesh {
+            submesh: Submesh {
+                first_index: data.indices.len() as u32,
+                index_count: indices.len() as u32,
+                vertex_offset: data.vertices.len() as i32,
+            },
+            material: primitive.material().index(),
+        });
+        data.vertices.extend_from_slice(&vertices);
+        data.indices.extend_from_slice(&indices);
+    }
+
+    Ok(data)
+}
+
+fn strip_to_list(strip: &[u32]) -> Vec<u32> {
+    let mut list = vec![];
+    for i in 0..strip.len().saturating_sub(2) {
+        // every other triangle is flipped to keep the winding
+        if i % 2 == 0 {
+            list.extend_from_slice(&[strip[i], strip[i + 1], strip[i + 2]]);
+        } else {
+            list.extend_from_slice(&[strip[i + 1], strip[i], strip[i + 2]]);
+        }
+    }
+    list
+}
+
+fn fan_to_list(fan: &[u32]) -> Vec<u32> {
+    let mut list = vec![];
+    for i in 1..fan.len().saturating_sub(1) {
+        list.extend_from_slice(&[fan[0], fan[i], fan[i + 1]]);
+    }
+    list
+}
+
+fn flat_normal(a: [f32; 3], b: [f32; 3], c: [f32; 3]) -> [f32; 3] {
+    let normal = (Vector3::from(b) - Vector3::from(a)).cross(Vector3::from(c) - Vector3::from(a));
+    let length = (normal.x * normal.x + normal.y * normal.y + normal.z * normal.z).sqrt();
+
+    if length > f32::EPSILON {
+        (normal / length).into()
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+fn import_material(material: &gltf::Material) -> PbrMaterial<GltfTextureRef> {
+    let pbr = material.pbr_metallic_roughness();
+    let texture_ref = |info: gltf::texture::Info| GltfTextureRef {
+        texture: info.texture().index(),
+        tex_coord: info.tex_coord(),
+    };
+    let emissive_strength = material.emissive_strength().unwrap_or(1.0);
+
+    PbrMaterial {
+        name: material.name().map_or_else(
+            || material.index().map_or_else(|| String::from("default"), |index| format!("material {}", index)),
+            String::from),
+        base_color_factor: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(texture_ref),
+        metallic_factor: pbr.metallic_factor(),
+        roughness_factor: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr.metallic_roughness_texture().map(texture_ref),
+        normal_texture: material.normal_texture().map(|normal| GltfTextureRef {
+            texture: normal.texture().index(),
+            tex_coord: normal.tex_coord(),
+        }),
+        normal_scale: material.normal_texture().map_or(1.0, |normal| normal.scale()),
+        occlusion_texture: material.occlusion_texture().map(|occlusion| GltfTextureRef {
+            texture: occlusion.texture().index(),
+            tex_coord: occlusion.tex_coord(),
+        }),
+        occlusion_strength: material.occlusion_texture().map_or(1.0, |occlusion| occlusion.strength()),
+        emissive_factor: material.emissive_factor().map(|component| component * emissive_strength),
+        emissive_texture: material.emissive_texture().map(texture_ref),
+        alpha_mode: match material.alpha_mode() {
+            gltf::material::AlphaMode::Opaque => AlphaMode::Opaque,
+            gltf::material::AlphaMode::Mask => AlphaMode::Mask,
+            gltf::material::AlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+    }
+}
+
+fn import_sampler(sampler: &gltf::texture::Sampler) -> SamplerDesc {
+    let address_mode = |wrapping: WrappingMode| match wrapping {
+        WrappingMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => vk::SamplerAddressMode::REPEAT,
+    };
+    let (min_filter, mipmap_mode) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) | Some(MinFilter::NearestMipmapNearest) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::NEAREST),
+        Some(MinFilter::NearestMipmapLinear) => (vk::Filter::NEAREST, vk::SamplerMipmapMode::LINEAR),
+        Some(MinFilter::LinearMipmapNearest) => (vk::Filter::LINEAR, vk::SamplerMipmapMode::NEAREST),
+        Some(MinFilter::Linear) | Some(MinFilter::LinearMipmapLinear) | None => (vk::Filter::LINEAR, vk::SamplerMipmapMode::LINEAR),
+    };
+
+    SamplerDesc {
+        mag_filter: match sampler.mag_filter() {
+            Some(MagFilter::Nearest) => vk::Filter::NEAREST,
+            Some(MagFilter::Linear) | None => vk::Filter::LINEAR,
+        },
+        min_filter,
+        mipmap_mode,
+        address_mode_u: address_mode(sampler.wrap_s()),
+        address_mode_v: address_mode(sampler.wrap_t()),
+    }
+}
+
+fn import_camera(camera: &gltf::Camera) -> Projection {
+    match camera.projection() {
+        gltf::camera::Projection::Perspective(perspective) => Projection::Perspective {
+            fov_y: Rad(perspective.yfov()),
+            near: perspective.znear(),
+            far: perspective.zfar().unwrap_or(INFINITE_CAMERA_FAR),
+        },
+        gltf::camera::Projection::Orthographic(orthographic) => Projection::Orthographic {
+            height: orthographic.ymag() * 2.0,
+            near: orthographic.znear(),
+            far: orthographic.zfar(),
+        },
+    }
+}
+
+/// Grey images are expanded to RGB, 16 bit and float channels are scaled down to 8 bits.
+fn to_rgba8(data: &gltf::image::Data) -> Vec<u8> {
+    let (channels, channel_size) = match data.format {
+        Format::R8 => (1, 1),
+        Format::R8G8 => (2, 1),
+        Format::R8G8B8 => (3, 1),
+        Format::R8G8B8A8 => (4, 1),
+        Format::R16 => (1, 2),
+        Format::R16G16 => (2, 2),
+        Format::R16G16B16 => (3, 2),
+        Format::R16G16B16A16 => (4, 2),
+        Format::R32G32B32FLOAT => (3, 4),
+        Format::R32G32B32A32FLOAT => (4, 4),
+    };
+    let channel = |bytes: &[u8]| -> u8 {
+        match channel_size {
+            1 => bytes[0],
+            2 => (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8,
+            _ => (f32::from_ne_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]).clamp(0.0, 1.0) * 255.0).round() as u8,
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(data.width as usize * data.height as usize * 4);
+    for pixel in data.pixels.chunks_exact(channels * channel_size) {
+        let values: Vec<u8> = pixel.chunks_exact(channel_size).map(channel).collect();
+        let rgba = match channels {
+            1 => [values[0], values[0], values[0], 255],
+            2 => [values[0], values[0], values[0], values[1]],
+            3 => [values[0], values[1], values[2], 255],
+            _ => [values[0], values[1], values[2], values[3]],
+        };
+        pixels.extend_from_slice(&rgba);
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn model_path(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("assets/models").join(name)
+    }
+
+    #[test]
+    fn loads_textured_quad_as_gltf_and_glb() {
+        for name in ["textured_quad.gltf", "textured_quad.glb"] {
+            let model = GltfModel::load(&model_path(name)).unwrap();
+            assert!(model.warnings.is_empty(), "{}: {:?}", name, model.warnings);
+
+            assert_eq!(model.roots, vec![0]);
+            assert_eq!(model.nodes.len(), 2);
+            assert_eq!(model.nodes[0].name, "quad");
+            assert_eq!(model.nodes[0].mesh, Some(0));
+            assert_eq!(model.nodes[0].children, vec![1]);
+            assert_eq!(model.nodes[1].camera, Some(0));
+            assert_eq!(model.nodes[1].transform.translation, Vector3::new(0.0, 0.0, 4.0));
+
+            assert_eq!(model.cameras, vec![Projection::Perspective { fov_y: Rad(0.8), near: 0.1, far: 100.0 }]);
+
+            let mesh = &model.meshes[0];
+            assert_eq!(mesh.vertices.len(), 4);
+            assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3]);
+            assert_eq!(mesh.vertices[2], MeshVertex { position: [1.0, 1.0, 0.0], normal: [0.0, 0.0, 1.0], uv: [1.0, 0.0] });
+            assert_eq!(mesh.submeshes.len(), 1);
+            assert_eq!(mesh.submeshes[0].material, Some(0));
+
+            let material = &model.materials[0];
+            assert_eq!(material.name, "checker");
+            assert_eq!(material.base_color_texture, Some(GltfTextureRef { texture: 0, tex_coord: 0 }));
+            assert_eq!(material.metallic_factor, 0.0);
+            assert_eq!(material.roughness_factor, 0.8);
+            assert!(material.double_sided);
+
+            assert_eq!(model.textures[0].image, 0);
+            assert_eq!(model.textures[0].sampler.mag_filter, vk::Filter::NEAREST);
+            assert_eq!(model.textures[0].sampler.address_mode_u, vk::SamplerAddressMode::CLAMP_TO_EDGE);
+            assert_eq!(model.images[0].extent, vk::Extent2D { width: 2, height: 2 });
+            assert_eq!(model.images[0].pixels.len(), 2 * 2 * 4);
+            assert!(model.images[0].srgb);
+        }
+    }
+
+    #[test]
+    fn loads_strip_with_flat_normals_and_warns_about_extensions() {
+        let model = GltfModel::load(&model_path("strip.gltf")).unwrap();
+
+        assert_eq!(model.warnings, vec![String::from("Unsupported extension KHR_materials_clearcoat is ignored")]);
+        assert_eq!(model.nodes[0].transform.scale, Vector3::new(2.0, 2.0, 2.0));
+        assert!(model.cameras.is_empty());
+        assert_eq!(model.materials[0].base_color_factor, [0.8, 0.1, 0.1, 1.0]);
+        assert_eq!(model.materials[0].base_color_texture, None);
+
+        // two triangles out of the strip, a vertex per corner
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.indices, (0..6).collect::<Vec<u32>>());
+        assert!(mesh.vertices.iter().all(|vertex| vertex.normal == [0.0, 0.0, 1.0]));
+        assert_eq!(mesh.vertices[3].position, [0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn rejects_attributes_with_other_counts_than_positions() {
+        let text = fs::read_to_string(model_path("textured_quad.gltf")).unwrap();
+        let directory = model_path("");
+
+        for (buffer_view, attribute) in [(1, "NORMAL"), (2, "TEXCOORD_0")] {
+            let accessor = format!("\"bufferView\": {},\n      \"componentType\": 5126,\n      \"count\": 4", buffer_view);
+            assert!(text.contains(&accessor));
+            let broken = text.replace(&accessor, &accessor.replace("\"count\": 4", "\"count\": 3"));
+
+            let error = GltfModel::from_slice(broken.as_bytes(), &directory).unwrap_err();
+            assert!(error.contains(attribute), "{}", error);
+        }
+    }
+}
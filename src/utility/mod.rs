@@ -1,4 +1,10 @@
 pub mod constants;
 pub mod debug;
 pub mod tools;
-pub mod fps;
\ No newline at end of file
+pub mod fps;
+pub mod shader_watcher;
+pub mod trace;
+pub mod profiler;
+pub mod timestep;
+pub mod obj;
+pub mod gltf;
\ No newline at end of file
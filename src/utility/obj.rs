@@ -0,0 +1,517 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::SplitWhitespace;
+
+use crate::vk::mesh::{MaterialSubmesh, MeshData, Submesh};
+use crate::vk::vertex::MeshVertex;
+
+/// Used for vertices whose normal can't be computed, as on faces with no area.
+const FALLBACK_NORMAL: [f32; 3] = [0.0, 0.0, 1.0];
+
+/// A material of an MTL library. Texture paths are resolved against the library's directory.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ObjMaterial {
+    pub name: String,
+    pub ambient: [f32; 3],
+    pub diffuse: [f32; 3],
+    pub specular: [f32; 3],
+    pub emissive: [f32; 3],
+    pub shininess: f32,
+    /// 1 is opaque
+    pub dissolve: f32,
+    pub diffuse_texture: Option<PathBuf>,
+    pub specular_texture: Option<PathBuf>,
+    pub normal_texture: Option<PathBuf>,
+    pub alpha_texture: Option<PathBuf>,
+}
+
+impl ObjMaterial {
+    fn new(name: &str) -> ObjMaterial {
+        ObjMaterial {
+            name: String::from(name),
+            ambient: [0.0; 3],
+            diffuse: [1.0; 3],
+            specular: [0.0; 3],
+            emissive: [0.0; 3],
+            shininess: 0.0,
+            dissolve: 1.0,
+            diffuse_texture: None,
+            specular_texture: None,
+            normal_texture: None,
+            alpha_texture: None,
+        }
+    }
+}
+
+/// The objects and groups of an OBJ file, each triangulated into a mesh with a vertex for every
+/// distinct position/UV/normal combination. Submesh materials index `materials`.
+#[derive(Clone, Debug)]
+pub struct ObjModel {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<ObjMaterial>,
+}
+
+impl ObjModel {
+    /// Reads an OBJ file and the MTL libraries it names, which are looked up next to it.
+    pub fn load(path: &Path) -> Result<ObjModel, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read model from {}: {}", path.display(), error))?;
+        let directory = path.parent().unwrap_or_else(|| Path::new(""));
+
+        ObjModel::parse(&text, |library| {
+            let library_path = directory.join(library);
+            let library_text = fs::read_to_string(&library_path)
+                .map_err(|error| format!("Failed to read material library {}: {}", library_path.display(), error))?;
+
+            let library_directory = library_path.parent().unwrap_or_else(|| Path::new(""));
+            parse_materials(&library_text, library_directory)
+                .map_err(|error| format!("{}: {}", library_path.display(), error))
+        })
+        .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    /// Parses OBJ text, getting the materials of each `mtllib` from `load_materials`.
+    ///
+    /// Polygons are split into fans, so they have to be convex, as exporters write them. Vertices
+    /// without a normal get the area-weighted average of the faces around them in their mesh.
+    pub fn parse<F>(text: &str, mut load_materials: F) -> Result<ObjModel, String>
+    where
+        F: FnMut(&str) -> Result<Vec<ObjMaterial>, String>,
+    {
+        let mut positions: Vec<[f32; 3]> = vec![];
+        let mut uvs: Vec<[f32; 2]> = vec![];
+        let mut normals: Vec<[f32; 3]> = vec![];
+
+        let mut materials: Vec<ObjMaterial> = vec![];
+        let mut current_material: Option<usize> = None;
+
+        let mut meshes = vec![];
+        let mut builder = MeshBuilder::new("");
+        let mut face: Vec<VertexKey> = vec![];
+
+        for (line_index, line) in text.lines().enumerate() {
+            let error = |message: String| format!("line {}: {}", line_index + 1, message);
+
+            let line = line.split('#').next().unwrap_or("");
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) => keyword,
+                None => continue,
+            };
+
+            match keyword {
+                "v" => {
+                    // an optional w is ignored
+                    positions.push(parse_floats(&mut tokens, 3, 4).map_err(error)?);
+                },
+                "vt" => {
+                    let uv: [f32; 2] = parse_floats(&mut tokens, 1, 3).map_err(error)?;
+                    uvs.push([uv[0], 1.0 - uv[1]]);
+                },
+                "vn" => {
+                    normals.push(parse_floats(&mut tokens, 3, 3).map_err(error)?);
+                },
+                "f" => {
+                    face.clear();
+                    for token in tokens {
+                        let key = parse_face_vertex(token, positions.len(), uvs.len(), normals.len()).map_err(error)?;
+                        face.push(key);
+                    }
+                    if face.len() < 3 {
+                        return Err(error(format!("A face needs at least 3 vertices, found {}", face.len())));
+                    }
+
+                    builder.add_polygon(&face, current_material, &positions, &uvs, &normals);
+                },
+                "o" | "g" => {
+                    let name = tokens.collect::<Vec<_>>().join(" ");
+                    let finished = std::mem::replace(&mut builder, MeshBuilder::new(&name));
+                    if let Some(mesh) = finished.finish() {
+                        meshes.push(mesh);
+                    }
+                },
+                "usemtl" => {
+                    let name = tokens.next()
+                        .ok_or_else(|| error(String::from("Expected a material name after `usemtl`")))?;
+                    let index = materials.iter().position(|material| material.name == name)
+                        .ok_or_else(|| error(format!("Unknown material `{}`", name)))?;
+                    current_material = Some(index);
+                },
+                "mtllib" => {
+                    let mut found = false;
+                    for library in tokens {
+                        found = true;
+                        for material in load_materials(library).map_err(error)? {
+                            // later libraries override materials of the same name
+                            match materials.iter().position(|existing| existing.name == material.name) {
+                                Some(index) => materials[index] = material,
+                                None => materials.push(material),
+                            }
+                        }
+                    }
+                    if !found {
+                        return Err(error(String::from("Expected a file name after `mtllib`")));
+                    }
+                },
+                // smoothing groups, lines, points and free-form geometry are not used
+                _ => {},
+            }
+        }
+
+        if let Some(mesh) = builder.finish() {
+            meshes.push(mesh);
+        }
+
+        Ok(ObjModel { meshes, materials })
+    }
+}
+
+/// Parses MTL text. Texture paths are taken relative to `directory`.
+pub fn parse_materials(text: &str, directory: &Path) -> Result<Vec<ObjMaterial>, String> {
+    let mut materials: Vec<ObjMaterial> = vec![];
+
+    for (line_index, line) in text.lines().enumerate() {
+        let error = |message: String| format!("line {}: {}", line_index + 1, message);
+
+        let line = line.split('#').next().unwrap_or("");
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = tokens.next()
+                .ok_or_else(|| error(String::from("Expected a material name after `newmtl`")))?;
+            materials.push(ObjMaterial::new(name));
+            continue;
+        }
+
+        let material = match materials.last_mut() {
+            Some(material) => material,
+            None => return Err(error(format!("`{}` comes before any `newmtl`", keyword))),
+        };
+
+        match keyword {
+            "Ka" => material.ambient = parse_color(&mut tokens).map_err(error)?,
+            "Kd" => material.diffuse = parse_color(&mut tokens).map_err(error)?,
+            "Ks" => material.specular = parse_color(&mut tokens).map_err(error)?,
+            "Ke" => material.emissive = parse_color(&mut tokens).map_err(error)?,
+            "Ns" => material.shininess = parse_floats::<1>(&mut tokens, 1, 1).map_err(error)?[0],
+            "d" => material.dissolve = parse_floats::<1>(&mut tokens, 1, 1).map_err(error)?[0],
+            "Tr" => material.dissolve = 1.0 - parse_floats::<1>(&mut tokens, 1, 1).map_err(error)?[0],
+            "map_Kd" => material.diffuse_texture = Some(parse_texture(tokens, directory).map_err(error)?),
+            "map_Ks" => material.specular_texture = Some(parse_texture(tokens, directory).map_err(error)?),
+            "map_Bump" | "map_bump" | "bump" | "norm" => {
+                material.normal_texture = Some(parse_texture(tokens, directory).map_err(error)?)
+            },
+            "map_d" => material.alpha_texture = Some(parse_texture(tokens, directory).map_err(error)?),
+            _ => {},
+        }
+    }
+
+    Ok(materials)
+}
+
+/// Reads `min` to `max` numbers, keeping the first `N`; the ones not given stay zero.
+fn parse_floats<const N: usize>(tokens: &mut SplitWhitespace, min: usize, max: usize) -> Result<[f32; N], String> {
+    let mut values = [0.0; N];
+    let mut count = 0;
+
+    for token in tokens {
+        let value = token.parse::<f32>()
+            .map_err(|_| format!("Expected a number, found `{}`", token))?;
+        if count < N {
+            values[count] = value;
+        }
+        count += 1;
+    }
+
+    if count < min || count > max {
+        return Err(format!("Expected {} to {} numbers, found {}", min, max, count));
+    }
+    Ok(values)
+}
+
+/// `r` alone stands for a grey of that value.
+fn parse_color(tokens: &mut SplitWhitespace) -> Result<[f32; 3], String> {
+    let values = tokens.clone().count();
+    let color: [f32; 3] = parse_floats(tokens, 1, 3)?;
+
+    match values {
+        1 => Ok([color[0]; 3]),
+        3 => Ok(color),
+        _ => Err(format!("Expected 1 or 3 color components, found {}", values)),
+    }
+}
+
+/// The file name is the last token, after any options such as `-bm 1.0`.
+fn parse_texture(tokens: SplitWhitespace, directory: &Path) -> Result<PathBuf, String> {
+    tokens.last()
+        .map(|name| directory.join(name.replace('\\', "/")))
+        .ok_or_else(|| String::from("Expected a texture file name"))
+}
+
+/// Zero-based indices of one face corner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct VertexKey {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// Parses `v`, `v/vt`, `v//vn` or `v/vt/vn`, with negative indices counting back from the end.
+fn parse_face_vertex(token: &str, position_count: usize, uv_count: usize, normal_count: usize) -> Result<VertexKey, String> {
+    let mut parts = token.split('/');
+
+    let position = match parts.next() {
+        Some(part) if !part.is_empty() => resolve_index(part, position_count, "position")?,
+        _ => return Err(format!("Face vertex `{}` has no position", token)),
+    };
+    let uv = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, uv_count, "texture coordinate")?),
+        _ => None,
+    };
+    let normal = match parts.next() {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, normal_count, "normal")?),
+        _ => None,
+    };
+    if parts.next().is_some() {
+        return Err(format!("Face vertex `{}` has more than 3 indices", token));
+    }
+
+    Ok(VertexKey { position, uv, normal })
+}
+
+fn resolve_index(text: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let index = text.parse::<i64>()
+        .map_err(|_| format!("Expected a {} index, found `{}`", kind, text))?;
+
+    let resolved = if index > 0 {
+        index - 1
+    } else {
+        count as i64 + index
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!("The {} index {} is out of range, {} are defined so far", kind, index, count));
+    }
+    Ok(resolved as usize)
+}
+
+struct MeshBuilder {
+    name: String,
+    vertices: Vec<MeshVertex>,
+    indices: Vec<u32>,
+    submeshes: Vec<MaterialSubmesh>,
+    vertex_lookup: HashMap<VertexKey, u32>,
+    /// summed face normals of the vertices that have none in the file
+    computed_normals: HashMap<u32, [f32; 3]>,
+}
+
+impl MeshBuilder {
+    fn new(name: &str) -> MeshBuilder {
+        MeshBuilder {
+            name: String::from(name),
+            vertices: vec![],
+            indices: vec![],
+            submeshes: vec![],
+            vertex_lookup: HashMap::new(),
+            computed_normals: HashMap::new(),
+        }
+    }
+
+    fn add_polygon(
+        &mut self,
+        face: &[VertexKey],
+        material: Option<usize>,
+        positions: &[[f32; 3]],
+        uvs: &[[f32; 2]],
+        normals: &[[f32; 3]],
+    ) {
+        let continues_submesh = self.submeshes.last().is_some_and(|submesh| submesh.material == material);
+        if !continues_submesh {
+            self.submeshes.push(MaterialSubmesh {
+                submesh: Submesh {
+                    first_index: self.indices.len() as u32,
+                    index_count: 0,
+                    vertex_offset: 0,
+                },
+                material,
+            });
+        }
+
+        let corners: Vec<u32> = face.iter().map(|key| self.vertex(*key, positions, uvs, normals)).collect();
+
+        for i in 1..corners.len() - 1 {
+            let triangle = [corners[0], corners[i], corners[i + 1]];
+            self.indices.extend_from_slice(&triangle);
+
+            // the cross product's length is twice the area, which weights the average
+            let [a, b, c] = triangle.map(|index| self.vertices[index as usize].position);
+            let face_normal = cross(sub(b, a), sub(c, a));
+            for index in triangle {
+                if let Some(normal) = self.computed_normals.get_mut(&index) {
+                    *normal = add(*normal, face_normal);
+                }
+            }
+        }
+
+        if let Some(submesh) = self.submeshes.last_mut() {
+            submesh.submesh.index_count = self.indices.len() as u32 - submesh.submesh.first_index;
+        }
+    }
+
+    fn vertex(&mut self, key: VertexKey, positions: &[[f32; 3]], uvs: &[[f32; 2]], normals: &[[f32; 3]]) -> u32 {
+        if let Some(&index) = self.vertex_lookup.get(&key) {
+            return index;
+        }
+
+        let index = self.vertices.len() as u32;
+        self.vertices.push(MeshVertex {
+            position: positions[key.position],
+            normal: key.normal.map_or(FALLBACK_NORMAL, |normal| normals[normal]),
+            uv: key.uv.map_or([0.0; 2], |uv| uvs[uv]),
+        });
+        if key.normal.is_none() {
+            self.computed_normals.insert(index, [0.0; 3]);
+        }
+        self.vertex_lookup.insert(key, index);
+        index
+    }
+
+    fn finish(mut self) -> Option<MeshData> {
+        if self.indices.is_empty() {
+            return None;
+        }
+
+        for (&index, &normal) in self.computed_normals.iter() {
+            let length = dot(normal, normal).sqrt();
+            if length > f32::EPSILON {
+                self.vertices[index as usize].normal = normal.map(|component| component / length);
+            }
+        }
+
+        Some(MeshData {
+            name: self.name,
+            vertices: self.vertices,
+            indices: self.indices,
+            submeshes: self.submeshes,
+        })
+    }
+}
+
+fn add(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] + b[0], a[1] + b[1], a[2] + b[2]]
+}
+
+fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<ObjModel, String> {
+        ObjModel::parse(text, |library| Err(format!("No library {}", library)))
+    }
+
+    const QUAD: &str = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0";
+
+    #[test]
+    fn polygons_are_split_into_fans() {
+        let model = parse(&format!("{}\nv 0.5 2 0\nf 1 2 3 4\nf 1 2 3 4 5", QUAD)).unwrap();
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 0, 1, 2, 0, 2, 3, 0, 3, 4]);
+        assert_eq!(mesh.submeshes(), vec![Submesh { first_index: 0, index_count: 15, vertex_offset: 0 }]);
+    }
+
+    #[test]
+    fn corners_with_the_same_indices_share_a_vertex() {
+        let text = format!("{}\nvt 0 0\nvt 1 1\nvn 0 0 1\nvn 0 0 -1\nf 1/1/1 2/1/1 3/1/1\nf 1/1/1 3/1/1 4/2/1\nf 1/1/2 3/1/1 4/1/1", QUAD);
+        let model = parse(&text).unwrap();
+
+        let mesh = &model.meshes[0];
+        // 1/1/1 2/1/1 3/1/1, then 4/2/1, then 1/1/2 and 4/1/1
+        assert_eq!(mesh.vertices.len(), 6);
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 2, 3, 4, 2, 5]);
+        // v is flipped so the origin is at the top left
+        assert_eq!(mesh.vertices[3].uv, [1.0, 0.0]);
+        assert_eq!(mesh.vertices[4].normal, [0.0, 0.0, -1.0]);
+    }
+
+    #[test]
+    fn missing_normals_average_the_faces_around_the_vertex() {
+        // two faces at a right angle along the x axis, the second twice as large
+        let text = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 1 0 2\nvn 1 0 0\nf 1 2 3\nf 2 1 4\nf 1//1 2//1 3//1";
+        let model = parse(text).unwrap();
+
+        let mesh = &model.meshes[0];
+        let shared = mesh.vertices[0].normal;
+        let expected = [0.0, 2.0 / 5.0f32.sqrt(), 1.0 / 5.0f32.sqrt()];
+        assert!(shared.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-6), "{:?}", shared);
+        assert_eq!(mesh.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_eq!(mesh.vertices[4].normal, [1.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_definition() {
+        let model = parse(&format!("{}\nf -4 -3 -2\nv 5 5 5\nf -5 -1 1", QUAD)).unwrap();
+
+        let mesh = &model.meshes[0];
+        assert_eq!(mesh.indices, vec![0, 1, 2, 0, 3, 0]);
+        assert_eq!(mesh.vertices[3].position, [5.0, 5.0, 5.0]);
+    }
+
+    #[test]
+    fn objects_and_materials_split_meshes_and_submeshes() {
+        let text = format!("mtllib a.mtl\n{}\no first\nusemtl red\nf 1 2 3\nusemtl blue\nf 1 3 4\no second\nf 1 2 3", QUAD);
+        let model = ObjModel::parse(&text, |library| {
+            assert_eq!(library, "a.mtl");
+            parse_materials("newmtl red\nKd 1 0 0\nnewmtl blue\nKd 0 0 1\nmap_Kd -bm 1 tex\\blue.png", Path::new("models"))
+        })
+        .unwrap();
+
+        assert_eq!(model.meshes.iter().map(|mesh| mesh.name.as_str()).collect::<Vec<_>>(), vec!["first", "second"]);
+        let materials: Vec<_> = model.meshes[0].submeshes.iter().map(|submesh| submesh.material).collect();
+        assert_eq!(materials, vec![Some(0), Some(1)]);
+        // `usemtl` carries over to the next object
+        assert_eq!(model.meshes[1].submeshes[0].material, Some(1));
+        assert_eq!(model.materials[0].diffuse, [1.0, 0.0, 0.0]);
+        assert_eq!(model.materials[1].diffuse_texture, Some(Path::new("models").join("tex/blue.png")));
+    }
+
+    #[test]
+    fn malformed_files_report_the_line() {
+        let error = |text: &str| parse(text).unwrap_err();
+
+        assert_eq!(error(&format!("{}\nf 1 2 5", QUAD)), "line 5: The position index 5 is out of range, 4 are defined so far");
+        assert_eq!(error(&format!("{}\nf 1 2 0", QUAD)), "line 5: The position index 0 is out of range, 4 are defined so far");
+        assert_eq!(error(&format!("{}\nf 1/1 2 3", QUAD)), "line 5: The texture coordinate index 1 is out of range, 0 are defined so far");
+        assert_eq!(error(&format!("{}\nf 1 2", QUAD)), "line 5: A face needs at least 3 vertices, found 2");
+        assert_eq!(error("v 1 2"), "line 1: Expected 3 to 4 numbers, found 2");
+        assert_eq!(error("v 1 x 2"), "line 1: Expected a number, found `x`");
+        assert_eq!(error("usemtl"), "line 1: Expected a material name after `usemtl`");
+        assert_eq!(error("mtllib"), "line 1: Expected a file name after `mtllib`");
+        assert_eq!(error("usemtl missing"), "line 1: Unknown material `missing`");
+        assert_eq!(error("mtllib a.mtl"), "line 1: No library a.mtl");
+
+        assert_eq!(parse_materials("Kd 1 1 1", Path::new("")).unwrap_err(), "line 1: `Kd` comes before any `newmtl`");
+        assert_eq!(parse_materials("newmtl a\nKd 1 1", Path::new("")).unwrap_err(), "line 2: Expected 1 or 3 color components, found 2");
+    }
+}
@@ -0,0 +1,244 @@
+//! Scoped CPU profiler. `profile_scope!` times the rest of the enclosing block on the calling thread,
+//! `profile_frame!` closes the frame. Without the `profiling` feature both expand to nothing.
+
+#[cfg(feature = "profiling")]
+pub use self::enabled::*;
+
+#[cfg(feature = "profiling")]
+mod enabled {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::fs::File;
+    use std::io::{self, BufWriter};
+    use std::path::Path;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Mutex, OnceLock};
+    use std::time::Instant;
+
+    use crate::utility::trace::{self, TraceEvent};
+
+    /// Frames kept for trace export.
+    const FRAME_HISTORY: usize = 300;
+
+    const CPU_TRACE_PROCESS_ID: u32 = 0;
+
+    #[derive(Clone)]
+    pub struct CpuScopeTiming {
+        pub name: &'static str,
+        pub thread_id: u64,
+        pub depth: u32,
+        /// microseconds since the profiler started
+        pub start_us: f64,
+        pub duration_us: f64,
+    }
+
+    struct FrameHistory {
+        current: Vec<CpuScopeTiming>,
+        frames: VecDeque<Vec<CpuScopeTiming>>,
+    }
+
+    static HISTORY: Mutex<FrameHistory> = Mutex::new(FrameHistory {
+        current: Vec::new(),
+        frames: VecDeque::new(),
+    });
+
+    static EPOCH: OnceLock<Instant> = OnceLock::new();
+    static NEXT_THREAD_ID: AtomicU64 = AtomicU64::new(0);
+
+    /// Scopes of one thread. They are handed to the shared history only when the outermost scope closes,
+    /// so nested scopes do not take the lock.
+    struct ThreadScopes {
+        thread_id: u64,
+        depth: u32,
+        completed: Vec<CpuScopeTiming>,
+    }
+
+    thread_local! {
+        static THREAD_SCOPES: RefCell<ThreadScopes> = RefCell::new(ThreadScopes {
+            thread_id: NEXT_THREAD_ID.fetch_add(1, Ordering::Relaxed),
+            depth: 0,
+            completed: vec![],
+        });
+    }
+
+    pub struct ScopeGuard {
+        name: &'static str,
+        depth: u32,
+        start: Instant,
+    }
+
+    impl ScopeGuard {
+        pub fn new(name: &'static str) -> ScopeGuard {
+            let depth = THREAD_SCOPES.with(|scopes| {
+                let mut scopes = scopes.borrow_mut();
+                scopes.depth += 1;
+                scopes.depth - 1
+            });
+
+            ScopeGuard {
+                name,
+                depth,
+                start: Instant::now(),
+            }
+        }
+    }
+
+    impl Drop for ScopeGuard {
+        fn drop(&mut self) {
+            let end = Instant::now();
+            let epoch = *EPOCH.get_or_init(|| self.start);
+
+            THREAD_SCOPES.with(|scopes| {
+                let mut scopes = scopes.borrow_mut();
+                scopes.depth -= 1;
+
+                let timing = CpuScopeTiming {
+                    name: self.name,
+                    thread_id: scopes.thread_id,
+                    depth: self.depth,
+                    start_us: self.start.saturating_duration_since(epoch).as_secs_f64() * 1_000_000.0,
+                    duration_us: end.duration_since(self.start).as_secs_f64() * 1_000_000.0,
+                };
+                scopes.completed.push(timing);
+
+                if scopes.depth == 0 {
+                    let mut history = HISTORY.lock().unwrap();
+                    history.current.append(&mut scopes.completed);
+                }
+            });
+        }
+    }
+
+    impl FrameHistory {
+        fn finish_frame(&mut self) {
+            let frame = std::mem::take(&mut self.current);
+            if self.frames.len() == FRAME_HISTORY {
+                self.frames.pop_front();
+            }
+            self.frames.push_back(frame);
+        }
+    }
+
+    /// Moves the scopes closed since the last call into the frame history.
+    pub fn finish_frame() {
+        HISTORY.lock().unwrap().finish_frame();
+    }
+
+    /// The scopes of the last finished frame.
+    pub fn last_frame() -> Vec<CpuScopeTiming> {
+        HISTORY.lock().unwrap().frames.back().cloned().unwrap_or_default()
+    }
+
+    /// Writes the frame history as a Chrome trace / Perfetto JSON file.
+    pub fn write_chrome_trace(path: &Path) -> io::Result<()> {
+        let events: Vec<TraceEvent> = HISTORY
+            .lock()
+            .unwrap()
+            .frames
+            .iter()
+            .flatten()
+            .map(|timing| TraceEvent {
+                name: timing.name.to_string(),
+                category: "cpu",
+                process_id: CPU_TRACE_PROCESS_ID,
+                thread_id: timing.thread_id,
+                start_us: timing.start_us,
+                duration_us: timing.duration_us,
+            })
+            .collect();
+
+        trace::write_chrome_trace(BufWriter::new(File::create(path)?), &events)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use std::thread;
+
+        use super::*;
+
+        /// The tests that finish frames of the shared history, one at a time.
+        static FRAME_LOCK: Mutex<()> = Mutex::new(());
+
+        fn timing(name: &'static str) -> CpuScopeTiming {
+            CpuScopeTiming { name, thread_id: 0, depth: 0, start_us: 0.0, duration_us: 0.0 }
+        }
+
+        fn has_scope(timings: &[CpuScopeTiming], name: &str) -> bool {
+            timings.iter().any(|timing| timing.name == name)
+        }
+
+        #[test]
+        fn nested_scopes_record_their_depth_once_the_outermost_closes() {
+            let _frame_lock = FRAME_LOCK.lock().unwrap();
+
+            thread::spawn(|| {
+                let outer = ScopeGuard::new("nesting test outer");
+                {
+                    let _middle = ScopeGuard::new("nesting test middle");
+                    let _inner = ScopeGuard::new("nesting test inner");
+                }
+                assert!(!has_scope(&HISTORY.lock().unwrap().current, "nesting test middle"));
+                drop(outer);
+            })
+            .join()
+            .unwrap();
+
+            finish_frame();
+            let frame = last_frame();
+            let find = |name| frame.iter().find(|timing| timing.name == name).unwrap();
+            let (outer, middle, inner) = (find("nesting test outer"), find("nesting test middle"), find("nesting test inner"));
+
+            assert_eq!((outer.depth, middle.depth, inner.depth), (0, 1, 2));
+            assert!(outer.thread_id == middle.thread_id && middle.thread_id == inner.thread_id);
+            assert!(outer.start_us <= middle.start_us && middle.start_us <= inner.start_us);
+            assert!(inner.start_us + inner.duration_us <= outer.start_us + outer.duration_us);
+        }
+
+        #[test]
+        fn the_history_keeps_the_last_frames() {
+            let mut history = FrameHistory { current: vec![timing("first frame")], frames: VecDeque::new() };
+            history.finish_frame();
+
+            for _ in 1..FRAME_HISTORY {
+                history.finish_frame();
+            }
+            assert_eq!(history.frames.len(), FRAME_HISTORY);
+            assert!(has_scope(&history.frames[0], "first frame"));
+
+            history.current.push(timing("latest frame"));
+            history.finish_frame();
+            assert_eq!(history.frames.len(), FRAME_HISTORY);
+            assert!(!history.frames.iter().any(|frame| has_scope(frame, "first frame")));
+            assert!(has_scope(history.frames.back().unwrap(), "latest frame"));
+            assert!(history.current.is_empty());
+        }
+    }
+}
+
+#[cfg(feature = "profiling")]
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {
+        let _profile_scope = $crate::utility::profiler::ScopeGuard::new($name);
+    };
+}
+
+#[cfg(not(feature = "profiling"))]
+#[macro_export]
+macro_rules! profile_scope {
+    ($name:expr) => {};
+}
+
+#[cfg(feature = "profiling")]
+#[macro_export]
+macro_rules! profile_frame {
+    () => {
+        $crate::utility::profiler::finish_frame();
+    };
+}
+
+#[cfg(not(feature = "profiling"))]
+#[macro_export]
+macro_rules! profile_frame {
+    () => {};
+}
@@ -0,0 +1,89 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::utility::constants;
+
+/// Watches shader sources and headers and reports the files that changed on disk.
+pub struct ShaderWatcher {
+    source_dir: PathBuf,
+
+    timestamps: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(source_dir: &Path) -> ShaderWatcher {
+        let mut watcher = ShaderWatcher {
+            source_dir: source_dir.to_path_buf(),
+            timestamps: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+
+        let mut sources = vec![];
+        ShaderWatcher::scan_sources(&watcher.source_dir, &mut sources);
+        for (path, modified) in sources {
+            watcher.timestamps.insert(path, modified);
+        }
+
+        watcher
+    }
+
+    /// Canonical paths of every file modified since the previous poll.
+    /// The directory is scanned at most once per `SHADER_POLL_INTERVAL_MS`.
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+        let mut changed = vec![];
+
+        if self.last_poll.elapsed() < Duration::from_millis(constants::SHADER_POLL_INTERVAL_MS) {
+            return changed;
+        }
+        self.last_poll = Instant::now();
+
+        let mut sources = vec![];
+        ShaderWatcher::scan_sources(&self.source_dir, &mut sources);
+
+        for (path, modified) in sources {
+            let is_changed = match self.timestamps.get(&path) {
+                Some(&last_modified) => modified > last_modified,
+                None => true,
+            };
+
+            if is_changed {
+                self.timestamps.insert(path.clone(), modified);
+                changed.push(fs::canonicalize(&path).unwrap_or(path));
+            }
+        }
+
+        changed
+    }
+
+    fn scan_sources(dir: &Path, sources: &mut Vec<(PathBuf, SystemTime)>) {
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+
+            if path.is_dir() {
+                ShaderWatcher::scan_sources(&path, sources);
+                continue;
+            }
+
+            let is_shader = matches!(
+                path.extension().and_then(|extension| extension.to_str()),
+                Some("vert") | Some("frag") | Some("comp") | Some("glsl")
+            );
+
+            if !is_shader {
+                continue;
+            }
+
+            if let Ok(modified) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                sources.push((path, modified));
+            }
+        }
+    }
+}
@@ -0,0 +1,99 @@
+use std::time::Duration;
+
+/// Accumulates frame time and hands it out in fixed simulation steps, so the simulation
+/// behaves the same at any frame rate.
+pub struct FixedTimestep {
+    step: Duration,
+    max_steps_per_frame: u32,
+    accumulator: Duration,
+}
+
+impl FixedTimestep {
+    /// Frames needing more than `max_steps_per_frame` updates drop the excess time instead of
+    /// falling further behind every frame.
+    pub fn new(updates_per_second: f32, max_steps_per_frame: u32) -> FixedTimestep {
+        assert!(updates_per_second > 0.0, "Fixed update rate has to be positive");
+
+        FixedTimestep {
+            step: Duration::from_secs_f64(1.0 / updates_per_second as f64),
+            max_steps_per_frame: max_steps_per_frame.max(1),
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// Adds the frame's `delta_time` and returns how many fixed updates to run now.
+    pub fn advance(&mut self, delta_time: Duration) -> u32 {
+        self.accumulator += delta_time;
+
+        let mut steps = 0;
+        while self.accumulator >= self.step && steps < self.max_steps_per_frame {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+
+        if steps == self.max_steps_per_frame && self.accumulator >= self.step {
+            self.accumulator = Duration::from_nanos(self.accumulator.as_nanos() as u64 % self.step.as_nanos() as u64);
+        }
+
+        steps
+    }
+
+    /// How far rendering is between the last two simulation states, in `[0, 1)`.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn whole_steps_are_run_and_the_rest_carries_over() {
+        let mut timestep = FixedTimestep::new(50.0, 5);
+        let step = timestep.step();
+        assert_eq!(step, Duration::from_millis(20));
+
+        assert_eq!(timestep.advance(step / 2), 0);
+        assert_eq!(timestep.advance(step * 2), 2);
+        assert!((timestep.alpha() - 0.5).abs() < 1e-6);
+        assert_eq!(timestep.advance(step / 2), 1);
+        assert_eq!(timestep.alpha(), 0.0);
+    }
+
+    #[test]
+    fn a_long_stall_runs_at_most_max_steps_and_drops_the_rest() {
+        let mut timestep = FixedTimestep::new(50.0, 5);
+        let step = timestep.step();
+
+        assert_eq!(timestep.advance(step * 100 + step / 4), 5);
+        assert!((timestep.alpha() - 0.25).abs() < 1e-6);
+        assert_eq!(timestep.advance(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn alpha_stays_in_the_unit_interval() {
+        let mut timestep = FixedTimestep::new(60.0, 8);
+        let mut total_steps = 0;
+
+        for frame in 0..1000 {
+            total_steps += timestep.advance(Duration::from_micros(3000 + frame % 7 * 4000));
+            let alpha = timestep.alpha();
+            assert!((0.0..1.0).contains(&alpha), "alpha {} out of [0, 1) on frame {}", alpha, frame);
+        }
+
+        // no frame was slow enough to drop time, so every whole step was run
+        let total_time: Duration = (0..1000).map(|frame| Duration::from_micros(3000 + frame % 7 * 4000)).sum();
+        assert_eq!(total_steps as u128, total_time.as_nanos() / timestep.step().as_nanos());
+    }
+
+    #[test]
+    #[should_panic(expected = "Fixed update rate has to be positive")]
+    fn non_positive_rates_are_rejected() {
+        FixedTimestep::new(0.0, 1);
+    }
+}
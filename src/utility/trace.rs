@@ -0,0 +1,98 @@
+use std::fmt::Write as _;
+use std::io::{self, Write};
+
+/// One complete ("X") event in the Chrome tracing format, which Perfetto and chrome://tracing both load.
+pub struct TraceEvent {
+    pub name: String,
+    pub category: &'static str,
+    pub process_id: u32,
+    pub thread_id: u64,
+    pub start_us: f64,
+    pub duration_us: f64,
+}
+
+pub fn write_chrome_trace<W: Write>(mut writer: W, events: &[TraceEvent]) -> io::Result<()> {
+    writer.write_all(b"{\"traceEvents\":[\n")?;
+
+    for (i, event) in events.iter().enumerate() {
+        let mut name = String::with_capacity(event.name.len());
+        escape_json(&event.name, &mut name);
+
+        writeln!(
+            writer,
+            "{{\"name\":\"{}\",\"cat\":\"{}\",\"ph\":\"X\",\"pid\":{},\"tid\":{},\"ts\":{:.3},\"dur\":{:.3}}}{}",
+            name,
+            event.category,
+            event.process_id,
+            event.thread_id,
+            event.start_us,
+            event.duration_us,
+            if i + 1 == events.len() { "" } else { "," }
+        )?;
+    }
+
+    writer.write_all(b"],\"displayTimeUnit\":\"ms\"}\n")
+}
+
+fn escape_json(value: &str, escaped: &mut String) {
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            },
+            c => escaped.push(c),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(name: &str, start_us: f64, duration_us: f64) -> TraceEvent {
+        TraceEvent {
+            name: name.to_string(),
+            category: "gpu",
+            process_id: 1,
+            thread_id: 2,
+            start_us,
+            duration_us,
+        }
+    }
+
+    fn trace_string(events: &[TraceEvent]) -> String {
+        let mut json = vec![];
+        write_chrome_trace(&mut json, events).unwrap();
+        String::from_utf8(json).unwrap()
+    }
+
+    #[test]
+    fn events_are_written_as_complete_events() {
+        let json = trace_string(&[event("shadow", 0.0, 12.5), event("main", 12.5, 0.0004)]);
+
+        assert_eq!(
+            json,
+            "{\"traceEvents\":[\n\
+             {\"name\":\"shadow\",\"cat\":\"gpu\",\"ph\":\"X\",\"pid\":1,\"tid\":2,\"ts\":0.000,\"dur\":12.500},\n\
+             {\"name\":\"main\",\"cat\":\"gpu\",\"ph\":\"X\",\"pid\":1,\"tid\":2,\"ts\":12.500,\"dur\":0.000}\n\
+             ],\"displayTimeUnit\":\"ms\"}\n"
+        );
+    }
+
+    #[test]
+    fn names_are_escaped() {
+        let json = trace_string(&[event("load \"a\\b\"\n\u{1}", 0.0, 1.0)]);
+
+        assert!(json.contains(r#""name":"load \"a\\b\"\n\u0001""#), "{}", json);
+    }
+
+    #[test]
+    fn an_empty_trace_is_still_valid() {
+        assert_eq!(trace_string(&[]), "{\"traceEvents\":[\n],\"displayTimeUnit\":\"ms\"}\n");
+    }
+}
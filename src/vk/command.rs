@@ -0,0 +1,135 @@
+use ash::vk;
+
+use crate::vk::compute::ComputePipeline;
+use crate::vk::pod::{self, Pod};
+
+/// Push constant offsets and sizes must be multiples of 4 bytes.
+const PUSH_CONSTANT_ALIGNMENT: usize = 4;
+
+/// Thin wrapper over a command buffer in the recording state.
+pub struct CommandRecorder<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+
+    limits: &'a vk::PhysicalDeviceLimits,
+}
+
+impl<'a> CommandRecorder<'a> {
+    pub fn new(
+        device: &'a ash::Device,
+        command_buffer: vk::CommandBuffer,
+        limits: &'a vk::PhysicalDeviceLimits,
+    ) -> CommandRecorder<'a> {
+        CommandRecorder {
+            device,
+            command_buffer,
+            limits,
+        }
+    }
+
+    /// Pushes `value` at `offset` of the push constant block of `stages`. Fails without recording
+    /// anything if the data does not fit `maxPushConstantsSize` or is not 4-byte aligned.
+    pub fn push_constants<T: Pod>(
+        &self,
+        pipeline_layout: vk::PipelineLayout,
+        stages: vk::ShaderStageFlags,
+        offset: u32,
+        value: &T,
+    ) -> Result<(), String> {
+        check_push_constant_range::<T>(self.limits, offset)?;
+
+        unsafe {
+            self.device
+                .cmd_push_constants(self.command_buffer, pipeline_layout, stages, offset, pod::bytes_of(value));
+        }
+        Ok(())
+    }
+
+    pub fn bind_compute_pipeline(&self, pipeline: &ComputePipeline, descriptor_sets: &[vk::DescriptorSet]) {
+        unsafe {
+            self.device
+                .cmd_bind_pipeline(self.command_buffer, vk::PipelineBindPoint::COMPUTE, pipeline.pipeline);
+
+            if !descriptor_sets.is_empty() {
+                self.device.cmd_bind_descriptor_sets(
+                    self.command_buffer,
+                    vk::PipelineBindPoint::COMPUTE,
+                    pipeline.pipeline_layout,
+                    0,
+                    descriptor_sets,
+                    &[],
+                );
+            }
+        }
+    }
+
+    /// Fails without recording anything if a group count exceeds `maxComputeWorkGroupCount`.
+    pub fn dispatch(&self, group_count_x: u32, group_count_y: u32, group_count_z: u32) -> Result<(), String> {
+        check_group_count(self.limits, [group_count_x, group_count_y, group_count_z])?;
+
+        unsafe {
+            self.device
+                .cmd_dispatch(self.command_buffer, group_count_x, group_count_y, group_count_z);
+        }
+        Ok(())
+    }
+}
+
+/// Checks that `T` pushed at `offset` is 4-byte aligned and fits `maxPushConstantsSize`.
+pub fn check_push_constant_range<T>(limits: &vk::PhysicalDeviceLimits, offset: u32) -> Result<(), String> {
+    let size = std::mem::size_of::<T>();
+
+    if !(offset as usize).is_multiple_of(PUSH_CONSTANT_ALIGNMENT) || !size.is_multiple_of(PUSH_CONSTANT_ALIGNMENT) {
+        return Err(format!(
+            "Push constant {} at offset {} with size {} is not {}-byte aligned",
+            std::any::type_name::<T>(), offset, size, PUSH_CONSTANT_ALIGNMENT));
+    }
+    if offset as usize + size > limits.max_push_constants_size as usize {
+        return Err(format!(
+            "Push constant {} at offset {} with size {} exceeds maxPushConstantsSize of {}",
+            std::any::type_name::<T>(), offset, size, limits.max_push_constants_size));
+    }
+    Ok(())
+}
+
+/// Checks a dispatch against `maxComputeWorkGroupCount`, e.g. before recording anything for it.
+pub fn check_group_count(limits: &vk::PhysicalDeviceLimits, group_count: [u32; 3]) -> Result<(), String> {
+    let fits = group_count.iter().zip(limits.max_compute_work_group_count.iter()).all(|(count, max)| count <= max);
+    if !fits {
+        return Err(format!(
+            "Dispatch of {:?} groups exceeds maxComputeWorkGroupCount of {:?}",
+            group_count, limits.max_compute_work_group_count));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn group_counts_past_the_limits_are_rejected() {
+        let limits = vk::PhysicalDeviceLimits {
+            max_compute_work_group_count: [65535, 65535, 64],
+            ..vk::PhysicalDeviceLimits::default()
+        };
+
+        assert!(check_group_count(&limits, [65535, 1, 64]).is_ok());
+        assert!(check_group_count(&limits, [65536, 1, 1]).is_err());
+        assert!(check_group_count(&limits, [1, 1, 65]).is_err());
+    }
+
+    #[test]
+    fn push_constants_past_the_limit_or_unaligned_are_rejected() {
+        let limits = vk::PhysicalDeviceLimits {
+            max_push_constants_size: 128,
+            ..vk::PhysicalDeviceLimits::default()
+        };
+
+        assert!(check_push_constant_range::<[f32; 16]>(&limits, 64).is_ok());
+        assert!(check_push_constant_range::<[f32; 16]>(&limits, 68).is_err());
+        assert!(check_push_constant_range::<[f32; 33]>(&limits, 0).is_err());
+        assert!(check_push_constant_range::<[u8; 6]>(&limits, 0).is_err());
+        assert!(check_push_constant_range::<u32>(&limits, 2).is_err());
+    }
+}
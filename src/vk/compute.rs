@@ -0,0 +1,438 @@
+use std::ffi::CString;
+use std::ptr;
+
+use ash::vk;
+
+use crate::utility::constants as global_constants;
+use crate::vk::command::{self, CommandRecorder};
+use crate::vk::constants;
+use crate::vk::descriptor::{DescriptorAllocator, DescriptorBinding, DescriptorLayoutCache, DescriptorWriter};
+use crate::vk::pod::Pod;
+use crate::vk::render_device::VkRenderDevice;
+use crate::vk::shader_compiler::ShaderCompiler;
+use crate::vk::shader_variants::{PipelineShader, SpecializationConstants};
+
+pub struct ComputePipeline {
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+}
+
+impl ComputePipeline {
+    pub fn new(
+        device: &ash::Device,
+        code: &[u32],
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+        specialization: &SpecializationConstants,
+    ) -> Result<ComputePipeline, String> {
+        let shader_module = VkRenderDevice::create_shader_module(device, code);
+        let main_function_name = CString::new("main").unwrap();
+        let specialization_info = specialization.info();
+
+        let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
+            s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineLayoutCreateFlags::empty(),
+            set_layout_count: set_layouts.len() as u32,
+            p_set_layouts: set_layouts.as_ptr(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
+        };
+
+        let pipeline_layout = unsafe {
+            device
+                .create_pipeline_layout(&pipeline_layout_create_info, None)
+                .expect("Failed to create compute pipeline layout!")
+        };
+
+        let compute_pipeline_create_infos = [vk::ComputePipelineCreateInfo {
+            s_type: vk::StructureType::COMPUTE_PIPELINE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineCreateFlags::empty(),
+            stage: vk::PipelineShaderStageCreateInfo {
+                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
+                p_next: ptr::null(),
+                flags: vk::PipelineShaderStageCreateFlags::empty(),
+                module: shader_module,
+                p_name: main_function_name.as_ptr(),
+                p_specialization_info: &specialization_info,
+                stage: vk::ShaderStageFlags::COMPUTE,
+            },
+            layout: pipeline_layout,
+            base_pipeline_handle: vk::Pipeline::null(),
+            base_pipeline_index: -1,
+        }];
+
+        let compute_pipelines = unsafe {
+            device.create_compute_pipelines(vk::PipelineCache::null(), &compute_pipeline_create_infos, None)
+        };
+
+        unsafe { device.destroy_shader_module(shader_module, None) };
+
+        match compute_pipelines {
+            Ok(compute_pipelines) => Ok(ComputePipeline {
+                pipeline: compute_pipelines[0],
+                pipeline_layout,
+            }),
+            Err((_, vk_result)) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                Err(format!("Failed to create compute pipeline: {}", vk_result))
+            },
+        }
+    }
+
+    /// Builds the pipeline from a `.comp` shader with its keywords and specialization constants.
+    pub fn from_shader(
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        shader: &mut PipelineShader,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<ComputePipeline, String> {
+        if shader.asset.stage != vk::ShaderStageFlags::COMPUTE {
+            return Err(format!("{:?} is not a compute shader", shader.asset.source));
+        }
+
+        let code = shader.code(shader_compiler).map_err(|error| error.to_string())?.to_vec();
+        ComputePipeline::new(device, &code, set_layouts, push_constant_ranges, &shader.specialization)
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
+
+/// A headless device with just a compute queue, for running kernels without a window.
+pub struct ComputeContext {
+    _entry: ash::Entry,
+    instance: ash::Instance,
+    pub device: ash::Device,
+
+    pub physical_device_properties: vk::PhysicalDeviceProperties,
+    memory_properties: vk::PhysicalDeviceMemoryProperties,
+
+    pub queue_family: u32,
+    pub queue: vk::Queue,
+    command_pool: vk::CommandPool,
+
+    descriptor_layout_cache: DescriptorLayoutCache,
+    descriptor_allocator: DescriptorAllocator,
+}
+
+impl ComputeContext {
+    /// Picks the first device with a compute queue, preferring a dedicated compute family.
+    pub fn new() -> Result<ComputeContext, String> {
+        let entry = ash::Entry::linked();
+
+        let app_name = CString::new(global_constants::ENGINE_TITLE).unwrap();
+        let app_info = vk::ApplicationInfo {
+            s_type: vk::StructureType::APPLICATION_INFO,
+            p_next: ptr::null(),
+            p_application_name: app_name.as_ptr(),
+            p_engine_name: app_name.as_ptr(),
+            application_version: global_constants::APPLICATION_VERSION,
+            engine_version: global_constants::ENGINE_VERSION,
+            api_version: constants::API_VERSION,
+        };
+
+        let instance_create_info = vk::InstanceCreateInfo {
+            s_type: vk::StructureType::INSTANCE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::InstanceCreateFlags::empty(),
+            p_application_info: &app_info,
+            pp_enabled_layer_names: ptr::null(),
+            enabled_layer_count: 0,
+            pp_enabled_extension_names: ptr::null(),
+            enabled_extension_count: 0,
+        };
+
+        let instance = unsafe { entry.create_instance(&instance_create_info, None) }
+            .map_err(|vk_result| format!("Failed to create VkInstance: {}", vk_result))?;
+
+        let physical_devices = unsafe { instance.enumerate_physical_devices() }
+            .map_err(|vk_result| format!("Failed to enumerate physical devices: {}", vk_result))?;
+
+        let compute_device = physical_devices.iter().find_map(|&physical_device| {
+            let queue_families = unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+            ComputeContext::pick_queue_family(&queue_families).map(|family| (physical_device, family))
+        });
+
+        let (physical_device, queue_family) = match compute_device {
+            Some(compute_device) => compute_device,
+            None => {
+                unsafe { instance.destroy_instance(None) };
+                return Err(String::from("No physical device with a compute queue"));
+            },
+        };
+
+        let queue_priorities = [1.0_f32];
+        let queue_create_info = vk::DeviceQueueCreateInfo {
+            s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::DeviceQueueCreateFlags::empty(),
+            queue_family_index: queue_family,
+            p_queue_priorities: queue_priorities.as_ptr(),
+            queue_count: queue_priorities.len() as u32,
+        };
+
+        let physical_device_features = vk::PhysicalDeviceFeatures::default();
+
+        let device_create_info = vk::DeviceCreateInfo {
+            s_type: vk::StructureType::DEVICE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::DeviceCreateFlags::empty(),
+            queue_create_info_count: 1,
+            p_queue_create_infos: &queue_create_info,
+            enabled_extension_count: 0,
+            pp_enabled_extension_names: ptr::null(),
+            p_enabled_features: &physical_device_features,
+            // device layers are deprecated and ignored, the instance's apply
+            ..Default::default()
+        };
+
+        let device = match unsafe { instance.create_device(physical_device, &device_create_info, None) } {
+            Ok(device) => device,
+            Err(vk_result) => {
+                unsafe { instance.destroy_instance(None) };
+                return Err(format!("Failed to create logical device: {}", vk_result));
+            },
+        };
+
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index: queue_family,
+        };
+
+        let command_pool = unsafe {
+            device
+                .create_command_pool(&command_pool_create_info, None)
+                .expect("Failed to create Command Pool!")
+        };
+
+        Ok(ComputeContext {
+            physical_device_properties: unsafe { instance.get_physical_device_properties(physical_device) },
+            memory_properties: unsafe { instance.get_physical_device_memory_properties(physical_device) },
+            queue: unsafe { device.get_device_queue(queue_family, 0) },
+            queue_family,
+            command_pool,
+            descriptor_layout_cache: DescriptorLayoutCache::new(),
+            descriptor_allocator: DescriptorAllocator::new(),
+            device,
+            instance,
+            _entry: entry,
+        })
+    }
+
+    /// Prefers a compute family without graphics, otherwise takes any family that supports compute.
+    fn pick_queue_family(queue_families: &[vk::QueueFamilyProperties]) -> Option<u32> {
+        let supports_compute = |queue_family: &vk::QueueFamilyProperties| {
+            queue_family.queue_count > 0 && queue_family.queue_flags.contains(vk::QueueFlags::COMPUTE)
+        };
+
+        let dedicated = queue_families.iter().position(|queue_family| {
+            supports_compute(queue_family) && !queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
+        });
+
+        dedicated
+            .or_else(|| queue_families.iter().position(supports_compute))
+            .map(|index| index as u32)
+    }
+
+    /// Runs `code` once over `data`, bound as a storage buffer at set 0 binding 0,
+    /// and returns the buffer contents after the dispatch. Blocks until the GPU is done.
+    /// `T: Pod` since whatever the kernel wrote is read back as `T`.
+    pub fn run_kernel<T: Pod>(&mut self, code: &[u32], data: &[T], group_count: [u32; 3]) -> Result<Vec<T>, String> {
+        command::check_group_count(&self.physical_device_properties.limits, group_count)?;
+
+        let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
+        if buffer_size == 0 {
+            return Ok(vec![]);
+        }
+
+        let set_layout = self.descriptor_layout_cache.get_layout(
+            &self.device,
+            &[DescriptorBinding::new(0, vk::DescriptorType::STORAGE_BUFFER, vk::ShaderStageFlags::COMPUTE)]);
+
+        let pipeline = ComputePipeline::new(&self.device, code, &[set_layout], &[], &SpecializationConstants::new())?;
+
+        let (buffer, buffer_memory) = VkRenderDevice::create_buffer(
+            &self.device,
+            buffer_size,
+            vk::BufferUsageFlags::STORAGE_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            &self.memory_properties,
+        );
+
+        unsafe {
+            let data_ptr = self.device
+                .map_memory(buffer_memory, 0, buffer_size, vk::MemoryMapFlags::empty())
+                .expect("Failed to Map Memory") as *mut T;
+            data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+            self.device.unmap_memory(buffer_memory);
+        }
+
+        let descriptor_set = self.descriptor_allocator.allocate(&self.device, set_layout);
+        DescriptorWriter::new()
+            .storage_buffer(0, buffer, buffer_size)
+            .update(&self.device, descriptor_set);
+
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            command_buffer_count: 1,
+            command_pool: self.command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+        };
+
+        let command_buffers = unsafe {
+            self.device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .expect("Failed to allocate Command Buffer!")
+        };
+        let command_buffer = command_buffers[0];
+
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: ptr::null(),
+            p_inheritance_info: ptr::null(),
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+        };
+
+        // make the shader writes visible to the host read after the fence
+        let host_read_barrier = vk::BufferMemoryBarrier {
+            s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+            p_next: ptr::null(),
+            src_access_mask: vk::AccessFlags::SHADER_WRITE,
+            dst_access_mask: vk::AccessFlags::HOST_READ,
+            src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+            buffer,
+            offset: 0,
+            size: vk::WHOLE_SIZE,
+        };
+
+        unsafe {
+            self.device
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+                .expect("Failed to begin recording Command Buffer at beginning!");
+
+            let recorder = CommandRecorder::new(&self.device, command_buffer, &self.physical_device_properties.limits);
+            recorder.bind_compute_pipeline(&pipeline, &[descriptor_set]);
+            recorder.dispatch(group_count[0], group_count[1], group_count[2])
+                .expect("Failed to dispatch checked group count!");
+
+            self.device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::COMPUTE_SHADER,
+                vk::PipelineStageFlags::HOST,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[host_read_barrier],
+                &[],
+            );
+
+            self.device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to record Command Buffer at Ending!");
+        }
+
+        let submit_infos = [vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: ptr::null(),
+            wait_semaphore_count: 0,
+            p_wait_semaphores: ptr::null(),
+            p_wait_dst_stage_mask: ptr::null(),
+            command_buffer_count: 1,
+            p_command_buffers: &command_buffer,
+            signal_semaphore_count: 0,
+            p_signal_semaphores: ptr::null(),
+        }];
+
+        let fence_create_info = vk::FenceCreateInfo {
+            s_type: vk::StructureType::FENCE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::FenceCreateFlags::empty(),
+        };
+
+        let mut result = Vec::with_capacity(data.len());
+
+        unsafe {
+            let fence = self.device
+                .create_fence(&fence_create_info, None)
+                .expect("Failed to create Fence Object!");
+
+            self.device
+                .queue_submit(self.queue, &submit_infos, fence)
+                .expect("Failed to execute queue submit.");
+            self.device
+                .wait_for_fences(&[fence], true, u64::MAX)
+                .expect("Failed to wait for Fence!");
+
+            let data_ptr = self.device
+                .map_memory(buffer_memory, 0, buffer_size, vk::MemoryMapFlags::empty())
+                .expect("Failed to Map Memory") as *const T;
+            result.extend_from_slice(std::slice::from_raw_parts(data_ptr, data.len()));
+            self.device.unmap_memory(buffer_memory);
+
+            self.device.destroy_fence(fence, None);
+            self.device.free_command_buffers(self.command_pool, &command_buffers);
+            self.device.destroy_buffer(buffer, None);
+            self.device.free_memory(buffer_memory, None);
+        }
+
+        pipeline.destroy(&self.device);
+        self.descriptor_allocator.reset(&self.device);
+
+        Ok(result)
+    }
+
+}
+
+impl Drop for ComputeContext {
+    fn drop(&mut self) {
+        unsafe {
+            self.device
+                .device_wait_idle()
+                .expect("Failed to wait device idle");
+
+            self.descriptor_allocator.destroy(&self.device);
+            self.descriptor_layout_cache.destroy(&self.device);
+            self.device.destroy_command_pool(self.command_pool, None);
+
+            self.device.destroy_device(None);
+            self.instance.destroy_instance(None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::*;
+
+    fn double_buffer_kernel() -> Vec<u32> {
+        let source_dir = Path::new(global_constants::SHADER_SOURCE_DIR);
+        let prebuilt_dir = Path::new(global_constants::SHADER_PREBUILT_DIR);
+        let mut shader_compiler = ShaderCompiler::new(&[source_dir], prebuilt_dir).with_prebuilt_dir(prebuilt_dir);
+
+        shader_compiler.compile_file(&source_dir.join("double-buffer.comp"), &[]).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs a Vulkan driver with a compute queue, run with --ignored"]
+    fn run_kernel_doubles_a_buffer() {
+        let mut context = ComputeContext::new().expect("Failed to create compute context");
+
+        // one group more than needed, its invocations past the end do nothing
+        let data: Vec<f32> = (0..200).map(|value| value as f32).collect();
+        let result = context.run_kernel(&double_buffer_kernel(), &data, [4, 1, 1]).unwrap();
+
+        assert_eq!(result, data.iter().map(|value| value * 2.0).collect::<Vec<f32>>());
+    }
+}
@@ -1,8 +1,8 @@
 use ash::vk;
 use crate::vk::debug;
-use std::ffi::CStr;
 
-pub const API_VERSION: u32 = vk::make_api_version(0, 1, 0, 0);
+/// 1.2 for core timeline semaphores.
+pub const API_VERSION: u32 = vk::make_api_version(0, 1, 2, 0);
 
 pub const VALIDATION: debug::ValidationInfo = debug::ValidationInfo {
     is_enable: true,
@@ -15,4 +15,7 @@ pub struct DeviceExtension {
 
 pub const DEVICE_EXTENSIONS: DeviceExtension = DeviceExtension {
     names: ["VK_KHR_swapchain"],
-};
\ No newline at end of file
+};
+
+/// HDR color the scene is rendered into before post-processing.
+pub const SCENE_COLOR_FORMAT: vk::Format = vk::Format::R16G16B16A16_SFLOAT;
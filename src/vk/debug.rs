@@ -13,7 +13,7 @@ pub struct ValidationInfo {
 pub fn setup_debug_utils(entry: &ash::Entry, instance: &ash::Instance) -> (ash::extensions::ext::DebugUtils, vk::DebugUtilsMessengerEXT){
     let debug_units_loader = ash::extensions::ext::DebugUtils::new(entry, instance);
 
-    if constants::VALIDATION.is_enable == false {
+    if !constants::VALIDATION.is_enable {
         (debug_units_loader, ash::vk::DebugUtilsMessengerEXT::null())
     }
     else {
@@ -64,10 +64,10 @@ pub fn check_validation_layer_support(
             }
         }
 
-        if is_layer_found == false {
+        if !is_layer_found {
             return false;
         }
     }
 
-    layer_properties.len() > 0
+    !layer_properties.is_empty()
 }
\ No newline at end of file
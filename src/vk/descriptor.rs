@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::ptr;
+
+use ash::vk;
+
+/// One binding of a descriptor set layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct DescriptorBinding {
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+}
+
+impl DescriptorBinding {
+    pub fn new(binding: u32, descriptor_type: vk::DescriptorType, stages: vk::ShaderStageFlags) -> DescriptorBinding {
+        DescriptorBinding {
+            binding,
+            descriptor_type,
+            count: 1,
+            stages,
+        }
+    }
+}
+
+/// Descriptor set layouts keyed by their binding description,
+/// so every user of the same bindings shares one layout.
+#[derive(Default)]
+pub struct DescriptorLayoutCache {
+    layouts: HashMap<Vec<DescriptorBinding>, vk::DescriptorSetLayout>,
+}
+
+impl DescriptorLayoutCache {
+    pub fn new() -> DescriptorLayoutCache {
+        DescriptorLayoutCache::default()
+    }
+
+    pub fn get_layout(&mut self, device: &ash::Device, bindings: &[DescriptorBinding]) -> vk::DescriptorSetLayout {
+        let key = DescriptorLayoutCache::layout_key(bindings);
+
+        if let Some(&layout) = self.layouts.get(&key) {
+            return layout;
+        }
+
+        let layout_bindings: Vec<vk::DescriptorSetLayoutBinding> = key
+            .iter()
+            .map(|binding| vk::DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.count,
+                stage_flags: binding.stages,
+                p_immutable_samplers: ptr::null(),
+            })
+            .collect();
+
+        let layout_create_info = vk::DescriptorSetLayoutCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
+            binding_count: layout_bindings.len() as u32,
+            p_bindings: layout_bindings.as_ptr(),
+        };
+
+        let layout = unsafe {
+            device
+                .create_descriptor_set_layout(&layout_create_info, None)
+                .expect("Failed to create Descriptor Set Layout!")
+        };
+
+        self.layouts.insert(key, layout);
+
+        layout
+    }
+
+    /// The bindings in binding order, so the same bindings listed in any order share a layout.
+    fn layout_key(bindings: &[DescriptorBinding]) -> Vec<DescriptorBinding> {
+        let mut key = bindings.to_vec();
+        key.sort_by_key(|binding| binding.binding);
+        key
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for (_, &layout) in self.layouts.iter() {
+                device.destroy_descriptor_set_layout(layout, None);
+            }
+        }
+
+        self.layouts.clear();
+    }
+}
+
+/// Descriptors reserved per set in every new pool, by type.
+const POOL_SIZE_RATIOS: [(vk::DescriptorType, f32); 7] = [
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 1.0),
+    (vk::DescriptorType::SAMPLER, 1.0),
+];
+
+/// Sets of the first pool. Every pool created after it holds twice as many, up to `MAX_SETS_PER_POOL`.
+const SETS_PER_POOL: u32 = 256;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+/// Allocates descriptor sets from a growing list of pools.
+/// A new, larger pool is created whenever the current one runs out of space.
+#[derive(Default)]
+pub struct DescriptorAllocator {
+    current_pool: Option<vk::DescriptorPool>,
+    used_pools: Vec<vk::DescriptorPool>,
+    free_pools: Vec<vk::DescriptorPool>,
+}
+
+impl DescriptorAllocator {
+    pub fn new() -> DescriptorAllocator {
+        DescriptorAllocator::default()
+    }
+
+    pub fn allocate(&mut self, device: &ash::Device, layout: vk::DescriptorSetLayout) -> vk::DescriptorSet {
+        let pool = match self.current_pool {
+            Some(pool) => pool,
+            None => self.grab_pool(device),
+        };
+
+        match DescriptorAllocator::allocate_from(device, pool, layout) {
+            Ok(descriptor_set) => descriptor_set,
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY) | Err(vk::Result::ERROR_FRAGMENTED_POOL) => {
+                let pool = self.grab_pool(device);
+
+                DescriptorAllocator::allocate_from(device, pool, layout)
+                    .expect("Failed to allocate descriptor set from a fresh pool!")
+            },
+            Err(vk_result) => panic!("Failed to allocate descriptor set: {}", vk_result),
+        }
+    }
+
+    /// Returns every set allocated so far to the pools. The sets must no longer be in use by the GPU.
+    pub fn reset(&mut self, device: &ash::Device) {
+        for &pool in self.used_pools.iter() {
+            unsafe {
+                device
+                    .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())
+                    .expect("Failed to reset Descriptor Pool!");
+            }
+        }
+
+        self.free_pools.append(&mut self.used_pools);
+        self.current_pool = None;
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for &pool in self.used_pools.iter().chain(self.free_pools.iter()) {
+                device.destroy_descriptor_pool(pool, None);
+            }
+        }
+
+        self.used_pools.clear();
+        self.free_pools.clear();
+        self.current_pool = None;
+    }
+
+    fn grab_pool(&mut self, device: &ash::Device) -> vk::DescriptorPool {
+        let pool = match self.free_pools.pop() {
+            Some(pool) => pool,
+            None => {
+                let created_pools = self.used_pools.len() + self.free_pools.len();
+                DescriptorAllocator::create_pool(device, DescriptorAllocator::pool_sets(created_pools))
+            },
+        };
+
+        self.used_pools.push(pool);
+        self.current_pool = Some(pool);
+
+        pool
+    }
+
+    fn allocate_from(
+        device: &ash::Device,
+        pool: vk::DescriptorPool,
+        layout: vk::DescriptorSetLayout,
+    ) -> Result<vk::DescriptorSet, vk::Result> {
+        let layouts = [layout];
+
+        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            descriptor_pool: pool,
+            descriptor_set_count: layouts.len() as u32,
+            p_set_layouts: layouts.as_ptr(),
+        };
+
+        unsafe {
+            device
+                .allocate_descriptor_sets(&descriptor_set_allocate_info)
+                .map(|descriptor_sets| descriptor_sets[0])
+        }
+    }
+
+    /// Sets of the pool created after `created_pools` others.
+    fn pool_sets(created_pools: usize) -> u32 {
+        let doublings = created_pools.min(MAX_SETS_PER_POOL.ilog2() as usize) as u32;
+        (SETS_PER_POOL << doublings).min(MAX_SETS_PER_POOL)
+    }
+
+    /// Descriptors of a pool for `max_sets` sets, by `POOL_SIZE_RATIOS`.
+    fn pool_sizes(max_sets: u32) -> Vec<vk::DescriptorPoolSize> {
+        POOL_SIZE_RATIOS
+            .iter()
+            .map(|&(ty, ratio)| vk::DescriptorPoolSize {
+                ty,
+                descriptor_count: (ratio * max_sets as f32) as u32,
+            })
+            .collect()
+    }
+
+    fn create_pool(device: &ash::Device, max_sets: u32) -> vk::DescriptorPool {
+        let pool_sizes = DescriptorAllocator::pool_sizes(max_sets);
+
+        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
+            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::DescriptorPoolCreateFlags::empty(),
+            max_sets,
+            pool_size_count: pool_sizes.len() as u32,
+            p_pool_sizes: pool_sizes.as_ptr(),
+        };
+
+        unsafe {
+            device
+                .create_descriptor_pool(&descriptor_pool_create_info, None)
+                .expect("Failed to create Descriptor Pool!")
+        }
+    }
+}
+
+/// One allocator per frame in flight for sets that only live for a single frame.
+/// A frame's allocator is reset when that frame slot comes around again.
+pub struct FrameDescriptorAllocators {
+    allocators: Vec<DescriptorAllocator>,
+}
+
+impl FrameDescriptorAllocators {
+    pub fn new(frames_in_flight: usize) -> FrameDescriptorAllocators {
+        FrameDescriptorAllocators {
+            allocators: (0..frames_in_flight).map(|_| DescriptorAllocator::new()).collect(),
+        }
+    }
+
+    /// Call once the GPU has finished the previous use of `frame`.
+    pub fn begin_frame(&mut self, device: &ash::Device, frame: usize) {
+        self.allocators[frame].reset(device);
+    }
+
+    pub fn allocate(&mut self, device: &ash::Device, frame: usize, layout: vk::DescriptorSetLayout) -> vk::DescriptorSet {
+        self.allocators[frame].allocate(device, layout)
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for allocator in self.allocators.iter_mut() {
+            allocator.destroy(device);
+        }
+    }
+}
+
+enum DescriptorInfo {
+    Buffer(usize),
+    Image(usize),
+}
+
+/// Collects buffer, image and sampler writes and applies them to a descriptor set in one call.
+#[derive(Default)]
+pub struct DescriptorWriter {
+    buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    image_infos: Vec<vk::DescriptorImageInfo>,
+    writes: Vec<(u32, vk::DescriptorType, DescriptorInfo)>,
+}
+
+impl DescriptorWriter {
+    pub fn new() -> DescriptorWriter {
+        DescriptorWriter::default()
+    }
+
+    pub fn buffer(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        buffer: vk::Buffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> DescriptorWriter {
+        self.buffer_infos.push(vk::DescriptorBufferInfo { buffer, offset, range });
+        self.writes.push((binding, descriptor_type, DescriptorInfo::Buffer(self.buffer_infos.len() - 1)));
+        self
+    }
+
+    pub fn uniform_buffer(self, binding: u32, buffer: vk::Buffer, range: vk::DeviceSize) -> DescriptorWriter {
+        self.buffer(binding, vk::DescriptorType::UNIFORM_BUFFER, buffer, 0, range)
+    }
+
+    pub fn storage_buffer(self, binding: u32, buffer: vk::Buffer, range: vk::DeviceSize) -> DescriptorWriter {
+        self.buffer(binding, vk::DescriptorType::STORAGE_BUFFER, buffer, 0, range)
+    }
+
+    pub fn image(
+        mut self,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        image_view: vk::ImageView,
+        image_layout: vk::ImageLayout,
+        sampler: vk::Sampler,
+    ) -> DescriptorWriter {
+        self.image_infos.push(vk::DescriptorImageInfo { sampler, image_view, image_layout });
+        self.writes.push((binding, descriptor_type, DescriptorInfo::Image(self.image_infos.len() - 1)));
+        self
+    }
+
+    pub fn combined_image_sampler(self, binding: u32, image_view: vk::ImageView, sampler: vk::Sampler) -> DescriptorWriter {
+        self.image(
+            binding,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            image_view,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            sampler,
+        )
+    }
+
+    pub fn sampled_image(self, binding: u32, image_view: vk::ImageView) -> DescriptorWriter {
+        self.image(
+            binding,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            image_view,
+            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            vk::Sampler::null(),
+        )
+    }
+
+    /// Storage images are read and written by shaders in the GENERAL layout.
+    pub fn storage_image(self, binding: u32, image_view: vk::ImageView) -> DescriptorWriter {
+        self.image(
+            binding,
+            vk::DescriptorType::STORAGE_IMAGE,
+            image_view,
+            vk::ImageLayout::GENERAL,
+            vk::Sampler::null(),
+        )
+    }
+
+    pub fn sampler(self, binding: u32, sampler: vk::Sampler) -> DescriptorWriter {
+        self.image(
+            binding,
+            vk::DescriptorType::SAMPLER,
+            vk::ImageView::null(),
+            vk::ImageLayout::UNDEFINED,
+            sampler,
+        )
+    }
+
+    pub fn update(&self, device: &ash::Device, descriptor_set: vk::DescriptorSet) {
+        let descriptor_writes: Vec<vk::WriteDescriptorSet> = self
+            .writes
+            .iter()
+            .map(|&(binding, descriptor_type, ref info)| {
+                let (p_buffer_info, p_image_info) = match *info {
+                    DescriptorInfo::Buffer(index) => (&self.buffer_infos[index] as *const _, ptr::null()),
+                    DescriptorInfo::Image(index) => (ptr::null(), &self.image_infos[index] as *const _),
+                };
+
+                vk::WriteDescriptorSet {
+                    s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
+                    p_next: ptr::null(),
+                    dst_set: descriptor_set,
+                    dst_binding: binding,
+                    dst_array_element: 0,
+                    descriptor_count: 1,
+                    descriptor_type,
+                    p_image_info,
+                    p_buffer_info,
+                    p_texel_buffer_view: ptr::null(),
+                }
+            })
+            .collect();
+
+        unsafe {
+            device.update_descriptor_sets(&descriptor_writes, &[]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
+
+    use super::*;
+
+    fn hash_of(key: &[DescriptorBinding]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn layout_keys_ignore_binding_order_but_not_stages() {
+        let uniform = DescriptorBinding::new(0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX);
+        let texture = DescriptorBinding::new(1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT);
+
+        let key = DescriptorLayoutCache::layout_key(&[uniform, texture]);
+        let reordered = DescriptorLayoutCache::layout_key(&[texture, uniform]);
+        assert_eq!(key, reordered);
+        assert_eq!(hash_of(&key), hash_of(&reordered));
+
+        let vertex_texture = DescriptorBinding { stages: vk::ShaderStageFlags::VERTEX, ..texture };
+        assert_ne!(key, DescriptorLayoutCache::layout_key(&[uniform, vertex_texture]));
+        let texture_array = DescriptorBinding { count: 4, ..texture };
+        assert_ne!(key, DescriptorLayoutCache::layout_key(&[uniform, texture_array]));
+    }
+
+    #[test]
+    fn pools_double_up_to_the_cap() {
+        let sets: Vec<u32> = (0..7).map(DescriptorAllocator::pool_sets).collect();
+
+        assert_eq!(sets, vec![256, 512, 1024, 2048, 4096, 4096, 4096]);
+        assert_eq!(DescriptorAllocator::pool_sets(usize::MAX), MAX_SETS_PER_POOL);
+    }
+
+    #[test]
+    fn pool_sizes_follow_the_ratios() {
+        let sizes = DescriptorAllocator::pool_sizes(512);
+
+        assert_eq!(sizes.len(), POOL_SIZE_RATIOS.len());
+        let count_of = |ty| sizes.iter().find(|size| size.ty == ty).unwrap().descriptor_count;
+        assert_eq!(count_of(vk::DescriptorType::UNIFORM_BUFFER), 1024);
+        assert_eq!(count_of(vk::DescriptorType::COMBINED_IMAGE_SAMPLER), 2048);
+        assert_eq!(count_of(vk::DescriptorType::SAMPLER), 512);
+    }
+}
@@ -0,0 +1,437 @@
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+use std::ptr;
+
+use ash::vk;
+
+use crate::utility::trace::{self, TraceEvent};
+
+/// Scopes beyond this many in one frame are not timed.
+const MAX_SCOPES_PER_FRAME: u32 = 64;
+/// Frames the per-scope averages are taken over.
+pub const AVERAGE_FRAME_COUNT: usize = 60;
+/// Frames kept for trace export.
+const TRACE_FRAME_COUNT: usize = 240;
+
+const GPU_TRACE_PROCESS_ID: u32 = 1;
+
+struct ScopeQueries {
+    name: String,
+    depth: u32,
+    start_query: u32,
+    end_query: Option<u32>,
+}
+
+struct FrameQueries {
+    query_pool: vk::QueryPool,
+    scopes: RefCell<Vec<ScopeQueries>>,
+    depth: Cell<u32>,
+    is_submitted: bool,
+}
+
+/// A scope as resolved from the timestamps, in nanoseconds since an arbitrary GPU epoch.
+#[derive(Clone)]
+pub struct GpuScopeTiming {
+    pub name: String,
+    pub depth: u32,
+    pub start_ns: f64,
+    pub end_ns: f64,
+}
+
+struct ScopeAverage {
+    name: String,
+    /// per frame total of the scopes with this name
+    samples: VecDeque<f64>,
+    frames_since_seen: usize,
+}
+
+/// Times named scopes of the command buffer with timestamp queries. Every frame in flight has its own
+/// query pool, which is only read back once that frame is retired, so reading never stalls.
+pub struct GpuProfiler {
+    frames: Vec<FrameQueries>,
+    current_frame: usize,
+
+    /// nanoseconds per timestamp tick
+    timestamp_period: f64,
+    timestamp_mask: u64,
+    is_supported: bool,
+
+    averages: Vec<ScopeAverage>,
+    history: VecDeque<Vec<GpuScopeTiming>>,
+}
+
+impl GpuProfiler {
+    /// `timestamp_valid_bits` is the one of the queue family the profiled command buffers are submitted to.
+    pub fn new(
+        device: &ash::Device,
+        limits: &vk::PhysicalDeviceLimits,
+        timestamp_valid_bits: u32,
+        frames_in_flight: usize,
+    ) -> GpuProfiler {
+        let is_supported = timestamp_valid_bits > 0 && limits.timestamp_period > 0.0;
+
+        let query_pool_create_info = vk::QueryPoolCreateInfo {
+            s_type: vk::StructureType::QUERY_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::QueryPoolCreateFlags::empty(),
+            query_type: vk::QueryType::TIMESTAMP,
+            query_count: MAX_SCOPES_PER_FRAME * 2,
+            pipeline_statistics: vk::QueryPipelineStatisticFlags::empty(),
+        };
+
+        let frames = if is_supported {
+            (0..frames_in_flight)
+                .map(|_| FrameQueries {
+                    query_pool: unsafe {
+                        device
+                            .create_query_pool(&query_pool_create_info, None)
+                            .expect("Failed to create timestamp Query Pool!")
+                    },
+                    scopes: RefCell::new(vec![]),
+                    depth: Cell::new(0),
+                    is_submitted: false,
+                })
+                .collect()
+        } else {
+            vec![]
+        };
+
+        GpuProfiler {
+            frames,
+            current_frame: 0,
+            timestamp_period: limits.timestamp_period as f64,
+            timestamp_mask: if timestamp_valid_bits >= 64 { u64::MAX } else { (1 << timestamp_valid_bits) - 1 },
+            is_supported,
+            averages: vec![],
+            history: VecDeque::with_capacity(TRACE_FRAME_COUNT),
+        }
+    }
+
+    pub fn is_supported(&self) -> bool {
+        self.is_supported
+    }
+
+    /// Reads back the timings `frame` recorded last time and resets its queries.
+    /// The frame has to be retired on the GPU and `command_buffer` must be outside a render pass.
+    pub fn begin_frame(&mut self, device: &ash::Device, frame: usize, command_buffer: vk::CommandBuffer) {
+        if !self.is_supported {
+            return;
+        }
+
+        self.current_frame = frame;
+
+        if self.frames[frame].is_submitted {
+            if let Some(timings) = self.read_back(device, frame) {
+                self.add_timings(timings);
+            }
+        }
+
+        let frame_queries = &mut self.frames[frame];
+        frame_queries.scopes.get_mut().clear();
+        frame_queries.depth.set(0);
+        frame_queries.is_submitted = false;
+
+        unsafe { device.cmd_reset_query_pool(command_buffer, frame_queries.query_pool, 0, MAX_SCOPES_PER_FRAME * 2) };
+    }
+
+    /// Marks the current frame's queries as submitted, so they are read back when the frame comes around again.
+    pub fn end_frame(&mut self) {
+        if !self.is_supported {
+            return;
+        }
+
+        let frame_queries = &mut self.frames[self.current_frame];
+        debug_assert!(frame_queries.depth.get() == 0, "GPU profiler scope left open at the end of the frame");
+        frame_queries.is_submitted = true;
+    }
+
+    /// Opens a scope. Pass the returned id to `end_scope` on the same command buffer.
+    pub fn begin_scope(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, name: &str) -> Option<usize> {
+        if !self.is_supported {
+            return None;
+        }
+
+        let frame_queries = &self.frames[self.current_frame];
+        let mut scopes = frame_queries.scopes.borrow_mut();
+        if scopes.len() as u32 >= MAX_SCOPES_PER_FRAME {
+            return None;
+        }
+
+        let depth = frame_queries.depth.get();
+        let start_query = scopes.len() as u32 * 2;
+        scopes.push(ScopeQueries {
+            name: name.to_string(),
+            depth,
+            start_query,
+            end_query: None,
+        });
+        frame_queries.depth.set(depth + 1);
+
+        unsafe {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                frame_queries.query_pool,
+                start_query,
+            );
+        }
+
+        Some(scopes.len() - 1)
+    }
+
+    pub fn end_scope(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, scope: Option<usize>) {
+        let scope = match scope {
+            Some(scope) => scope,
+            None => return,
+        };
+
+        let frame_queries = &self.frames[self.current_frame];
+        let mut scopes = frame_queries.scopes.borrow_mut();
+        let end_query = scopes[scope].start_query + 1;
+        scopes[scope].end_query = Some(end_query);
+        frame_queries.depth.set(frame_queries.depth.get() - 1);
+
+        unsafe {
+            device.cmd_write_timestamp(
+                command_buffer,
+                vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                frame_queries.query_pool,
+                end_query,
+            );
+        }
+    }
+
+    /// Times everything `record` writes into `command_buffer`.
+    pub fn scope<R, F: FnOnce() -> R>(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        name: &str,
+        record: F,
+    ) -> R {
+        let scope = self.begin_scope(device, command_buffer, name);
+        let result = record();
+        self.end_scope(device, command_buffer, scope);
+
+        result
+    }
+
+    /// Average GPU time per scope in milliseconds over the last `AVERAGE_FRAME_COUNT` frames,
+    /// in the order the scopes were first seen.
+    pub fn averages(&self) -> Vec<(String, f64)> {
+        self.averages
+            .iter()
+            .map(|average| {
+                let total: f64 = average.samples.iter().sum();
+                (average.name.clone(), total / average.samples.len().max(1) as f64 / 1_000_000.0)
+            })
+            .collect()
+    }
+
+    /// The most recent frames, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &[GpuScopeTiming]> {
+        self.history.iter().map(|timings| timings.as_slice())
+    }
+
+    /// Writes the recent frames as a Chrome trace / Perfetto JSON file.
+    pub fn write_chrome_trace(&self, path: &Path) -> io::Result<()> {
+        let epoch_ns = self.history
+            .iter()
+            .flat_map(|timings| timings.iter())
+            .map(|timing| timing.start_ns)
+            .fold(f64::INFINITY, f64::min);
+
+        let events: Vec<TraceEvent> = self.history
+            .iter()
+            .flat_map(|timings| timings.iter())
+            .map(|timing| TraceEvent {
+                name: timing.name.clone(),
+                category: "gpu",
+                process_id: GPU_TRACE_PROCESS_ID,
+                thread_id: 0,
+                start_us: (timing.start_ns - epoch_ns) / 1000.0,
+                duration_us: (timing.end_ns - timing.start_ns) / 1000.0,
+            })
+            .collect();
+
+        trace::write_chrome_trace(BufWriter::new(File::create(path)?), &events)
+    }
+
+    fn read_back(&self, device: &ash::Device, frame: usize) -> Option<Vec<GpuScopeTiming>> {
+        let frame_queries = &self.frames[frame];
+        let scopes = frame_queries.scopes.borrow();
+        if scopes.is_empty() {
+            return Some(vec![]);
+        }
+
+        let mut timestamps = vec![0_u64; scopes.len() * 2];
+        let query_result = unsafe {
+            device.get_query_pool_results(
+                frame_queries.query_pool,
+                0,
+                timestamps.len() as u32,
+                &mut timestamps,
+                vk::QueryResultFlags::TYPE_64,
+            )
+        };
+
+        // NOT_READY only if the frame is not actually retired; then the frame is skipped instead of waited for
+        if query_result.is_err() {
+            return None;
+        }
+
+        Some(self.resolve_timings(&scopes, &timestamps))
+    }
+
+    /// Turns the raw timestamps of a frame into the timings of its ended scopes.
+    fn resolve_timings(&self, scopes: &[ScopeQueries], timestamps: &[u64]) -> Vec<GpuScopeTiming> {
+        scopes
+            .iter()
+            .filter_map(|scope| {
+                let end_query = scope.end_query?;
+                let start = timestamps[scope.start_query as usize] & self.timestamp_mask;
+                let end = timestamps[end_query as usize] & self.timestamp_mask;
+                // the counter may wrap around within the valid bits
+                let duration = end.wrapping_sub(start) & self.timestamp_mask;
+
+                let start_ns = start as f64 * self.timestamp_period;
+                Some(GpuScopeTiming {
+                    name: scope.name.clone(),
+                    depth: scope.depth,
+                    start_ns,
+                    end_ns: start_ns + duration as f64 * self.timestamp_period,
+                })
+            })
+            .collect()
+    }
+
+    fn add_timings(&mut self, timings: Vec<GpuScopeTiming>) {
+        // a scope recorded several times in a frame, e.g. once per pass, counts as one sample of its total
+        let mut frame_totals: Vec<(&str, f64)> = vec![];
+        for timing in timings.iter() {
+            match frame_totals.iter_mut().find(|(name, _)| *name == timing.name) {
+                Some((_, total)) => *total += timing.end_ns - timing.start_ns,
+                None => frame_totals.push((&timing.name, timing.end_ns - timing.start_ns)),
+            }
+        }
+
+        for average in self.averages.iter_mut() {
+            average.frames_since_seen += 1;
+        }
+
+        for (name, total) in frame_totals {
+            let index = match self.averages.iter().position(|average| average.name == name) {
+                Some(index) => index,
+                None => {
+                    self.averages.push(ScopeAverage {
+                        name: name.to_string(),
+                        samples: VecDeque::with_capacity(AVERAGE_FRAME_COUNT),
+                        frames_since_seen: 0,
+                    });
+                    self.averages.len() - 1
+                },
+            };
+
+            let average = &mut self.averages[index];
+            if average.samples.len() == AVERAGE_FRAME_COUNT {
+                average.samples.pop_front();
+            }
+            average.samples.push_back(total);
+            average.frames_since_seen = 0;
+        }
+
+        // scopes that are no longer recorded, e.g. a pass that was removed, drop out of the averages
+        self.averages.retain(|average| average.frames_since_seen < AVERAGE_FRAME_COUNT);
+
+        if self.history.len() == TRACE_FRAME_COUNT {
+            self.history.pop_front();
+        }
+        self.history.push_back(timings);
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for frame_queries in self.frames.drain(..) {
+            unsafe { device.destroy_query_pool(frame_queries.query_pool, None) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A profiler without query pools, fed timestamps directly.
+    fn host_profiler() -> GpuProfiler {
+        GpuProfiler {
+            frames: vec![],
+            current_frame: 0,
+            timestamp_period: 2.0,
+            timestamp_mask: u32::MAX as u64,
+            is_supported: true,
+            averages: vec![],
+            history: VecDeque::new(),
+        }
+    }
+
+    fn scope(name: &str, index: u32, is_ended: bool) -> ScopeQueries {
+        ScopeQueries {
+            name: name.to_string(),
+            depth: 0,
+            start_query: index * 2,
+            end_query: if is_ended { Some(index * 2 + 1) } else { None },
+        }
+    }
+
+    fn average_ms(profiler: &GpuProfiler, name: &str) -> Option<f64> {
+        profiler.averages().into_iter().find(|(scope, _)| scope == name).map(|(_, average)| average)
+    }
+
+    #[test]
+    fn ticks_become_nanoseconds_across_a_counter_wrap() {
+        let profiler = host_profiler();
+        let scopes = [scope("shadow", 0, true), scope("main", 1, true), scope("unfinished", 2, false)];
+        let timestamps = [100, 600, 0xFFFF_FF00, 0x1_0000_0100, 700, 0];
+
+        let timings = profiler.resolve_timings(&scopes, &timestamps);
+        assert_eq!(timings.len(), 2);
+        assert_eq!((timings[0].start_ns, timings[0].end_ns), (200.0, 1200.0));
+        // only the valid bits count, so the end is 0x100 after wrapping
+        assert_eq!(timings[1].end_ns - timings[1].start_ns, 0x200 as f64 * 2.0);
+    }
+
+    #[test]
+    fn averages_sum_each_frame_and_average_over_frames() {
+        let mut profiler = host_profiler();
+        let scopes = [scope("shadow", 0, true), scope("main", 1, true), scope("shadow", 2, true)];
+
+        let first = profiler.resolve_timings(&scopes, &[100, 600, 600, 1600, 1600, 1850]);
+        profiler.add_timings(first);
+        let second = profiler.resolve_timings(&scopes[..2], &[0, 250, 0, 3000]);
+        profiler.add_timings(second);
+
+        // shadow: 1500 ns then 500 ns, main: 2000 ns then 6000 ns
+        assert!((average_ms(&profiler, "shadow").unwrap() - 0.001).abs() < 1e-12);
+        assert!((average_ms(&profiler, "main").unwrap() - 0.004).abs() < 1e-12);
+        assert_eq!(profiler.history().count(), 2);
+    }
+
+    #[test]
+    fn averages_keep_the_latest_frames_and_drop_unseen_scopes() {
+        let mut profiler = host_profiler();
+        let scopes = [scope("pass", 0, true)];
+
+        profiler.add_timings(profiler.resolve_timings(&scopes, &[0, 1_000_000]));
+        for _ in 0..AVERAGE_FRAME_COUNT {
+            profiler.add_timings(profiler.resolve_timings(&scopes, &[0, 500_000]));
+        }
+        assert!((average_ms(&profiler, "pass").unwrap() - 1.0).abs() < 1e-9);
+
+        for _ in 0..AVERAGE_FRAME_COUNT {
+            profiler.add_timings(vec![]);
+        }
+        assert_eq!(average_ms(&profiler, "pass"), None);
+    }
+}
@@ -0,0 +1,495 @@
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use ash::vk;
+use imgui::{DrawCmd, DrawData};
+
+use crate::vk::command::CommandRecorder;
+use crate::vk::descriptor::{DescriptorAllocator, DescriptorBinding, DescriptorLayoutCache, DescriptorWriter};
+use crate::vk::pod::Pod;
+use crate::vk::render_device::{PipelineState, RenderPassKey, TextureId, VkRenderDevice};
+use crate::vk::render_graph::{Access, RenderGraph, ResourceHandle};
+use crate::vk::shader_compiler::ShaderCompiler;
+use crate::vk::shader_variants::PipelineShader;
+use crate::vk::timeline::{DeletionQueue, TimelinePoint};
+use crate::vk::vertex::{AttributeDescriptions, BindingDescriptions, Vertex};
+
+const UI_PIPELINE_NAME: &str = "imgui";
+
+/// Vertices and indices each frame's buffers start out with room for.
+const INITIAL_VERTEX_CAPACITY: usize = 4096;
+const INITIAL_INDEX_CAPACITY: usize = 8192;
+
+/// A vertex of imgui's draw lists, laid out like `imgui::DrawVert`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Vertex)]
+pub struct UiVertex {
+    pub pos: [f32; 2],
+    pub uv: [f32; 2],
+    pub col: [u8; 4],
+}
+
+const _: () = assert!(std::mem::size_of::<UiVertex>() == std::mem::size_of::<imgui::DrawVert>());
+
+/// Maps imgui's display rectangle to clip space, pushed to the vertex shader.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct UiTransform {
+    scale: [f32; 2],
+    translate: [f32; 2],
+}
+
+unsafe impl Pod for UiTransform {}
+
+/// One `DrawCmd::Elements` with its offsets into the frame's merged buffers.
+#[derive(Debug, Clone, Copy)]
+struct UiDrawCommand {
+    clip_rect: [f32; 4],
+    index_count: u32,
+    first_index: u32,
+    vertex_offset: i32,
+}
+
+/// Host visible vertices and indices of one frame in flight, regrown when a frame needs more.
+struct UiBuffers {
+    vertex_buffer: vk::Buffer,
+    vertex_memory: vk::DeviceMemory,
+    vertex_capacity: usize,
+    index_buffer: vk::Buffer,
+    index_memory: vk::DeviceMemory,
+    index_capacity: usize,
+}
+
+/// Draws imgui's draw data over the swapchain image after the post-process chain.
+/// Only the font atlas is bound, so user textures in the draw lists are drawn with it as well.
+pub struct ImguiRenderer {
+    shaders: Vec<PipelineShader>,
+    render_pass: vk::RenderPass,
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+
+    font_layout: vk::DescriptorSetLayout,
+    font_set: Option<vk::DescriptorSet>,
+    font_texture: Option<TextureId>,
+
+    /// the draw data of the next recorded frame
+    vertices: Vec<UiVertex>,
+    indices: Vec<imgui::DrawIdx>,
+    commands: Vec<UiDrawCommand>,
+    transform: UiTransform,
+
+    frame_buffers: Vec<UiBuffers>,
+}
+
+impl ImguiRenderer {
+    /// `render_pass_key` is the one of the swapchain the UI is drawn into.
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        shader_compiler: &mut ShaderCompiler,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        shader_source_dir: &Path,
+        render_pass_key: &RenderPassKey,
+        frames_in_flight: usize,
+    ) -> Result<ImguiRenderer, String> {
+        let font_layout = descriptor_layout_cache.get_layout(
+            device,
+            &[DescriptorBinding::new(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)]);
+
+        let mut renderer = ImguiRenderer {
+            shaders: vec![
+                PipelineShader::new(&shader_source_dir.join("imgui.vert")),
+                PipelineShader::new(&shader_source_dir.join("imgui.frag")),
+            ],
+            render_pass: VkRenderDevice::create_overlay_render_pass(device, render_pass_key),
+            pipeline: vk::Pipeline::null(),
+            pipeline_layout: vk::PipelineLayout::null(),
+            font_layout,
+            font_set: None,
+            font_texture: None,
+            vertices: vec![],
+            indices: vec![],
+            commands: vec![],
+            transform: UiTransform::default(),
+            frame_buffers: (0..frames_in_flight)
+                .map(|_| ImguiRenderer::create_buffers(device, memory_properties, INITIAL_VERTEX_CAPACITY, INITIAL_INDEX_CAPACITY))
+                .collect(),
+        };
+
+        if let Err(error) = renderer.rebuild_pipeline(device, shader_compiler) {
+            renderer.destroy(device);
+            return Err(error);
+        }
+
+        Ok(renderer)
+    }
+
+    /// Points the font descriptor at a newly uploaded atlas and returns the texture it replaces.
+    /// A new set is allocated since frames in flight may still read the old one.
+    pub fn set_font_texture(
+        &mut self,
+        device: &ash::Device,
+        descriptor_allocator: &mut DescriptorAllocator,
+        texture: TextureId,
+        view: vk::ImageView,
+        sampler: vk::Sampler,
+    ) -> Option<TextureId> {
+        let font_set = descriptor_allocator.allocate(device, self.font_layout);
+        DescriptorWriter::new()
+            .combined_image_sampler(0, view, sampler)
+            .update(device, font_set);
+
+        self.font_set = Some(font_set);
+        self.font_texture.replace(texture)
+    }
+
+    /// Rebuilds the render pass and the pipeline for a swapchain that is no longer compatible with them.
+    pub fn set_render_pass_key(
+        &mut self,
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        render_pass_key: &RenderPassKey,
+    ) -> Result<(), String> {
+        unsafe { device.destroy_render_pass(self.render_pass, None) };
+        self.render_pass = VkRenderDevice::create_overlay_render_pass(device, render_pass_key);
+
+        self.rebuild_pipeline(device, shader_compiler)
+    }
+
+    /// Rebuilds the pipeline if its shaders depend on any of the `changed` files,
+    /// keeping the last good one if that fails.
+    pub fn reload_shaders(
+        &mut self,
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        changed: &[PathBuf],
+    ) -> Vec<(String, Result<(), String>)> {
+        let mut is_pipeline_affected = false;
+        for shader in self.shaders.iter_mut() {
+            let is_shader_affected = shader_compiler
+                .dependencies(&shader.asset.source)
+                .iter()
+                .any(|dependency| changed.contains(dependency));
+
+            if is_shader_affected {
+                shader.asset.reload();
+                is_pipeline_affected = true;
+            }
+        }
+
+        if !is_pipeline_affected {
+            return vec![];
+        }
+
+        unsafe {
+            device
+                .device_wait_idle()
+                .expect("Failed to wait device idle")
+        };
+
+        vec![(UI_PIPELINE_NAME.to_string(), self.rebuild_pipeline(device, shader_compiler))]
+    }
+
+    fn rebuild_pipeline(&mut self, device: &ash::Device, shader_compiler: &mut ShaderCompiler) -> Result<(), String> {
+        let push_constant_ranges = [vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::VERTEX,
+            offset: 0,
+            size: std::mem::size_of::<UiTransform>() as u32,
+        }];
+
+        // imgui winds its triangles either way
+        let state = PipelineState {
+            cull_mode: vk::CullModeFlags::NONE,
+            alpha_blend: true,
+        };
+
+        let (pipeline, pipeline_layout) = VkRenderDevice::create_graphics_pipeline(
+            device,
+            shader_compiler,
+            &mut self.shaders,
+            self.render_pass,
+            &state,
+            &[self.font_layout],
+            &push_constant_ranges,
+            &UiVertex::get_binding_descriptions(),
+            &UiVertex::get_attribute_descriptions())?;
+
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+
+        self.pipeline = pipeline;
+        self.pipeline_layout = pipeline_layout;
+
+        Ok(())
+    }
+
+    /// Copies out what the next recorded frame draws, `draw_data` is only valid until imgui's next frame.
+    pub fn set_draw_data(&mut self, draw_data: &DrawData) {
+        self.vertices.clear();
+        self.indices.clear();
+        self.commands.clear();
+
+        let [width, height] = draw_data.display_size;
+        if width <= 0.0 || height <= 0.0 {
+            return;
+        }
+
+        let scale = [2.0 / width, 2.0 / height];
+        self.transform = UiTransform {
+            scale,
+            translate: [
+                -1.0 - draw_data.display_pos[0] * scale[0],
+                -1.0 - draw_data.display_pos[1] * scale[1],
+            ],
+        };
+
+        for draw_list in draw_data.draw_lists() {
+            let first_vertex = self.vertices.len();
+            let first_index = self.indices.len();
+
+            self.vertices.extend(draw_list.vtx_buffer().iter().map(|vertex| UiVertex {
+                pos: vertex.pos,
+                uv: vertex.uv,
+                col: vertex.col,
+            }));
+            self.indices.extend_from_slice(draw_list.idx_buffer());
+
+            for command in draw_list.commands() {
+                match command {
+                    DrawCmd::Elements { count, cmd_params } => {
+                        // clip rectangles are in display coordinates, scissors in framebuffer pixels
+                        let clip_rect = [
+                            (cmd_params.clip_rect[0] - draw_data.display_pos[0]) * draw_data.framebuffer_scale[0],
+                            (cmd_params.clip_rect[1] - draw_data.display_pos[1]) * draw_data.framebuffer_scale[1],
+                            (cmd_params.clip_rect[2] - draw_data.display_pos[0]) * draw_data.framebuffer_scale[0],
+                            (cmd_params.clip_rect[3] - draw_data.display_pos[1]) * draw_data.framebuffer_scale[1],
+                        ];
+
+                        self.commands.push(UiDrawCommand {
+                            clip_rect,
+                            index_count: count as u32,
+                            first_index: (first_index + cmd_params.idx_offset) as u32,
+                            vertex_offset: (first_vertex + cmd_params.vtx_offset) as i32,
+                        });
+                    },
+                    // the state is set once for the whole pass and no command changes it
+                    DrawCmd::ResetRenderState => {},
+                    DrawCmd::RawCallback { .. } => {},
+                }
+            }
+        }
+    }
+
+    /// Writes the draw data to the frame's buffers, growing them if it does not fit.
+    pub fn prepare_frame(
+        &mut self,
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        deletion_queue: &mut DeletionQueue,
+        last_submitted: TimelinePoint,
+        frame: usize,
+    ) {
+        if self.commands.is_empty() {
+            return;
+        }
+
+        let buffers = &mut self.frame_buffers[frame];
+        if self.vertices.len() > buffers.vertex_capacity || self.indices.len() > buffers.index_capacity {
+            let vertex_capacity = buffers.vertex_capacity.max(self.vertices.len().next_power_of_two());
+            let index_capacity = buffers.index_capacity.max(self.indices.len().next_power_of_two());
+
+            let old_buffers = std::mem::replace(
+                buffers,
+                ImguiRenderer::create_buffers(device, memory_properties, vertex_capacity, index_capacity));
+            deletion_queue.push(last_submitted, move |device| unsafe {
+                ImguiRenderer::destroy_buffers(device, &old_buffers);
+            });
+        }
+
+        unsafe {
+            ImguiRenderer::write_buffer(device, buffers.vertex_memory, &self.vertices);
+            ImguiRenderer::write_buffer(device, buffers.index_memory, &self.indices);
+        }
+    }
+
+    unsafe fn write_buffer<T: Copy>(device: &ash::Device, memory: vk::DeviceMemory, data: &[T]) {
+        let data_ptr = device
+            .map_memory(
+                memory,
+                0,
+                std::mem::size_of_val(data) as u64,
+                vk::MemoryMapFlags::empty(),
+            )
+            .expect("Failed to Map Memory") as *mut T;
+
+        data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
+
+        device.unmap_memory(memory);
+    }
+
+    /// Adds a pass drawing the UI over `backbuffer`, nothing if the frame has no UI or no fonts yet.
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        backbuffer: ResourceHandle,
+        swapchain_framebuffer: vk::Framebuffer,
+        swapchain_extent: vk::Extent2D,
+        limits: &'a vk::PhysicalDeviceLimits,
+        frame: usize,
+    ) {
+        let font_set = match self.font_set {
+            Some(font_set) if !self.commands.is_empty() => font_set,
+            _ => return,
+        };
+
+        graph.add_pass(
+            "ui",
+            |builder| {
+                builder.write(backbuffer, Access::ColorAttachmentWrite);
+            },
+            move |context| {
+                let recorder = CommandRecorder::new(context.device, context.command_buffer, limits);
+                self.record(&recorder, swapchain_framebuffer, swapchain_extent, font_set, frame);
+            },
+        );
+    }
+
+    fn record(
+        &self,
+        recorder: &CommandRecorder,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        font_set: vk::DescriptorSet,
+        frame: usize,
+    ) {
+        let device = recorder.device;
+        let command_buffer = recorder.command_buffer;
+        let buffers = &self.frame_buffers[frame];
+
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+            p_next: ptr::null(),
+            render_pass: self.render_pass,
+            framebuffer,
+            render_area,
+            clear_value_count: 0,
+            p_clear_values: ptr::null(),
+        };
+
+        unsafe {
+            device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[font_set],
+                &[],
+            );
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &[buffers.vertex_buffer], &[0]);
+            device.cmd_bind_index_buffer(command_buffer, buffers.index_buffer, 0, vk::IndexType::UINT16);
+        }
+
+        let commands = match recorder.push_constants(self.pipeline_layout, vk::ShaderStageFlags::VERTEX, 0, &self.transform) {
+            Ok(()) => self.commands.as_slice(),
+            Err(error) => {
+                println!("[Imgui] Skipping the UI: {}", error);
+                &[]
+            },
+        };
+
+        for command in commands.iter() {
+            let min_x = command.clip_rect[0].max(0.0);
+            let min_y = command.clip_rect[1].max(0.0);
+            let max_x = command.clip_rect[2].min(extent.width as f32);
+            let max_y = command.clip_rect[3].min(extent.height as f32);
+            if max_x <= min_x || max_y <= min_y {
+                continue;
+            }
+
+            let scissor = vk::Rect2D {
+                offset: vk::Offset2D { x: min_x as i32, y: min_y as i32 },
+                extent: vk::Extent2D {
+                    width: (max_x - min_x) as u32,
+                    height: (max_y - min_y) as u32,
+                },
+            };
+
+            unsafe {
+                device.cmd_set_scissor(command_buffer, 0, &[scissor]);
+                device.cmd_draw_indexed(command_buffer, command.index_count, 1, command.first_index, command.vertex_offset, 0);
+            }
+        }
+
+        unsafe { device.cmd_end_render_pass(command_buffer) };
+    }
+
+    fn create_buffers(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        vertex_capacity: usize,
+        index_capacity: usize,
+    ) -> UiBuffers {
+        let (vertex_buffer, vertex_memory) = VkRenderDevice::create_buffer(
+            device,
+            (std::mem::size_of::<UiVertex>() * vertex_capacity) as u64,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            memory_properties,
+        );
+        let (index_buffer, index_memory) = VkRenderDevice::create_buffer(
+            device,
+            (std::mem::size_of::<imgui::DrawIdx>() * index_capacity) as u64,
+            vk::BufferUsageFlags::INDEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            memory_properties,
+        );
+
+        UiBuffers {
+            vertex_buffer,
+            vertex_memory,
+            vertex_capacity,
+            index_buffer,
+            index_memory,
+            index_capacity,
+        }
+    }
+
+    unsafe fn destroy_buffers(device: &ash::Device, buffers: &UiBuffers) {
+        device.destroy_buffer(buffers.vertex_buffer, None);
+        device.free_memory(buffers.vertex_memory, None);
+        device.destroy_buffer(buffers.index_buffer, None);
+        device.free_memory(buffers.index_memory, None);
+    }
+
+    /// The font texture belongs to the render device and is freed with its other textures.
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for buffers in self.frame_buffers.drain(..) {
+                ImguiRenderer::destroy_buffers(device, &buffers);
+            }
+
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
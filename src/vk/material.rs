@@ -0,0 +1,63 @@
+use crate::vk::render_device::{SamplerId, TextureId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// fragments below `PbrMaterial::alpha_cutoff` are discarded, the rest are opaque
+    Mask,
+    Blend,
+}
+
+/// A texture a material samples, and which set of texture coordinates it samples with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialTexture {
+    pub texture: TextureId,
+    pub sampler: SamplerId,
+    pub tex_coord: u32,
+}
+
+/// Metallic-roughness PBR parameters, as in glTF. Textures are `T`, so importers can fill them
+/// with their own references before the textures exist on the device.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PbrMaterial<T = MaterialTexture> {
+    pub name: String,
+    /// linear RGBA, multiplied with the base color texture
+    pub base_color_factor: [f32; 4],
+    pub base_color_texture: Option<T>,
+    pub metallic_factor: f32,
+    pub roughness_factor: f32,
+    /// roughness in green, metalness in blue
+    pub metallic_roughness_texture: Option<T>,
+    pub normal_texture: Option<T>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<T>,
+    pub occlusion_strength: f32,
+    pub emissive_factor: [f32; 3],
+    pub emissive_texture: Option<T>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl<T> PbrMaterial<T> {
+    /// The same material with every texture replaced by what `f` maps it to.
+    pub fn map_textures<U, F: FnMut(&T) -> U>(&self, mut f: F) -> PbrMaterial<U> {
+        PbrMaterial {
+            name: self.name.clone(),
+            base_color_factor: self.base_color_factor,
+            base_color_texture: self.base_color_texture.as_ref().map(&mut f),
+            metallic_factor: self.metallic_factor,
+            roughness_factor: self.roughness_factor,
+            metallic_roughness_texture: self.metallic_roughness_texture.as_ref().map(&mut f),
+            normal_texture: self.normal_texture.as_ref().map(&mut f),
+            normal_scale: self.normal_scale,
+            occlusion_texture: self.occlusion_texture.as_ref().map(&mut f),
+            occlusion_strength: self.occlusion_strength,
+            emissive_factor: self.emissive_factor,
+            emissive_texture: self.emissive_texture.as_ref().map(&mut f),
+            alpha_mode: self.alpha_mode,
+            alpha_cutoff: self.alpha_cutoff,
+            double_sided: self.double_sided,
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use ash::vk;
+
+use crate::vk::pod::{self, Pod};
+use crate::vk::render_device::{MaterialId, VkRenderDevice};
+use crate::vk::timeline::{DeletionQueue, GpuTimeline, TimelinePoint};
+use crate::vk::vertex::{AttributeDescriptions, BindingDescriptions, MeshVertex, VertexLayout};
+
+/// Index data for `Mesh::new`. 16-bit indices halve the index buffer for meshes under 65536 vertices.
+#[derive(Clone, Copy, Debug)]
+pub enum MeshIndices<'a> {
+    U16(&'a [u16]),
+    U32(&'a [u32]),
+}
+
+impl<'a> MeshIndices<'a> {
+    pub fn len(&self) -> usize {
+        match self {
+            MeshIndices::U16(indices) => indices.len(),
+            MeshIndices::U32(indices) => indices.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn index_type(&self) -> vk::IndexType {
+        match self {
+            MeshIndices::U16(_) => vk::IndexType::UINT16,
+            MeshIndices::U32(_) => vk::IndexType::UINT32,
+        }
+    }
+}
+
+/// A range of a mesh's indices drawn as one `cmd_draw_indexed`, e.g. the part of a model using one material.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Submesh {
+    pub first_index: u32,
+    pub index_count: u32,
+    /// added to every index of the range before fetching vertices
+    pub vertex_offset: i32,
+}
+
+/// A submesh and the material it is drawn with, an index into the materials of the model it came from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MaterialSubmesh {
+    pub submesh: Submesh,
+    pub material: Option<usize>,
+}
+
+/// Geometry of an imported model on the CPU, as handed to `VkRenderDevice::create_mesh_data`.
+#[derive(Clone, Debug, Default)]
+pub struct MeshData {
+    pub name: String,
+    pub vertices: Vec<MeshVertex>,
+    pub indices: Vec<u32>,
+    pub submeshes: Vec<MaterialSubmesh>,
+}
+
+impl MeshData {
+    pub fn submeshes(&self) -> Vec<Submesh> {
+        self.submeshes.iter().map(|submesh| submesh.submesh).collect()
+    }
+}
+
+/// The vertices of one vertex buffer binding, as bytes, and the layout of the type they were made of.
+#[derive(Clone, Debug)]
+pub struct VertexStream<'a> {
+    pub data: &'a [u8],
+    pub vertex_count: usize,
+    pub layout: VertexLayout,
+}
+
+impl<'a> VertexStream<'a> {
+    /// `V: Pod` keeps padding out of the bytes, `#[derive(Vertex)]` implements all three.
+    pub fn new<V: Pod + BindingDescriptions + AttributeDescriptions>(vertices: &'a [V]) -> VertexStream<'a> {
+        VertexStream {
+            data: pod::slice_bytes(vertices),
+            vertex_count: vertices.len(),
+            layout: VertexLayout::of::<V>(),
+        }
+    }
+}
+
+/// Vertex and index buffers in device local memory, drawn as a list of submeshes. Each vertex
+/// stream gets its own buffer, bound at the binding of its position in the list.
+pub struct Mesh {
+    /// the streams as a pipeline has to consume them, each at the binding of its position
+    pub vertex_layout: VertexLayout,
+    pub vertex_buffers: Vec<vk::Buffer>,
+    vertex_buffers_memory: Vec<vk::DeviceMemory>,
+    pub index_buffer: vk::Buffer,
+    index_buffer_memory: vk::DeviceMemory,
+    pub index_type: vk::IndexType,
+
+    pub vertex_count: u32,
+    pub index_count: u32,
+    pub submeshes: Vec<Submesh>,
+    /// the material of each submesh, `None` for the device's default one
+    pub materials: Vec<Option<MaterialId>>,
+}
+
+impl Mesh {
+    /// Uploads the vertex streams and indices through staging buffers on `timeline`, so the mesh
+    /// can be drawn in any later submission on it. No `submeshes` draws all indices as one.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        timeline: &mut GpuTimeline,
+        deletion_queue: &mut DeletionQueue,
+        vertex_streams: &[VertexStream],
+        indices: MeshIndices,
+        submeshes: &[Submesh],
+    ) -> Result<Mesh, String> {
+        let vertex_count = vertex_streams.first().map_or(0, |stream| stream.vertex_count);
+        if vertex_count == 0 || indices.is_empty() {
+            return Err(String::from("Failed to create mesh: it has no vertices or no indices"));
+        }
+        if let Some(stream) = vertex_streams.iter().find(|stream| stream.vertex_count != vertex_count) {
+            return Err(format!(
+                "Failed to create mesh: its vertex streams have {} and {} vertices", vertex_count, stream.vertex_count));
+        }
+
+        let mut vertex_layout = VertexLayout::new();
+        for (binding, stream) in vertex_streams.iter().enumerate() {
+            vertex_layout = vertex_layout.append(&stream.layout.clone().at_binding(binding as u32))
+                .map_err(|error| format!("Failed to create mesh: {}", error))?;
+        }
+
+        let index_count = indices.len() as u32;
+        let submeshes = if submeshes.is_empty() {
+            vec![Submesh {
+                first_index: 0,
+                index_count,
+                vertex_offset: 0,
+            }]
+        } else {
+            submeshes.to_vec()
+        };
+
+        Mesh::check_submeshes(&submeshes, indices, vertex_count)?;
+
+        let (vertex_buffers, vertex_buffers_memory) = vertex_streams.iter()
+            .map(|stream| VkRenderDevice::create_device_local_buffer(
+                device,
+                memory_properties,
+                command_pool,
+                timeline,
+                deletion_queue,
+                stream.data,
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+            ))
+            .unzip();
+
+        let (index_buffer, index_buffer_memory) = match indices {
+            MeshIndices::U16(indices) => VkRenderDevice::create_device_local_buffer(
+                device, memory_properties, command_pool, timeline, deletion_queue, indices, vk::BufferUsageFlags::INDEX_BUFFER),
+            MeshIndices::U32(indices) => VkRenderDevice::create_device_local_buffer(
+                device, memory_properties, command_pool, timeline, deletion_queue, indices, vk::BufferUsageFlags::INDEX_BUFFER),
+        };
+
+        Ok(Mesh {
+            vertex_layout,
+            vertex_buffers,
+            vertex_buffers_memory,
+            index_buffer,
+            index_buffer_memory,
+            index_type: indices.index_type(),
+            vertex_count: vertex_count as u32,
+            index_count,
+            materials: vec![None; submeshes.len()],
+            submeshes,
+        })
+    }
+
+    /// Binds the vertex streams from binding 0 on. Stays valid for any number of `draw`s.
+    pub fn bind(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        let offsets = vec![0; self.vertex_buffers.len()];
+
+        unsafe {
+            device.cmd_bind_vertex_buffers(command_buffer, 0, &self.vertex_buffers, &offsets);
+            device.cmd_bind_index_buffer(command_buffer, self.index_buffer, 0, self.index_type);
+        }
+    }
+
+    /// Draws every submesh. The mesh has to be bound.
+    pub fn draw(&self, device: &ash::Device, command_buffer: vk::CommandBuffer) {
+        self.draw_instances(device, command_buffer, 0, 1);
+    }
+
+    /// Draws every submesh `instance_count` times, reading per-instance streams from `first_instance` on.
+    pub fn draw_instances(&self, device: &ash::Device, command_buffer: vk::CommandBuffer, first_instance: u32, instance_count: u32) {
+        for submesh in self.submeshes.iter() {
+            self.draw_submesh(device, command_buffer, submesh, first_instance, instance_count);
+        }
+    }
+
+    pub fn draw_submesh(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        submesh: &Submesh,
+        first_instance: u32,
+        instance_count: u32,
+    ) {
+        unsafe {
+            device.cmd_draw_indexed(
+                command_buffer,
+                submesh.index_count,
+                instance_count,
+                submesh.first_index,
+                submesh.vertex_offset,
+                first_instance,
+            );
+        }
+    }
+
+    /// Destroys the buffers once the GPU is past `point`, the last submission that may draw the mesh.
+    pub fn destroy_after(self, deletion_queue: &mut DeletionQueue, point: TimelinePoint) {
+        deletion_queue.push(point, move |device| self.destroy(device));
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            for (&buffer, &memory) in self.vertex_buffers.iter().zip(self.vertex_buffers_memory.iter()) {
+                device.destroy_buffer(buffer, None);
+                device.free_memory(memory, None);
+            }
+            device.destroy_buffer(self.index_buffer, None);
+            device.free_memory(self.index_buffer_memory, None);
+        }
+    }
+
+    /// Every submesh has to stay within the indices, and its indices plus its vertex offset within the vertices.
+    fn check_submeshes(submeshes: &[Submesh], indices: MeshIndices, vertex_count: usize) -> Result<(), String> {
+        let index_count = indices.len() as u64;
+
+        for submesh in submeshes.iter() {
+            if submesh.first_index as u64 + submesh.index_count as u64 > index_count {
+                return Err(format!(
+                    "Failed to create mesh: submesh {:?} reaches past its {} indices", submesh, index_count));
+            }
+
+            let index_range = match indices {
+                MeshIndices::U16(indices) => indices_range(&indices[Mesh::range(submesh)]),
+                MeshIndices::U32(indices) => indices_range(&indices[Mesh::range(submesh)]),
+            };
+            if let Some((min_index, max_index)) = index_range {
+                if min_index as i64 + (submesh.vertex_offset as i64) < 0 {
+                    return Err(format!(
+                        "Failed to create mesh: submesh {:?} indexes before its first vertex", submesh));
+                }
+                if max_index as i64 + submesh.vertex_offset as i64 >= vertex_count as i64 {
+                    return Err(format!(
+                        "Failed to create mesh: submesh {:?} indexes past its {} vertices", submesh, vertex_count));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn range(submesh: &Submesh) -> std::ops::Range<usize> {
+        submesh.first_index as usize..(submesh.first_index + submesh.index_count) as usize
+    }
+}
+
+fn indices_range<I: Copy + Into<u32>>(indices: &[I]) -> Option<(u32, u32)> {
+    let min = indices.iter().map(|&index| index.into()).min()?;
+    let max = indices.iter().map(|&index| index.into()).max()?;
+    Some((min, max))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn submesh(first_index: u32, index_count: u32, vertex_offset: i32) -> Submesh {
+        Submesh {
+            first_index,
+            index_count,
+            vertex_offset,
+        }
+    }
+
+    #[test]
+    fn submeshes_must_stay_within_indices_and_vertices() {
+        let indices = MeshIndices::U16(&[0, 1, 2, 2, 3, 0]);
+
+        assert!(Mesh::check_submeshes(&[submesh(0, 3, 0), submesh(3, 3, 0)], indices, 4).is_ok());
+        assert!(Mesh::check_submeshes(&[submesh(3, 3, 1)], indices, 5).is_ok());
+        assert!(Mesh::check_submeshes(&[submesh(3, 4, 0)], indices, 4).is_err());
+        assert!(Mesh::check_submeshes(&[submesh(0, 3, 2)], indices, 4).is_err());
+    }
+
+    #[test]
+    fn negative_vertex_offsets_must_not_reach_before_the_first_vertex() {
+        let indices = MeshIndices::U32(&[4, 5, 6, 0, 1, 2]);
+
+        // the first triangle's indices still land on vertices 0 to 2
+        assert!(Mesh::check_submeshes(&[submesh(0, 3, -4)], indices, 7).is_ok());
+        let error = Mesh::check_submeshes(&[submesh(0, 6, -4)], indices, 7).unwrap_err();
+        assert!(error.contains("before its first vertex"), "{}", error);
+    }
+}
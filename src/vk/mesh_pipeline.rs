@@ -0,0 +1,166 @@
+use std::path::PathBuf;
+
+use ash::vk;
+
+use crate::vk::pod::Pod;
+use crate::vk::render_device::{PipelineState, VkRenderDevice};
+use crate::vk::shader_compiler::ShaderCompiler;
+use crate::vk::shader_variants::PipelineShader;
+use crate::vk::timeline::{DeletionQueue, TimelinePoint};
+use crate::vk::vertex::{InstanceTransform, VertexLayout};
+
+/// The factors of a submesh's material, pushed to the fragment stage before its draw.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MaterialConstants {
+    /// linear RGBA, multiplied with the base color texture
+    pub base_color_factor: [f32; 4],
+    /// fragments with a lower alpha are discarded, 0 keeps them all
+    pub alpha_cutoff: f32,
+}
+
+// four floats and one, without padding
+unsafe impl Pod for MaterialConstants {}
+
+impl Default for MaterialConstants {
+    fn default() -> MaterialConstants {
+        MaterialConstants {
+            base_color_factor: [1.0; 4],
+            alpha_cutoff: 0.0,
+        }
+    }
+}
+
+/// A pipeline of the main pass. It draws the meshes whose `Mesh::vertex_layout` is `mesh_layout`,
+/// reading their `InstanceTransform`s from the first binding after the mesh's streams and their
+/// `MaterialConstants` from the push constants.
+pub struct MeshPipeline {
+    pub name: String,
+    pub mesh_layout: VertexLayout,
+    pub shaders: Vec<PipelineShader>,
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    pub pipeline: vk::Pipeline,
+    pub pipeline_layout: vk::PipelineLayout,
+}
+
+impl MeshPipeline {
+    pub fn new(
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        name: &str,
+        mut shaders: Vec<PipelineShader>,
+        mesh_layout: VertexLayout,
+        render_pass: vk::RenderPass,
+        set_layouts: &[vk::DescriptorSetLayout],
+    ) -> Result<MeshPipeline, String> {
+        let vertex_layout = MeshPipeline::vertex_layout_for(&mesh_layout)
+            .map_err(|error| format!("Failed to create {} pipeline: {}", name, error))?;
+        let (pipeline, pipeline_layout) = MeshPipeline::create_pipeline(
+            device, shader_compiler, &mut shaders, render_pass, set_layouts, &vertex_layout)?;
+
+        Ok(MeshPipeline {
+            name: name.to_string(),
+            mesh_layout,
+            shaders,
+            set_layouts: set_layouts.to_vec(),
+            pipeline,
+            pipeline_layout,
+        })
+    }
+
+    /// The binding the instance transforms are read from.
+    pub fn instance_binding(&self) -> u32 {
+        self.mesh_layout.next_binding()
+    }
+
+    /// The mesh's streams followed by the instance transforms.
+    pub fn vertex_layout(&self) -> VertexLayout {
+        MeshPipeline::vertex_layout_for(&self.mesh_layout).unwrap()
+    }
+
+    fn vertex_layout_for(mesh_layout: &VertexLayout) -> Result<VertexLayout, String> {
+        mesh_layout.clone().append(&VertexLayout::of::<InstanceTransform>().at_binding(mesh_layout.next_binding()))
+    }
+
+    /// Rebuilds the pipeline if any of the `changed` files is one of its shaders or included by them.
+    /// Returns `None` when it is not affected. The old pipeline is destroyed after `point`, the
+    /// last submission that may still use it, and kept if the new one fails to build.
+    pub fn reload_shaders(
+        &mut self,
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        render_pass: vk::RenderPass,
+        changed: &[PathBuf],
+        deletion_queue: &mut DeletionQueue,
+        point: TimelinePoint,
+    ) -> Option<Result<(), String>> {
+        let mut is_pipeline_affected = false;
+        for shader in self.shaders.iter_mut() {
+            let is_shader_affected = shader_compiler
+                .dependencies(&shader.asset.source)
+                .iter()
+                .any(|dependency| changed.contains(dependency));
+
+            if is_shader_affected {
+                shader.asset.reload();
+                is_pipeline_affected = true;
+            }
+        }
+
+        if !is_pipeline_affected {
+            return None;
+        }
+
+        let vertex_layout = self.vertex_layout();
+        let (pipeline, pipeline_layout) = match MeshPipeline::create_pipeline(
+            device, shader_compiler, &mut self.shaders, render_pass, &self.set_layouts, &vertex_layout) {
+            Ok(pipeline) => pipeline,
+            Err(error) => return Some(Err(error)),
+        };
+
+        let (old_pipeline, old_pipeline_layout) = (self.pipeline, self.pipeline_layout);
+        deletion_queue.push(point, move |device| unsafe {
+            device.destroy_pipeline(old_pipeline, None);
+            device.destroy_pipeline_layout(old_pipeline_layout, None);
+        });
+
+        self.pipeline = pipeline;
+        self.pipeline_layout = pipeline_layout;
+        Some(Ok(()))
+    }
+
+    pub fn push_constant_range() -> vk::PushConstantRange {
+        vk::PushConstantRange {
+            stage_flags: vk::ShaderStageFlags::FRAGMENT,
+            offset: 0,
+            size: std::mem::size_of::<MaterialConstants>() as u32,
+        }
+    }
+
+    fn create_pipeline(
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        shaders: &mut [PipelineShader],
+        render_pass: vk::RenderPass,
+        set_layouts: &[vk::DescriptorSetLayout],
+        vertex_layout: &VertexLayout,
+    ) -> Result<(vk::Pipeline, vk::PipelineLayout), String> {
+        VkRenderDevice::create_graphics_pipeline(
+            device,
+            shader_compiler,
+            shaders,
+            render_pass,
+            &PipelineState::default(),
+            set_layouts,
+            &[MeshPipeline::push_constant_range()],
+            vertex_layout.bindings(),
+            vertex_layout.attributes())
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            device.destroy_pipeline_layout(self.pipeline_layout, None);
+        }
+    }
+}
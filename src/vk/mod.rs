@@ -3,4 +3,21 @@ pub mod debug;
 pub mod constants;
 pub mod swap_chain;
 pub mod platforms;
-pub mod vertex;
\ No newline at end of file
+pub mod vertex;
+pub mod shader_compiler;
+pub mod shader_variants;
+pub mod descriptor;
+pub mod command;
+pub mod render_graph;
+pub mod render_target;
+pub mod post_process;
+pub mod compute;
+pub mod timeline;
+pub mod parallel;
+pub mod gpu_profiler;
+pub mod mesh;
+pub mod texture;
+pub mod material;
+pub mod imgui_renderer;
+pub mod mesh_pipeline;
+pub mod pod;
\ No newline at end of file
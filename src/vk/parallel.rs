@@ -0,0 +1,237 @@
+use std::ops::Range;
+use std::panic::{self, AssertUnwindSafe};
+use std::ptr;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+use ash::vk;
+
+use crate::vk::command::CommandRecorder;
+
+/// What the secondary command buffers continue: a subpass of a render pass instance.
+#[derive(Clone, Copy)]
+pub struct SecondaryInheritance {
+    pub render_pass: vk::RenderPass,
+    pub subpass: u32,
+    pub framebuffer: vk::Framebuffer,
+}
+
+/// The command pool and secondary command buffer of one worker for one frame in flight.
+struct WorkerFrame {
+    command_pool: vk::CommandPool,
+    command_buffer: vk::CommandBuffer,
+}
+
+type RecordFn<'a> = dyn Fn(&CommandRecorder, Range<usize>) + Sync + 'a;
+
+/// One range to record. `record` really borrows from the `ParallelRecorder::record` call,
+/// which doesn't return before the worker has answered.
+struct Job {
+    frame: usize,
+    inheritance: SecondaryInheritance,
+    limits: vk::PhysicalDeviceLimits,
+    range: Range<usize>,
+    record: &'static RecordFn<'static>,
+}
+
+type JobResult = thread::Result<vk::CommandBuffer>;
+
+/// A recording thread that lives as long as the recorder, with its pools of every frame in flight.
+struct Worker {
+    jobs: Sender<Job>,
+    results: Receiver<JobResult>,
+    thread: JoinHandle<Vec<WorkerFrame>>,
+}
+
+/// Records secondary command buffers on a pool of threads started with the recorder. Each worker owns
+/// a transient pool per frame in flight, so pools are only ever touched by one thread and reset once that frame is retired.
+pub struct ParallelRecorder {
+    workers: Vec<Worker>,
+}
+
+impl ParallelRecorder {
+    pub fn new(device: &ash::Device, queue_family_index: u32, frames_in_flight: usize) -> ParallelRecorder {
+        let worker_count = thread::available_parallelism().map(|count| count.get()).unwrap_or(1);
+
+        let workers = (0..worker_count)
+            .map(|index| {
+                let frames = (0..frames_in_flight)
+                    .map(|_| ParallelRecorder::create_worker_frame(device, queue_family_index))
+                    .collect();
+                ParallelRecorder::spawn_worker(index, device.clone(), frames)
+            })
+            .collect();
+
+        ParallelRecorder { workers }
+    }
+
+    pub fn worker_count(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Splits `item_count` items into contiguous ranges, records each range into its own secondary
+    /// command buffer on a worker thread and returns the buffers in range order, ready for `cmd_execute_commands`.
+    /// The frame has to be retired on the GPU, since its pools are reset here.
+    pub fn record<F>(
+        &self,
+        frame: usize,
+        inheritance: SecondaryInheritance,
+        limits: &vk::PhysicalDeviceLimits,
+        item_count: usize,
+        record: F,
+    ) -> Vec<vk::CommandBuffer>
+    where
+        F: Fn(&CommandRecorder, Range<usize>) + Sync,
+    {
+        let chunk_count = self.workers.len().min(item_count);
+        if chunk_count == 0 {
+            return vec![];
+        }
+
+        let record: &RecordFn = &record;
+        // every job sent below is answered before this call returns, so `record` outlives them
+        let record: &'static RecordFn<'static> = unsafe { std::mem::transmute(record) };
+
+        let chunk_size = item_count.div_ceil(chunk_count);
+        let sent_to: Vec<Option<&Worker>> = self.workers
+            .iter()
+            .zip((0..item_count).step_by(chunk_size).map(|start| start..(start + chunk_size).min(item_count)))
+            .map(|(worker, range)| {
+                let job = Job { frame, inheritance, limits: *limits, range, record };
+                worker.jobs.send(job).ok().map(|_| worker)
+            })
+            .collect();
+
+        // wait for every worker before giving up on one, so none is still using `record`
+        let results: Vec<Option<JobResult>> = sent_to
+            .iter()
+            .map(|worker| worker.and_then(|worker| worker.results.recv().ok()))
+            .collect();
+        results
+            .into_iter()
+            .map(|result| match result {
+                Some(Ok(command_buffer)) => command_buffer,
+                Some(Err(panic)) => panic::resume_unwind(panic),
+                None => panic!("A secondary recording worker has stopped"),
+            })
+            .collect()
+    }
+
+    fn spawn_worker(index: usize, device: ash::Device, frames: Vec<WorkerFrame>) -> Worker {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (result_sender, results) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name(format!("Record worker {}", index))
+            .spawn(move || {
+                // the recorder drops its senders when it is destroyed, which ends the loop
+                for job in job_receiver {
+                    let worker_frame = &frames[job.frame];
+                    let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                        ParallelRecorder::record_worker(&device, worker_frame, job.inheritance, &job.limits, job.range, job.record);
+                        worker_frame.command_buffer
+                    }));
+
+                    if result_sender.send(result).is_err() {
+                        break;
+                    }
+                }
+                frames
+            })
+            .expect("Failed to spawn a recording worker thread!");
+
+        Worker { jobs, results, thread }
+    }
+
+    fn record_worker(
+        device: &ash::Device,
+        worker_frame: &WorkerFrame,
+        inheritance: SecondaryInheritance,
+        limits: &vk::PhysicalDeviceLimits,
+        range: Range<usize>,
+        record: &RecordFn,
+    ) {
+        crate::profile_scope!("record secondary");
+
+        let inheritance_info = vk::CommandBufferInheritanceInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_INHERITANCE_INFO,
+            p_next: ptr::null(),
+            render_pass: inheritance.render_pass,
+            subpass: inheritance.subpass,
+            framebuffer: inheritance.framebuffer,
+            occlusion_query_enable: vk::FALSE,
+            query_flags: vk::QueryControlFlags::empty(),
+            pipeline_statistics: vk::QueryPipelineStatisticFlags::empty(),
+        };
+
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT
+                | vk::CommandBufferUsageFlags::RENDER_PASS_CONTINUE,
+            p_inheritance_info: &inheritance_info,
+        };
+
+        unsafe {
+            device
+                .reset_command_pool(worker_frame.command_pool, vk::CommandPoolResetFlags::empty())
+                .expect("Failed to reset worker Command Pool!");
+            device
+                .begin_command_buffer(worker_frame.command_buffer, &command_buffer_begin_info)
+                .expect("Failed to begin recording secondary Command Buffer!");
+        }
+
+        record(&CommandRecorder::new(device, worker_frame.command_buffer, limits), range);
+
+        unsafe {
+            device
+                .end_command_buffer(worker_frame.command_buffer)
+                .expect("Failed to record secondary Command Buffer!");
+        }
+    }
+
+    fn create_worker_frame(device: &ash::Device, queue_family_index: u32) -> WorkerFrame {
+        let command_pool_create_info = vk::CommandPoolCreateInfo {
+            s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandPoolCreateFlags::TRANSIENT,
+            queue_family_index,
+        };
+
+        let command_pool = unsafe {
+            device
+                .create_command_pool(&command_pool_create_info, None)
+                .expect("Failed to create worker Command Pool!")
+        };
+
+        let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            command_buffer_count: 1,
+            command_pool,
+            level: vk::CommandBufferLevel::SECONDARY,
+        };
+
+        let command_buffer = unsafe {
+            device
+                .allocate_command_buffers(&command_buffer_allocate_info)
+                .expect("Failed to allocate secondary Command Buffer!")[0]
+        };
+
+        WorkerFrame {
+            command_pool,
+            command_buffer,
+        }
+    }
+
+    /// Stops the workers and destroys their pools.
+    pub fn destroy(&mut self, device: &ash::Device) {
+        for worker in self.workers.drain(..) {
+            drop(worker.jobs);
+            let frames = worker.thread.join().expect("A recording worker thread panicked!");
+            for worker_frame in frames {
+                unsafe { device.destroy_command_pool(worker_frame.command_pool, None) };
+            }
+        }
+    }
+}
@@ -7,7 +7,7 @@ use ash::extensions::khr::Surface;
 
 use crate::rhi::window;
 
-#[cfg(all(windows))]
+#[cfg(windows)]
 pub fn required_extension_names() -> Vec<*const i8> {
     vec![
         Surface::name().as_ptr(),
@@ -16,6 +16,9 @@ pub fn required_extension_names() -> Vec<*const i8> {
     ]
 }
 
+/// # Safety
+///
+/// `instance` has to be created from `entry` with `required_extension_names` enabled, and outlive the surface.
 #[cfg(target_os = "windows")]
 pub unsafe fn create_surface(
     entry: &ash::Entry,
@@ -0,0 +1,39 @@
+use cgmath::{Matrix2, Matrix3, Matrix4, Point2, Point3, Vector2, Vector3, Vector4};
+
+/// Plain data that can be viewed as bytes and read back from GPU memory: no padding, no
+/// references, and every bit pattern is a valid value. `#[derive(Vertex)]` implements it.
+///
+/// # Safety
+///
+/// Only implement it for `#[repr(C)]` types whose fields are all `Pod`, with no padding
+/// between or after them.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($type:ty),*) => {
+        $(unsafe impl Pod for $type {})*
+    };
+}
+
+impl_pod!(u8, i8, u16, i16, u32, i32, u64, i64, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+// cgmath's vectors, points and matrices are `#[repr(C)]` runs of one scalar type
+unsafe impl<S: Pod> Pod for Vector2<S> {}
+unsafe impl<S: Pod> Pod for Vector3<S> {}
+unsafe impl<S: Pod> Pod for Vector4<S> {}
+unsafe impl<S: Pod> Pod for Point2<S> {}
+unsafe impl<S: Pod> Pod for Point3<S> {}
+unsafe impl<S: Pod> Pod for Matrix2<S> {}
+unsafe impl<S: Pod> Pod for Matrix3<S> {}
+unsafe impl<S: Pod> Pod for Matrix4<S> {}
+
+pub fn bytes_of<T: Pod>(value: &T) -> &[u8] {
+    slice_bytes(std::slice::from_ref(value))
+}
+
+pub fn slice_bytes<T: Pod>(values: &[T]) -> &[u8] {
+    // `T: Pod` has no padding, so every byte of the slice is initialized
+    unsafe { std::slice::from_raw_parts(values.as_ptr() as *const u8, std::mem::size_of_val(values)) }
+}
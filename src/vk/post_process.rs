@@ -0,0 +1,385 @@
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use ash::vk;
+
+use crate::vk::descriptor::{DescriptorBinding, DescriptorLayoutCache, DescriptorWriter, FrameDescriptorAllocators};
+use crate::vk::render_device::{PipelineState, RenderPassKey, VkRenderDevice};
+use crate::vk::render_graph::{Access, RenderGraph, ResourceHandle};
+use crate::vk::render_target::{RenderTarget, RenderTargetDesc};
+use crate::vk::shader_compiler::ShaderCompiler;
+use crate::vk::shader_variants::{PipelineShader, SpecializationConstants};
+
+/// One fullscreen pass of the chain. Every pass samples the previous output at binding 0 of set 0.
+pub struct PostProcessPassDesc {
+    pub name: String,
+    pub fragment_shader: PathBuf,
+    pub specialization: SpecializationConstants,
+    /// `None` renders into the swapchain, which only the last pass of a chain may do.
+    pub target: Option<RenderTargetDesc>,
+}
+
+impl PostProcessPassDesc {
+    pub fn new(name: &str, fragment_shader: &Path, target: Option<RenderTargetDesc>) -> PostProcessPassDesc {
+        PostProcessPassDesc {
+            name: name.to_string(),
+            fragment_shader: fragment_shader.to_path_buf(),
+            specialization: SpecializationConstants::new(),
+            target,
+        }
+    }
+
+    pub fn with_specialization(mut self, specialization: SpecializationConstants) -> PostProcessPassDesc {
+        self.specialization = specialization;
+        self
+    }
+}
+
+struct PostProcessPass {
+    name: String,
+    shaders: Vec<PipelineShader>,
+
+    target: Option<RenderTarget>,
+    render_pass: vk::RenderPass,
+    framebuffer: vk::Framebuffer,
+
+    pipeline: vk::Pipeline,
+    pipeline_layout: vk::PipelineLayout,
+}
+
+/// Fullscreen passes run one after another over the scene color,
+/// the last one writing (usually tonemapping) into the swapchain image.
+pub struct PostProcessChain {
+    passes: Vec<PostProcessPass>,
+
+    sampler: vk::Sampler,
+    input_layout: vk::DescriptorSetLayout,
+}
+
+impl PostProcessChain {
+    /// `present_render_pass` is the swapchain render pass the last pass draws with.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        shader_compiler: &mut ShaderCompiler,
+        descriptor_layout_cache: &mut DescriptorLayoutCache,
+        vertex_shader: &Path,
+        pass_descs: Vec<PostProcessPassDesc>,
+        present_render_pass: vk::RenderPass,
+        swapchain_extent: vk::Extent2D,
+    ) -> Result<PostProcessChain, String> {
+        match pass_descs.iter().position(|desc| desc.target.is_none()) {
+            Some(index) if index == pass_descs.len() - 1 => {},
+            _ => return Err(String::from("Exactly the last post-process pass has to render into the swapchain")),
+        }
+
+        let input_layout = descriptor_layout_cache.get_layout(
+            device,
+            &[DescriptorBinding::new(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)]);
+
+        let mut chain = PostProcessChain {
+            passes: vec![],
+            sampler: PostProcessChain::create_sampler(device),
+            input_layout,
+        };
+
+        for desc in pass_descs {
+            let target = desc
+                .target
+                .map(|target_desc| RenderTarget::new(device, memory_properties, target_desc, swapchain_extent));
+
+            let (render_pass, framebuffer) = match &target {
+                Some(target) => {
+                    let render_pass = VkRenderDevice::create_render_pass(device, &RenderPassKey::new(target.desc.format));
+                    (render_pass, RenderTarget::create_framebuffer(device, render_pass, &[target]))
+                },
+                None => (present_render_pass, vk::Framebuffer::null()),
+            };
+
+            chain.passes.push(PostProcessPass {
+                name: desc.name,
+                shaders: vec![
+                    PipelineShader::new(vertex_shader),
+                    PipelineShader::new(&desc.fragment_shader).with_specialization(desc.specialization),
+                ],
+                target,
+                render_pass,
+                framebuffer,
+                pipeline: vk::Pipeline::null(),
+                pipeline_layout: vk::PipelineLayout::null(),
+            });
+        }
+
+        for i in 0..chain.passes.len() {
+            if let Err(error) = chain.rebuild_pipeline(device, shader_compiler, i) {
+                chain.destroy(device);
+                return Err(error);
+            }
+        }
+
+        Ok(chain)
+    }
+
+    /// Recreates the targets whose size follows the swapchain.
+    pub fn resize(
+        &mut self,
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        swapchain_extent: vk::Extent2D,
+    ) {
+        for pass in self.passes.iter_mut() {
+            let target = match pass.target.as_mut() {
+                Some(target) => target,
+                None => continue,
+            };
+
+            if target.resize(device, memory_properties, swapchain_extent) {
+                unsafe { device.destroy_framebuffer(pass.framebuffer, None) };
+                pass.framebuffer = RenderTarget::create_framebuffer(device, pass.render_pass, &[target]);
+            }
+        }
+    }
+
+    /// Rebuilds the swapchain pass against a new, incompatible swapchain render pass.
+    pub fn set_present_render_pass(
+        &mut self,
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        present_render_pass: vk::RenderPass,
+    ) -> Result<(), String> {
+        let output = self.passes.len() - 1;
+        self.passes[output].render_pass = present_render_pass;
+
+        self.rebuild_pipeline(device, shader_compiler, output)
+    }
+
+    /// Rebuilds the passes whose shaders depend on any of the `changed` files,
+    /// keeping the last good pipeline of a pass that fails.
+    pub fn reload_shaders(
+        &mut self,
+        device: &ash::Device,
+        shader_compiler: &mut ShaderCompiler,
+        changed: &[PathBuf],
+    ) -> Vec<(String, Result<(), String>)> {
+        let mut results = vec![];
+
+        for i in 0..self.passes.len() {
+            let mut is_pass_affected = false;
+            for shader in self.passes[i].shaders.iter_mut() {
+                let is_shader_affected = shader_compiler
+                    .dependencies(&shader.asset.source)
+                    .iter()
+                    .any(|dependency| changed.contains(dependency));
+
+                if is_shader_affected {
+                    shader.asset.reload();
+                    is_pass_affected = true;
+                }
+            }
+
+            if is_pass_affected {
+                unsafe {
+                    device
+                        .device_wait_idle()
+                        .expect("Failed to wait device idle")
+                };
+
+                let result = self.rebuild_pipeline(device, shader_compiler, i);
+                results.push((self.passes[i].name.clone(), result));
+            }
+        }
+
+        results
+    }
+
+    fn rebuild_pipeline(&mut self, device: &ash::Device, shader_compiler: &mut ShaderCompiler, index: usize) -> Result<(), String> {
+        let pass = &mut self.passes[index];
+
+        let (pipeline, pipeline_layout) = VkRenderDevice::create_graphics_pipeline(
+            device,
+            shader_compiler,
+            &mut pass.shaders,
+            pass.render_pass,
+            &PipelineState::default(),
+            &[self.input_layout],
+            &[],
+            &[],
+            &[])?;
+
+        unsafe {
+            device.destroy_pipeline(pass.pipeline, None);
+            device.destroy_pipeline_layout(pass.pipeline_layout, None);
+        }
+
+        pass.pipeline = pipeline;
+        pass.pipeline_layout = pipeline_layout;
+
+        Ok(())
+    }
+
+    /// Allocates this frame's input descriptors; pass 0 samples `scene_color`.
+    pub fn prepare_frame(
+        &self,
+        device: &ash::Device,
+        frame_descriptor_allocators: &mut FrameDescriptorAllocators,
+        frame: usize,
+        scene_color: &RenderTarget,
+    ) -> Vec<vk::DescriptorSet> {
+        let mut descriptor_sets = vec![];
+
+        for i in 0..self.passes.len() {
+            let input = match i {
+                0 => scene_color,
+                _ => self.passes[i - 1].target.as_ref().unwrap(),
+            };
+
+            let descriptor_set = frame_descriptor_allocators.allocate(device, frame, self.input_layout);
+            DescriptorWriter::new()
+                .combined_image_sampler(0, input.view, self.sampler)
+                .update(device, descriptor_set);
+
+            descriptor_sets.push(descriptor_set);
+        }
+
+        descriptor_sets
+    }
+
+    /// Adds the chain to `graph`, reading `scene_color` and finishing in `backbuffer`.
+    pub fn add_to_graph<'a>(
+        &'a self,
+        graph: &mut RenderGraph<'a>,
+        scene_color: ResourceHandle,
+        backbuffer: ResourceHandle,
+        descriptor_sets: Vec<vk::DescriptorSet>,
+        swapchain_framebuffer: vk::Framebuffer,
+        swapchain_extent: vk::Extent2D,
+    ) {
+        let mut input = scene_color;
+
+        for (i, pass) in self.passes.iter().enumerate() {
+            let (output, framebuffer, extent) = match &pass.target {
+                Some(target) => (graph.import_image(&pass.name, target.imported()), pass.framebuffer, target.extent),
+                None => (backbuffer, swapchain_framebuffer, swapchain_extent),
+            };
+            let descriptor_set = descriptor_sets[i];
+
+            graph.add_pass(
+                &pass.name,
+                |builder| {
+                    builder
+                        .read(input, Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER))
+                        .write(output, Access::ColorAttachmentWrite);
+                },
+                move |context| pass.record(context.device, context.command_buffer, framebuffer, extent, descriptor_set),
+            );
+
+            input = output;
+        }
+    }
+
+    fn create_sampler(device: &ash::Device) -> vk::Sampler {
+        let sampler_create_info = vk::SamplerCreateInfo {
+            s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::SamplerCreateFlags::empty(),
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: vk::FALSE,
+            max_anisotropy: 1.0,
+            compare_enable: vk::FALSE,
+            compare_op: vk::CompareOp::ALWAYS,
+            min_lod: 0.0,
+            max_lod: 0.0,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+            unnormalized_coordinates: vk::FALSE,
+        };
+
+        unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create post-process Sampler!")
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for pass in self.passes.drain(..) {
+                device.destroy_pipeline(pass.pipeline, None);
+                device.destroy_pipeline_layout(pass.pipeline_layout, None);
+
+                // the swapchain pass borrows the render pass of the device
+                if let Some(target) = pass.target {
+                    device.destroy_framebuffer(pass.framebuffer, None);
+                    device.destroy_render_pass(pass.render_pass, None);
+                    target.destroy(device);
+                }
+            }
+
+            device.destroy_sampler(self.sampler, None);
+        }
+    }
+}
+
+impl PostProcessPass {
+    fn record(
+        &self,
+        device: &ash::Device,
+        command_buffer: vk::CommandBuffer,
+        framebuffer: vk::Framebuffer,
+        extent: vk::Extent2D,
+        descriptor_set: vk::DescriptorSet,
+    ) {
+        let clear_values = [vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
+            },
+        }];
+
+        let render_area = vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent,
+        };
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: extent.width as f32,
+            height: extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+            p_next: ptr::null(),
+            render_pass: self.render_pass,
+            framebuffer,
+            render_area,
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
+        };
+
+        unsafe {
+            device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, vk::SubpassContents::INLINE);
+            device.cmd_bind_pipeline(command_buffer, vk::PipelineBindPoint::GRAPHICS, self.pipeline);
+            device.cmd_set_viewport(command_buffer, 0, &viewports);
+            device.cmd_set_scissor(command_buffer, 0, &[render_area]);
+            device.cmd_bind_descriptor_sets(
+                command_buffer,
+                vk::PipelineBindPoint::GRAPHICS,
+                self.pipeline_layout,
+                0,
+                &[descriptor_set],
+                &[],
+            );
+            device.cmd_draw(command_buffer, 3, 1, 0, 0);
+            device.cmd_end_render_pass(command_buffer);
+        }
+    }
+}
@@ -1,17 +1,14 @@
 use ash::vk;
-use ash::vk::ValidationCacheCreateInfoEXT;
 use ash;
 use cgmath::SquareMatrix;
 
 use std::ffi::CString;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::ptr;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
-use std::os::raw::c_char;
+use std::os::raw::c_void;
 
-use crate::vk::vertex::AttributeDescriptions;
-use crate::vk::vertex::BindingDescriptions;
 use crate::vk::constants;
 use crate::utility::constants as global_constants;
 use crate::vk::platforms;
@@ -20,35 +17,131 @@ use crate::utility::tools;
 
 use crate::rhi::render_device;
 use crate::rhi::window;
+use crate::core::camera::Camera;
+use crate::core::scene::SceneGraph;
 
 use crate::vk::swap_chain;
+use crate::vk::shader_compiler::ShaderCompiler;
+use crate::vk::shader_variants::{PipelineShader, SpecializationConstants};
+use crate::vk::render_graph::{Access, ImageDesc, ImportedImage, PassContext, RenderGraph, ResourceState, TransientResources};
+use crate::vk::render_target::{RenderTarget, RenderTargetDesc, RenderTargetSize};
+use crate::vk::post_process::{PostProcessChain, PostProcessPassDesc};
+use crate::vk::imgui_renderer::ImguiRenderer;
+use crate::vk::gpu_profiler::GpuProfiler;
+use crate::vk::mesh::{Mesh, MeshData, MeshIndices, Submesh, VertexStream};
+use crate::vk::pod::Pod;
+use crate::vk::mesh_pipeline::{MaterialConstants, MeshPipeline};
+use crate::vk::command::CommandRecorder;
+use crate::vk::texture::{SamplerDesc, Texture};
+use crate::vk::material::{AlphaMode, PbrMaterial};
+use crate::vk::parallel::{ParallelRecorder, SecondaryInheritance};
+use crate::vk::timeline::{DeletionQueue, GpuTimeline, TimelinePoint, TimelineSubmit};
+use crate::vk::descriptor::{DescriptorAllocator, DescriptorBinding, DescriptorLayoutCache, DescriptorWriter, FrameDescriptorAllocators};
 
 use super::swap_chain::VkSpawChain;
 
-use crate::vk::vertex::{Vertex};
+use crate::vk::vertex::{AttributeDescriptions, BindingDescriptions, InstanceTransform, MeshVertex, Vertex, VertexLayout};
 
-use cgmath::{Deg, Matrix4, Point3, Vector3};
+use cgmath::Matrix4;
 
 #[repr(C)]
 #[derive(Clone, Debug, Copy)]
 struct UniformBufferObject {
-    model: Matrix4<f32>,
     view: Matrix4<f32>,
     proj: Matrix4<f32>,
 }
 
+/// A mesh created through `VkRenderDevice::create_mesh`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MeshId(u32);
+
+/// A texture created through `VkRenderDevice::create_texture`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TextureId(u32);
+
+/// A sampler got from `VkRenderDevice::create_sampler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SamplerId(u32);
+
+/// A material created through `VkRenderDevice::create_material`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(u32);
+
+/// What the draws of a material's submeshes bind, at set 1 of the mesh pipelines, and push.
+struct GpuMaterial {
+    descriptor_set: vk::DescriptorSet,
+    constants: MaterialConstants,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct MeshDraw {
+    pub mesh: MeshId,
+    pub model: Matrix4<f32>,
+}
+
+/// Instances of a mesh drawn with one `cmd_draw_indexed`, reading their transforms from
+/// `first_instance` on in the frame's instance buffer.
+#[derive(Clone, Copy, Debug)]
+struct DrawBatch {
+    /// index of the mesh pipeline taking the mesh's vertex layout
+    pipeline: usize,
+    mesh: MeshId,
+    first_instance: u32,
+    instance_count: u32,
+}
+
+/// Host visible per-instance transforms of one frame in flight, regrown when a frame needs more.
+struct InstanceBuffer {
+    buffer: vk::Buffer,
+    memory: vk::DeviceMemory,
+    capacity: usize,
+}
+
 pub struct QueueFamilyIndices {
     pub graphics_family: Option<u32>,
     pub present_family: Option<u32>,
 }
 
+/// Everything that makes two render passes compatible for the pipelines built against them.
+/// Framebuffer size is not part of it, so a resize that keeps the key keeps the pipelines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RenderPassKey {
+    pub color_format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+}
+
+impl RenderPassKey {
+    pub fn new(color_format: vk::Format) -> RenderPassKey {
+        RenderPassKey {
+            color_format,
+            samples: vk::SampleCountFlags::TYPE_1,
+        }
+    }
+}
+
+/// The fixed function state that differs between the engine's pipelines.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PipelineState {
+    pub cull_mode: vk::CullModeFlags,
+    /// blends the output over the attachment by its alpha instead of replacing it
+    pub alpha_blend: bool,
+}
+
+impl Default for PipelineState {
+    fn default() -> PipelineState {
+        PipelineState {
+            cull_mode: vk::CullModeFlags::BACK,
+            alpha_blend: false,
+        }
+    }
+}
+
 pub struct SyncObjects {
     pub image_available_semaphores: Vec<vk::Semaphore>,
     pub render_finished_semaphores: Vec<vk::Semaphore>,
-    pub inflight_fences: Vec<vk::Fence>,
 }
 
-const VERTICES_DATA: [Vertex; 4] = [
+const QUAD_VERTICES: [Vertex; 4] = [
     Vertex {
         pos: [-0.5, -0.5],
         color: [1.0, 0.0, 0.0],
@@ -66,7 +159,19 @@ const VERTICES_DATA: [Vertex; 4] = [
         color: [1.0, 1.0, 1.0],
     },
 ];
-const INDICES_DATA: [u32; 6] = [0, 1, 2, 2, 3, 0];
+const QUAD_INDICES: [u16; 6] = [0, 1, 2, 2, 3, 0];
+
+const GRAPHICS_PIPELINE_NAME: &str = "graphics";
+const MESH_PIPELINE_NAME: &str = "mesh";
+
+/// Instances each frame's instance buffer starts out with room for.
+const INITIAL_INSTANCE_CAPACITY: usize = 64;
+
+impl Default for QueueFamilyIndices {
+    fn default() -> QueueFamilyIndices {
+        QueueFamilyIndices::new()
+    }
+}
 
 impl QueueFamilyIndices {
     pub fn new() -> QueueFamilyIndices {
@@ -82,7 +187,8 @@ impl QueueFamilyIndices {
 }
 pub struct VkRenderDevice {
     instance: ash::Instance,
-    entry: ash::Entry,
+    /// keeps the Vulkan loader alive as long as the instance
+    _entry: ash::Entry,
 
     surface: VkSurface,
 
@@ -90,6 +196,8 @@ pub struct VkRenderDevice {
     debug_messager: vk::DebugUtilsMessengerEXT,
 
     physical_device: vk::PhysicalDevice,
+    pub physical_device_properties: vk::PhysicalDeviceProperties,
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
     pub device: ash::Device,
 
     pub graphics_queue: vk::Queue,
@@ -100,27 +208,64 @@ pub struct VkRenderDevice {
     pub swapchain: swap_chain::VkSpawChain,
 
     pub render_pass: vk::RenderPass,
+    render_pass_key: RenderPassKey,
+    pub scene_render_pass: vk::RenderPass,
+    pub scene_target: RenderTarget,
+    scene_framebuffer: vk::Framebuffer,
+    pub post_process: PostProcessChain,
+    pub imgui_renderer: ImguiRenderer,
     ubo_layout: vk::DescriptorSetLayout,
-    pub pipeline_layout: vk::PipelineLayout,
-    pub graphics_pipeline: vk::Pipeline,
-    
-    vertex_buffer: vk::Buffer,
-    vertex_buffer_memory: vk::DeviceMemory,
+    material_layout: vk::DescriptorSetLayout,
+    /// the pipelines of the main pass, one per mesh vertex layout
+    mesh_pipelines: Vec<MeshPipeline>,
 
-    index_buffer: vk::Buffer,
-    index_buffer_memory: vk::DeviceMemory,
+    pub shader_compiler: ShaderCompiler,
+    
+    /// indexed by `MeshId`, `None` once destroyed. Slots are not reused, so stale ids draw nothing.
+    meshes: Vec<Option<Mesh>>,
+    quad_mesh: MeshId,
+    /// indexed by `TextureId`, slots are not reused like the meshes'
+    textures: Vec<Option<Texture>>,
+    /// indexed by `SamplerId`, kept until the device is dropped
+    samplers: Vec<vk::Sampler>,
+    sampler_ids: HashMap<SamplerDesc, SamplerId>,
+    /// indexed by `MaterialId`, kept until the device is dropped like the samplers
+    materials: Vec<GpuMaterial>,
+    /// white, for submeshes without a material
+    default_material: MaterialId,
 
     uniform_transform: UniformBufferObject,
+    mesh_draws: Vec<MeshDraw>,
+    /// meshes and transforms from `draw_mesh_instanced`, for the next recorded frame only
+    instanced_draws: Vec<(MeshId, u32)>,
+    instanced_transforms: Vec<InstanceTransform>,
+    /// what the frame being recorded draws, built from both lists above
+    draw_batches: Vec<DrawBatch>,
+    /// meshes left out of the draws because no pipeline takes their vertex layout, reported once each
+    unsupported_meshes: HashSet<MeshId>,
+    instance_buffers: Vec<InstanceBuffer>,
     uniform_buffers: Vec<vk::Buffer>,
     uniform_buffers_memory: Vec<vk::DeviceMemory>,
 
-    descriptor_pool: vk::DescriptorPool,
+    pub descriptor_layout_cache: DescriptorLayoutCache,
+    pub descriptor_allocator: DescriptorAllocator,
+    pub frame_descriptor_allocators: FrameDescriptorAllocators,
     descriptor_sets: Vec<vk::DescriptorSet>,
 
     pub command_pool: vk::CommandPool,
     pub command_buffers: Vec<vk::CommandBuffer>,
+    parallel_recorder: ParallelRecorder,
+    pub gpu_profiler: GpuProfiler,
+    render_graph_transients: Vec<TransientResources>,
 
     pub sync_objects: SyncObjects,
+    pub graphics_timeline: GpuTimeline,
+    /// The submission of each frame in flight, waited on before the frame's resources are reused.
+    frame_points: Vec<TimelinePoint>,
+    /// The last upload submitted since the previous frame, waited on by the next frame so its
+    /// transfer writes are visible to the draws.
+    upload_point: Option<TimelinePoint>,
+    pub deletion_queue: DeletionQueue,
     pub current_frame: usize,
 }
 
@@ -131,14 +276,13 @@ impl render_device::RenderDevice for VkRenderDevice {
 impl VkRenderDevice
 {
     pub fn new (window: &window::Window) -> VkRenderDevice {
-        let entry = unsafe {
-            ash::Entry::linked()
-        };
+        let entry = ash::Entry::linked();
         let instance = VkRenderDevice::create_instance(&entry);
         let (debug_units_loader, debug_messager) = debug::setup_debug_utils(&entry, &instance);
         let surface = VkRenderDevice::create_surface(&entry, &instance, window);
         let physical_device = VkRenderDevice::pick_physical_device(&instance, &surface);
-        let (device, indices) = VkRenderDevice::create_device(&instance, physical_device, &constants::VALIDATION, &surface);
+        let physical_device_properties = unsafe { instance.get_physical_device_properties(physical_device) };
+        let (device, indices) = VkRenderDevice::create_device(&instance, physical_device, &surface);
         
         let graphics_queue = unsafe { 
             device.get_device_queue(indices.graphics_family.unwrap(), 0)
@@ -148,7 +292,7 @@ impl VkRenderDevice
             device.get_device_queue(indices.present_family.unwrap(), 0)
         };
 
-        let swapchain = VkSpawChain::create_swapchain(
+        let mut swapchain = VkSpawChain::create_swapchain(
             &instance, 
             &device, 
             physical_device, 
@@ -156,17 +300,60 @@ impl VkRenderDevice
             &indices);
         let swapchain_image_views = swapchain.create_image_views(&device);
 
+        let render_pass_key = RenderPassKey::new(swapchain.swapchain_format);
         let render_pass = VkRenderDevice::create_render_pass(
             &device, 
-            swapchain.swapchain_format);
+            &render_pass_key);
+
+        let mut descriptor_layout_cache = DescriptorLayoutCache::new();
+        let ubo_layout = descriptor_layout_cache.get_layout(
+            &device,
+            &[DescriptorBinding::new(0, vk::DescriptorType::UNIFORM_BUFFER, vk::ShaderStageFlags::VERTEX)]);
+        let material_layout = descriptor_layout_cache.get_layout(
+            &device,
+            &[DescriptorBinding::new(0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, vk::ShaderStageFlags::FRAGMENT)]);
 
-        let ubo_layout = VkRenderDevice::create_descriptor_set_layout(&device);
+        let mut shader_compiler = ShaderCompiler::new(
+            &[Path::new(global_constants::SHADER_SOURCE_DIR)],
+            Path::new(global_constants::SHADER_SPV_DIR))
+            .with_prebuilt_dir(Path::new(global_constants::SHADER_PREBUILT_DIR));
 
-        let (pipeline, pipeline_layout) = VkRenderDevice::create_graphics_pipeline(
-            &device, 
-            &swapchain, 
-            render_pass,
-            ubo_layout);
+        let shader_source_dir = Path::new(global_constants::SHADER_SOURCE_DIR);
+        let mut fragment_specialization = SpecializationConstants::new();
+        fragment_specialization.set(0, 1.0f32);
+
+        let graphics_shaders = vec![
+            PipelineShader::new(&shader_source_dir.join("21-shader-ubo.vert")),
+            PipelineShader::new(&shader_source_dir.join("21-shader-ubo.frag"))
+                .with_specialization(fragment_specialization),
+        ];
+
+        let scene_render_pass = VkRenderDevice::create_render_pass(
+            &device,
+            &RenderPassKey::new(constants::SCENE_COLOR_FORMAT));
+
+        let graphics_pipeline = MeshPipeline::new(
+            &device,
+            &mut shader_compiler,
+            GRAPHICS_PIPELINE_NAME,
+            graphics_shaders,
+            VkRenderDevice::graphics_mesh_layout(),
+            scene_render_pass,
+            &[ubo_layout, material_layout])
+            .expect("Failed to create graphics pipeline!");
+
+        let mesh_pipeline = MeshPipeline::new(
+            &device,
+            &mut shader_compiler,
+            MESH_PIPELINE_NAME,
+            vec![
+                PipelineShader::new(&shader_source_dir.join("mesh.vert")),
+                PipelineShader::new(&shader_source_dir.join("mesh.frag")),
+            ],
+            VertexLayout::of::<MeshVertex>(),
+            scene_render_pass,
+            &[ubo_layout, material_layout])
+            .expect("Failed to create mesh pipeline!");
 
         let framebuffers = VkSpawChain::create_framebuffers(
             &device, 
@@ -174,28 +361,80 @@ impl VkRenderDevice
             &swapchain_image_views, 
             &swapchain.swapchain_extent);
 
-        let command_pool = VkRenderDevice::create_command_pool(
-            &device, 
-            &indices);
+        swapchain.swapchain_image_views = swapchain_image_views;
+        swapchain.swapchain_framebuffers = framebuffers;
 
         let physical_device_memory_properties =
             unsafe { instance.get_physical_device_memory_properties(physical_device) };
 
-        let (vertex_buffer, vertex_buffer_memory) =
-            VkRenderDevice::create_vertex_buffer(
-                &instance, 
-                &device, 
-                &physical_device_memory_properties, 
-                command_pool, 
-                graphics_queue);
+        let scene_target = RenderTarget::new(
+            &device,
+            &physical_device_memory_properties,
+            RenderTargetDesc::new(constants::SCENE_COLOR_FORMAT, RenderTargetSize::SwapchainRelative(1.0)),
+            swapchain.swapchain_extent);
+        let scene_framebuffer = RenderTarget::create_framebuffer(&device, scene_render_pass, &[&scene_target]);
+
+        let mut tonemap_specialization = SpecializationConstants::new();
+        tonemap_specialization.set(0, 1.0f32);
 
-        let (index_buffer, index_buffer_memory) = VkRenderDevice::create_index_buffer(
-            &instance,
+        let post_process = PostProcessChain::new(
+            &device,
+            &physical_device_memory_properties,
+            &mut shader_compiler,
+            &mut descriptor_layout_cache,
+            &shader_source_dir.join("fullscreen.vert"),
+            vec![
+                PostProcessPassDesc::new(
+                    "vignette",
+                    &shader_source_dir.join("post-vignette.frag"),
+                    Some(RenderTargetDesc::new(constants::SCENE_COLOR_FORMAT, RenderTargetSize::SwapchainRelative(1.0)))),
+                PostProcessPassDesc::new("tonemap", &shader_source_dir.join("post-tonemap.frag"), None)
+                    .with_specialization(tonemap_specialization),
+            ],
+            render_pass,
+            swapchain.swapchain_extent)
+            .expect("Failed to create post-process chain!");
+
+        let imgui_renderer = ImguiRenderer::new(
+            &device,
+            &physical_device_memory_properties,
+            &mut shader_compiler,
+            &mut descriptor_layout_cache,
+            shader_source_dir,
+            &render_pass_key,
+            global_constants::MAX_FRAMES_IN_FLIGHT)
+            .expect("Failed to create imgui renderer!");
+
+        let command_pool = VkRenderDevice::create_command_pool(
+            &device, 
+            &indices);
+
+        let mut graphics_timeline = GpuTimeline::new(&device, graphics_queue);
+        let mut deletion_queue = DeletionQueue::new();
+
+        let quad = Mesh::new(
             &device,
             &physical_device_memory_properties,
             command_pool,
-            graphics_queue,
-        );
+            &mut graphics_timeline,
+            &mut deletion_queue,
+            &[VertexStream::new(&QUAD_VERTICES)],
+            MeshIndices::U16(&QUAD_INDICES),
+            &[])
+            .expect("Failed to create quad mesh!");
+
+        let white_texture = Texture::new(
+            &device,
+            &physical_device_memory_properties,
+            command_pool,
+            &mut graphics_timeline,
+            &mut deletion_queue,
+            vk::Extent2D { width: 1, height: 1 },
+            &[255; 4],
+            false)
+            .expect("Failed to create default texture!");
+        let default_sampler_desc = SamplerDesc::default();
+        let default_sampler = default_sampler_desc.create_sampler(&device);
 
         let (uniform_buffers, uniform_buffers_memory) = VkRenderDevice::create_uniform_buffers(
             &device,
@@ -203,193 +442,155 @@ impl VkRenderDevice
             swapchain.swapchain_images.len()
         );
 
-        let descriptor_pool = VkRenderDevice::create_descriptor_pool(
-            &device, 
-            swapchain.swapchain_images.len()
-        );
+        let mut descriptor_allocator = DescriptorAllocator::new();
         let descriptor_sets = VkRenderDevice::create_descriptor_sets(
             &device,
-            descriptor_pool,
+            &mut descriptor_allocator,
             ubo_layout,
             &uniform_buffers,
-            swapchain.swapchain_images.len(),
         );
+        let default_material = GpuMaterial {
+            descriptor_set: VkRenderDevice::create_material_set(
+                &device, &mut descriptor_allocator, material_layout, white_texture.view, default_sampler),
+            constants: MaterialConstants::default(),
+        };
 
         let command_buffers = VkRenderDevice::create_command_buffers(
             &device,
             command_pool,
-            pipeline,
-            &framebuffers,
-            render_pass,
-            swapchain.swapchain_extent,
-            vertex_buffer,
-            index_buffer,
-            pipeline_layout,
-            &descriptor_sets
+            global_constants::MAX_FRAMES_IN_FLIGHT as u32
         );
 
+        let instance_buffers = (0..global_constants::MAX_FRAMES_IN_FLIGHT)
+            .map(|_| VkRenderDevice::create_instance_buffer(&device, &physical_device_memory_properties, INITIAL_INSTANCE_CAPACITY))
+            .collect();
+
+        let parallel_recorder = ParallelRecorder::new(
+            &device,
+            indices.graphics_family.unwrap(),
+            global_constants::MAX_FRAMES_IN_FLIGHT);
+
+        let graphics_queue_family = unsafe { instance.get_physical_device_queue_family_properties(physical_device) }
+            [indices.graphics_family.unwrap() as usize];
+        let gpu_profiler = GpuProfiler::new(
+            &device,
+            &physical_device_properties.limits,
+            graphics_queue_family.timestamp_valid_bits,
+            global_constants::MAX_FRAMES_IN_FLIGHT);
+
         let sync_ojbects = VkRenderDevice::create_sync_objects(&device);
 
         let uniform_transform = UniformBufferObject {
-            model: Matrix4::<f32>::identity(),
-            view: Matrix4::look_at(
-                Point3::new(2.0, 2.0, 2.0),
-                Point3::new(0.0, 0.0, 0.0),
-                Vector3::new(0.0, 0.0, 1.0),
-            ),
-            proj: cgmath::perspective(
-                Deg(45.0),
-                (swapchain.swapchain_extent.width as f32) / (swapchain.swapchain_extent.height as f32),
-                0.1,
-                10.0,
-            ),
+            view: Matrix4::identity(),
+            proj: Matrix4::identity(),
         };
 
         VkRenderDevice {
-            entry: entry,
-            instance: instance,
-            surface: surface,
+            _entry: entry,
+            instance,
+            surface,
             debug_utils_loader: debug_units_loader,
-            debug_messager: debug_messager,
-            physical_device: physical_device,
-            device: device,
-
-            graphics_queue: graphics_queue,
-            present_queue: present_queue,
-            indices: indices,
-
-            swapchain: swapchain,
-
-            render_pass: render_pass,
-            pipeline_layout: pipeline_layout,
-            ubo_layout: ubo_layout,
-            graphics_pipeline: pipeline,
-
-            vertex_buffer: vertex_buffer,
-            vertex_buffer_memory: vertex_buffer_memory,
+            debug_messager,
+            physical_device,
+            physical_device_properties,
+            memory_properties: physical_device_memory_properties,
+            device,
 
-            index_buffer: index_buffer,
-            index_buffer_memory: index_buffer_memory,
+            graphics_queue,
+            present_queue,
+            indices,
 
-            uniform_transform: uniform_transform,
-            uniform_buffers: uniform_buffers,
-            uniform_buffers_memory: uniform_buffers_memory,
+            swapchain,
 
-            descriptor_pool: descriptor_pool,
-            descriptor_sets: descriptor_sets,
+            render_pass,
+            render_pass_key,
+            scene_render_pass,
+            scene_target,
+            scene_framebuffer,
+            post_process,
+            imgui_renderer,
+            ubo_layout,
+            material_layout,
+            mesh_pipelines: vec![graphics_pipeline, mesh_pipeline],
+
+            shader_compiler,
+
+            meshes: vec![Some(quad)],
+            quad_mesh: MeshId(0),
+            textures: vec![Some(white_texture)],
+            samplers: vec![default_sampler],
+            sampler_ids: HashMap::from([(default_sampler_desc, SamplerId(0))]),
+            materials: vec![default_material],
+            default_material: MaterialId(0),
+
+            uniform_transform,
+            mesh_draws: vec![],
+            instanced_draws: vec![],
+            instanced_transforms: vec![],
+            draw_batches: vec![],
+            unsupported_meshes: HashSet::new(),
+            instance_buffers,
+            uniform_buffers,
+            uniform_buffers_memory,
+
+            descriptor_layout_cache,
+            descriptor_allocator,
+            frame_descriptor_allocators: FrameDescriptorAllocators::new(global_constants::MAX_FRAMES_IN_FLIGHT),
+            descriptor_sets,
 
-            command_pool: command_pool,
-            command_buffers: command_buffers,
+            command_pool,
+            command_buffers,
+            parallel_recorder,
+            gpu_profiler,
+            render_graph_transients: (0..global_constants::MAX_FRAMES_IN_FLIGHT).map(|_| TransientResources::new()).collect(),
 
             sync_objects: sync_ojbects,
+            frame_points: vec![graphics_timeline.last_submitted(); global_constants::MAX_FRAMES_IN_FLIGHT],
+            upload_point: Some(graphics_timeline.last_submitted()),
+            graphics_timeline,
+            deletion_queue,
             current_frame: 0
         }
     }
 
     fn create_descriptor_sets(
         device: &ash::Device,
-        descriptor_pool: vk::DescriptorPool,
+        descriptor_allocator: &mut DescriptorAllocator,
         descriptor_set_layout: vk::DescriptorSetLayout,
-        uniforms_buffers: &Vec<vk::Buffer>,
-        swapchain_images_size: usize,
+        uniforms_buffers: &[vk::Buffer],
     ) -> Vec<vk::DescriptorSet> {
-        let mut layouts: Vec<vk::DescriptorSetLayout> = vec![];
-        for _ in 0..swapchain_images_size {
-            layouts.push(descriptor_set_layout);
-        }
-
-        let descriptor_set_allocate_info = vk::DescriptorSetAllocateInfo {
-            s_type: vk::StructureType::DESCRIPTOR_SET_ALLOCATE_INFO,
-            p_next: ptr::null(),
-            descriptor_pool,
-            descriptor_set_count: swapchain_images_size as u32,
-            p_set_layouts: layouts.as_ptr(),
-        };
+        let mut descriptor_sets = vec![];
 
-        let descriptor_sets = unsafe {
-            device
-                .allocate_descriptor_sets(&descriptor_set_allocate_info)
-                .expect("Failed to allocate descriptor sets!")
-        };
+        for &uniform_buffer in uniforms_buffers.iter() {
+            let descriptor_set = descriptor_allocator.allocate(device, descriptor_set_layout);
 
-        for (i, &descritptor_set) in descriptor_sets.iter().enumerate() {
-            let descriptor_buffer_info = [vk::DescriptorBufferInfo {
-                buffer: uniforms_buffers[i],
-                offset: 0,
-                range: std::mem::size_of::<UniformBufferObject>() as u64,
-            }];
-
-            let descriptor_write_sets = [vk::WriteDescriptorSet {
-                s_type: vk::StructureType::WRITE_DESCRIPTOR_SET,
-                p_next: ptr::null(),
-                dst_set: descritptor_set,
-                dst_binding: 0,
-                dst_array_element: 0,
-                descriptor_count: 1,
-                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                p_image_info: ptr::null(),
-                p_buffer_info: descriptor_buffer_info.as_ptr(),
-                p_texel_buffer_view: ptr::null(),
-            }];
+            DescriptorWriter::new()
+                .uniform_buffer(0, uniform_buffer, std::mem::size_of::<UniformBufferObject>() as u64)
+                .update(device, descriptor_set);
 
-            unsafe {
-                device.update_descriptor_sets(&descriptor_write_sets, &[]);
-            }
+            descriptor_sets.push(descriptor_set);
         }
 
         descriptor_sets
     }
 
-    fn create_descriptor_pool(
+    fn create_material_set(
         device: &ash::Device,
-        swapchain_images_size: usize,
-    ) -> vk::DescriptorPool {
-        let pool_sizes = [vk::DescriptorPoolSize {
-            ty: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: swapchain_images_size as u32,
-        }];
-
-        let descriptor_pool_create_info = vk::DescriptorPoolCreateInfo {
-            s_type: vk::StructureType::DESCRIPTOR_POOL_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::DescriptorPoolCreateFlags::empty(),
-            max_sets: swapchain_images_size as u32,
-            pool_size_count: pool_sizes.len() as u32,
-            p_pool_sizes: pool_sizes.as_ptr(),
-        };
-
-        unsafe {
-            device
-                .create_descriptor_pool(&descriptor_pool_create_info, None)
-                .expect("Failed to create Descriptor Pool!")
-        }
+        descriptor_allocator: &mut DescriptorAllocator,
+        material_layout: vk::DescriptorSetLayout,
+        base_color_view: vk::ImageView,
+        base_color_sampler: vk::Sampler,
+    ) -> vk::DescriptorSet {
+        let descriptor_set = descriptor_allocator.allocate(device, material_layout);
+
+        DescriptorWriter::new()
+            .combined_image_sampler(0, base_color_view, base_color_sampler)
+            .update(device, descriptor_set);
+
+        descriptor_set
     }
 
-    fn create_descriptor_set_layout(device: &ash::Device) -> vk::DescriptorSetLayout {
-        let ubo_layout_bindings = [vk::DescriptorSetLayoutBinding {
-            binding: 0,
-            descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-            descriptor_count: 1,
-            stage_flags: vk::ShaderStageFlags::VERTEX,
-            p_immutable_samplers: ptr::null(),
-        }];
-
-        let ubo_layout_create_info = vk::DescriptorSetLayoutCreateInfo {
-            s_type: vk::StructureType::DESCRIPTOR_SET_LAYOUT_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
-            binding_count: ubo_layout_bindings.len() as u32,
-            p_bindings: ubo_layout_bindings.as_ptr(),
-        };
-
-        unsafe {
-            device
-                .create_descriptor_set_layout(&ubo_layout_create_info, None)
-                .expect("Failed to create Descriptor Set Layout!")
-        }
-    }
-
-    fn create_buffer(
+    pub fn create_buffer(
         device: &ash::Device,
         size: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
@@ -442,14 +643,17 @@ impl VkRenderDevice
         (buffer, buffer_memory)
     }
 
+    /// Records and submits the copy without waiting for it. The command buffer is freed once the copy is done.
+    /// No barrier follows the copy, so later submissions reading `dst_buffer` have to wait on the returned point.
     fn copy_buffer(
         device: &ash::Device,
-        submit_queue: vk::Queue,
+        timeline: &mut GpuTimeline,
+        deletion_queue: &mut DeletionQueue,
         command_pool: vk::CommandPool,
         src_buffer: vk::Buffer,
         dst_buffer: vk::Buffer,
         size: vk::DeviceSize,
-    ) {
+    ) -> TimelinePoint {
         let allocate_info = vk::CommandBufferAllocateInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
             p_next: ptr::null(),
@@ -490,38 +694,27 @@ impl VkRenderDevice
                 .expect("Failed to end Command Buffer");
         }
 
-        let submit_info = [vk::SubmitInfo {
-            s_type: vk::StructureType::SUBMIT_INFO,
-            p_next: ptr::null(),
-            wait_semaphore_count: 0,
-            p_wait_semaphores: ptr::null(),
-            p_wait_dst_stage_mask: ptr::null(),
-            command_buffer_count: 1,
-            p_command_buffers: &command_buffer,
-            signal_semaphore_count: 0,
-            p_signal_semaphores: ptr::null(),
-        }];
-
-        unsafe {
-            device
-                .queue_submit(submit_queue, &submit_info, vk::Fence::null())
-                .expect("Failed to Submit Queue.");
-            device
-                .queue_wait_idle(submit_queue)
-                .expect("Failed to wait Queue idle");
+        let copy_point = timeline.submit(device, &TimelineSubmit::new(&command_buffers));
 
+        deletion_queue.push(copy_point, move |device| unsafe {
             device.free_command_buffers(command_pool, &command_buffers);
-        }
+        });
+
+        copy_point
     }
 
-    fn create_index_buffer(
-        instance: &ash::Instance,
+    /// Creates a device local buffer holding `data`, uploaded through a staging buffer that is
+    /// freed once the copy on `timeline` completes.
+    pub fn create_device_local_buffer<T: Copy>(
         device: &ash::Device,
         device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
         command_pool: vk::CommandPool,
-        submit_queue: vk::Queue,
+        timeline: &mut GpuTimeline,
+        deletion_queue: &mut DeletionQueue,
+        data: &[T],
+        usage: vk::BufferUsageFlags,
     ) -> (vk::Buffer, vk::DeviceMemory) {
-        let buffer_size = std::mem::size_of_val(&INDICES_DATA) as vk::DeviceSize;
+        let buffer_size = std::mem::size_of_val(data) as vk::DeviceSize;
 
         let (staging_buffer, staging_buffer_memory) = VkRenderDevice::create_buffer(
             device,
@@ -539,94 +732,40 @@ impl VkRenderDevice
                     buffer_size,
                     vk::MemoryMapFlags::empty(),
                 )
-                .expect("Failed to Map Memory") as *mut u32;
-
-            data_ptr.copy_from_nonoverlapping(INDICES_DATA.as_ptr(), INDICES_DATA.len());
-
-            device.unmap_memory(staging_buffer_memory);
-        }
-
-        let (index_buffer, index_buffer_memory) = VkRenderDevice::create_buffer(
-            device,
-            buffer_size,
-            vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::INDEX_BUFFER,
-            vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            &device_memory_properties,
-        );
-
-        VkRenderDevice::copy_buffer(
-            device,
-            submit_queue,
-            command_pool,
-            staging_buffer,
-            index_buffer,
-            buffer_size,
-        );
-
-        unsafe {
-            device.destroy_buffer(staging_buffer, None);
-            device.free_memory(staging_buffer_memory, None);
-        }
-
-        (index_buffer, index_buffer_memory)
-    }
-
-    fn create_vertex_buffer(
-        instance: &ash::Instance,
-        device: &ash::Device,
-        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
-        command_pool: vk::CommandPool,
-        submit_queue: vk::Queue,
-    ) -> (vk::Buffer, vk::DeviceMemory) {
-        let buffer_size = std::mem::size_of_val(&VERTICES_DATA) as vk::DeviceSize;
-
-        let (staging_buffer, staging_buffer_memory) = VkRenderDevice::create_buffer(device,
-            buffer_size,
-            vk::BufferUsageFlags::TRANSFER_SRC,
-            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
-            &device_memory_properties,);
-
-        unsafe {
-            let data_ptr = device
-                .map_memory(
-                    staging_buffer_memory,
-                    0,
-                    buffer_size,
-                    vk::MemoryMapFlags::empty(),
-                )
-                .expect("Failed to Map Memory") as *mut Vertex;
+                .expect("Failed to Map Memory") as *mut T;
 
-            data_ptr.copy_from_nonoverlapping(VERTICES_DATA.as_ptr(), VERTICES_DATA.len());
+            data_ptr.copy_from_nonoverlapping(data.as_ptr(), data.len());
 
             device.unmap_memory(staging_buffer_memory);
         }
 
-        let (vertex_buffer, vertex_buffer_memory) = VkRenderDevice::create_buffer(
+        let (buffer, buffer_memory) = VkRenderDevice::create_buffer(
             device,
             buffer_size,
-            vk::BufferUsageFlags::TRANSFER_DST | vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::BufferUsageFlags::TRANSFER_DST | usage,
             vk::MemoryPropertyFlags::DEVICE_LOCAL,
-            &device_memory_properties,
+            device_memory_properties,
         );
 
-        VkRenderDevice::copy_buffer(
+        let copy_point = VkRenderDevice::copy_buffer(
             device,
-            submit_queue,
+            timeline,
+            deletion_queue,
             command_pool,
             staging_buffer,
-            vertex_buffer,
+            buffer,
             buffer_size,
         );
 
-        unsafe {
+        deletion_queue.push(copy_point, move |device| unsafe {
             device.destroy_buffer(staging_buffer, None);
             device.free_memory(staging_buffer_memory, None);
-        }
+        });
 
-        (vertex_buffer, vertex_buffer_memory)
+        (buffer, buffer_memory)
     }
 
-    fn find_memory_type(
+    pub fn find_memory_type(
         type_filter: u32,
         required_properties: vk::MemoryPropertyFlags,
         mem_properties: &vk::PhysicalDeviceMemoryProperties,
@@ -667,12 +806,321 @@ impl VkRenderDevice
         (uniform_buffers, uniform_buffers_memory)
     }
 
-    pub fn update_uniform_buffer(&mut self, current_image: usize, delta_time: f32) {
-        self.uniform_transform.model =
-            Matrix4::from_axis_angle(Vector3::new(0.0, 0.0, 1.0), Deg(90.0) * delta_time * 0.001f32 * 0.001f32)
-                * self.uniform_transform.model;
+    fn create_instance_buffer(
+        device: &ash::Device,
+        device_memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        capacity: usize,
+    ) -> InstanceBuffer {
+        let (buffer, memory) = VkRenderDevice::create_buffer(
+            device,
+            (std::mem::size_of::<InstanceTransform>() * capacity) as u64,
+            vk::BufferUsageFlags::VERTEX_BUFFER,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            device_memory_properties,
+        );
+
+        InstanceBuffer {
+            buffer,
+            memory,
+            capacity,
+        }
+    }
+
+    /// The streams a mesh needs to be drawn with the built-in graphics pipeline.
+    pub fn graphics_mesh_layout() -> VertexLayout {
+        VertexLayout::of::<Vertex>()
+    }
+
+    /// Adds a pipeline to the main pass for the meshes whose vertex layout is `mesh_layout`, e.g.
+    /// `VertexLayout::new().stream_at::<Positions>(0).stream_at::<Attributes>(1)` for meshes of
+    /// two streams. The shaders get the camera's view and projection at set 0, binding 0, the
+    /// submesh's base color texture at set 1, binding 0, its `MaterialConstants` as fragment push
+    /// constants and the `InstanceTransform` at locations 4 to 7. `MeshVertex` meshes are drawn by a built-in pipeline. Fails if a pipeline already
+    /// takes the layout.
+    pub fn create_mesh_pipeline(&mut self, name: &str, shaders: Vec<PipelineShader>, mesh_layout: VertexLayout) -> Result<(), String> {
+        if let Some(pipeline) = self.mesh_pipelines.iter().find(|pipeline| pipeline.mesh_layout == mesh_layout) {
+            return Err(format!("Failed to create {} pipeline: {} already draws meshes of its layout", name, pipeline.name));
+        }
+
+        let pipeline = MeshPipeline::new(
+            &self.device,
+            &mut self.shader_compiler,
+            name,
+            shaders,
+            mesh_layout,
+            self.scene_render_pass,
+            &[self.ubo_layout, self.material_layout])?;
+
+        self.mesh_pipelines.push(pipeline);
+        // meshes skipped for want of a pipeline get another chance
+        self.unsupported_meshes.clear();
+        Ok(())
+    }
+
+    /// The built-in unit quad in the XY plane.
+    pub fn quad_mesh(&self) -> MeshId {
+        self.quad_mesh
+    }
+
+    /// Meshes are only drawn by a pipeline taking their vertex layout, see `create_mesh_pipeline`.
+    pub fn create_mesh<V: Pod + BindingDescriptions + AttributeDescriptions>(&mut self, vertices: &[V], indices: MeshIndices, submeshes: &[Submesh]) -> Result<MeshId, String> {
+        self.create_mesh_streams(&[VertexStream::new(vertices)], indices, submeshes)
+    }
+
+    /// Creates a mesh with one vertex buffer per stream, bound from binding 0 on in order.
+    pub fn create_mesh_streams(&mut self, streams: &[VertexStream], indices: MeshIndices, submeshes: &[Submesh]) -> Result<MeshId, String> {
+        let mesh = Mesh::new(
+            &self.device,
+            &self.memory_properties,
+            self.command_pool,
+            &mut self.graphics_timeline,
+            &mut self.deletion_queue,
+            streams,
+            indices,
+            submeshes)?;
+
+        self.upload_point = Some(self.graphics_timeline.last_submitted());
+        self.meshes.push(Some(mesh));
+        Ok(MeshId(self.meshes.len() as u32 - 1))
+    }
+
+    /// Creates an imported mesh, drawing each submesh with the entry of `materials` its material indexes.
+    pub fn create_mesh_data(&mut self, data: &MeshData, materials: &[MaterialId]) -> Result<MeshId, String> {
+        let mut submesh_materials = vec![];
+        for submesh in data.submeshes.iter() {
+            let material = match submesh.material {
+                Some(index) => Some(*materials.get(index).ok_or_else(|| format!(
+                    "{}: submesh material {} is not one of its {} materials", data.name, index, materials.len()))?),
+                None => None,
+            };
+            submesh_materials.push(material);
+        }
+
+        let id = self.create_mesh(&data.vertices, MeshIndices::U32(&data.indices), &data.submeshes())
+            .map_err(|error| format!("{}: {}", data.name, error))?;
+        if !submesh_materials.is_empty() {
+            self.meshes[id.0 as usize].as_mut().unwrap().materials = submesh_materials;
+        }
+        Ok(id)
+    }
+
+    pub fn mesh(&self, id: MeshId) -> Option<&Mesh> {
+        self.meshes.get(id.0 as usize).and_then(|mesh| mesh.as_ref())
+    }
+
+    /// Frees the mesh once the frames already submitted are done with it.
+    pub fn destroy_mesh(&mut self, id: MeshId) {
+        if let Some(mesh) = self.meshes.get_mut(id.0 as usize).and_then(|mesh| mesh.take()) {
+            mesh.destroy_after(&mut self.deletion_queue, self.graphics_timeline.last_submitted());
+        }
+    }
 
-        let ubos = [self.uniform_transform.clone()];
+    /// Creates a texture from tightly packed RGBA8 `pixels`, see `Texture::new`.
+    pub fn create_texture(&mut self, extent: vk::Extent2D, pixels: &[u8], srgb: bool) -> Result<TextureId, String> {
+        let texture = Texture::new(
+            &self.device,
+            &self.memory_properties,
+            self.command_pool,
+            &mut self.graphics_timeline,
+            &mut self.deletion_queue,
+            extent,
+            pixels,
+            srgb)?;
+
+        self.upload_point = Some(self.graphics_timeline.last_submitted());
+        self.textures.push(Some(texture));
+        Ok(TextureId(self.textures.len() as u32 - 1))
+    }
+
+    pub fn texture(&self, id: TextureId) -> Option<&Texture> {
+        self.textures.get(id.0 as usize).and_then(|texture| texture.as_ref())
+    }
+
+    /// Frees the texture once the frames already submitted are done with it.
+    pub fn destroy_texture(&mut self, id: TextureId) {
+        if let Some(texture) = self.textures.get_mut(id.0 as usize).and_then(|texture| texture.take()) {
+            texture.destroy_after(&mut self.deletion_queue, self.graphics_timeline.last_submitted());
+        }
+    }
+
+    /// Returns the sampler for `desc`, created the first time it is asked for.
+    pub fn create_sampler(&mut self, desc: &SamplerDesc) -> SamplerId {
+        if let Some(&id) = self.sampler_ids.get(desc) {
+            return id;
+        }
+
+        self.samplers.push(desc.create_sampler(&self.device));
+        let id = SamplerId(self.samplers.len() as u32 - 1);
+        self.sampler_ids.insert(*desc, id);
+        id
+    }
+
+    pub fn sampler(&self, id: SamplerId) -> vk::Sampler {
+        self.samplers[id.0 as usize]
+    }
+
+    /// Creates the material submeshes are drawn with. Its textures must outlive it, and like the
+    /// samplers it is kept until the device is dropped.
+    pub fn create_material(&mut self, material: &PbrMaterial) -> Result<MaterialId, String> {
+        let descriptor_set = match material.base_color_texture {
+            Some(texture) => {
+                let view = self.texture(texture.texture)
+                    .ok_or_else(|| format!("Failed to create material {}: its base color texture was destroyed", material.name))?
+                    .view;
+                let sampler = self.sampler(texture.sampler);
+                VkRenderDevice::create_material_set(&self.device, &mut self.descriptor_allocator, self.material_layout, view, sampler)
+            },
+            // samples the default material's white texture
+            None => self.materials[self.default_material.0 as usize].descriptor_set,
+        };
+
+        let constants = MaterialConstants {
+            base_color_factor: material.base_color_factor,
+            alpha_cutoff: if material.alpha_mode == AlphaMode::Mask { material.alpha_cutoff } else { 0.0 },
+        };
+
+        self.materials.push(GpuMaterial { descriptor_set, constants });
+        Ok(MaterialId(self.materials.len() as u32 - 1))
+    }
+
+    /// Uploads imgui's font atlas from tightly packed RGBA8 `pixels`, replacing the previous one.
+    pub fn set_ui_fonts(&mut self, extent: vk::Extent2D, pixels: &[u8]) -> Result<(), String> {
+        let texture = self.create_texture(extent, pixels, false)?;
+        let sampler = self.create_sampler(&SamplerDesc {
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            ..SamplerDesc::default()
+        });
+
+        let view = self.texture(texture).unwrap().view;
+        let sampler = self.sampler(sampler);
+        if let Some(old_texture) = self.imgui_renderer.set_font_texture(&self.device, &mut self.descriptor_allocator, texture, view, sampler) {
+            self.destroy_texture(old_texture);
+        }
+
+        Ok(())
+    }
+
+    /// The UI drawn over the next recorded frame.
+    pub fn set_ui_draw_data(&mut self, draw_data: &imgui::DrawData) {
+        self.imgui_renderer.set_draw_data(draw_data);
+    }
+
+    /// Draws every node of `scene` that has a mesh, with its world transform, from the next recorded frame on.
+    pub fn draw_scene(&mut self, scene: &SceneGraph) {
+        self.mesh_draws.clear();
+        scene.collect_mesh_draws(&mut self.mesh_draws);
+
+        // draws of the same mesh next to each other bind its buffers once and become one instanced draw
+        self.mesh_draws.sort_by_key(|draw| draw.mesh);
+    }
+
+    /// Draws `mesh` once per transform in the next recorded frame, as a single instanced draw.
+    /// Unlike `draw_scene` this has to be called again for every frame.
+    pub fn draw_mesh_instanced(&mut self, mesh: MeshId, transforms: &[Matrix4<f32>]) {
+        if transforms.is_empty() {
+            return;
+        }
+
+        self.instanced_draws.push((mesh, transforms.len() as u32));
+        self.instanced_transforms.extend(transforms.iter().map(|&model| InstanceTransform { model }));
+    }
+
+    /// Groups the frame's draws into batches and writes their transforms to the frame's instance buffer.
+    fn prepare_instances(&mut self) {
+        let mut transforms = Vec::with_capacity(self.mesh_draws.len() + self.instanced_transforms.len());
+        self.draw_batches.clear();
+
+        for draw in self.mesh_draws.iter() {
+            match self.draw_batches.last_mut() {
+                Some(batch) if batch.mesh == draw.mesh => batch.instance_count += 1,
+                _ => self.draw_batches.push(DrawBatch {
+                    pipeline: 0,
+                    mesh: draw.mesh,
+                    first_instance: transforms.len() as u32,
+                    instance_count: 1,
+                }),
+            }
+            transforms.push(InstanceTransform { model: draw.model });
+        }
+
+        let mut first_instance = transforms.len() as u32;
+        for &(mesh, instance_count) in self.instanced_draws.iter() {
+            self.draw_batches.push(DrawBatch {
+                pipeline: 0,
+                mesh,
+                first_instance,
+                instance_count,
+            });
+            first_instance += instance_count;
+        }
+        transforms.append(&mut self.instanced_transforms);
+        self.instanced_draws.clear();
+
+        // binding a mesh with other streams than the pipeline reads would fetch its vertices with the wrong strides
+        let meshes = &self.meshes;
+        let mesh_pipelines = &self.mesh_pipelines;
+        let unsupported_meshes = &mut self.unsupported_meshes;
+        self.draw_batches.retain_mut(|batch| {
+            let mesh = match meshes.get(batch.mesh.0 as usize).and_then(|mesh| mesh.as_ref()) {
+                Some(mesh) => mesh,
+                None => return false,
+            };
+            if let Some(pipeline) = mesh_pipelines.iter().position(|pipeline| pipeline.mesh_layout == mesh.vertex_layout) {
+                batch.pipeline = pipeline;
+                return true;
+            }
+
+            if unsupported_meshes.insert(batch.mesh) {
+                println!("Skipping mesh {:?}: no pipeline takes its vertex layout {:?}", batch.mesh, mesh.vertex_layout);
+            }
+            false
+        });
+        // batches read their instances by offset, so grouping them by pipeline keeps pipeline switches down
+        self.draw_batches.sort_by_key(|batch| batch.pipeline);
+
+        if transforms.is_empty() {
+            return;
+        }
+
+        let instance_buffer = &mut self.instance_buffers[self.current_frame];
+        if transforms.len() > instance_buffer.capacity {
+            let capacity = transforms.len().next_power_of_two();
+            let old_buffer = std::mem::replace(
+                instance_buffer,
+                VkRenderDevice::create_instance_buffer(&self.device, &self.memory_properties, capacity));
+            self.deletion_queue.push(self.graphics_timeline.last_submitted(), move |device| unsafe {
+                device.destroy_buffer(old_buffer.buffer, None);
+                device.free_memory(old_buffer.memory, None);
+            });
+        }
+
+        let instance_buffer = &self.instance_buffers[self.current_frame];
+        unsafe {
+            let data_ptr = self.device
+                .map_memory(
+                    instance_buffer.memory,
+                    0,
+                    (std::mem::size_of::<InstanceTransform>() * transforms.len()) as u64,
+                    vk::MemoryMapFlags::empty(),
+                )
+                .expect("Failed to Map Memory") as *mut InstanceTransform;
+
+            data_ptr.copy_from_nonoverlapping(transforms.as_ptr(), transforms.len());
+
+            self.device.unmap_memory(instance_buffer.memory);
+        }
+    }
+
+    /// View and projection the scene is drawn with from the next recorded frame on.
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.uniform_transform.view = camera.view_matrix();
+        self.uniform_transform.proj = camera.projection_matrix();
+    }
+
+    pub fn update_uniform_buffer(&mut self, current_image: usize) {
+
+        let ubos = [self.uniform_transform];
 
         let buffer_size = (std::mem::size_of::<UniformBufferObject>() * ubos.len()) as u64;
 
@@ -694,6 +1142,8 @@ impl VkRenderDevice
         }
     }
 
+    /// Recreates the swapchain and everything sized by it. The swapchain render pass and the
+    /// pipelines drawing with it are only rebuilt when the new swapchain is no longer compatible with them.
     pub fn recreate_swapchain(&mut self) {
         unsafe {
             self.device
@@ -705,33 +1155,61 @@ impl VkRenderDevice
 
         self.swapchain = VkSpawChain::create_swapchain(&self.instance, &self.device, self.physical_device, &self.surface, &self.indices);
 
-        let swapchain_image_views = self.swapchain.create_image_views(&self.device);
+        let render_pass_key = RenderPassKey::new(self.swapchain.swapchain_format);
+        if render_pass_key != self.render_pass_key {
+            unsafe { self.device.destroy_render_pass(self.render_pass, None) };
 
-        self.render_pass = VkRenderDevice::create_render_pass(&self.device, self.swapchain.swapchain_format);
+            self.render_pass_key = render_pass_key;
+            self.render_pass = VkRenderDevice::create_render_pass(&self.device, &self.render_pass_key);
 
-        (self.graphics_pipeline, self.pipeline_layout) = VkRenderDevice::create_graphics_pipeline(&self.device, &self.swapchain, self.render_pass, self.ubo_layout);
-    
+            self.post_process.set_present_render_pass(&self.device, &mut self.shader_compiler, self.render_pass)
+                .expect("Failed to create post-process pipeline!");
+            self.imgui_renderer.set_render_pass_key(&self.device, &mut self.shader_compiler, &self.render_pass_key)
+                .expect("Failed to create imgui pipeline!");
+        }
+
+        if self.scene_target.resize(&self.device, &self.memory_properties, self.swapchain.swapchain_extent) {
+            unsafe { self.device.destroy_framebuffer(self.scene_framebuffer, None) };
+            self.scene_framebuffer = RenderTarget::create_framebuffer(&self.device, self.scene_render_pass, &[&self.scene_target]);
+        }
+        self.post_process.resize(&self.device, &self.memory_properties, self.swapchain.swapchain_extent);
+
+        let swapchain_image_views = self.swapchain.create_image_views(&self.device);
         let framebuffers = VkSpawChain::create_framebuffers(&self.device, self.render_pass, &swapchain_image_views, &self.swapchain.swapchain_extent);
 
-        self.command_buffers = VkRenderDevice::create_command_buffers(
-            &self.device,
-            self.command_pool,
-            self.graphics_pipeline,
-            &framebuffers,
-            self.render_pass,
-            self.swapchain.swapchain_extent,
-            self.vertex_buffer,
-            self.index_buffer,
-            self.pipeline_layout,
-            &self.descriptor_sets
-        );
+        self.swapchain.swapchain_image_views = swapchain_image_views;
+        self.swapchain.swapchain_framebuffers = framebuffers;
+    }
+
+    /// Rebuilds the pipelines whose shaders depend on any of the `changed` files
+    /// and reports the outcome per pipeline. A pipeline that fails to build keeps its last good version.
+    pub fn reload_shaders(&mut self, changed: &[PathBuf]) -> Vec<(String, Result<(), String>)> {
+        let mut results = self.post_process.reload_shaders(&self.device, &mut self.shader_compiler, changed);
+        results.extend(self.imgui_renderer.reload_shaders(&self.device, &mut self.shader_compiler, changed));
+
+        // frames in flight may still draw with the old pipelines
+        let last_submitted = self.graphics_timeline.last_submitted();
+        for pipeline in self.mesh_pipelines.iter_mut() {
+            let result = pipeline.reload_shaders(
+                &self.device,
+                &mut self.shader_compiler,
+                self.scene_render_pass,
+                changed,
+                &mut self.deletion_queue,
+                last_submitted);
+
+            if let Some(result) = result {
+                results.push((pipeline.name.clone(), result));
+            }
+        }
+
+        results
     }
 
     fn create_sync_objects(device: &ash::Device) -> SyncObjects {
         let mut sync_objects = SyncObjects {
             image_available_semaphores: vec![],
             render_finished_semaphores: vec![],
-            inflight_fences: vec![],
         };
 
         let semaphore_create_info = vk::SemaphoreCreateInfo {
@@ -740,12 +1218,6 @@ impl VkRenderDevice
             flags: vk::SemaphoreCreateFlags::empty(),
         };
 
-        let fence_create_info = vk::FenceCreateInfo {
-            s_type: vk::StructureType::FENCE_CREATE_INFO,
-            p_next: ptr::null(),
-            flags: vk::FenceCreateFlags::SIGNALED,
-        };
-
         for _ in 0..global_constants::MAX_FRAMES_IN_FLIGHT {
             unsafe {
                 let image_available_semaphore = device
@@ -754,9 +1226,6 @@ impl VkRenderDevice
                 let render_finished_semaphore = device
                     .create_semaphore(&semaphore_create_info, None)
                     .expect("Failed to create Semaphore Object!");
-                let inflight_fence = device
-                    .create_fence(&fence_create_info, None)
-                    .expect("Failed to create Fence Object!");
 
                 sync_objects
                     .image_available_semaphores
@@ -764,7 +1233,6 @@ impl VkRenderDevice
                 sync_objects
                     .render_finished_semaphores
                     .push(render_finished_semaphore);
-                sync_objects.inflight_fences.push(inflight_fence);
             }
         }
 
@@ -792,7 +1260,7 @@ impl VkRenderDevice
     }
 
     pub fn create_instance(entry: &ash::Entry) -> ash::Instance {
-        if constants::VALIDATION.is_enable && debug::check_validation_layer_support(entry) == false {
+        if constants::VALIDATION.is_enable && !debug::check_validation_layer_support(entry) {
             panic!("Validation layers requested, but not available!");
         }
 
@@ -844,10 +1312,8 @@ impl VkRenderDevice
 
         let mut result = None;
         for &physical_device in physical_devices.iter() {
-            if VkRenderDevice::is_physical_device_suitable(instance, physical_device, surface) {
-                if result.is_none() {
-                    result = Some(physical_device);
-                }
+            if result.is_none() && VkRenderDevice::is_physical_device_suitable(instance, physical_device, surface) {
+                result = Some(physical_device);
             }
         }
 
@@ -948,15 +1414,18 @@ impl VkRenderDevice
         let is_queue_family_supported = indices.is_complete();
 
         let is_swapchain_supported = if is_device_extension_supported {
-            let swapchain_support = VkSpawChain::query_swapchain_support(physical_device, &surface);
+            let swapchain_support = VkSpawChain::query_swapchain_support(physical_device, surface);
             !swapchain_support.formats.is_empty() && !swapchain_support.present_modes.is_empty()
         } else {
             false
         };
 
-        return is_queue_family_supported
+        let is_timeline_semaphore_supported = device_properties.api_version >= constants::API_VERSION;
+
+        is_queue_family_supported
             && is_device_extension_supported
-            && is_swapchain_supported;
+            && is_swapchain_supported
+            && is_timeline_semaphore_supported
     }
 
     fn check_device_extension_support(
@@ -991,7 +1460,7 @@ impl VkRenderDevice
             required_extensions.remove(extension_name);
         }
 
-        return required_extensions.is_empty();
+        required_extensions.is_empty()
     }
 
     fn find_queue_family(
@@ -1007,8 +1476,8 @@ impl VkRenderDevice
             present_family: None,
         };
 
-        let mut index = 0;
-        for queue_family in queue_families.iter() {
+        for (index, queue_family) in queue_families.iter().enumerate() {
+            let index = index as u32;
             if queue_family.queue_count > 0
                 && queue_family.queue_flags.contains(vk::QueueFlags::GRAPHICS)
             {
@@ -1020,7 +1489,7 @@ impl VkRenderDevice
                     .surface_loader
                     .get_physical_device_surface_support(
                         physical_device,
-                        index as u32,
+                        index,
                         surface.surface,
                     )
             };
@@ -1032,8 +1501,6 @@ impl VkRenderDevice
             if queue_family_indices.is_complete() {
                 break;
             }
-
-            index += 1;
         }
 
         queue_family_indices
@@ -1042,7 +1509,6 @@ impl VkRenderDevice
     pub fn create_device(
         instance: &ash::Instance,
         physical_device: vk::PhysicalDevice,
-        validation: &debug::ValidationInfo,
         surface: &VkSurface
         ) -> (ash::Device, QueueFamilyIndices) {
         let indices = VkRenderDevice::find_queue_family(instance, physical_device, surface);
@@ -1069,40 +1535,26 @@ impl VkRenderDevice
             ..Default::default()
         };
 
-        let required_validation_layer_raw_names: Vec<CString> = validation
-            .required_validation_layers
-            .iter()
-            .map(|layer_name| CString::new(*layer_name).unwrap())
-            .collect();
-
-        let enable_layer_names: Vec<*const c_char> = required_validation_layer_raw_names
-            .iter()
-            .map(|layer_name| layer_name.as_ptr())
-            .collect();
+        let mut vulkan_12_features = vk::PhysicalDeviceVulkan12Features {
+            timeline_semaphore: vk::TRUE,
+            ..Default::default()
+        };
 
         let enable_extension_names = [
             ash::extensions::khr::Swapchain::name().as_ptr(), // currently just enable the Swapchain extension.
         ];
 
         let device_create_info = vk::DeviceCreateInfo {
-            s_type: vk::StructureType::DEVICE_QUEUE_CREATE_INFO,
-            p_next: ptr::null(),
+            s_type: vk::StructureType::DEVICE_CREATE_INFO,
+            p_next: &mut vulkan_12_features as *mut vk::PhysicalDeviceVulkan12Features as *const c_void,
             flags: vk::DeviceCreateFlags::empty(),
             queue_create_info_count: queue_create_infos.len() as u32,
             p_queue_create_infos: queue_create_infos.as_ptr(),
-            enabled_layer_count: if validation.is_enable {
-                enable_layer_names.len()
-            } else {
-                0
-            } as u32,
-            pp_enabled_layer_names: if validation.is_enable {
-                enable_layer_names.as_ptr()
-            } else {
-                ptr::null()
-            },
             enabled_extension_count: enable_extension_names.len() as u32,
             pp_enabled_extension_names: enable_extension_names.as_ptr(),
-            p_enabled_features: &physical_device_features
+            p_enabled_features: &physical_device_features,
+            // device layers are deprecated and ignored, the instance's apply
+            ..Default::default()
         };
 
         let device = unsafe {
@@ -1121,7 +1573,7 @@ impl VkRenderDevice
         let command_pool_create_info = vk::CommandPoolCreateInfo {
             s_type: vk::StructureType::COMMAND_POOL_CREATE_INFO,
             p_next: ptr::null(),
-            flags: vk::CommandPoolCreateFlags::empty(),
+            flags: vk::CommandPoolCreateFlags::RESET_COMMAND_BUFFER,
             queue_family_index: queue_families.graphics_family.unwrap(),
         };
 
@@ -1135,121 +1587,343 @@ impl VkRenderDevice
     pub fn create_command_buffers (
         device: &ash::Device,
         command_pool: vk::CommandPool,
-        graphics_pipeline: vk::Pipeline,
-        framebuffers: &Vec<vk::Framebuffer>,
-        render_pass: vk::RenderPass,
-        surface_extent: vk::Extent2D,
-        vertex_buffer: vk::Buffer,
-        index_buffer: vk::Buffer,
-        pipeline_layout: vk::PipelineLayout,
-        descriptor_sets: &Vec<vk::DescriptorSet>
+        command_buffer_count: u32,
     ) -> Vec<vk::CommandBuffer> {
         let command_buffer_allocate_info = vk::CommandBufferAllocateInfo {
             s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
             p_next: ptr::null(),
-            command_buffer_count: framebuffers.len() as u32,
-            command_pool: command_pool,
+            command_buffer_count,
+            command_pool,
             level: vk::CommandBufferLevel::PRIMARY,
         };
 
-        let command_buffers = unsafe {
+        unsafe {
             device
                 .allocate_command_buffers(&command_buffer_allocate_info)
                 .expect("Failed to allocate Command Buffers!")
+        }
+    }
+
+    /// Waits until the GPU is done with the current frame's previous submission, then retires
+    /// everything that is no longer in use.
+    pub fn begin_frame(&mut self) {
+        crate::profile_scope!("wait for frame");
+
+        self.frame_points[self.current_frame].wait(&self.device, u64::MAX);
+        self.deletion_queue.collect(&self.device);
+
+        self.frame_descriptor_allocators.begin_frame(&self.device, self.current_frame);
+    }
+
+    /// Submits the current frame's command buffer after the swapchain image is acquired
+    /// and signals the semaphore presentation waits on.
+    pub fn submit_frame(&mut self) -> TimelinePoint {
+        let command_buffers = [self.command_buffers[self.current_frame]];
+
+        let mut submission = TimelineSubmit::new(&command_buffers)
+            .wait_binary(
+                self.sync_objects.image_available_semaphores[self.current_frame],
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
+            .signal_binary(self.sync_objects.render_finished_semaphores[self.current_frame]);
+
+        // submission order alone does not make the copies visible to vertex input and shader reads
+        if let Some(upload_point) = self.upload_point.take() {
+            submission = submission.wait(
+                upload_point,
+                vk::PipelineStageFlags::VERTEX_INPUT | vk::PipelineStageFlags::VERTEX_SHADER | vk::PipelineStageFlags::FRAGMENT_SHADER);
+        }
+
+        let frame_point = self.graphics_timeline.submit(&self.device, &submission);
+        self.frame_points[self.current_frame] = frame_point;
+
+        frame_point
+    }
+
+    /// Re-records the command buffer of the current frame to draw into the swapchain image `image_index`.
+    pub fn record_command_buffer(&mut self, image_index: usize) {
+        crate::profile_scope!("record command buffer");
+
+        let command_buffer = self.command_buffers[self.current_frame];
+
+        let command_buffer_begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: ptr::null(),
+            p_inheritance_info: ptr::null(),
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
         };
 
-        for (i, &command_buffer) in command_buffers.iter().enumerate() {
-            let command_buffer_begin_info = vk::CommandBufferBeginInfo {
-                s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
-                p_next: ptr::null(),
-                p_inheritance_info: ptr::null(),
-                flags: vk::CommandBufferUsageFlags::SIMULTANEOUS_USE,
-            };
+        unsafe {
+            self.device
+                .reset_command_buffer(command_buffer, vk::CommandBufferResetFlags::empty())
+                .expect("Failed to reset Command Buffer!");
+            self.device
+                .begin_command_buffer(command_buffer, &command_buffer_begin_info)
+                .expect("Failed to begin recording Command Buffer at beginning!");
+        }
 
-            unsafe {
-                device
-                    .begin_command_buffer(command_buffer, &command_buffer_begin_info)
-                    .expect("Failed to begin recording Command Buffer at beginning!");
-            }
+        self.gpu_profiler.begin_frame(&self.device, self.current_frame, command_buffer);
 
-            let clear_values = [vk::ClearValue {
-                color: vk::ClearColorValue {
-                    float32: [0.0, 0.0, 0.0, 1.0],
-                },
-            }];
+        self.prepare_instances();
+        self.imgui_renderer.prepare_frame(
+            &self.device,
+            &self.memory_properties,
+            &mut self.deletion_queue,
+            self.graphics_timeline.last_submitted(),
+            self.current_frame);
 
-            let render_pass_begin_info = vk::RenderPassBeginInfo {
-                s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
-                p_next: ptr::null(),
-                render_pass,
-                framebuffer: framebuffers[i],
-                render_area: vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: surface_extent,
-                },
-                clear_value_count: clear_values.len() as u32,
-                p_clear_values: clear_values.as_ptr(),
-            };
+        let post_process_sets = self.post_process.prepare_frame(
+            &self.device,
+            &mut self.frame_descriptor_allocators,
+            self.current_frame,
+            &self.scene_target);
+
+        let mut transients = std::mem::take(&mut self.render_graph_transients[self.current_frame]);
+        let render_device: &VkRenderDevice = self;
+
+        let mut graph = RenderGraph::new();
+        let backbuffer = graph.import_image("backbuffer", ImportedImage {
+            image: render_device.swapchain.swapchain_images[image_index],
+            view: render_device.swapchain.swapchain_image_views[image_index],
+            desc: ImageDesc {
+                format: render_device.swapchain.swapchain_format,
+                extent: render_device.swapchain.swapchain_extent,
+                usage: vk::ImageUsageFlags::empty(),
+            },
+            // the acquire semaphore is waited on at this stage and the old contents are discarded
+            initial_state: ResourceState {
+                stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                access: vk::AccessFlags::empty(),
+                layout: vk::ImageLayout::UNDEFINED,
+            },
+            final_state: Some(ResourceState {
+                stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                access: vk::AccessFlags::empty(),
+                layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            }),
+        });
+
+        let scene_color = graph.import_image("scene_color", render_device.scene_target.imported());
+
+        graph.add_pass(
+            "main",
+            |pass| {
+                pass.write(scene_color, Access::ColorAttachmentWrite);
+            },
+            move |context| render_device.record_main_pass(context, image_index),
+        );
 
-            unsafe {
-                device.cmd_begin_render_pass(
-                    command_buffer,
-                    &render_pass_begin_info,
-                    vk::SubpassContents::INLINE,
-                );
-                device.cmd_bind_pipeline(
-                    command_buffer,
-                    vk::PipelineBindPoint::GRAPHICS,
-                    graphics_pipeline,
-                );
-
-                let vertex_buffers = [vertex_buffer];
-                let offsets = [0_u64];
-                let descriptor_sets_to_bind = [descriptor_sets[i]];
-
-                device.cmd_bind_vertex_buffers(command_buffer, 0, &vertex_buffers, &offsets);
-                device.cmd_bind_index_buffer(
-                    command_buffer, 
-                    index_buffer, 
-                    0, 
-                    vk::IndexType::UINT32
-                );
-                device.cmd_bind_descriptor_sets(
-                    command_buffer, 
-                    vk::PipelineBindPoint::GRAPHICS, 
-                    pipeline_layout, 
-                    0, 
-                    &descriptor_sets_to_bind, 
-                    &[]
-                );
-
-                device.cmd_draw_indexed(command_buffer, INDICES_DATA.len() as u32, 1, 0, 0, 0);
-
-                device.cmd_end_render_pass(command_buffer);
-
-                device
-                    .end_command_buffer(command_buffer)
-                    .expect("Failed to record Command Buffer at Ending!");
-            }
+        render_device.post_process.add_to_graph(
+            &mut graph,
+            scene_color,
+            backbuffer,
+            post_process_sets,
+            render_device.swapchain.swapchain_framebuffers[image_index],
+            render_device.swapchain.swapchain_extent);
+
+        render_device.imgui_renderer.add_to_graph(
+            &mut graph,
+            backbuffer,
+            render_device.swapchain.swapchain_framebuffers[image_index],
+            render_device.swapchain.swapchain_extent,
+            &render_device.physical_device_properties.limits,
+            render_device.current_frame);
+
+        let compiled_graph = graph.compile().expect("Failed to compile render graph!");
+        render_device.gpu_profiler.scope(&render_device.device, command_buffer, "frame", || {
+            graph.execute(
+                &compiled_graph,
+                &render_device.device,
+                &render_device.memory_properties,
+                command_buffer,
+                &mut transients,
+                Some(&render_device.gpu_profiler));
+        });
+        drop(graph);
+
+        self.render_graph_transients[self.current_frame] = transients;
+        self.gpu_profiler.end_frame();
+
+        unsafe {
+            self.device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to record Command Buffer at Ending!");
         }
+    }
+
+    fn record_main_pass(&self, context: &PassContext, image_index: usize) {
+        let command_buffer = context.command_buffer;
+
+        let clear_values = [vk::ClearValue {
+            color: vk::ClearColorValue {
+                float32: [0.0, 0.0, 0.0, 1.0],
+            },
+        }];
+
+        let viewports = [vk::Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: self.scene_target.extent.width as f32,
+            height: self.scene_target.extent.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+
+        let scissors = [vk::Rect2D {
+            offset: vk::Offset2D { x: 0, y: 0 },
+            extent: self.scene_target.extent,
+        }];
+
+        let render_pass_begin_info = vk::RenderPassBeginInfo {
+            s_type: vk::StructureType::RENDER_PASS_BEGIN_INFO,
+            p_next: ptr::null(),
+            render_pass: self.scene_render_pass,
+            framebuffer: self.scene_framebuffer,
+            render_area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent: self.scene_target.extent,
+            },
+            clear_value_count: clear_values.len() as u32,
+            p_clear_values: clear_values.as_ptr(),
+        };
+
+        let inheritance = SecondaryInheritance {
+            render_pass: self.scene_render_pass,
+            subpass: 0,
+            framebuffer: self.scene_framebuffer,
+        };
+
+        let mesh_pipelines = &self.mesh_pipelines[..];
+        let meshes = &self.meshes[..];
+        let materials = &self.materials[..];
+        let default_material = self.default_material;
+        let limits = &self.physical_device_properties.limits;
+        let descriptor_sets_to_bind = [self.descriptor_sets[image_index]];
+        let instance_buffers = [self.instance_buffers[self.current_frame].buffer];
+        let batches = &self.draw_batches[..];
+
+        // dynamic state is not inherited, so every secondary sets it again
+        let secondary_command_buffers = self.parallel_recorder.record(
+            self.current_frame,
+            inheritance,
+            &self.physical_device_properties.limits,
+            batches.len(),
+            |recorder, range| unsafe {
+                let command_buffer = recorder.command_buffer;
+
+                recorder.device.cmd_set_viewport(command_buffer, 0, &viewports);
+                recorder.device.cmd_set_scissor(command_buffer, 0, &scissors);
+
+                let mut bound_pipeline = None;
+                let mut bound_mesh = None;
+                let mut bound_material = None;
+                for batch in batches[range].iter() {
+                    let mesh = match meshes.get(batch.mesh.0 as usize).and_then(|mesh| mesh.as_ref()) {
+                        Some(mesh) => mesh,
+                        None => continue,
+                    };
+                    if bound_pipeline != Some(batch.pipeline) {
+                        let pipeline = &mesh_pipelines[batch.pipeline];
+                        recorder.device.cmd_bind_pipeline(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline.pipeline,
+                        );
+                        recorder.device.cmd_bind_descriptor_sets(
+                            command_buffer,
+                            vk::PipelineBindPoint::GRAPHICS,
+                            pipeline.pipeline_layout,
+                            0,
+                            &descriptor_sets_to_bind,
+                            &[]
+                        );
+                        // the mesh's streams take the bindings before it
+                        recorder.device.cmd_bind_vertex_buffers(command_buffer, pipeline.instance_binding(), &instance_buffers, &[0]);
+                        bound_pipeline = Some(batch.pipeline);
+                        bound_material = None;
+                    }
+                    if bound_mesh != Some(batch.mesh) {
+                        mesh.bind(recorder.device, command_buffer);
+                        bound_mesh = Some(batch.mesh);
+                    }
+
+                    let pipeline_layout = mesh_pipelines[batch.pipeline].pipeline_layout;
+                    for (submesh, material) in mesh.submeshes.iter().zip(mesh.materials.iter()) {
+                        let material = material.filter(|material| (material.0 as usize) < materials.len()).unwrap_or(default_material);
+                        if bound_material != Some(material) {
+                            let gpu_material = &materials[material.0 as usize];
+                            recorder.device.cmd_bind_descriptor_sets(
+                                command_buffer,
+                                vk::PipelineBindPoint::GRAPHICS,
+                                pipeline_layout,
+                                1,
+                                &[gpu_material.descriptor_set],
+                                &[]
+                            );
+                            let pushed = CommandRecorder::new(recorder.device, command_buffer, limits).push_constants(
+                                pipeline_layout,
+                                MeshPipeline::push_constant_range().stage_flags,
+                                0,
+                                &gpu_material.constants);
+                            if let Err(error) = pushed {
+                                println!("[Render] Skipping a submesh of {:?}: {}", batch.mesh, error);
+                                continue;
+                            }
+                            bound_material = Some(material);
+                        }
+
+                        mesh.draw_submesh(recorder.device, command_buffer, submesh, batch.first_instance, batch.instance_count);
+                    }
+                }
+            });
+
+        // timestamps can't be written inside a pass that only executes secondaries, and executing none is invalid
+        let subpass_contents = if secondary_command_buffers.is_empty() {
+            vk::SubpassContents::INLINE
+        } else {
+            vk::SubpassContents::SECONDARY_COMMAND_BUFFERS
+        };
 
-        command_buffers
+        self.gpu_profiler.scope(&self.device, command_buffer, "scene draws", || unsafe {
+            self.device.cmd_begin_render_pass(command_buffer, &render_pass_begin_info, subpass_contents);
+            if !secondary_command_buffers.is_empty() {
+                self.device.cmd_execute_commands(command_buffer, &secondary_command_buffers);
+            }
+            self.device.cmd_end_render_pass(command_buffer);
+        });
+    }
+
+    pub fn create_render_pass(
+        device: &ash::Device,
+        render_pass_key: &RenderPassKey
+    ) -> vk::RenderPass {
+        VkRenderDevice::create_color_render_pass(device, render_pass_key, vk::AttachmentLoadOp::CLEAR)
+    }
+
+    /// Like `create_render_pass` but keeps what earlier passes drew, for drawing over it.
+    /// Both are compatible, so they share framebuffers and pipelines.
+    pub fn create_overlay_render_pass(
+        device: &ash::Device,
+        render_pass_key: &RenderPassKey
+    ) -> vk::RenderPass {
+        VkRenderDevice::create_color_render_pass(device, render_pass_key, vk::AttachmentLoadOp::LOAD)
     }
 
-    fn create_render_pass(
+    fn create_color_render_pass(
         device: &ash::Device,
-        surface_format: vk::Format
+        render_pass_key: &RenderPassKey,
+        load_op: vk::AttachmentLoadOp,
     ) -> vk::RenderPass {
         let color_attachment = vk::AttachmentDescription {
             flags: vk::AttachmentDescriptionFlags::empty(),
-            format: surface_format,
-            samples: vk::SampleCountFlags::TYPE_1,
-            load_op: vk::AttachmentLoadOp::CLEAR,
+            format: render_pass_key.color_format,
+            samples: render_pass_key.samples,
+            load_op,
             store_op: vk::AttachmentStoreOp::STORE,
             stencil_load_op: vk::AttachmentLoadOp::DONT_CARE,
             stencil_store_op: vk::AttachmentStoreOp::DONT_CARE,
-            initial_layout: vk::ImageLayout::UNDEFINED,
-            final_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            // layout transitions around the pass are done by the render graph
+            initial_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            final_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
         };
 
         let color_attachment_ref = vk::AttachmentReference {
@@ -1292,56 +1966,57 @@ impl VkRenderDevice
 
     } 
 
-    fn create_graphics_pipeline(
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_graphics_pipeline(
         device: &ash::Device,
-        swap_chain: &VkSpawChain,
+        shader_compiler: &mut ShaderCompiler,
+        shaders: &mut [PipelineShader],
         render_pass: vk::RenderPass,
-        ubo_layout: vk::DescriptorSetLayout
-    ) -> (vk::Pipeline, vk::PipelineLayout) {
-        let vert_shader_module = VkRenderDevice::create_shader_module(
-            device,
-            VkRenderDevice::read_shader_code(Path::new("shaders/spv/21-shader-ubo.vert.spv")));
+        state: &PipelineState,
+        set_layouts: &[vk::DescriptorSetLayout],
+        push_constant_ranges: &[vk::PushConstantRange],
+        binding_descriptions: &[vk::VertexInputBindingDescription],
+        attribute_descriptions: &[vk::VertexInputAttributeDescription]
+    ) -> Result<(vk::Pipeline, vk::PipelineLayout), String> {
+        let mut shader_codes = vec![];
+        for shader in shaders.iter_mut() {
+            let code = shader.code(shader_compiler).map_err(|error| error.to_string())?;
+            shader_codes.push(code.to_vec());
+        }
+
+        let shader_modules: Vec<vk::ShaderModule> = shader_codes
+            .iter()
+            .map(|code| VkRenderDevice::create_shader_module(device, code))
+            .collect();
 
-        let frag_shader_module = VkRenderDevice::create_shader_module(
-            device, 
-            VkRenderDevice::read_shader_code(Path::new("shaders/spv/21-shader-ubo.frag.spv")));
+        let specialization_infos: Vec<vk::SpecializationInfo> = shaders
+            .iter()
+            .map(|shader| shader.specialization.info())
+            .collect();
 
         let main_function_name = CString::new("main").unwrap();
 
-        let shader_stages = [
-            vk::PipelineShaderStageCreateInfo {
-                // Vertex Shader
-                s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
-                p_next: ptr::null(),
-                flags: vk::PipelineShaderStageCreateFlags::empty(),
-                module: vert_shader_module,
-                p_name: main_function_name.as_ptr(),
-                p_specialization_info: ptr::null(),
-                stage: vk::ShaderStageFlags::VERTEX,
-            },
-            vk::PipelineShaderStageCreateInfo {
-                // Fragment Shader
+        let mut shader_stages = vec![];
+        for (i, shader) in shaders.iter().enumerate() {
+            shader_stages.push(vk::PipelineShaderStageCreateInfo {
                 s_type: vk::StructureType::PIPELINE_SHADER_STAGE_CREATE_INFO,
                 p_next: ptr::null(),
                 flags: vk::PipelineShaderStageCreateFlags::empty(),
-                module: frag_shader_module,
+                module: shader_modules[i],
                 p_name: main_function_name.as_ptr(),
-                p_specialization_info: ptr::null(),
-                stage: vk::ShaderStageFlags::FRAGMENT,
-            },
-        ];
-
-        let binding_description = Vertex::get_binding_descriptions();
-        let attribute_description = Vertex::get_attribute_descriptions();
+                p_specialization_info: &specialization_infos[i],
+                stage: shader.asset.stage,
+            });
+        }
 
         let vertex_input_state_create_info = vk::PipelineVertexInputStateCreateInfo {
             s_type: vk::StructureType::PIPELINE_VERTEX_INPUT_STATE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::PipelineVertexInputStateCreateFlags::empty(),
-            vertex_attribute_description_count: attribute_description.len() as u32,
-            p_vertex_attribute_descriptions: attribute_description.as_ptr(),
-            vertex_binding_description_count: binding_description.len() as u32,
-            p_vertex_binding_descriptions: binding_description.as_ptr(),
+            vertex_attribute_description_count: attribute_descriptions.len() as u32,
+            p_vertex_attribute_descriptions: attribute_descriptions.as_ptr(),
+            vertex_binding_description_count: binding_descriptions.len() as u32,
+            p_vertex_binding_descriptions: binding_descriptions.as_ptr(),
         };
 
         let vertex_input_assembly_state_create_info = vk::PipelineInputAssemblyStateCreateInfo {
@@ -1352,28 +2027,15 @@ impl VkRenderDevice
             topology: vk::PrimitiveTopology::TRIANGLE_LIST,
         };
 
-        let viewports = [vk::Viewport {
-            x: 0.0,
-            y: 0.0,
-            width: swap_chain.swapchain_extent.width as f32,
-            height: swap_chain.swapchain_extent.height as f32,
-            min_depth: 0.0,
-            max_depth: 1.0,
-        }];
-
-        let scissors = [vk::Rect2D {
-            offset: vk::Offset2D { x: 0, y: 0 },
-            extent: swap_chain.swapchain_extent,
-        }];
-
+        // viewport and scissor are set while recording, so the pipeline outlives swapchain resizes
         let viewport_state_create_info = vk::PipelineViewportStateCreateInfo {
             s_type: vk::StructureType::PIPELINE_VIEWPORT_STATE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::PipelineViewportStateCreateFlags::empty(),
-            scissor_count: scissors.len() as u32,
-            p_scissors: scissors.as_ptr(),
-            viewport_count: viewports.len() as u32,
-            p_viewports: viewports.as_ptr(),
+            scissor_count: 1,
+            p_scissors: ptr::null(),
+            viewport_count: 1,
+            p_viewports: ptr::null(),
         };
 
         let rasterization_state_create_info = vk::PipelineRasterizationStateCreateInfo {
@@ -1381,8 +2043,9 @@ impl VkRenderDevice
             p_next: ptr::null(),
             flags: vk::PipelineRasterizationStateCreateFlags::empty(),
             depth_clamp_enable: vk::FALSE,
-            cull_mode: vk::CullModeFlags::BACK,
-            front_face: vk::FrontFace::CLOCKWISE,
+            cull_mode: state.cull_mode,
+            // the camera's projection flips Y, so counter-clockwise in the world stays counter-clockwise on screen
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
             line_width: 1.0,
             polygon_mode: vk::PolygonMode::FILL,
             rasterizer_discard_enable: vk::FALSE,
@@ -1429,15 +2092,28 @@ impl VkRenderDevice
             min_depth_bounds: 0.0,
         };
 
-        let color_blend_attachment_states = [vk::PipelineColorBlendAttachmentState {
-            blend_enable: vk::FALSE,
-            color_write_mask: vk::ColorComponentFlags::RGBA,
-            src_color_blend_factor: vk::BlendFactor::ONE,
-            dst_color_blend_factor: vk::BlendFactor::ZERO,
-            color_blend_op: vk::BlendOp::ADD,
-            src_alpha_blend_factor: vk::BlendFactor::ONE,
-            dst_alpha_blend_factor: vk::BlendFactor::ZERO,
-            alpha_blend_op: vk::BlendOp::ADD,
+        let color_blend_attachment_states = [if state.alpha_blend {
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: vk::TRUE,
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+                src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ONE,
+                dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+                alpha_blend_op: vk::BlendOp::ADD,
+            }
+        } else {
+            vk::PipelineColorBlendAttachmentState {
+                blend_enable: vk::FALSE,
+                color_write_mask: vk::ColorComponentFlags::RGBA,
+                src_color_blend_factor: vk::BlendFactor::ONE,
+                dst_color_blend_factor: vk::BlendFactor::ZERO,
+                color_blend_op: vk::BlendOp::ADD,
+                src_alpha_blend_factor: vk::BlendFactor::ONE,
+                dst_alpha_blend_factor: vk::BlendFactor::ZERO,
+                alpha_blend_op: vk::BlendOp::ADD,
+            }
         }];
 
         let color_blend_state = vk::PipelineColorBlendStateCreateInfo {
@@ -1451,17 +2127,14 @@ impl VkRenderDevice
             blend_constants: [0.0, 0.0, 0.0, 0.0],
         };
 
-        //                leaving the dynamic statue unconfigurated right now
-        //                let dynamic_state = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
-        //                let dynamic_state_info = vk::PipelineDynamicStateCreateInfo {
-        //                    s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
-        //                    p_next: ptr::null(),
-        //                    flags: vk::PipelineDynamicStateCreateFlags::empty(),
-        //                    dynamic_state_count: dynamic_state.len() as u32,
-        //                    p_dynamic_states: dynamic_state.as_ptr(),
-        //                };
-
-        let set_layouts = [ubo_layout];     
+        let dynamic_states = [vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        let dynamic_state_info = vk::PipelineDynamicStateCreateInfo {
+            s_type: vk::StructureType::PIPELINE_DYNAMIC_STATE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::PipelineDynamicStateCreateFlags::empty(),
+            dynamic_state_count: dynamic_states.len() as u32,
+            p_dynamic_states: dynamic_states.as_ptr(),
+        };
 
         let pipeline_layout_create_info = vk::PipelineLayoutCreateInfo {
             s_type: vk::StructureType::PIPELINE_LAYOUT_CREATE_INFO,
@@ -1469,8 +2142,8 @@ impl VkRenderDevice
             flags: vk::PipelineLayoutCreateFlags::empty(),
             set_layout_count: set_layouts.len() as u32,
             p_set_layouts: set_layouts.as_ptr(),
-            push_constant_range_count: 0,
-            p_push_constant_ranges: ptr::null(),
+            push_constant_range_count: push_constant_ranges.len() as u32,
+            p_push_constant_ranges: push_constant_ranges.as_ptr(),
         };
 
         let pipeline_layout = unsafe {
@@ -1493,9 +2166,9 @@ impl VkRenderDevice
             p_multisample_state: &multisample_state_create_info,
             p_depth_stencil_state: &depth_stencil_state_create_info,
             p_color_blend_state: &color_blend_state,
-            p_dynamic_state: ptr::null(),
+            p_dynamic_state: &dynamic_state_info,
             layout: pipeline_layout,
-            render_pass: render_pass,
+            render_pass,
             subpass: 0,
             base_pipeline_handle: vk::Pipeline::null(),
             base_pipeline_index: -1,
@@ -1504,24 +2177,30 @@ impl VkRenderDevice
         let graphics_pipelines = unsafe {
             device
                 .create_graphics_pipelines(vk::PipelineCache::null(), &graphic_pipeline_create_infos, None)
-                .expect("Failed to create graphics pipeline")
         }; 
 
         unsafe {
-            device.destroy_shader_module(vert_shader_module, None);
-            device.destroy_shader_module(frag_shader_module, None);
+            for &shader_module in shader_modules.iter() {
+                device.destroy_shader_module(shader_module, None);
+            }
         }
 
-        (graphics_pipelines[0], pipeline_layout)
+        match graphics_pipelines {
+            Ok(graphics_pipelines) => Ok((graphics_pipelines[0], pipeline_layout)),
+            Err((_, vk_result)) => {
+                unsafe { device.destroy_pipeline_layout(pipeline_layout, None) };
+                Err(format!("Failed to create graphics pipeline: {}", vk_result))
+            }
+        }
     }
 
-    fn create_shader_module(device: &ash::Device, code: Vec<u8>) -> vk::ShaderModule {
+    pub fn create_shader_module(device: &ash::Device, code: &[u32]) -> vk::ShaderModule {
         let shader_module_create_indo = vk::ShaderModuleCreateInfo {
             s_type: vk::StructureType::SHADER_MODULE_CREATE_INFO,
             p_next: ptr::null(),
             flags: vk::ShaderModuleCreateFlags::empty(),
-            code_size: code.len(),
-            p_code: code.as_ptr() as *const u32,
+            code_size: std::mem::size_of_val(code),
+            p_code: code.as_ptr(),
         };
 
         unsafe {
@@ -1531,46 +2210,76 @@ impl VkRenderDevice
         }
     }
 
-    fn read_shader_code(shader_path: &Path) -> Vec<u8> {
-        use std::fs::File;
-        use std::io::Read;
-
-        let spv_file = File::open(shader_path)
-            .expect(&format!("Failed to find spv file at {:?}", shader_path));
-        let bytes_code: Vec<u8> = spv_file.bytes().filter_map(|byte| byte.ok()).collect();
-
-        bytes_code
+    fn cleanup_swapchain_resources(&self) {
+        self.swapchain.cleanup_swapchain(&self.device);
+        self.swapchain.destroy_swapchain();
     }
 
-    fn cleanup_swapchain_resources(&self) {
+    fn cleanup_pipeline_resources(&self) {
         unsafe {
-            self.device
-                .free_command_buffers(self.command_pool, &self.command_buffers);
-
-            self.swapchain.cleanup_swapchain(&self.device);
+            for pipeline in self.mesh_pipelines.iter() {
+                pipeline.destroy(&self.device);
+            }
 
-            self.device.destroy_pipeline_layout(self.pipeline_layout, None);
+            self.device.destroy_render_pass(self.scene_render_pass, None);
 
             self.device.destroy_render_pass(self.render_pass, None);
-
-            self.device.destroy_pipeline(self.graphics_pipeline, None);        
-
-            self.swapchain.destroy_swapchain();
         };
     }
 
-    pub fn drop(&self) {
+    pub fn destroy(&mut self) {
+        // submitted frames may still wait on or signal the swapchain semaphores
+        self.graphics_timeline.wait_idle(&self.device);
+
         unsafe {
             for i in 0..global_constants::MAX_FRAMES_IN_FLIGHT {
                 self.device
                     .destroy_semaphore(self.sync_objects.image_available_semaphores[i], None);
                 self.device
                     .destroy_semaphore(self.sync_objects.render_finished_semaphores[i], None);
-                self.device.destroy_fence(self.sync_objects.inflight_fences[i], None);
+            }
+
+            self.deletion_queue.flush(&self.device);
+            self.graphics_timeline.destroy(&self.device);
+
+            for mesh in self.meshes.drain(..).flatten() {
+                mesh.destroy(&self.device);
+            }
+            for texture in self.textures.drain(..).flatten() {
+                texture.destroy(&self.device);
+            }
+            for sampler in self.samplers.drain(..) {
+                self.device.destroy_sampler(sampler, None);
+            }
+            for instance_buffer in self.instance_buffers.drain(..) {
+                self.device.destroy_buffer(instance_buffer.buffer, None);
+                self.device.free_memory(instance_buffer.memory, None);
+            }
+            for (&uniform_buffer, &uniform_buffer_memory) in self.uniform_buffers.iter().zip(self.uniform_buffers_memory.iter()) {
+                self.device.destroy_buffer(uniform_buffer, None);
+                self.device.free_memory(uniform_buffer_memory, None);
             }
 
             self.cleanup_swapchain_resources();
 
+            self.post_process.destroy(&self.device);
+            self.imgui_renderer.destroy(&self.device);
+            self.device.destroy_framebuffer(self.scene_framebuffer, None);
+            self.scene_target.destroy(&self.device);
+
+            self.cleanup_pipeline_resources();
+
+            for transients in self.render_graph_transients.iter_mut() {
+                transients.destroy(&self.device);
+            }
+
+            self.frame_descriptor_allocators.destroy(&self.device);
+            self.descriptor_allocator.destroy(&self.device);
+            self.descriptor_layout_cache.destroy(&self.device);
+
+            self.gpu_profiler.destroy(&self.device);
+            self.parallel_recorder.destroy(&self.device);
+            self.device.free_command_buffers(self.command_pool, &self.command_buffers);
             self.device.destroy_command_pool(self.command_pool, None);
 
             self.device.destroy_device(None);
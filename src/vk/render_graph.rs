@@ -0,0 +1,1192 @@
+use std::ptr;
+
+use ash::vk;
+
+use crate::vk::gpu_profiler::GpuProfiler;
+use crate::vk::render_device::VkRenderDevice;
+use crate::vk::render_target::RenderTarget;
+
+/// Access bits that make a resource state a write.
+const WRITE_ACCESS: vk::AccessFlags = vk::AccessFlags::from_raw(
+    vk::AccessFlags::SHADER_WRITE.as_raw()
+        | vk::AccessFlags::COLOR_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw()
+        | vk::AccessFlags::TRANSFER_WRITE.as_raw()
+        | vk::AccessFlags::HOST_WRITE.as_raw()
+        | vk::AccessFlags::MEMORY_WRITE.as_raw(),
+);
+
+/// A virtual image or buffer of the graph. Only valid for the graph that returned it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ResourceHandle(usize);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageDesc {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    /// Extra usage on top of what the declared accesses imply.
+    pub usage: vk::ImageUsageFlags,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferDesc {
+    pub size: vk::DeviceSize,
+    /// Extra usage on top of what the declared accesses imply.
+    pub usage: vk::BufferUsageFlags,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ResourceDesc {
+    Image(ImageDesc),
+    Buffer(BufferDesc),
+}
+
+/// Where and how a resource was last touched. `layout` is ignored for buffers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResourceState {
+    pub stage: vk::PipelineStageFlags,
+    pub access: vk::AccessFlags,
+    pub layout: vk::ImageLayout,
+}
+
+impl ResourceState {
+    pub fn is_write(&self) -> bool {
+        self.access.intersects(WRITE_ACCESS)
+    }
+}
+
+/// How a pass uses a resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    ColorAttachmentWrite,
+    DepthAttachmentWrite,
+    DepthAttachmentRead,
+    SampledRead(vk::PipelineStageFlags),
+    StorageRead(vk::PipelineStageFlags),
+    StorageWrite(vk::PipelineStageFlags),
+    UniformRead(vk::PipelineStageFlags),
+    VertexBufferRead,
+    IndexBufferRead,
+    TransferRead,
+    TransferWrite,
+}
+
+impl Access {
+    pub fn is_write(&self) -> bool {
+        self.state().is_write()
+    }
+
+    pub fn state(&self) -> ResourceState {
+        let (stage, access, layout) = match *self {
+            Access::ColorAttachmentWrite => (
+                vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+                vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ),
+            Access::DepthAttachmentWrite => (
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ),
+            Access::DepthAttachmentRead => (
+                vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS,
+                vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+                vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ),
+            Access::SampledRead(stage) => (stage, vk::AccessFlags::SHADER_READ, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            Access::StorageRead(stage) => (stage, vk::AccessFlags::SHADER_READ, vk::ImageLayout::GENERAL),
+            Access::StorageWrite(stage) => (stage, vk::AccessFlags::SHADER_WRITE, vk::ImageLayout::GENERAL),
+            Access::UniformRead(stage) => (stage, vk::AccessFlags::UNIFORM_READ, vk::ImageLayout::UNDEFINED),
+            Access::VertexBufferRead => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::VERTEX_ATTRIBUTE_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Access::IndexBufferRead => (
+                vk::PipelineStageFlags::VERTEX_INPUT,
+                vk::AccessFlags::INDEX_READ,
+                vk::ImageLayout::UNDEFINED,
+            ),
+            Access::TransferRead => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ),
+            Access::TransferWrite => (
+                vk::PipelineStageFlags::TRANSFER,
+                vk::AccessFlags::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+            ),
+        };
+
+        ResourceState { stage, access, layout }
+    }
+
+    fn image_usage(&self) -> vk::ImageUsageFlags {
+        match *self {
+            Access::ColorAttachmentWrite => vk::ImageUsageFlags::COLOR_ATTACHMENT,
+            Access::DepthAttachmentWrite | Access::DepthAttachmentRead => vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT,
+            Access::SampledRead(_) => vk::ImageUsageFlags::SAMPLED,
+            Access::StorageRead(_) | Access::StorageWrite(_) => vk::ImageUsageFlags::STORAGE,
+            Access::TransferRead => vk::ImageUsageFlags::TRANSFER_SRC,
+            Access::TransferWrite => vk::ImageUsageFlags::TRANSFER_DST,
+            Access::UniformRead(_) | Access::VertexBufferRead | Access::IndexBufferRead => vk::ImageUsageFlags::empty(),
+        }
+    }
+
+    fn buffer_usage(&self) -> vk::BufferUsageFlags {
+        match *self {
+            Access::StorageRead(_) | Access::StorageWrite(_) => vk::BufferUsageFlags::STORAGE_BUFFER,
+            Access::UniformRead(_) => vk::BufferUsageFlags::UNIFORM_BUFFER,
+            Access::VertexBufferRead => vk::BufferUsageFlags::VERTEX_BUFFER,
+            Access::IndexBufferRead => vk::BufferUsageFlags::INDEX_BUFFER,
+            Access::TransferRead => vk::BufferUsageFlags::TRANSFER_SRC,
+            Access::TransferWrite => vk::BufferUsageFlags::TRANSFER_DST,
+            _ => vk::BufferUsageFlags::empty(),
+        }
+    }
+}
+
+/// An image owned outside the graph, e.g. a swapchain image.
+/// Setting `final_state` makes it a graph output that is transitioned to that state at the end.
+#[derive(Clone, Copy, Debug)]
+pub struct ImportedImage {
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub desc: ImageDesc,
+    pub initial_state: ResourceState,
+    pub final_state: Option<ResourceState>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct ImportedBuffer {
+    pub buffer: vk::Buffer,
+    pub desc: BufferDesc,
+    pub initial_state: ResourceState,
+    pub final_state: Option<ResourceState>,
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Imported {
+    Image(ImportedImage),
+    Buffer(ImportedBuffer),
+}
+
+impl Imported {
+    fn initial_state(&self) -> ResourceState {
+        match self {
+            Imported::Image(image) => image.initial_state,
+            Imported::Buffer(buffer) => buffer.initial_state,
+        }
+    }
+
+    fn final_state(&self) -> Option<ResourceState> {
+        match self {
+            Imported::Image(image) => image.final_state,
+            Imported::Buffer(buffer) => buffer.final_state,
+        }
+    }
+}
+
+struct ResourceNode {
+    name: String,
+    desc: ResourceDesc,
+    imported: Option<Imported>,
+    is_output: bool,
+}
+
+struct PassNode<'a> {
+    name: String,
+    accesses: Vec<(ResourceHandle, Access)>,
+    has_side_effects: bool,
+    execute: Box<dyn Fn(&PassContext) + 'a>,
+}
+
+/// Collects the resources a pass reads and writes.
+pub struct PassBuilder {
+    accesses: Vec<(ResourceHandle, Access)>,
+    has_side_effects: bool,
+}
+
+impl PassBuilder {
+    pub fn read(&mut self, resource: ResourceHandle, access: Access) -> &mut PassBuilder {
+        assert!(!access.is_write(), "{:?} is not a read access", access);
+        self.accesses.push((resource, access));
+        self
+    }
+
+    pub fn write(&mut self, resource: ResourceHandle, access: Access) -> &mut PassBuilder {
+        assert!(access.is_write(), "{:?} is not a write access", access);
+        self.accesses.push((resource, access));
+        self
+    }
+
+    /// Keeps the pass even if none of its outputs are used, e.g. for readbacks.
+    pub fn side_effects(&mut self) -> &mut PassBuilder {
+        self.has_side_effects = true;
+        self
+    }
+}
+
+/// One barrier in front of a pass, or at the end of the graph for outputs.
+/// A transient's first use discards the previous contents, so `old_layout` is `UNDEFINED`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Barrier {
+    pub resource: ResourceHandle,
+    pub src_stage: vk::PipelineStageFlags,
+    pub src_access: vk::AccessFlags,
+    pub dst_stage: vk::PipelineStageFlags,
+    pub dst_access: vk::AccessFlags,
+    pub old_layout: vk::ImageLayout,
+    pub new_layout: vk::ImageLayout,
+}
+
+#[derive(Clone, Debug)]
+pub struct CompiledPass {
+    pub pass: usize,
+    pub name: String,
+    /// Earlier passes this one has to wait for.
+    pub dependencies: Vec<usize>,
+    pub barriers: Vec<Barrier>,
+}
+
+/// The result of `RenderGraph::compile`. Building it never touches the device.
+#[derive(Clone, Debug)]
+pub struct CompiledGraph {
+    /// Passes that survived culling, in execution order.
+    pub passes: Vec<CompiledPass>,
+    pub culled_passes: Vec<usize>,
+    pub final_barriers: Vec<Barrier>,
+    /// Physical slot of every transient resource, `None` for imported or unused ones.
+    pub transient_slots: Vec<Option<usize>>,
+    /// Descriptions of the physical transients; resources with disjoint lifetimes share a slot.
+    pub physical_resources: Vec<ResourceDesc>,
+}
+
+#[derive(Clone, Copy)]
+enum ResolvedResource {
+    Image {
+        image: vk::Image,
+        view: vk::ImageView,
+        extent: vk::Extent2D,
+    },
+    Buffer(vk::Buffer),
+    Unused,
+}
+
+/// What a pass sees while it records.
+pub struct PassContext<'a> {
+    pub device: &'a ash::Device,
+    pub command_buffer: vk::CommandBuffer,
+
+    resources: Vec<ResolvedResource>,
+}
+
+impl<'a> PassContext<'a> {
+    pub fn image(&self, resource: ResourceHandle) -> vk::Image {
+        match self.resources[resource.0] {
+            ResolvedResource::Image { image, .. } => image,
+            _ => panic!("Resource {:?} is not an image used by the graph", resource),
+        }
+    }
+
+    pub fn image_view(&self, resource: ResourceHandle) -> vk::ImageView {
+        match self.resources[resource.0] {
+            ResolvedResource::Image { view, .. } => view,
+            _ => panic!("Resource {:?} is not an image used by the graph", resource),
+        }
+    }
+
+    pub fn extent(&self, resource: ResourceHandle) -> vk::Extent2D {
+        match self.resources[resource.0] {
+            ResolvedResource::Image { extent, .. } => extent,
+            _ => panic!("Resource {:?} is not an image used by the graph", resource),
+        }
+    }
+
+    pub fn buffer(&self, resource: ResourceHandle) -> vk::Buffer {
+        match self.resources[resource.0] {
+            ResolvedResource::Buffer(buffer) => buffer,
+            _ => panic!("Resource {:?} is not a buffer used by the graph", resource),
+        }
+    }
+}
+
+/// A frame described as passes and the resources they read and write.
+/// `compile` culls the unused passes, aliases transients and plans barriers; `execute` records
+/// the result. Passes are never reordered: they run in the order they were added, so a pass
+/// has to be added after the passes writing what it reads.
+pub struct RenderGraph<'a> {
+    resources: Vec<ResourceNode>,
+    passes: Vec<PassNode<'a>>,
+}
+
+impl<'a> Default for RenderGraph<'a> {
+    fn default() -> RenderGraph<'a> {
+        RenderGraph::new()
+    }
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> RenderGraph<'a> {
+        RenderGraph {
+            resources: vec![],
+            passes: vec![],
+        }
+    }
+
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ResourceHandle {
+        self.add_resource(name, ResourceDesc::Image(desc), None)
+    }
+
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> ResourceHandle {
+        self.add_resource(name, ResourceDesc::Buffer(desc), None)
+    }
+
+    pub fn import_image(&mut self, name: &str, image: ImportedImage) -> ResourceHandle {
+        self.add_resource(name, ResourceDesc::Image(image.desc), Some(Imported::Image(image)))
+    }
+
+    pub fn import_buffer(&mut self, name: &str, buffer: ImportedBuffer) -> ResourceHandle {
+        self.add_resource(name, ResourceDesc::Buffer(buffer.desc), Some(Imported::Buffer(buffer)))
+    }
+
+    /// Keeps the passes writing `resource` alive even if nothing in the graph reads it.
+    pub fn mark_output(&mut self, resource: ResourceHandle) {
+        self.resources[resource.0].is_output = true;
+    }
+
+    pub fn resource_name(&self, resource: ResourceHandle) -> &str {
+        &self.resources[resource.0].name
+    }
+
+    /// Adds a pass. `setup` declares its accesses, `execute` records it once the graph runs.
+    /// A read sees the latest write to the resource declared before it.
+    pub fn add_pass<S, E>(&mut self, name: &str, setup: S, execute: E)
+    where
+        S: FnOnce(&mut PassBuilder),
+        E: Fn(&PassContext) + 'a,
+    {
+        let mut builder = PassBuilder {
+            accesses: vec![],
+            has_side_effects: false,
+        };
+        setup(&mut builder);
+
+        self.passes.push(PassNode {
+            name: name.to_string(),
+            accesses: builder.accesses,
+            has_side_effects: builder.has_side_effects,
+            execute: Box::new(execute),
+        });
+    }
+
+    fn add_resource(&mut self, name: &str, desc: ResourceDesc, imported: Option<Imported>) -> ResourceHandle {
+        let is_output = imported.is_some_and(|imported| imported.final_state().is_some());
+
+        self.resources.push(ResourceNode {
+            name: name.to_string(),
+            desc,
+            imported,
+            is_output,
+        });
+
+        ResourceHandle(self.resources.len() - 1)
+    }
+
+    pub fn compile(&self) -> Result<CompiledGraph, String> {
+        self.validate()?;
+
+        let live_passes = self.cull_passes();
+        let culled_passes = (0..self.passes.len())
+            .filter(|pass| !live_passes.contains(pass))
+            .collect();
+
+        let physical_descs = self.physical_descs(&live_passes);
+        let (transient_slots, physical_resources) = self.alias_transients(&live_passes, &physical_descs);
+
+        // barrier state is tracked per physical resource, so aliased transients wait for each other
+        let slot_count = self.resources.len() + physical_resources.len();
+        let state_slot = |resource: usize| match transient_slots[resource] {
+            Some(slot) => self.resources.len() + slot,
+            None => resource,
+        };
+
+        let mut states: Vec<Option<ResourceState>> = vec![None; slot_count];
+        for (i, resource) in self.resources.iter().enumerate() {
+            states[i] = resource.imported.map(|imported| imported.initial_state());
+        }
+
+        let mut last_writer: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut readers: Vec<Vec<usize>> = vec![vec![]; self.resources.len()];
+        let mut is_first_use = vec![true; self.resources.len()];
+
+        let mut passes = vec![];
+        for &pass_index in live_passes.iter() {
+            let pass = &self.passes[pass_index];
+            let mut dependencies = vec![];
+            let mut barriers = vec![];
+
+            for (resource, state) in self.pass_states(pass)? {
+                let is_write = state.is_write();
+
+                dependencies.extend(last_writer[resource.0]);
+                if is_write {
+                    dependencies.extend(readers[resource.0].iter().filter(|&&reader| reader != pass_index));
+                    readers[resource.0].clear();
+                    last_writer[resource.0] = Some(pass_index);
+                } else {
+                    readers[resource.0].push(pass_index);
+                }
+
+                let is_image = matches!(self.resources[resource.0].desc, ResourceDesc::Image(_));
+                let slot = state_slot(resource.0);
+                let discard = self.resources[resource.0].imported.is_none() && is_first_use[resource.0];
+                is_first_use[resource.0] = false;
+
+                let barrier = match states[slot] {
+                    None => {
+                        states[slot] = Some(state);
+                        if is_image {
+                            Some(Barrier {
+                                resource,
+                                src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+                                src_access: vk::AccessFlags::empty(),
+                                dst_stage: state.stage,
+                                dst_access: state.access,
+                                old_layout: vk::ImageLayout::UNDEFINED,
+                                new_layout: state.layout,
+                            })
+                        } else {
+                            None
+                        }
+                    },
+                    Some(previous) => {
+                        let is_layout_change = is_image && (discard || previous.layout != state.layout);
+
+                        if is_layout_change || is_write || previous.is_write() {
+                            states[slot] = Some(state);
+                            Some(Barrier {
+                                resource,
+                                src_stage: previous.stage,
+                                src_access: previous.access & WRITE_ACCESS,
+                                dst_stage: state.stage,
+                                dst_access: state.access,
+                                old_layout: if discard { vk::ImageLayout::UNDEFINED } else { previous.layout },
+                                new_layout: state.layout,
+                            })
+                        } else {
+                            // read after read in the same layout, a later write has to wait for both
+                            states[slot] = Some(ResourceState {
+                                stage: previous.stage | state.stage,
+                                access: previous.access | state.access,
+                                layout: previous.layout,
+                            });
+                            None
+                        }
+                    },
+                };
+
+                barriers.extend(barrier);
+            }
+
+            dependencies.sort_unstable();
+            dependencies.dedup();
+            dependencies.retain(|&dependency| dependency != pass_index);
+
+            passes.push(CompiledPass {
+                pass: pass_index,
+                name: pass.name.clone(),
+                dependencies,
+                barriers,
+            });
+        }
+
+        let mut final_barriers = vec![];
+        for (i, resource) in self.resources.iter().enumerate() {
+            let final_state = match resource.imported.and_then(|imported| imported.final_state()) {
+                Some(final_state) => final_state,
+                None => continue,
+            };
+
+            let current = states[i].unwrap_or(final_state);
+            let is_image = matches!(resource.desc, ResourceDesc::Image(_));
+
+            if current != final_state && (current.is_write() || (is_image && current.layout != final_state.layout)) {
+                final_barriers.push(Barrier {
+                    resource: ResourceHandle(i),
+                    src_stage: current.stage,
+                    src_access: current.access & WRITE_ACCESS,
+                    dst_stage: final_state.stage,
+                    dst_access: final_state.access,
+                    old_layout: current.layout,
+                    new_layout: final_state.layout,
+                });
+            }
+        }
+
+        Ok(CompiledGraph {
+            passes,
+            culled_passes,
+            final_barriers,
+            transient_slots,
+            physical_resources,
+        })
+    }
+
+    /// Every transient has to be written before it is read, and handles must belong to this graph.
+    fn validate(&self) -> Result<(), String> {
+        let mut is_written = vec![false; self.resources.len()];
+
+        for pass in self.passes.iter() {
+            for &(resource, access) in pass.accesses.iter() {
+                let node = self.resources.get(resource.0)
+                    .ok_or_else(|| format!("Pass {} uses a resource of another graph", pass.name))?;
+
+                let is_valid = match (node.desc, access) {
+                    (ResourceDesc::Image(_), access) => access.image_usage() != vk::ImageUsageFlags::empty(),
+                    (ResourceDesc::Buffer(_), access) => access.buffer_usage() != vk::BufferUsageFlags::empty(),
+                };
+                if !is_valid {
+                    return Err(format!("Pass {} uses {} as {:?}, which does not apply to it", pass.name, node.name, access));
+                }
+
+                if !access.is_write() && node.imported.is_none() && !is_written[resource.0] {
+                    return Err(format!("Pass {} reads {} before any pass writes it", pass.name, node.name));
+                }
+            }
+
+            for &(resource, access) in pass.accesses.iter() {
+                if access.is_write() {
+                    is_written[resource.0] = true;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Walks the passes backwards from the outputs and keeps only those that contribute to one.
+    fn cull_passes(&self) -> Vec<usize> {
+        let mut is_needed: Vec<bool> = self.resources.iter().map(|resource| resource.is_output).collect();
+        let mut live_passes = vec![];
+
+        for (pass_index, pass) in self.passes.iter().enumerate().rev() {
+            let is_live = pass.has_side_effects || pass
+                .accesses
+                .iter()
+                .any(|&(resource, access)| access.is_write() && is_needed[resource.0]);
+
+            if !is_live {
+                continue;
+            }
+
+            for &(resource, _) in pass.accesses.iter() {
+                is_needed[resource.0] = true;
+            }
+            live_passes.push(pass_index);
+        }
+
+        live_passes.reverse();
+        live_passes
+    }
+
+    /// Resource descriptions with the usage implied by the accesses of the live passes added.
+    fn physical_descs(&self, live_passes: &[usize]) -> Vec<ResourceDesc> {
+        let mut descs: Vec<ResourceDesc> = self.resources.iter().map(|resource| resource.desc).collect();
+
+        for &pass_index in live_passes.iter() {
+            for &(resource, access) in self.passes[pass_index].accesses.iter() {
+                match &mut descs[resource.0] {
+                    ResourceDesc::Image(desc) => desc.usage |= access.image_usage(),
+                    ResourceDesc::Buffer(desc) => desc.usage |= access.buffer_usage(),
+                }
+            }
+        }
+
+        descs
+    }
+
+    /// Gives transients with identical descriptions and disjoint lifetimes the same physical slot.
+    fn alias_transients(&self, live_passes: &[usize], descs: &[ResourceDesc]) -> (Vec<Option<usize>>, Vec<ResourceDesc>) {
+        let mut lifetimes: Vec<Option<(usize, usize)>> = vec![None; self.resources.len()];
+
+        for (position, &pass_index) in live_passes.iter().enumerate() {
+            for &(resource, _) in self.passes[pass_index].accesses.iter() {
+                if self.resources[resource.0].imported.is_some() {
+                    continue;
+                }
+
+                lifetimes[resource.0] = match lifetimes[resource.0] {
+                    Some((first, _)) => Some((first, position)),
+                    None => Some((position, position)),
+                };
+            }
+        }
+
+        let mut transients: Vec<(usize, (usize, usize))> = lifetimes
+            .iter()
+            .enumerate()
+            .filter_map(|(resource, lifetime)| lifetime.map(|lifetime| (resource, lifetime)))
+            .collect();
+        transients.sort_by_key(|&(resource, (first, _))| (first, resource));
+
+        let mut transient_slots = vec![None; self.resources.len()];
+        let mut physical_resources: Vec<ResourceDesc> = vec![];
+        let mut slot_last_use: Vec<usize> = vec![];
+
+        for (resource, (first, last)) in transients {
+            let free_slot = (0..physical_resources.len())
+                .find(|&slot| physical_resources[slot] == descs[resource] && slot_last_use[slot] < first);
+
+            let slot = match free_slot {
+                Some(slot) => slot,
+                None => {
+                    physical_resources.push(descs[resource]);
+                    slot_last_use.push(0);
+                    physical_resources.len() - 1
+                },
+            };
+
+            slot_last_use[slot] = last;
+            transient_slots[resource] = Some(slot);
+        }
+
+        (transient_slots, physical_resources)
+    }
+
+    /// The accesses of a pass merged per resource, in first-use order.
+    fn pass_states(&self, pass: &PassNode) -> Result<Vec<(ResourceHandle, ResourceState)>, String> {
+        let mut states: Vec<(ResourceHandle, ResourceState)> = vec![];
+
+        for &(resource, access) in pass.accesses.iter() {
+            let state = access.state();
+
+            match states.iter_mut().find(|(existing, _)| *existing == resource) {
+                Some((_, existing)) => {
+                    let is_image = matches!(self.resources[resource.0].desc, ResourceDesc::Image(_));
+                    if is_image && existing.layout != state.layout {
+                        return Err(format!(
+                            "Pass {} uses {} in both {:?} and {:?} layouts",
+                            pass.name, self.resources[resource.0].name, existing.layout, state.layout
+                        ));
+                    }
+
+                    existing.stage |= state.stage;
+                    existing.access |= state.access;
+                },
+                None => states.push((resource, state)),
+            }
+        }
+
+        Ok(states)
+    }
+
+    /// Records the compiled graph. Transients come from `transients`, which has to belong to
+    /// the frame that owns `command_buffer`.
+    pub fn execute(
+        &self,
+        compiled: &CompiledGraph,
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_buffer: vk::CommandBuffer,
+        transients: &mut TransientResources,
+        profiler: Option<&GpuProfiler>,
+    ) {
+        transients.prepare(device, memory_properties, &compiled.physical_resources);
+
+        let resources: Vec<ResolvedResource> = self
+            .resources
+            .iter()
+            .enumerate()
+            .map(|(i, resource)| match (resource.imported, compiled.transient_slots[i]) {
+                (Some(Imported::Image(image)), _) => ResolvedResource::Image {
+                    image: image.image,
+                    view: image.view,
+                    extent: image.desc.extent,
+                },
+                (Some(Imported::Buffer(buffer)), _) => ResolvedResource::Buffer(buffer.buffer),
+                (None, Some(slot)) => transients.resolve(slot),
+                (None, None) => ResolvedResource::Unused,
+            })
+            .collect();
+
+        let context = PassContext {
+            device,
+            command_buffer,
+            resources,
+        };
+
+        for compiled_pass in compiled.passes.iter() {
+            self.record_barriers(&context, &compiled_pass.barriers);
+
+            let scope = profiler.and_then(|profiler| profiler.begin_scope(device, command_buffer, &compiled_pass.name));
+            (self.passes[compiled_pass.pass].execute)(&context);
+            if let Some(profiler) = profiler {
+                profiler.end_scope(device, command_buffer, scope);
+            }
+        }
+
+        self.record_barriers(&context, &compiled.final_barriers);
+    }
+
+    fn record_barriers(&self, context: &PassContext, barriers: &[Barrier]) {
+        if barriers.is_empty() {
+            return;
+        }
+
+        let mut src_stage = vk::PipelineStageFlags::empty();
+        let mut dst_stage = vk::PipelineStageFlags::empty();
+        let mut image_barriers = vec![];
+        let mut buffer_barriers = vec![];
+
+        for barrier in barriers.iter() {
+            src_stage |= barrier.src_stage;
+            dst_stage |= barrier.dst_stage;
+
+            match context.resources[barrier.resource.0] {
+                ResolvedResource::Image { image, .. } => {
+                    let aspect_mask = match self.resources[barrier.resource.0].desc {
+                        ResourceDesc::Image(desc) => aspect_mask(desc.format),
+                        ResourceDesc::Buffer(_) => unreachable!(),
+                    };
+
+                    image_barriers.push(vk::ImageMemoryBarrier {
+                        s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                        p_next: ptr::null(),
+                        src_access_mask: barrier.src_access,
+                        dst_access_mask: barrier.dst_access,
+                        old_layout: barrier.old_layout,
+                        new_layout: barrier.new_layout,
+                        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        image,
+                        subresource_range: vk::ImageSubresourceRange {
+                            aspect_mask,
+                            base_mip_level: 0,
+                            level_count: vk::REMAINING_MIP_LEVELS,
+                            base_array_layer: 0,
+                            layer_count: vk::REMAINING_ARRAY_LAYERS,
+                        },
+                    });
+                },
+                ResolvedResource::Buffer(buffer) => {
+                    buffer_barriers.push(vk::BufferMemoryBarrier {
+                        s_type: vk::StructureType::BUFFER_MEMORY_BARRIER,
+                        p_next: ptr::null(),
+                        src_access_mask: barrier.src_access,
+                        dst_access_mask: barrier.dst_access,
+                        src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                        buffer,
+                        offset: 0,
+                        size: vk::WHOLE_SIZE,
+                    });
+                },
+                ResolvedResource::Unused => {},
+            }
+        }
+
+        if src_stage.is_empty() {
+            src_stage = vk::PipelineStageFlags::TOP_OF_PIPE;
+        }
+        if dst_stage.is_empty() {
+            dst_stage = vk::PipelineStageFlags::BOTTOM_OF_PIPE;
+        }
+
+        unsafe {
+            context.device.cmd_pipeline_barrier(
+                context.command_buffer,
+                src_stage,
+                dst_stage,
+                vk::DependencyFlags::empty(),
+                &[],
+                &buffer_barriers,
+                &image_barriers,
+            );
+        }
+    }
+}
+
+pub fn aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM | vk::Format::D32_SFLOAT | vk::Format::X8_D24_UNORM_PACK32 => vk::ImageAspectFlags::DEPTH,
+        vk::Format::D16_UNORM_S8_UINT | vk::Format::D24_UNORM_S8_UINT | vk::Format::D32_SFLOAT_S8_UINT => {
+            vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL
+        },
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
+enum PhysicalResource {
+    Image {
+        image: vk::Image,
+        view: vk::ImageView,
+        memory: vk::DeviceMemory,
+        extent: vk::Extent2D,
+    },
+    Buffer {
+        buffer: vk::Buffer,
+        memory: vk::DeviceMemory,
+    },
+}
+
+/// Device memory behind the transients of a graph. Kept between frames and only
+/// recreated when the physical resources of the compiled graph change.
+#[derive(Default)]
+pub struct TransientResources {
+    descs: Vec<ResourceDesc>,
+    resources: Vec<PhysicalResource>,
+}
+
+impl TransientResources {
+    pub fn new() -> TransientResources {
+        TransientResources::default()
+    }
+
+    fn prepare(
+        &mut self,
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        descs: &[ResourceDesc],
+    ) {
+        if self.descs == descs {
+            return;
+        }
+
+        self.destroy(device);
+
+        for desc in descs.iter() {
+            let resource = match *desc {
+                ResourceDesc::Image(desc) => TransientResources::create_image(device, memory_properties, &desc),
+                ResourceDesc::Buffer(desc) => TransientResources::create_buffer(device, memory_properties, &desc),
+            };
+            self.resources.push(resource);
+        }
+        self.descs = descs.to_vec();
+    }
+
+    fn resolve(&self, slot: usize) -> ResolvedResource {
+        match self.resources[slot] {
+            PhysicalResource::Image { image, view, extent, .. } => ResolvedResource::Image { image, view, extent },
+            PhysicalResource::Buffer { buffer, .. } => ResolvedResource::Buffer(buffer),
+        }
+    }
+
+    fn create_image(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        desc: &ImageDesc,
+    ) -> PhysicalResource {
+        let (image, memory, view) = RenderTarget::create_image(device, memory_properties, desc.format, desc.extent, desc.usage);
+
+        PhysicalResource::Image {
+            image,
+            view,
+            memory,
+            extent: desc.extent,
+        }
+    }
+
+    fn create_buffer(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        desc: &BufferDesc,
+    ) -> PhysicalResource {
+        let buffer_create_info = vk::BufferCreateInfo {
+            s_type: vk::StructureType::BUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::BufferCreateFlags::empty(),
+            size: desc.size,
+            usage: desc.usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+        };
+
+        let buffer = unsafe {
+            device
+                .create_buffer(&buffer_create_info, None)
+                .expect("Failed to create transient buffer!")
+        };
+
+        let memory_requirements = unsafe { device.get_buffer_memory_requirements(buffer) };
+        let memory = TransientResources::allocate_memory(device, memory_properties, memory_requirements);
+
+        unsafe {
+            device
+                .bind_buffer_memory(buffer, memory, 0)
+                .expect("Failed to bind transient buffer memory!");
+        }
+
+        PhysicalResource::Buffer { buffer, memory }
+    }
+
+    fn allocate_memory(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        memory_requirements: vk::MemoryRequirements,
+    ) -> vk::DeviceMemory {
+        let allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            allocation_size: memory_requirements.size,
+            memory_type_index: VkRenderDevice::find_memory_type(
+                memory_requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                memory_properties,
+            ),
+        };
+
+        unsafe {
+            device
+                .allocate_memory(&allocate_info, None)
+                .expect("Failed to allocate transient memory!")
+        }
+    }
+
+    pub fn destroy(&mut self, device: &ash::Device) {
+        unsafe {
+            for resource in self.resources.drain(..) {
+                match resource {
+                    PhysicalResource::Image { image, view, memory, .. } => {
+                        device.destroy_image_view(view, None);
+                        device.destroy_image(image, None);
+                        device.free_memory(memory, None);
+                    },
+                    PhysicalResource::Buffer { buffer, memory } => {
+                        device.destroy_buffer(buffer, None);
+                        device.free_memory(memory, None);
+                    },
+                }
+            }
+        }
+
+        self.descs.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn image_desc(format: vk::Format) -> ImageDesc {
+        ImageDesc {
+            format,
+            extent: vk::Extent2D { width: 64, height: 64 },
+            usage: vk::ImageUsageFlags::empty(),
+        }
+    }
+
+    /// An imported image that starts out sampled and ends up presented.
+    fn import_backbuffer(graph: &mut RenderGraph) -> ResourceHandle {
+        graph.import_image("backbuffer", ImportedImage {
+            image: vk::Image::null(),
+            view: vk::ImageView::null(),
+            desc: image_desc(vk::Format::B8G8R8A8_SRGB),
+            initial_state: Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER).state(),
+            final_state: Some(ResourceState {
+                stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+                access: vk::AccessFlags::empty(),
+                layout: vk::ImageLayout::PRESENT_SRC_KHR,
+            }),
+        })
+    }
+
+    fn pass_names(graph: &RenderGraph, compiled: &CompiledGraph) -> Vec<String> {
+        compiled.passes.iter().map(|pass| graph.passes[pass.pass].name.clone()).collect()
+    }
+
+    #[test]
+    fn culls_passes_that_reach_no_output() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = import_backbuffer(&mut graph);
+        let scene = graph.create_image("scene", image_desc(vk::Format::R16G16B16A16_SFLOAT));
+        let unused = graph.create_image("unused", image_desc(vk::Format::R16G16B16A16_SFLOAT));
+        let readback = graph.create_image("readback", image_desc(vk::Format::R8G8B8A8_UNORM));
+
+        graph.add_pass("scene", |pass| { pass.write(scene, Access::ColorAttachmentWrite); }, |_| {});
+        graph.add_pass("unused", |pass| { pass.write(unused, Access::ColorAttachmentWrite); }, |_| {});
+        graph.add_pass("readback", |pass| { pass.write(readback, Access::TransferWrite).side_effects(); }, |_| {});
+        graph.add_pass("present", |pass| {
+            pass.read(scene, Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER));
+            pass.write(backbuffer, Access::ColorAttachmentWrite);
+        }, |_| {});
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(pass_names(&graph, &compiled), ["scene", "readback", "present"]);
+        assert_eq!(compiled.culled_passes, vec![1]);
+        assert_eq!(compiled.transient_slots[unused.0], None);
+    }
+
+    #[test]
+    fn keeps_declaration_order_and_reports_dependencies() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = import_backbuffer(&mut graph);
+        let shadow = graph.create_image("shadow", image_desc(vk::Format::D32_SFLOAT));
+        let scene = graph.create_image("scene", image_desc(vk::Format::R16G16B16A16_SFLOAT));
+
+        graph.add_pass("shadow", |pass| { pass.write(shadow, Access::DepthAttachmentWrite); }, |_| {});
+        graph.add_pass("scene", |pass| {
+            pass.read(shadow, Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER));
+            pass.write(scene, Access::ColorAttachmentWrite);
+        }, |_| {});
+        graph.add_pass("present", |pass| {
+            pass.read(scene, Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER));
+            pass.write(backbuffer, Access::ColorAttachmentWrite);
+        }, |_| {});
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(pass_names(&graph, &compiled), ["shadow", "scene", "present"]);
+        let dependencies: Vec<_> = compiled.passes.iter().map(|pass| pass.dependencies.clone()).collect();
+        assert_eq!(dependencies, vec![vec![], vec![0], vec![1]]);
+    }
+
+    #[test]
+    fn rejects_reads_before_the_first_write() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = import_backbuffer(&mut graph);
+        let scene = graph.create_image("scene", image_desc(vk::Format::R16G16B16A16_SFLOAT));
+
+        graph.add_pass("present", |pass| {
+            pass.read(scene, Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER));
+            pass.write(backbuffer, Access::ColorAttachmentWrite);
+        }, |_| {});
+        graph.add_pass("scene", |pass| { pass.write(scene, Access::ColorAttachmentWrite); }, |_| {});
+
+        assert!(graph.compile().is_err());
+    }
+
+    #[test]
+    fn write_then_read_transitions_to_the_reading_layout() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = import_backbuffer(&mut graph);
+        let scene = graph.create_image("scene", image_desc(vk::Format::R16G16B16A16_SFLOAT));
+
+        graph.add_pass("scene", |pass| { pass.write(scene, Access::ColorAttachmentWrite); }, |_| {});
+        graph.add_pass("present", |pass| {
+            pass.read(scene, Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER));
+            pass.write(backbuffer, Access::ColorAttachmentWrite);
+        }, |_| {});
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(compiled.passes[0].barriers, vec![Barrier {
+            resource: scene,
+            src_stage: vk::PipelineStageFlags::TOP_OF_PIPE,
+            src_access: vk::AccessFlags::empty(),
+            dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }]);
+        assert_eq!(compiled.passes[1].barriers[0], Barrier {
+            resource: scene,
+            src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            dst_access: vk::AccessFlags::SHADER_READ,
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+        });
+    }
+
+    #[test]
+    fn read_then_write_waits_for_the_reads_without_flushing() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = import_backbuffer(&mut graph);
+        let copy = graph.create_image("copy", image_desc(vk::Format::B8G8R8A8_SRGB));
+
+        graph.add_pass("copy", |pass| {
+            pass.read(backbuffer, Access::TransferRead);
+            pass.write(copy, Access::TransferWrite).side_effects();
+        }, |_| {});
+        graph.add_pass("overlay", |pass| { pass.write(backbuffer, Access::ColorAttachmentWrite); }, |_| {});
+
+        let compiled = graph.compile().unwrap();
+
+        // sampled in the initial state, then copied from
+        assert_eq!(compiled.passes[0].barriers[0], Barrier {
+            resource: backbuffer,
+            src_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access: vk::AccessFlags::empty(),
+            dst_stage: vk::PipelineStageFlags::TRANSFER,
+            dst_access: vk::AccessFlags::TRANSFER_READ,
+            old_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        });
+        assert_eq!(compiled.passes[1].dependencies, vec![0]);
+        assert_eq!(compiled.passes[1].barriers, vec![Barrier {
+            resource: backbuffer,
+            src_stage: vk::PipelineStageFlags::TRANSFER,
+            src_access: vk::AccessFlags::empty(),
+            dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            old_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        }]);
+        assert_eq!(compiled.final_barriers, vec![Barrier {
+            resource: backbuffer,
+            src_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            src_access: vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            dst_stage: vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            dst_access: vk::AccessFlags::empty(),
+            old_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            new_layout: vk::ImageLayout::PRESENT_SRC_KHR,
+        }]);
+    }
+
+    #[test]
+    fn aliases_transients_with_disjoint_lifetimes() {
+        let mut graph = RenderGraph::new();
+        let backbuffer = import_backbuffer(&mut graph);
+        let color = image_desc(vk::Format::R16G16B16A16_SFLOAT);
+        let first = graph.create_image("first", color);
+        let second = graph.create_image("second", color);
+        let third = graph.create_image("third", color);
+        let depth = graph.create_image("depth", image_desc(vk::Format::D32_SFLOAT));
+        let sampled = Access::SampledRead(vk::PipelineStageFlags::FRAGMENT_SHADER);
+
+        graph.add_pass("first", |pass| {
+            pass.write(first, Access::ColorAttachmentWrite);
+            pass.write(depth, Access::DepthAttachmentWrite);
+        }, |_| {});
+        graph.add_pass("second", |pass| {
+            pass.read(first, sampled);
+            pass.write(second, Access::ColorAttachmentWrite);
+        }, |_| {});
+        // `first` is done, but `second` is still read below
+        graph.add_pass("third", |pass| {
+            pass.read(second, sampled);
+            pass.write(third, Access::ColorAttachmentWrite);
+        }, |_| {});
+        graph.add_pass("present", |pass| {
+            pass.read(second, sampled);
+            pass.read(third, sampled);
+            pass.read(depth, sampled);
+            pass.write(backbuffer, Access::ColorAttachmentWrite);
+        }, |_| {});
+
+        let compiled = graph.compile().unwrap();
+
+        assert_eq!(compiled.transient_slots[first.0], compiled.transient_slots[third.0]);
+        assert_ne!(compiled.transient_slots[first.0], compiled.transient_slots[second.0]);
+        assert_ne!(compiled.transient_slots[depth.0], compiled.transient_slots[first.0]);
+        assert_eq!(compiled.transient_slots[backbuffer.0], None);
+        assert_eq!(compiled.physical_resources.len(), 3);
+
+        // the aliased image discards what `first` left, after `second` is done reading it
+        assert_eq!(compiled.passes[2].barriers[1], Barrier {
+            resource: third,
+            src_stage: vk::PipelineStageFlags::FRAGMENT_SHADER,
+            src_access: vk::AccessFlags::empty(),
+            dst_stage: vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            dst_access: vk::AccessFlags::COLOR_ATTACHMENT_READ | vk::AccessFlags::COLOR_ATTACHMENT_WRITE,
+            old_layout: vk::ImageLayout::UNDEFINED,
+            new_layout: vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+        });
+    }
+}
@@ -0,0 +1,260 @@
+use std::ptr;
+
+use ash::vk;
+
+use crate::vk::render_device::VkRenderDevice;
+use crate::vk::render_graph::{aspect_mask, ImageDesc, ImportedImage, ResourceState};
+
+/// Size of a render target, either fixed or a fraction of the swapchain so it tracks resizes.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderTargetSize {
+    Absolute(vk::Extent2D),
+    SwapchainRelative(f32),
+}
+
+impl RenderTargetSize {
+    pub fn resolve(&self, swapchain_extent: vk::Extent2D) -> vk::Extent2D {
+        match *self {
+            RenderTargetSize::Absolute(extent) => extent,
+            RenderTargetSize::SwapchainRelative(scale) => vk::Extent2D {
+                width: ((swapchain_extent.width as f32 * scale) as u32).max(1),
+                height: ((swapchain_extent.height as f32 * scale) as u32).max(1),
+            },
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderTargetDesc {
+    pub format: vk::Format,
+    pub size: RenderTargetSize,
+}
+
+impl RenderTargetDesc {
+    pub fn new(format: vk::Format, size: RenderTargetSize) -> RenderTargetDesc {
+        RenderTargetDesc { format, size }
+    }
+
+    pub fn is_depth(&self) -> bool {
+        aspect_mask(self.format).contains(vk::ImageAspectFlags::DEPTH)
+    }
+}
+
+/// A color or depth image that can be rendered to and sampled afterwards.
+pub struct RenderTarget {
+    pub desc: RenderTargetDesc,
+    pub image: vk::Image,
+    pub view: vk::ImageView,
+    pub extent: vk::Extent2D,
+
+    memory: vk::DeviceMemory,
+}
+
+impl RenderTarget {
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        desc: RenderTargetDesc,
+        swapchain_extent: vk::Extent2D,
+    ) -> RenderTarget {
+        let extent = desc.size.resolve(swapchain_extent);
+        let attachment_usage = if desc.is_depth() {
+            vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+        } else {
+            vk::ImageUsageFlags::COLOR_ATTACHMENT
+        };
+
+        let (image, memory, view) = RenderTarget::create_image(
+            device,
+            memory_properties,
+            desc.format,
+            extent,
+            attachment_usage | vk::ImageUsageFlags::SAMPLED,
+        );
+
+        RenderTarget {
+            desc,
+            image,
+            view,
+            extent,
+            memory,
+        }
+    }
+
+    /// Recreates the image if the swapchain resize changed its size. Returns whether it did,
+    /// in which case framebuffers and descriptors pointing at it are stale.
+    pub fn resize(
+        &mut self,
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        swapchain_extent: vk::Extent2D,
+    ) -> bool {
+        if self.desc.size.resolve(swapchain_extent) == self.extent {
+            return false;
+        }
+
+        self.destroy(device);
+        *self = RenderTarget::new(device, memory_properties, self.desc, swapchain_extent);
+
+        true
+    }
+
+    /// The target as a render graph import. It is fully rewritten every frame,
+    /// so the graph discards the previous contents once the last frame is done sampling them.
+    pub fn imported(&self) -> ImportedImage {
+        let stage = if self.desc.is_depth() {
+            vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS
+        } else {
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT
+        };
+
+        ImportedImage {
+            image: self.image,
+            view: self.view,
+            desc: ImageDesc {
+                format: self.desc.format,
+                extent: self.extent,
+                usage: vk::ImageUsageFlags::SAMPLED,
+            },
+            initial_state: ResourceState {
+                stage: stage | vk::PipelineStageFlags::FRAGMENT_SHADER,
+                access: vk::AccessFlags::empty(),
+                layout: vk::ImageLayout::UNDEFINED,
+            },
+            final_state: None,
+        }
+    }
+
+    /// Framebuffer over `attachments`, which all have to be the same size.
+    pub fn create_framebuffer(
+        device: &ash::Device,
+        render_pass: vk::RenderPass,
+        attachments: &[&RenderTarget],
+    ) -> vk::Framebuffer {
+        let extent = attachments[0].extent;
+        assert!(
+            attachments.iter().all(|attachment| attachment.extent == extent),
+            "Framebuffer attachments differ in size"
+        );
+
+        let attachment_views: Vec<vk::ImageView> = attachments.iter().map(|attachment| attachment.view).collect();
+
+        let framebuffer_create_info = vk::FramebufferCreateInfo {
+            s_type: vk::StructureType::FRAMEBUFFER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::FramebufferCreateFlags::empty(),
+            render_pass,
+            attachment_count: attachment_views.len() as u32,
+            p_attachments: attachment_views.as_ptr(),
+            width: extent.width,
+            height: extent.height,
+            layers: 1,
+        };
+
+        unsafe {
+            device
+                .create_framebuffer(&framebuffer_create_info, None)
+                .expect("Failed to create render target Framebuffer!")
+        }
+    }
+
+    /// Creates a single-mip 2D image in device local memory together with a view of it.
+    pub fn create_image(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        format: vk::Format,
+        extent: vk::Extent2D,
+        usage: vk::ImageUsageFlags,
+    ) -> (vk::Image, vk::DeviceMemory, vk::ImageView) {
+        let image_create_info = vk::ImageCreateInfo {
+            s_type: vk::StructureType::IMAGE_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::ImageCreateFlags::empty(),
+            image_type: vk::ImageType::TYPE_2D,
+            format,
+            extent: vk::Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            },
+            mip_levels: 1,
+            array_layers: 1,
+            samples: vk::SampleCountFlags::TYPE_1,
+            tiling: vk::ImageTiling::OPTIMAL,
+            usage,
+            sharing_mode: vk::SharingMode::EXCLUSIVE,
+            queue_family_index_count: 0,
+            p_queue_family_indices: ptr::null(),
+            initial_layout: vk::ImageLayout::UNDEFINED,
+        };
+
+        let image = unsafe {
+            device
+                .create_image(&image_create_info, None)
+                .expect("Failed to create Image!")
+        };
+
+        let memory_requirements = unsafe { device.get_image_memory_requirements(image) };
+
+        let allocate_info = vk::MemoryAllocateInfo {
+            s_type: vk::StructureType::MEMORY_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            allocation_size: memory_requirements.size,
+            memory_type_index: VkRenderDevice::find_memory_type(
+                memory_requirements.memory_type_bits,
+                vk::MemoryPropertyFlags::DEVICE_LOCAL,
+                memory_properties,
+            ),
+        };
+
+        let memory = unsafe {
+            device
+                .allocate_memory(&allocate_info, None)
+                .expect("Failed to allocate Image memory!")
+        };
+
+        unsafe {
+            device
+                .bind_image_memory(image, memory, 0)
+                .expect("Failed to bind Image memory!");
+        }
+
+        let image_view_create_info = vk::ImageViewCreateInfo {
+            s_type: vk::StructureType::IMAGE_VIEW_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::ImageViewCreateFlags::empty(),
+            view_type: vk::ImageViewType::TYPE_2D,
+            format,
+            components: vk::ComponentMapping {
+                r: vk::ComponentSwizzle::IDENTITY,
+                g: vk::ComponentSwizzle::IDENTITY,
+                b: vk::ComponentSwizzle::IDENTITY,
+                a: vk::ComponentSwizzle::IDENTITY,
+            },
+            subresource_range: vk::ImageSubresourceRange {
+                aspect_mask: aspect_mask(format),
+                base_mip_level: 0,
+                level_count: 1,
+                base_array_layer: 0,
+                layer_count: 1,
+            },
+            image,
+        };
+
+        let view = unsafe {
+            device
+                .create_image_view(&image_view_create_info, None)
+                .expect("Failed to create Image View!")
+        };
+
+        (image, memory, view)
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
@@ -0,0 +1,451 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+
+/// One compiler message mapped back to the file and line it came from.
+#[derive(Debug, Clone)]
+pub struct ShaderDiagnostic {
+    pub file: String,
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct ShaderCompileError {
+    pub diagnostics: Vec<ShaderDiagnostic>,
+}
+
+impl fmt::Display for ShaderCompileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for diagnostic in self.diagnostics.iter() {
+            match diagnostic.line {
+                Some(line) => writeln!(f, "{}:{}: {}", diagnostic.file, line, diagnostic.message)?,
+                None => writeln!(f, "{}: {}", diagnostic.file, diagnostic.message)?,
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl ShaderCompileError {
+    pub fn new(file: &Path, message: String) -> ShaderCompileError {
+        ShaderCompileError {
+            diagnostics: vec![ShaderDiagnostic {
+                file: file.display().to_string(),
+                line: None,
+                message,
+            }],
+        }
+    }
+
+    /// Splits glslang output (`file:line: error: message`) into diagnostics.
+    #[cfg(feature = "shader-compiler")]
+    fn from_compiler_output(file: &Path, output: &str) -> ShaderCompileError {
+        let mut diagnostics = vec![];
+
+        for output_line in output.lines().filter(|line| !line.trim().is_empty()) {
+            let diagnostic = match ShaderCompileError::split_location(output_line) {
+                Some((file, line, message)) => ShaderDiagnostic {
+                    file: file.to_string(),
+                    line: Some(line),
+                    message: message.trim().to_string(),
+                },
+                None => ShaderDiagnostic {
+                    file: file.display().to_string(),
+                    line: None,
+                    message: output_line.trim().to_string(),
+                },
+            };
+
+            diagnostics.push(diagnostic);
+        }
+
+        ShaderCompileError { diagnostics }
+    }
+
+    /// Finds the first `:<line>:` in `output_line`, which also works for `C:\` style paths.
+    #[cfg(feature = "shader-compiler")]
+    fn split_location(output_line: &str) -> Option<(&str, u32, &str)> {
+        for (separator, _) in output_line.match_indices(':') {
+            let rest = &output_line[separator + 1..];
+            let line_end = rest.find(':')?;
+
+            if let Ok(line) = rest[..line_end].trim().parse::<u32>() {
+                return Some((&output_line[..separator], line, &rest[line_end + 1..]));
+            }
+        }
+
+        None
+    }
+}
+
+/// How an `#include` names its file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum IncludeType {
+    /// `#include "file"`, looked up next to the including file first
+    Relative,
+    /// `#include <file>`, looked up in the include directories only
+    Standard,
+}
+
+/// FNV-1a. Unlike `DefaultHasher` it is the same on every platform and Rust version,
+/// so cache file names can be checked in as prebuilt SPIR-V.
+struct StableHasher(u64);
+
+impl StableHasher {
+    fn new() -> StableHasher {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes.iter() {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
+        }
+    }
+
+    /// Hashes `text` with CRLF line endings as LF, so a checkout with either hashes the same.
+    fn write_text(&mut self, text: &str) {
+        for (i, line) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.write(b"\n");
+            }
+            self.write(line.strip_suffix('\r').unwrap_or(line).as_bytes());
+        }
+        // keeps "ab" + "c" apart from "a" + "bc"
+        self.write(&[0]);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Looks GLSL sources up as SPIR-V in the on-disk cache, then among the prebuilt files checked in
+/// with the engine, and compiles the ones that match neither in-process. Compiling needs the
+/// default `shader-compiler` feature; builds without it only load what is cached or prebuilt.
+pub struct ShaderCompiler {
+    #[cfg(feature = "shader-compiler")]
+    compiler: shaderc::Compiler,
+    include_dirs: Vec<PathBuf>,
+    cache_dir: PathBuf,
+    prebuilt_dir: Option<PathBuf>,
+}
+
+impl ShaderCompiler {
+    pub fn new(include_dirs: &[&Path], cache_dir: &Path) -> ShaderCompiler {
+        ShaderCompiler {
+            #[cfg(feature = "shader-compiler")]
+            compiler: shaderc::Compiler::new().expect("Failed to create shader compiler!"),
+            include_dirs: include_dirs.iter().map(|dir| dir.to_path_buf()).collect(),
+            cache_dir: cache_dir.to_path_buf(),
+            prebuilt_dir: None,
+        }
+    }
+
+    /// Also looks in `prebuilt_dir` for SPIR-V before compiling. Its files are named like the cache's,
+    /// see the `prebuild_shaders` example.
+    pub fn with_prebuilt_dir(mut self, prebuilt_dir: &Path) -> ShaderCompiler {
+        self.prebuilt_dir = Some(prebuilt_dir.to_path_buf());
+        self
+    }
+
+    /// Whether sources without cached or prebuilt SPIR-V can be compiled, e.g. after a hot reload.
+    pub fn can_compile() -> bool {
+        cfg!(feature = "shader-compiler")
+    }
+
+    pub fn shader_stage(source: &Path) -> Option<vk::ShaderStageFlags> {
+        match source.extension().and_then(|extension| extension.to_str()) {
+            Some("vert") => Some(vk::ShaderStageFlags::VERTEX),
+            Some("frag") => Some(vk::ShaderStageFlags::FRAGMENT),
+            Some("comp") => Some(vk::ShaderStageFlags::COMPUTE),
+            _ => None,
+        }
+    }
+
+    /// Compiles `source` with the given preprocessor `defines`.
+    /// The stage is taken from the file extension (`.vert`, `.frag`, `.comp`).
+    pub fn compile_file(
+        &mut self,
+        source: &Path,
+        defines: &[(&str, &str)],
+    ) -> Result<Vec<u32>, ShaderCompileError> {
+        crate::profile_scope!("compile shader");
+
+        if ShaderCompiler::shader_stage(source).is_none() {
+            return Err(ShaderCompileError::new(source, String::from("Unknown shader stage")));
+        }
+
+        let source_text = fs::read_to_string(source)
+            .map_err(|error| ShaderCompileError::new(source, error.to_string()))?;
+
+        let cache_name = self.cache_name(source, &source_text, defines);
+        if let Some(code) = ShaderCompiler::read_cache(&self.cache_dir.join(&cache_name)) {
+            return Ok(code);
+        }
+        if let Some(code) = self.prebuilt_dir.as_ref().and_then(|dir| ShaderCompiler::read_cache(&dir.join(&cache_name))) {
+            return Ok(code);
+        }
+
+        self.compile_source(source, &source_text, defines, &cache_name)
+    }
+
+    #[cfg(not(feature = "shader-compiler"))]
+    fn compile_source(
+        &mut self,
+        source: &Path,
+        _source_text: &str,
+        _defines: &[(&str, &str)],
+        cache_name: &str,
+    ) -> Result<Vec<u32>, ShaderCompileError> {
+        Err(ShaderCompileError::new(
+            source,
+            format!(
+                "No prebuilt SPIR-V {} for this source and defines. Build with the `shader-compiler` feature \
+                 to compile shaders at runtime, or run the `prebuild_shaders` example after changing them",
+                cache_name),
+        ))
+    }
+
+    #[cfg(feature = "shader-compiler")]
+    fn compile_source(
+        &mut self,
+        source: &Path,
+        source_text: &str,
+        defines: &[(&str, &str)],
+        cache_name: &str,
+    ) -> Result<Vec<u32>, ShaderCompileError> {
+        let shader_kind = match ShaderCompiler::shader_stage(source) {
+            Some(vk::ShaderStageFlags::VERTEX) => shaderc::ShaderKind::Vertex,
+            Some(vk::ShaderStageFlags::FRAGMENT) => shaderc::ShaderKind::Fragment,
+            _ => shaderc::ShaderKind::Compute,
+        };
+
+        let include_dirs = self.include_dirs.clone();
+        let mut options = shaderc::CompileOptions::new().expect("Failed to create shader compile options!");
+        options.set_target_env(shaderc::TargetEnv::Vulkan, shaderc::EnvVersion::Vulkan1_0 as u32);
+        options.set_include_callback(move |requested, include_type, requesting_source, _depth| {
+            let include_type = match include_type {
+                shaderc::IncludeType::Relative => IncludeType::Relative,
+                shaderc::IncludeType::Standard => IncludeType::Standard,
+            };
+
+            ShaderCompiler::resolve_include(&include_dirs, requested, include_type, requesting_source)
+                .map(|(resolved_name, content)| shaderc::ResolvedInclude { resolved_name, content })
+        });
+        for (name, value) in defines.iter() {
+            options.add_macro_definition(name, Some(value));
+        }
+
+        let artifact = self
+            .compiler
+            .compile_into_spirv(source_text, shader_kind, &source.to_string_lossy(), "main", Some(&options))
+            .map_err(|error| match error {
+                shaderc::Error::CompilationError(_, output) => ShaderCompileError::from_compiler_output(source, &output),
+                error => ShaderCompileError::new(source, error.to_string()),
+            })?;
+
+        let code = artifact.as_binary().to_vec();
+
+        // a failed cache write only costs a recompile next time
+        let _ = fs::create_dir_all(&self.cache_dir)
+            .and_then(|_| fs::write(self.cache_dir.join(cache_name), artifact.as_binary_u8()));
+
+        Ok(code)
+    }
+
+    /// `source` and every file it includes, directly or not.
+    pub fn dependencies(&self, source: &Path) -> Vec<PathBuf> {
+        let mut dependencies = vec![];
+        self.collect_dependencies(source, &mut dependencies);
+
+        dependencies
+    }
+
+    fn collect_dependencies(&self, source: &Path, dependencies: &mut Vec<PathBuf>) {
+        let source = fs::canonicalize(source).unwrap_or_else(|_| source.to_path_buf());
+        if dependencies.contains(&source) {
+            return;
+        }
+
+        let source_text = fs::read_to_string(&source).unwrap_or_default();
+        dependencies.push(source.clone());
+
+        for line in source_text.lines() {
+            let (requested, include_type) = match ShaderCompiler::parse_include(line) {
+                Some(include) => include,
+                None => continue,
+            };
+
+            let requesting_source = source.to_string_lossy();
+            if let Ok((resolved_name, _)) = ShaderCompiler::resolve_include(&self.include_dirs, requested, include_type, &requesting_source) {
+                self.collect_dependencies(Path::new(&resolved_name), dependencies);
+            }
+        }
+    }
+
+    fn parse_include(line: &str) -> Option<(&str, IncludeType)> {
+        let directive = line.trim_start().strip_prefix('#')?.trim_start();
+        let target = directive.strip_prefix("include")?.trim();
+
+        if let Some(relative) = target.strip_prefix('"').and_then(|target| target.strip_suffix('"')) {
+            Some((relative, IncludeType::Relative))
+        } else {
+            let standard = target.strip_prefix('<').and_then(|target| target.strip_suffix('>'))?;
+            Some((standard, IncludeType::Standard))
+        }
+    }
+
+    /// The resolved file name and its content.
+    fn resolve_include(
+        include_dirs: &[PathBuf],
+        requested: &str,
+        include_type: IncludeType,
+        requesting_source: &str,
+    ) -> Result<(String, String), String> {
+        let mut candidates = vec![];
+
+        if include_type == IncludeType::Relative {
+            if let Some(requesting_dir) = Path::new(requesting_source).parent() {
+                candidates.push(requesting_dir.join(requested));
+            }
+        }
+        for include_dir in include_dirs.iter() {
+            candidates.push(include_dir.join(requested));
+        }
+
+        for candidate in candidates.iter() {
+            if let Ok(content) = fs::read_to_string(candidate) {
+                return Ok((candidate.to_string_lossy().into_owned(), content));
+            }
+        }
+
+        Err(format!("Failed to find include \"{}\" requested by {}", requested, requesting_source))
+    }
+
+    /// Cache entries are keyed by the source, everything it includes and the defines,
+    /// so editing a header invalidates every shader that uses it.
+    fn cache_name(&self, source: &Path, source_text: &str, defines: &[(&str, &str)]) -> String {
+        let mut hasher = StableHasher::new();
+        hasher.write_text(source_text);
+        for (name, value) in defines.iter() {
+            hasher.write_text(name);
+            hasher.write_text(value);
+        }
+        for dependency in self.dependencies(source).iter().skip(1) {
+            hasher.write_text(&fs::read_to_string(dependency).unwrap_or_default());
+        }
+
+        let file_name = source.file_name().unwrap().to_string_lossy();
+        format!("{}-{:016x}.spv", file_name, hasher.finish())
+    }
+
+    fn read_cache(cache_path: &Path) -> Option<Vec<u32>> {
+        let bytes = fs::read(cache_path).ok()?;
+        if bytes.is_empty() || bytes.len() % 4 != 0 {
+            return None;
+        }
+
+        Some(
+            bytes
+                .chunks_exact(4)
+                .map(|word| u32::from_ne_bytes([word[0], word[1], word[2], word[3]]))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utility::constants;
+    use crate::vk::shader_variants::ShaderAsset;
+
+    #[test]
+    fn stable_hash_ignores_line_endings() {
+        let mut lf = StableHasher::new();
+        lf.write_text("#version 450\nvoid main() {}\n");
+        let mut crlf = StableHasher::new();
+        crlf.write_text("#version 450\r\nvoid main() {}\r\n");
+
+        assert_eq!(lf.finish(), crlf.finish());
+    }
+
+    #[test]
+    fn stable_hash_separates_texts() {
+        let mut first = StableHasher::new();
+        first.write_text("ab");
+        first.write_text("c");
+        let mut second = StableHasher::new();
+        second.write_text("a");
+        second.write_text("bc");
+
+        assert_ne!(first.finish(), second.finish());
+    }
+
+    /// Compiles or loads every shader in `shaders/src` in every combination of its keywords.
+    fn build_every_variant(shader_compiler: &mut ShaderCompiler) {
+        for entry in fs::read_dir(constants::SHADER_SOURCE_DIR).unwrap() {
+            let source = entry.unwrap().path();
+            if ShaderCompiler::shader_stage(&source).is_none() {
+                continue;
+            }
+
+            let mut asset = ShaderAsset::load(&source);
+            let keywords = asset.keywords().to_vec();
+            for variant in 0..1u64 << keywords.len() {
+                let enabled: Vec<&str> = keywords
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| variant & (1 << i) != 0)
+                    .map(|(_, keyword)| keyword.as_str())
+                    .collect();
+
+                match asset.variant(shader_compiler, &enabled) {
+                    Ok(code) => assert_eq!(code.first(), Some(&0x0723_0203), "{:?} is not SPIR-V", source),
+                    Err(error) => panic!("{}", error),
+                }
+            }
+        }
+    }
+
+    /// Fails when a shader changed without running the `prebuild_shaders` example.
+    #[cfg(not(feature = "shader-compiler"))]
+    #[test]
+    fn every_shader_variant_is_prebuilt() {
+        let source_dir = Path::new(constants::SHADER_SOURCE_DIR);
+        let prebuilt_dir = Path::new(constants::SHADER_PREBUILT_DIR);
+
+        build_every_variant(&mut ShaderCompiler::new(&[source_dir], prebuilt_dir).with_prebuilt_dir(prebuilt_dir));
+    }
+
+    /// Compiles into an empty cache without prebuilt SPIR-V, so every variant goes through shaderc.
+    #[cfg(feature = "shader-compiler")]
+    #[test]
+    fn every_shader_variant_compiles() {
+        let source_dir = Path::new(constants::SHADER_SOURCE_DIR);
+        let cache_dir = std::env::temp_dir().join(format!("pupsy_shader_cache_{}", std::process::id()));
+
+        build_every_variant(&mut ShaderCompiler::new(&[source_dir], &cache_dir));
+        let _ = fs::remove_dir_all(&cache_dir);
+    }
+
+    #[cfg(feature = "shader-compiler")]
+    #[test]
+    fn compile_errors_point_at_the_line() {
+        let source_dir = Path::new(constants::SHADER_SOURCE_DIR);
+        let cache_dir = std::env::temp_dir().join(format!("pupsy_shader_errors_{}", std::process::id()));
+        fs::create_dir_all(&cache_dir).unwrap();
+        let source = cache_dir.join("broken.frag");
+        fs::write(&source, "#version 450\nvoid main() {\n    undefined_call();\n}\n").unwrap();
+
+        let error = ShaderCompiler::new(&[source_dir], &cache_dir).compile_file(&source, &[]).unwrap_err();
+        let _ = fs::remove_dir_all(&cache_dir);
+
+        assert_eq!(error.diagnostics[0].line, Some(3), "{}", error);
+    }
+}
@@ -0,0 +1,293 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+
+use ash::vk;
+
+use crate::vk::shader_compiler::{ShaderCompileError, ShaderCompiler};
+
+/// Keywords a shader source can be compiled with, declared in the source as
+/// `#pragma keywords TEXTURED DEPTH_TEST`. Every keyword is passed to the compiler
+/// as a define set to `1` or `0`, so the source switches on them with `#if TEXTURED`.
+const KEYWORDS_PRAGMA: &str = "keywords";
+
+/// One shader source and the variants compiled from it so far, cached by keyword combination.
+pub struct ShaderAsset {
+    pub source: PathBuf,
+    pub stage: vk::ShaderStageFlags,
+    keywords: Vec<String>,
+
+    variants: HashMap<u64, Vec<u32>>,
+}
+
+impl ShaderAsset {
+    pub fn load(source: &Path) -> ShaderAsset {
+        crate::profile_scope!("load shader asset");
+
+        let stage = ShaderCompiler::shader_stage(source)
+            .unwrap_or_else(|| panic!("Unknown shader stage of {:?}", source));
+
+        ShaderAsset {
+            source: source.to_path_buf(),
+            stage,
+            keywords: ShaderAsset::read_keywords(source),
+            variants: HashMap::new(),
+        }
+    }
+
+    pub fn keywords(&self) -> &[String] {
+        &self.keywords
+    }
+
+    /// Drops every compiled variant and re-reads the declared keywords, e.g. after the source changed.
+    pub fn reload(&mut self) {
+        self.keywords = ShaderAsset::read_keywords(&self.source);
+        self.variants.clear();
+    }
+
+    /// Returns the SPIR-V of the variant with exactly the `enabled` keywords switched on,
+    /// compiling it on first use.
+    pub fn variant(
+        &mut self,
+        shader_compiler: &mut ShaderCompiler,
+        enabled: &[&str],
+    ) -> Result<&[u32], ShaderCompileError> {
+        let variant_key = self.variant_key(enabled)?;
+
+        if !self.variants.contains_key(&variant_key) {
+            let defines: Vec<(&str, &str)> = self
+                .keywords
+                .iter()
+                .enumerate()
+                .map(|(i, keyword)| {
+                    let value = if variant_key & (1 << i) != 0 { "1" } else { "0" };
+                    (keyword.as_str(), value)
+                })
+                .collect();
+
+            let code = shader_compiler.compile_file(&self.source, &defines)?;
+            self.variants.insert(variant_key, code);
+        }
+
+        Ok(&self.variants[&variant_key])
+    }
+
+    fn variant_key(&self, enabled: &[&str]) -> Result<u64, ShaderCompileError> {
+        let mut variant_key = 0u64;
+
+        for &keyword in enabled.iter() {
+            match self.keywords.iter().position(|declared| declared == keyword) {
+                Some(index) => variant_key |= 1 << index,
+                None => return Err(ShaderCompileError::new(
+                    &self.source,
+                    format!("Keyword {} is not declared by the shader", keyword),
+                )),
+            }
+        }
+
+        Ok(variant_key)
+    }
+
+    fn read_keywords(source: &Path) -> Vec<String> {
+        let keywords = ShaderAsset::parse_keywords(&fs::read_to_string(source).unwrap_or_default());
+        assert!(keywords.len() <= 64, "{:?} declares more than 64 keywords", source);
+
+        keywords
+    }
+
+    /// The keywords of every `#pragma keywords` line in declaration order, each once.
+    fn parse_keywords(source_text: &str) -> Vec<String> {
+        let mut keywords: Vec<String> = vec![];
+
+        for line in source_text.lines() {
+            let pragma = match line.trim_start().strip_prefix('#') {
+                Some(directive) => directive.trim_start().strip_prefix("pragma"),
+                None => None,
+            };
+
+            let declared = match pragma.and_then(|pragma| pragma.trim_start().strip_prefix(KEYWORDS_PRAGMA)) {
+                Some(declared) => declared,
+                None => continue,
+            };
+
+            for keyword in declared.split_whitespace() {
+                if !keywords.iter().any(|existing| existing == keyword) {
+                    keywords.push(keyword.to_string());
+                }
+            }
+        }
+
+        keywords
+    }
+}
+
+/// Plain values that can be written into a specialization constant.
+pub trait SpecializationValue: Copy {
+    fn to_bytes(self) -> Vec<u8>;
+}
+
+impl SpecializationValue for u32 {
+    fn to_bytes(self) -> Vec<u8> {
+        self.to_ne_bytes().to_vec()
+    }
+}
+
+impl SpecializationValue for i32 {
+    fn to_bytes(self) -> Vec<u8> {
+        self.to_ne_bytes().to_vec()
+    }
+}
+
+impl SpecializationValue for f32 {
+    fn to_bytes(self) -> Vec<u8> {
+        self.to_ne_bytes().to_vec()
+    }
+}
+
+impl SpecializationValue for bool {
+    // GLSL bools are specialized as VkBool32
+    fn to_bytes(self) -> Vec<u8> {
+        (if self { vk::TRUE } else { vk::FALSE }).to_ne_bytes().to_vec()
+    }
+}
+
+/// Specialization constant values keyed by `constant_id`.
+#[derive(Clone, Default)]
+pub struct SpecializationConstants {
+    map_entries: Vec<vk::SpecializationMapEntry>,
+    data: Vec<u8>,
+}
+
+impl SpecializationConstants {
+    pub fn new() -> SpecializationConstants {
+        SpecializationConstants::default()
+    }
+
+    /// Sets `constant_id` to `value`, replacing the previous value of that id.
+    pub fn set<T: SpecializationValue>(&mut self, constant_id: u32, value: T) -> &mut SpecializationConstants {
+        let bytes = value.to_bytes();
+
+        if let Some(index) = self.map_entries.iter().position(|entry| entry.constant_id == constant_id) {
+            let entry = self.map_entries.remove(index);
+            self.data.drain(entry.offset as usize..entry.offset as usize + entry.size);
+
+            for other in self.map_entries.iter_mut().filter(|other| other.offset > entry.offset) {
+                other.offset -= entry.size as u32;
+            }
+        }
+
+        self.map_entries.push(vk::SpecializationMapEntry {
+            constant_id,
+            offset: self.data.len() as u32,
+            size: bytes.len(),
+        });
+        self.data.extend_from_slice(&bytes);
+
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.map_entries.is_empty()
+    }
+
+    /// The returned info points into `self`, which has to outlive the pipeline creation call.
+    pub fn info(&self) -> vk::SpecializationInfo {
+        vk::SpecializationInfo {
+            map_entry_count: self.map_entries.len() as u32,
+            p_map_entries: if self.is_empty() { ptr::null() } else { self.map_entries.as_ptr() },
+            data_size: self.data.len(),
+            p_data: if self.is_empty() { ptr::null() } else { self.data.as_ptr() as *const _ },
+        }
+    }
+}
+
+/// A pipeline stage: the shader asset, the keywords its variant is built with
+/// and the specialization constants applied when the pipeline is created.
+pub struct PipelineShader {
+    pub asset: ShaderAsset,
+    pub keywords: Vec<String>,
+    pub specialization: SpecializationConstants,
+}
+
+impl PipelineShader {
+    pub fn new(source: &Path) -> PipelineShader {
+        PipelineShader {
+            asset: ShaderAsset::load(source),
+            keywords: vec![],
+            specialization: SpecializationConstants::new(),
+        }
+    }
+
+    pub fn with_keywords(mut self, keywords: &[&str]) -> PipelineShader {
+        self.keywords = keywords.iter().map(|keyword| keyword.to_string()).collect();
+        self
+    }
+
+    pub fn with_specialization(mut self, specialization: SpecializationConstants) -> PipelineShader {
+        self.specialization = specialization;
+        self
+    }
+
+    pub fn code(&mut self, shader_compiler: &mut ShaderCompiler) -> Result<&[u32], ShaderCompileError> {
+        let keywords: Vec<&str> = self.keywords.iter().map(|keyword| keyword.as_str()).collect();
+        self.asset.variant(shader_compiler, &keywords)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(source_text: &str) -> ShaderAsset {
+        ShaderAsset {
+            source: PathBuf::from("test.frag"),
+            stage: vk::ShaderStageFlags::FRAGMENT,
+            keywords: ShaderAsset::parse_keywords(source_text),
+            variants: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn keywords_are_collected_from_every_pragma_once() {
+        let source_text = "#version 450\n#pragma keywords TEXTURED FOG\n  # pragma  keywords DEPTH_TEST TEXTURED\n\
+                           #pragma once\n// #pragma keywords COMMENTED\nvoid main() {}";
+
+        assert_eq!(ShaderAsset::parse_keywords(source_text), vec!["TEXTURED", "FOG", "DEPTH_TEST"]);
+    }
+
+    #[test]
+    fn variant_keys_set_the_bit_of_each_declared_keyword() {
+        let asset = asset("#pragma keywords TEXTURED FOG DEPTH_TEST");
+
+        assert_eq!(asset.variant_key(&[]).unwrap(), 0);
+        assert_eq!(asset.variant_key(&["FOG"]).unwrap(), 0b010);
+        assert_eq!(asset.variant_key(&["DEPTH_TEST", "TEXTURED"]).unwrap(), 0b101);
+        assert!(asset.variant_key(&["SHADOWS"]).is_err());
+    }
+
+    /// (constant_id, offset, size) of every entry
+    fn entries(constants: &SpecializationConstants) -> Vec<(u32, u32, usize)> {
+        constants.map_entries.iter().map(|entry| (entry.constant_id, entry.offset, entry.size)).collect()
+    }
+
+    #[test]
+    fn set_packs_values_and_replaces_existing_ids() {
+        let mut constants = SpecializationConstants::new();
+        constants.set(0, 7u32).set(3, 0.5f32).set(1, true);
+        assert_eq!(entries(&constants), vec![(0, 0, 4), (3, 4, 4), (1, 8, 4)]);
+
+        // the replaced value moves to the end and the ones after it close the gap
+        constants.set(0, -2i32);
+        assert_eq!(entries(&constants), vec![(3, 0, 4), (1, 4, 4), (0, 8, 4)]);
+
+        let mut data = 0.5f32.to_ne_bytes().to_vec();
+        data.extend_from_slice(&vk::TRUE.to_ne_bytes());
+        data.extend_from_slice(&(-2i32).to_ne_bytes());
+        assert_eq!(constants.data, data);
+
+        let info = constants.info();
+        assert_eq!((info.map_entry_count, info.data_size), (3, 12));
+        assert!(SpecializationConstants::new().info().p_data.is_null());
+    }
+}
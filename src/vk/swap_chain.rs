@@ -1,23 +1,13 @@
 use ash::vk;
-use ash::vk::SurfaceFormatKHR;
-use ash::vk::ValidationCacheCreateInfoEXT;
-use std::ffi::CString;
-use std::marker::PhantomData;
 use std::ptr;
 
 use ash;
 
-use std::os::raw::c_char;
 
-use crate::vk::constants;
 use crate::utility::constants as global_constants;
-use crate::vk::debug;
-use crate::utility::tools;
 
 use crate::vk::render_device;
-use crate::rhi::window;
 
-use super::render_device::VkSurface;
 
 pub struct VkSpawChain {
     pub swapchain_loader: ash::extensions::khr::Swapchain,
@@ -45,7 +35,7 @@ impl VkSpawChain {
         surface: &render_device::VkSurface,
         queue_family: &render_device::QueueFamilyIndices
     ) -> VkSpawChain {
-        let swapchain_support = VkSpawChain::query_swapchain_support(physical_device, &surface);
+        let swapchain_support = VkSpawChain::query_swapchain_support(physical_device, surface);
 
         let surface_format = VkSpawChain::choose_swapchain_format(&swapchain_support.formats);
         let present_mode = VkSpawChain::choose_swapchain_present_mode(&swapchain_support.present_modes);
@@ -63,7 +53,7 @@ impl VkSpawChain {
             if queue_family.graphics_family != queue_family.present_family {
                 (
                     vk::SharingMode::CONCURRENT,
-                    2 as u32,
+                    2_u32,
                     vec![
                         queue_family.graphics_family.unwrap(),
                         queue_family.present_family.unwrap(),
@@ -83,12 +73,12 @@ impl VkSpawChain {
             image_format: surface_format.format,
             image_extent: extent,
             image_usage: vk::ImageUsageFlags::COLOR_ATTACHMENT,
-            image_sharing_mode: image_sharing_mode,
+            image_sharing_mode,
             p_queue_family_indices: queue_family_indices.as_ptr(),
-            queue_family_index_count: queue_family_index_count,
+            queue_family_index_count,
             pre_transform: swapchain_support.capabilities.current_transform,
             composite_alpha: vk::CompositeAlphaFlagsKHR::OPAQUE,
-            present_mode: present_mode,
+            present_mode,
             clipped: vk::TRUE,
             old_swapchain: vk::SwapchainKHR::null(),
             image_array_layers: 1
@@ -108,11 +98,11 @@ impl VkSpawChain {
         };
 
         VkSpawChain {
-            swapchain_loader: swapchain_loader,
-            swapchain: swapchain,
+            swapchain_loader,
+            swapchain,
             swapchain_format: surface_format.format,
             swapchain_extent: extent,
-            swapchain_images: swapchain_images,
+            swapchain_images,
             swapchain_framebuffers: vec![],
             swapchain_image_views: vec![],
         }
@@ -121,7 +111,7 @@ impl VkSpawChain {
     pub fn create_framebuffers(
         device: &ash::Device,
         render_pass: vk::RenderPass,
-        image_views: &Vec<vk::ImageView>,
+        image_views: &[vk::ImageView],
         swapchain_extent: &vk::Extent2D
     ) -> Vec<vk::Framebuffer> {
         let mut framebuffers = vec![];
@@ -217,28 +207,28 @@ impl VkSpawChain {
         };
 
         SwapChainSupportDetail {
-            capabilities: capabilities,
-            formats: formats,
-            present_modes: present_modes
+            capabilities,
+            formats,
+            present_modes
         }
     }
 
     fn choose_swapchain_format(
-        available_formats: &Vec<ash::vk::SurfaceFormatKHR>
+        available_formats: &[ash::vk::SurfaceFormatKHR]
     ) -> ash::vk::SurfaceFormatKHR {
 
         for format in available_formats.iter() {
             if format.format == ash::vk::Format::B8G8R8A8_SRGB
                 && format.color_space == ash::vk::ColorSpaceKHR::SRGB_NONLINEAR {
-                    return format.clone();
-                }
+                return *format;
+            }
         }
 
-        return available_formats.first().unwrap().clone();
+        *available_formats.first().unwrap()
     }
 
     fn choose_swapchain_present_mode(
-        present_modes: &Vec<ash::vk::PresentModeKHR>
+        present_modes: &[ash::vk::PresentModeKHR]
     ) -> ash::vk::PresentModeKHR {
 
         for &present_mode in present_modes.iter() {
@@ -247,14 +237,14 @@ impl VkSpawChain {
            }
         }
 
-        return present_modes.first().unwrap().clone();
+        *present_modes.first().unwrap()
     }
 
     fn choose_swapchain_extent(
         capabilities: &ash::vk::SurfaceCapabilitiesKHR
     ) -> ash::vk::Extent2D {
 
-        if capabilities.current_extent.width != u32::max_value() || capabilities.current_extent.height != u32::max_value() {
+        if capabilities.current_extent.width != u32::MAX || capabilities.current_extent.height != u32::MAX {
             capabilities.current_extent
         } else {
             use num::clamp;
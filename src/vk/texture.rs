@@ -0,0 +1,271 @@
+use std::ptr;
+
+use ash::vk;
+
+use crate::vk::render_device::VkRenderDevice;
+use crate::vk::render_target::RenderTarget;
+use crate::vk::timeline::{DeletionQueue, GpuTimeline, TimelinePoint, TimelineSubmit};
+
+/// How a texture is filtered and wrapped. Equal descriptions share one `vk::Sampler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> SamplerDesc {
+        SamplerDesc {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+impl SamplerDesc {
+    pub fn create_sampler(&self, device: &ash::Device) -> vk::Sampler {
+        let sampler_create_info = vk::SamplerCreateInfo {
+            s_type: vk::StructureType::SAMPLER_CREATE_INFO,
+            p_next: ptr::null(),
+            flags: vk::SamplerCreateFlags::empty(),
+            mag_filter: self.mag_filter,
+            min_filter: self.min_filter,
+            mipmap_mode: self.mipmap_mode,
+            address_mode_u: self.address_mode_u,
+            address_mode_v: self.address_mode_v,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            mip_lod_bias: 0.0,
+            anisotropy_enable: vk::FALSE,
+            max_anisotropy: 1.0,
+            compare_enable: vk::FALSE,
+            compare_op: vk::CompareOp::ALWAYS,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_OPAQUE_BLACK,
+            unnormalized_coordinates: vk::FALSE,
+        };
+
+        unsafe {
+            device
+                .create_sampler(&sampler_create_info, None)
+                .expect("Failed to create texture Sampler!")
+        }
+    }
+}
+
+/// A sampled RGBA8 image in device local memory.
+pub struct Texture {
+    pub image: vk::Image,
+    memory: vk::DeviceMemory,
+    pub view: vk::ImageView,
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+}
+
+impl Texture {
+    /// Uploads tightly packed RGBA8 `pixels` through a staging buffer on `timeline`, leaving the
+    /// image ready for sampling in any later submission on it. `srgb` is for colour data, which
+    /// sampling then returns in linear space.
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        device: &ash::Device,
+        memory_properties: &vk::PhysicalDeviceMemoryProperties,
+        command_pool: vk::CommandPool,
+        timeline: &mut GpuTimeline,
+        deletion_queue: &mut DeletionQueue,
+        extent: vk::Extent2D,
+        pixels: &[u8],
+        srgb: bool,
+    ) -> Result<Texture, String> {
+        let expected_size = extent.width as usize * extent.height as usize * 4;
+        if extent.width == 0 || extent.height == 0 || pixels.len() != expected_size {
+            return Err(format!(
+                "Failed to create texture: {}x{} RGBA8 takes {} bytes, got {}",
+                extent.width, extent.height, expected_size, pixels.len()));
+        }
+
+        let format = if srgb { vk::Format::R8G8B8A8_SRGB } else { vk::Format::R8G8B8A8_UNORM };
+        let (image, memory, view) = RenderTarget::create_image(
+            device,
+            memory_properties,
+            format,
+            extent,
+            vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED,
+        );
+
+        let buffer_size = pixels.len() as vk::DeviceSize;
+        let (staging_buffer, staging_buffer_memory) = VkRenderDevice::create_buffer(
+            device,
+            buffer_size,
+            vk::BufferUsageFlags::TRANSFER_SRC,
+            vk::MemoryPropertyFlags::HOST_VISIBLE | vk::MemoryPropertyFlags::HOST_COHERENT,
+            memory_properties,
+        );
+
+        unsafe {
+            let data_ptr = device
+                .map_memory(staging_buffer_memory, 0, buffer_size, vk::MemoryMapFlags::empty())
+                .expect("Failed to Map Memory") as *mut u8;
+
+            data_ptr.copy_from_nonoverlapping(pixels.as_ptr(), pixels.len());
+
+            device.unmap_memory(staging_buffer_memory);
+        }
+
+        let upload_point = Texture::upload(device, timeline, deletion_queue, command_pool, staging_buffer, image, extent);
+
+        deletion_queue.push(upload_point, move |device| unsafe {
+            device.destroy_buffer(staging_buffer, None);
+            device.free_memory(staging_buffer_memory, None);
+        });
+
+        Ok(Texture {
+            image,
+            memory,
+            view,
+            format,
+            extent,
+        })
+    }
+
+    fn upload(
+        device: &ash::Device,
+        timeline: &mut GpuTimeline,
+        deletion_queue: &mut DeletionQueue,
+        command_pool: vk::CommandPool,
+        staging_buffer: vk::Buffer,
+        image: vk::Image,
+        extent: vk::Extent2D,
+    ) -> TimelinePoint {
+        let allocate_info = vk::CommandBufferAllocateInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_ALLOCATE_INFO,
+            p_next: ptr::null(),
+            command_buffer_count: 1,
+            command_pool,
+            level: vk::CommandBufferLevel::PRIMARY,
+        };
+
+        let command_buffers = unsafe {
+            device
+                .allocate_command_buffers(&allocate_info)
+                .expect("Failed to allocate Command Buffer")
+        };
+        let command_buffer = command_buffers[0];
+
+        let begin_info = vk::CommandBufferBeginInfo {
+            s_type: vk::StructureType::COMMAND_BUFFER_BEGIN_INFO,
+            p_next: ptr::null(),
+            flags: vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT,
+            p_inheritance_info: ptr::null(),
+        };
+
+        let subresource_range = vk::ImageSubresourceRange {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            base_mip_level: 0,
+            level_count: 1,
+            base_array_layer: 0,
+            layer_count: 1,
+        };
+
+        unsafe {
+            device
+                .begin_command_buffer(command_buffer, &begin_info)
+                .expect("Failed to begin Command Buffer");
+
+            let to_transfer = vk::ImageMemoryBarrier {
+                s_type: vk::StructureType::IMAGE_MEMORY_BARRIER,
+                p_next: ptr::null(),
+                src_access_mask: vk::AccessFlags::empty(),
+                dst_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                old_layout: vk::ImageLayout::UNDEFINED,
+                new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                src_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                dst_queue_family_index: vk::QUEUE_FAMILY_IGNORED,
+                image,
+                subresource_range,
+            };
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TOP_OF_PIPE,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_transfer],
+            );
+
+            let copy_regions = [vk::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: 0,
+                    base_array_layer: 0,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D { x: 0, y: 0, z: 0 },
+                image_extent: vk::Extent3D {
+                    width: extent.width,
+                    height: extent.height,
+                    depth: 1,
+                },
+            }];
+            device.cmd_copy_buffer_to_image(
+                command_buffer,
+                staging_buffer,
+                image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &copy_regions,
+            );
+
+            let to_shader_read = vk::ImageMemoryBarrier {
+                src_access_mask: vk::AccessFlags::TRANSFER_WRITE,
+                dst_access_mask: vk::AccessFlags::SHADER_READ,
+                old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                new_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                ..to_transfer
+            };
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                vk::PipelineStageFlags::TRANSFER,
+                vk::PipelineStageFlags::FRAGMENT_SHADER,
+                vk::DependencyFlags::empty(),
+                &[],
+                &[],
+                &[to_shader_read],
+            );
+
+            device
+                .end_command_buffer(command_buffer)
+                .expect("Failed to end Command Buffer");
+        }
+
+        let upload_point = timeline.submit(device, &TimelineSubmit::new(&command_buffers));
+
+        deletion_queue.push(upload_point, move |device| unsafe {
+            device.free_command_buffers(command_pool, &command_buffers);
+        });
+
+        upload_point
+    }
+
+    /// Destroys the image once the GPU is past `point`, the last submission that may sample it.
+    pub fn destroy_after(self, deletion_queue: &mut DeletionQueue, point: TimelinePoint) {
+        deletion_queue.push(point, move |device| self.destroy(device));
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe {
+            device.destroy_image_view(self.view, None);
+            device.destroy_image(self.image, None);
+            device.free_memory(self.memory, None);
+        }
+    }
+}
@@ -0,0 +1,279 @@
+use std::collections::VecDeque;
+use std::ptr;
+
+use ash::vk;
+
+/// A value on a timeline semaphore. The GPU work it stands for is done once the semaphore reaches it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimelinePoint {
+    pub semaphore: vk::Semaphore,
+    pub value: u64,
+}
+
+impl TimelinePoint {
+    pub fn is_complete(&self, device: &ash::Device) -> bool {
+        let completed_value = unsafe {
+            device
+                .get_semaphore_counter_value(self.semaphore)
+                .expect("Failed to get Semaphore counter value!")
+        };
+
+        completed_value >= self.value
+    }
+
+    /// Blocks until the point is reached or `timeout` nanoseconds pass. Returns whether it was reached.
+    pub fn wait(&self, device: &ash::Device, timeout: u64) -> bool {
+        let semaphores = [self.semaphore];
+        let values = [self.value];
+
+        let semaphore_wait_info = vk::SemaphoreWaitInfo {
+            s_type: vk::StructureType::SEMAPHORE_WAIT_INFO,
+            p_next: ptr::null(),
+            flags: vk::SemaphoreWaitFlags::empty(),
+            semaphore_count: semaphores.len() as u32,
+            p_semaphores: semaphores.as_ptr(),
+            p_values: values.as_ptr(),
+        };
+
+        match unsafe { device.wait_semaphores(&semaphore_wait_info, timeout) } {
+            Ok(()) => true,
+            Err(vk::Result::TIMEOUT) => false,
+            Err(vk_result) => panic!("Failed to wait for timeline Semaphore: {}", vk_result),
+        }
+    }
+}
+
+/// One submission to a `GpuTimeline`. Binary semaphores are still needed for the swapchain.
+pub struct TimelineSubmit<'a> {
+    command_buffers: &'a [vk::CommandBuffer],
+    wait_points: Vec<(TimelinePoint, vk::PipelineStageFlags)>,
+    wait_semaphores: Vec<(vk::Semaphore, vk::PipelineStageFlags)>,
+    signal_semaphores: Vec<vk::Semaphore>,
+}
+
+impl<'a> TimelineSubmit<'a> {
+    pub fn new(command_buffers: &'a [vk::CommandBuffer]) -> TimelineSubmit<'a> {
+        TimelineSubmit {
+            command_buffers,
+            wait_points: vec![],
+            wait_semaphores: vec![],
+            signal_semaphores: vec![],
+        }
+    }
+
+    /// Waits for `point` before `stage`. The point may come from a timeline on another queue.
+    pub fn wait(mut self, point: TimelinePoint, stage: vk::PipelineStageFlags) -> TimelineSubmit<'a> {
+        self.wait_points.push((point, stage));
+        self
+    }
+
+    pub fn wait_binary(mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags) -> TimelineSubmit<'a> {
+        self.wait_semaphores.push((semaphore, stage));
+        self
+    }
+
+    pub fn signal_binary(mut self, semaphore: vk::Semaphore) -> TimelineSubmit<'a> {
+        self.signal_semaphores.push(semaphore);
+        self
+    }
+}
+
+/// A timeline semaphore owned by one queue. Every submission signals the next value on it.
+pub struct GpuTimeline {
+    pub semaphore: vk::Semaphore,
+    pub queue: vk::Queue,
+
+    last_submitted_value: u64,
+}
+
+impl GpuTimeline {
+    pub fn new(device: &ash::Device, queue: vk::Queue) -> GpuTimeline {
+        let semaphore_type_create_info = vk::SemaphoreTypeCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_TYPE_CREATE_INFO,
+            p_next: ptr::null(),
+            semaphore_type: vk::SemaphoreType::TIMELINE,
+            initial_value: 0,
+        };
+
+        let semaphore_create_info = vk::SemaphoreCreateInfo {
+            s_type: vk::StructureType::SEMAPHORE_CREATE_INFO,
+            p_next: &semaphore_type_create_info as *const vk::SemaphoreTypeCreateInfo as *const _,
+            flags: vk::SemaphoreCreateFlags::empty(),
+        };
+
+        let semaphore = unsafe {
+            device
+                .create_semaphore(&semaphore_create_info, None)
+                .expect("Failed to create timeline Semaphore!")
+        };
+
+        GpuTimeline {
+            semaphore,
+            queue,
+            last_submitted_value: 0,
+        }
+    }
+
+    /// The point of the most recent submission. Waiting on it waits for everything submitted so far.
+    pub fn last_submitted(&self) -> TimelinePoint {
+        TimelinePoint {
+            semaphore: self.semaphore,
+            value: self.last_submitted_value,
+        }
+    }
+
+    pub fn completed_value(&self, device: &ash::Device) -> u64 {
+        unsafe {
+            device
+                .get_semaphore_counter_value(self.semaphore)
+                .expect("Failed to get Semaphore counter value!")
+        }
+    }
+
+    pub fn submit(&mut self, device: &ash::Device, submission: &TimelineSubmit) -> TimelinePoint {
+        let signal_point = TimelinePoint {
+            semaphore: self.semaphore,
+            value: self.last_submitted_value + 1,
+        };
+
+        // binary semaphores ignore their value, so they just get 0
+        let wait_semaphores: Vec<vk::Semaphore> = submission.wait_points.iter().map(|(point, _)| point.semaphore)
+            .chain(submission.wait_semaphores.iter().map(|&(semaphore, _)| semaphore))
+            .collect();
+        let wait_values: Vec<u64> = submission.wait_points.iter().map(|(point, _)| point.value)
+            .chain(submission.wait_semaphores.iter().map(|_| 0))
+            .collect();
+        let wait_stages: Vec<vk::PipelineStageFlags> = submission.wait_points.iter().map(|&(_, stage)| stage)
+            .chain(submission.wait_semaphores.iter().map(|&(_, stage)| stage))
+            .collect();
+
+        let signal_semaphores: Vec<vk::Semaphore> = std::iter::once(signal_point.semaphore)
+            .chain(submission.signal_semaphores.iter().copied())
+            .collect();
+        let signal_values: Vec<u64> = std::iter::once(signal_point.value)
+            .chain(submission.signal_semaphores.iter().map(|_| 0))
+            .collect();
+
+        let timeline_submit_info = vk::TimelineSemaphoreSubmitInfo {
+            s_type: vk::StructureType::TIMELINE_SEMAPHORE_SUBMIT_INFO,
+            p_next: ptr::null(),
+            wait_semaphore_value_count: wait_values.len() as u32,
+            p_wait_semaphore_values: wait_values.as_ptr(),
+            signal_semaphore_value_count: signal_values.len() as u32,
+            p_signal_semaphore_values: signal_values.as_ptr(),
+        };
+
+        let submit_infos = [vk::SubmitInfo {
+            s_type: vk::StructureType::SUBMIT_INFO,
+            p_next: &timeline_submit_info as *const vk::TimelineSemaphoreSubmitInfo as *const _,
+            wait_semaphore_count: wait_semaphores.len() as u32,
+            p_wait_semaphores: wait_semaphores.as_ptr(),
+            p_wait_dst_stage_mask: wait_stages.as_ptr(),
+            command_buffer_count: submission.command_buffers.len() as u32,
+            p_command_buffers: submission.command_buffers.as_ptr(),
+            signal_semaphore_count: signal_semaphores.len() as u32,
+            p_signal_semaphores: signal_semaphores.as_ptr(),
+        }];
+
+        unsafe {
+            device
+                .queue_submit(self.queue, &submit_infos, vk::Fence::null())
+                .expect("Failed to execute queue submit.");
+        }
+
+        self.last_submitted_value = signal_point.value;
+        signal_point
+    }
+
+    /// Blocks until everything submitted to this timeline has finished.
+    pub fn wait_idle(&self, device: &ash::Device) {
+        self.last_submitted().wait(device, u64::MAX);
+    }
+
+    pub fn destroy(&self, device: &ash::Device) {
+        unsafe { device.destroy_semaphore(self.semaphore, None) };
+    }
+}
+
+type Deleter<D> = Box<dyn FnOnce(&D)>;
+
+/// Destruction deferred until the GPU has passed the point that last used the resources.
+/// Deleters get the device, or whatever `D` owns the resources.
+pub struct DeletionQueue<D = ash::Device> {
+    pending: VecDeque<(TimelinePoint, Deleter<D>)>,
+}
+
+impl DeletionQueue {
+    /// Runs the deleters whose points have been reached.
+    pub fn collect(&mut self, device: &ash::Device) {
+        self.collect_reached(device, |point| point.is_complete(device));
+    }
+}
+
+impl<D> DeletionQueue<D> {
+    pub fn new() -> DeletionQueue<D> {
+        DeletionQueue { pending: VecDeque::new() }
+    }
+
+    pub fn push<F: FnOnce(&D) + 'static>(&mut self, point: TimelinePoint, deleter: F) {
+        self.pending.push_back((point, Box::new(deleter)));
+    }
+
+    /// Runs the deleters whose points `is_reached` says the GPU has passed, in the order they were pushed.
+    pub fn collect_reached<F: FnMut(&TimelinePoint) -> bool>(&mut self, owner: &D, mut is_reached: F) {
+        let mut still_pending = VecDeque::with_capacity(self.pending.len());
+
+        for (point, deleter) in self.pending.drain(..) {
+            if is_reached(&point) {
+                deleter(owner);
+            } else {
+                still_pending.push_back((point, deleter));
+            }
+        }
+
+        self.pending = still_pending;
+    }
+
+    /// Runs every deleter. The caller has to make sure the GPU is idle.
+    pub fn flush(&mut self, owner: &D) {
+        for (_, deleter) in self.pending.drain(..) {
+            deleter(owner);
+        }
+    }
+}
+
+impl<D> Default for DeletionQueue<D> {
+    fn default() -> DeletionQueue<D> {
+        DeletionQueue::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    fn point(value: u64) -> TimelinePoint {
+        TimelinePoint { semaphore: vk::Semaphore::null(), value }
+    }
+
+    #[test]
+    fn only_deleters_of_reached_points_run() {
+        let deleted = RefCell::new(vec![]);
+        let mut deletion_queue = DeletionQueue::<RefCell<Vec<&str>>>::new();
+        deletion_queue.push(point(3), |deleted| deleted.borrow_mut().push("texture"));
+        deletion_queue.push(point(1), |deleted| deleted.borrow_mut().push("staging buffer"));
+        deletion_queue.push(point(2), |deleted| deleted.borrow_mut().push("mesh"));
+
+        let completed_value = 2;
+        deletion_queue.collect_reached(&deleted, |point| completed_value >= point.value);
+        assert_eq!(*deleted.borrow(), vec!["staging buffer", "mesh"]);
+
+        deletion_queue.collect_reached(&deleted, |point| completed_value >= point.value);
+        assert_eq!(deleted.borrow().len(), 2);
+
+        deletion_queue.flush(&deleted);
+        assert_eq!(*deleted.borrow(), vec!["staging buffer", "mesh", "texture"]);
+    }
+}
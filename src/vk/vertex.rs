@@ -1,8 +1,11 @@
 use ash::vk;
-use memoffset::offset_of;
+use cgmath::Matrix4;
+
+/// Fills in both traits from the field types, see its documentation for the attributes it takes.
+pub use pupsy_engine_derive::Vertex;
 
 #[repr(C)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, Vertex)]
 pub struct Vertex {
     pub pos: [f32; 2],
     pub color: [f32; 3],
@@ -16,41 +19,206 @@ pub trait AttributeDescriptions {
     fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription>;
 }
 
-impl BindingDescriptions for Vertex {
-    fn get_binding_descriptions() -> Vec<vk::VertexInputBindingDescription> {
-        let mut result = vec![];
+/// A vertex of imported models: a position, a normal and texture coordinates with the origin at the top left.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Vertex)]
+pub struct MeshVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+/// The per-instance stream of the mesh pipelines, bound after the mesh's own streams. It takes
+/// locations 4 to 7, after those of the vertex types above.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Vertex)]
+#[vertex(binding = 1, per_instance)]
+pub struct InstanceTransform {
+    #[location(4)]
+    pub model: Matrix4<f32>,
+}
+
+/// The vertex input of a pipeline put together from one type per stream, e.g. positions and
+/// the other attributes apart, or a per-vertex and a per-instance stream.
+#[derive(Clone, Debug, Default)]
+pub struct VertexLayout {
+    bindings: Vec<vk::VertexInputBindingDescription>,
+    attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    pub fn new() -> VertexLayout {
+        VertexLayout::default()
+    }
+
+    /// The stream of `V` alone, at the binding it declares.
+    pub fn of<V: BindingDescriptions + AttributeDescriptions>() -> VertexLayout {
+        VertexLayout {
+            bindings: V::get_binding_descriptions(),
+            attributes: V::get_attribute_descriptions(),
+        }
+    }
 
-        result.push(
-            vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: std::mem::size_of::<Self>() as u32,
-            input_rate: vk::VertexInputRate::VERTEX,
-        });
+    /// Adds the stream described by `V`. Its binding and locations must not be in use yet.
+    pub fn stream<V: BindingDescriptions + AttributeDescriptions>(self) -> VertexLayout {
+        self.append(&VertexLayout::of::<V>())
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Like `stream`, but puts `V` at `binding` instead of the binding it declares.
+    pub fn stream_at<V: BindingDescriptions + AttributeDescriptions>(self, binding: u32) -> VertexLayout {
+        self.append(&VertexLayout::of::<V>().at_binding(binding))
+            .unwrap_or_else(|error| panic!("{}", error))
+    }
+
+    /// Moves every stream of the layout to `binding`, for layouts of a single stream.
+    pub fn at_binding(mut self, binding: u32) -> VertexLayout {
+        assert!(self.bindings.len() <= 1, "Only a single stream can be moved to binding {}", binding);
+        for description in self.bindings.iter_mut() {
+            description.binding = binding;
+        }
+        for attribute in self.attributes.iter_mut() {
+            attribute.binding = binding;
+        }
+        self
+    }
+
+    /// Adds the streams of `other`, failing if one of its bindings or locations is already in use.
+    pub fn append(mut self, other: &VertexLayout) -> Result<VertexLayout, String> {
+        for binding in other.bindings.iter() {
+            if self.bindings.iter().any(|existing| existing.binding == binding.binding) {
+                return Err(format!("Vertex binding {} is used by two streams", binding.binding));
+            }
+            self.bindings.push(*binding);
+        }
+        for attribute in other.attributes.iter() {
+            if self.attributes.iter().any(|existing| existing.location == attribute.location) {
+                return Err(format!("Vertex location {} is used by two streams", attribute.location));
+            }
+            self.attributes.push(*attribute);
+        }
+        Ok(self)
+    }
+
+    /// The first binding after every stream of the layout.
+    pub fn next_binding(&self) -> u32 {
+        self.bindings.iter().map(|binding| binding.binding + 1).max().unwrap_or(0)
+    }
+
+    pub fn bindings(&self) -> &[vk::VertexInputBindingDescription] {
+        &self.bindings
+    }
 
-        
-        result
+    pub fn attributes(&self) -> &[vk::VertexInputAttributeDescription] {
+        &self.attributes
     }
 }
 
-impl AttributeDescriptions for Vertex {
-    fn get_attribute_descriptions() -> Vec<vk::VertexInputAttributeDescription> {
-        let mut result = vec![];
+// the ash descriptions don't implement `PartialEq`
+impl PartialEq for VertexLayout {
+    fn eq(&self, other: &VertexLayout) -> bool {
+        let same_bindings = self.bindings.len() == other.bindings.len()
+            && self.bindings.iter().zip(other.bindings.iter()).all(|(a, b)| {
+                a.binding == b.binding && a.stride == b.stride && a.input_rate == b.input_rate
+            });
+        let same_attributes = self.attributes.len() == other.attributes.len()
+            && self.attributes.iter().zip(other.attributes.iter()).all(|(a, b)| {
+                a.location == b.location && a.binding == b.binding && a.format == b.format && a.offset == b.offset
+            });
 
-        result.push(
-            vk::VertexInputAttributeDescription {
-            binding: 0,
-            location: 0,
-            format: vk::Format::R32G32_SFLOAT,
-            offset: offset_of!(Self, pos) as u32,
-        });
-        
-        result.push(vk::VertexInputAttributeDescription {
-            binding: 0,
-            location: 1,
-            format: vk::Format::R32G32B32_SFLOAT,
-            offset: offset_of!(Self, color) as u32,
-        });
+        same_bindings && same_attributes
+    }
+}
+
+impl Eq for VertexLayout {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cgmath::{Point3, Vector2};
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Vertex)]
+    struct Skinned {
+        position: Point3<f32>,
+        uv: Vector2<f32>,
+        joints: [u32; 4],
+        #[location(6)]
+        weight: f32,
+        color: [u8; 4],
+        #[format(R16G16_SFLOAT)]
+        packed: [u16; 2],
+    }
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Vertex)]
+    #[vertex(binding = 2, per_instance)]
+    struct Instance {
+        tint: [u8; 2],
+        flags: [u8; 2],
+        model: [[f32; 4]; 4],
+    }
+
+    /// (location, binding, format, offset) of every attribute
+    fn attributes<V: AttributeDescriptions>() -> Vec<(u32, u32, vk::Format, u32)> {
+        V::get_attribute_descriptions().iter()
+            .map(|attribute| (attribute.location, attribute.binding, attribute.format, attribute.offset))
+            .collect()
+    }
+
+    #[test]
+    fn derive_follows_field_types_offsets_and_locations() {
+        assert_eq!(attributes::<Skinned>(), vec![
+            (0, 0, vk::Format::R32G32B32_SFLOAT, 0),
+            (1, 0, vk::Format::R32G32_SFLOAT, 12),
+            (2, 0, vk::Format::R32G32B32A32_UINT, 20),
+            (6, 0, vk::Format::R32_SFLOAT, 36),
+            (7, 0, vk::Format::R8G8B8A8_UNORM, 40),
+            (8, 0, vk::Format::R16G16_SFLOAT, 44),
+        ]);
 
-        result
+        let bindings = Skinned::get_binding_descriptions();
+        assert_eq!(bindings.len(), 1);
+        assert_eq!((bindings[0].binding, bindings[0].stride), (0, 48));
+        assert_eq!(bindings[0].input_rate, vk::VertexInputRate::VERTEX);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn derive_expands_matrices_into_columns_per_instance() {
+        let model_offset = std::mem::offset_of!(Instance, model) as u32;
+        assert_eq!(attributes::<Instance>(), vec![
+            (0, 2, vk::Format::R8G8_UNORM, 0),
+            (1, 2, vk::Format::R8G8_UNORM, 2),
+            (2, 2, vk::Format::R32G32B32A32_SFLOAT, model_offset),
+            (3, 2, vk::Format::R32G32B32A32_SFLOAT, model_offset + 16),
+            (4, 2, vk::Format::R32G32B32A32_SFLOAT, model_offset + 32),
+            (5, 2, vk::Format::R32G32B32A32_SFLOAT, model_offset + 48),
+        ]);
+
+        let bindings = Instance::get_binding_descriptions();
+        assert_eq!((bindings[0].binding, bindings[0].stride), (2, std::mem::size_of::<Instance>() as u32));
+        assert_eq!(bindings[0].input_rate, vk::VertexInputRate::INSTANCE);
+
+        // cgmath's matrix takes the same four locations
+        let locations: Vec<u32> = attributes::<InstanceTransform>().iter().map(|attribute| attribute.0).collect();
+        assert_eq!(locations, vec![4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn stream_at_moves_bindings_and_attributes() {
+        let layout = VertexLayout::new().stream::<MeshVertex>().stream_at::<InstanceTransform>(1);
+
+        assert_eq!(layout, VertexLayout::of::<MeshVertex>().append(&VertexLayout::of::<InstanceTransform>()).unwrap());
+        assert!(layout.attributes().iter().filter(|attribute| attribute.location >= 4).all(|attribute| attribute.binding == 1));
+        assert_ne!(VertexLayout::of::<MeshVertex>(), VertexLayout::of::<Vertex>());
+    }
+
+    #[test]
+    fn append_rejects_used_bindings_and_locations() {
+        let layout = VertexLayout::of::<Vertex>();
+
+        assert!(layout.clone().append(&VertexLayout::of::<MeshVertex>()).is_err());
+        assert!(layout.clone().append(&VertexLayout::of::<MeshVertex>().at_binding(1)).is_err());
+        assert!(layout.append(&VertexLayout::of::<InstanceTransform>()).is_ok());
+    }
+}